@@ -108,6 +108,10 @@ pub enum ProviderError {
         /// Block hash
         block_hash: BlockHash,
     },
+    /// The state root computation failed for a reason other than a database error, e.g. it was
+    /// cancelled or the computed root didn't match an expected one.
+    #[error("state root computation failed: {0}")]
+    StateRootComputation(String),
     /// State is not available for the given block number because it is pruned.
     #[error("state at block #{0} is pruned")]
     StateAtBlockPruned(BlockNumber),
@@ -1,6 +1,6 @@
 use crate::{
     trie::{hash_builder::HashBuilderState, StoredSubNode},
-    Address, BlockNumber, B256,
+    Account, Address, BlockNumber, B256,
 };
 use bytes::{Buf, BufMut};
 use reth_codecs::{derive_arbitrary, main_codec, Compact};
@@ -24,6 +24,9 @@ pub struct MerkleCheckpoint {
     pub walker_stack: Vec<StoredSubNode>,
     /// The hash builder state.
     pub state: HashBuilderState,
+    /// The storage root computation of the account at [Self::last_account_key], if the progress
+    /// was saved in the middle of its storage trie.
+    pub storage_root_checkpoint: Option<StorageRootMerkleCheckpoint>,
 }
 
 impl MerkleCheckpoint {
@@ -40,8 +43,18 @@ impl MerkleCheckpoint {
             walker_stack,
             state,
             last_walker_key: Vec::default(),
+            storage_root_checkpoint: None,
         }
     }
+
+    /// Sets the interrupted storage root computation of the account at the last account key.
+    pub fn with_storage_root_checkpoint(
+        mut self,
+        storage_root_checkpoint: Option<StorageRootMerkleCheckpoint>,
+    ) -> Self {
+        self.storage_root_checkpoint = storage_root_checkpoint;
+        self
+    }
 }
 
 impl Compact for MerkleCheckpoint {
@@ -68,6 +81,12 @@ impl Compact for MerkleCheckpoint {
         }
 
         len += self.state.to_compact(buf);
+
+        // Checkpoints without an interrupted storage root computation keep the encoding of the
+        // checkpoints written before it could be saved.
+        if let Some(storage_root_checkpoint) = self.storage_root_checkpoint {
+            len += storage_root_checkpoint.to_compact(buf);
+        }
         len
     }
 
@@ -92,7 +111,14 @@ impl Compact for MerkleCheckpoint {
             buf = rest;
         }
 
-        let (state, buf) = HashBuilderState::from_compact(buf, 0);
+        let (state, mut buf) = HashBuilderState::from_compact(buf, 0);
+
+        let mut storage_root_checkpoint = None;
+        if !buf.is_empty() {
+            let (checkpoint, rest) = StorageRootMerkleCheckpoint::from_compact(buf, buf.len());
+            storage_root_checkpoint = Some(checkpoint);
+            buf = rest;
+        }
         (
             MerkleCheckpoint {
                 target_block,
@@ -100,12 +126,90 @@ impl Compact for MerkleCheckpoint {
                 last_walker_key,
                 walker_stack,
                 state,
+                storage_root_checkpoint,
             },
             buf,
         )
     }
 }
 
+/// Saves the progress of the storage root computation of a single account, interrupted in the
+/// middle of its storage trie, see [MerkleCheckpoint::storage_root_checkpoint].
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct StorageRootMerkleCheckpoint {
+    /// The account whose storage root is computed. Its leaf is not in the hash builder of the
+    /// account trie yet.
+    pub account: Account,
+    /// The last hashed storage slot processed.
+    pub last_slot_key: B256,
+    /// Previously recorded walker stack of the storage trie.
+    pub walker_stack: Vec<StoredSubNode>,
+    /// The hash builder state of the storage trie.
+    pub state: HashBuilderState,
+}
+
+impl StorageRootMerkleCheckpoint {
+    /// Creates a new storage root checkpoint.
+    pub fn new(
+        account: Account,
+        last_slot_key: B256,
+        walker_stack: Vec<StoredSubNode>,
+        state: HashBuilderState,
+    ) -> Self {
+        Self { account, last_slot_key, walker_stack, state }
+    }
+}
+
+impl Compact for StorageRootMerkleCheckpoint {
+    fn to_compact<B>(self, buf: &mut B) -> usize
+    where
+        B: bytes::BufMut + AsMut<[u8]>,
+    {
+        let mut len = 0;
+
+        let mut account = Vec::new();
+        self.account.to_compact(&mut account);
+        buf.put_u16(account.len() as u16);
+        buf.put_slice(&account);
+        len += 2 + account.len();
+
+        buf.put_slice(self.last_slot_key.as_slice());
+        len += self.last_slot_key.len();
+
+        buf.put_u16(self.walker_stack.len() as u16);
+        len += 2;
+        for item in self.walker_stack.into_iter() {
+            len += item.to_compact(buf);
+        }
+
+        len += self.state.to_compact(buf);
+        len
+    }
+
+    fn from_compact(mut buf: &[u8], _len: usize) -> (Self, &[u8])
+    where
+        Self: Sized,
+    {
+        let account_len = buf.get_u16() as usize;
+        let (account, _) = Account::from_compact(&buf[..account_len], account_len);
+        buf.advance(account_len);
+
+        let last_slot_key = B256::from_slice(&buf[..32]);
+        buf.advance(32);
+
+        let walker_stack_len = buf.get_u16() as usize;
+        let mut walker_stack = Vec::with_capacity(walker_stack_len);
+        for _ in 0..walker_stack_len {
+            let (item, rest) = StoredSubNode::from_compact(buf, 0);
+            walker_stack.push(item);
+            buf = rest;
+        }
+
+        let (state, buf) = HashBuilderState::from_compact(buf, 0);
+        (StorageRootMerkleCheckpoint { account, last_slot_key, walker_stack, state }, buf)
+    }
+}
+
 /// Saves the progress of AccountHashing stage.
 #[main_codec]
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
@@ -385,6 +489,7 @@ stage_unit_checkpoints!(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::U256;
     use rand::Rng;
 
     #[test]
@@ -400,12 +505,36 @@ mod tests {
                 node: None,
             }],
             state: HashBuilderState::default(),
+            storage_root_checkpoint: None,
         };
 
         let mut buf = Vec::new();
         let encoded = checkpoint.clone().to_compact(&mut buf);
         let (decoded, _) = MerkleCheckpoint::from_compact(&buf, encoded);
         assert_eq!(decoded, checkpoint);
+
+        // with an interrupted storage root computation
+        let checkpoint =
+            checkpoint.with_storage_root_checkpoint(Some(StorageRootMerkleCheckpoint {
+                account: Account {
+                    nonce: rng.gen(),
+                    balance: U256::from(rng.gen::<u64>()),
+                    bytecode_hash: Some(rng.gen()),
+                },
+                last_slot_key: rng.gen(),
+                walker_stack: vec![StoredSubNode {
+                    key: B256::random_with(&mut rng).to_vec(),
+                    nibble: None,
+                    node: None,
+                }],
+                state: HashBuilderState::default(),
+            }));
+
+        let mut buf = Vec::new();
+        let encoded = checkpoint.clone().to_compact(&mut buf);
+        let (decoded, rest) = MerkleCheckpoint::from_compact(&buf, encoded);
+        assert_eq!(decoded, checkpoint);
+        assert!(rest.is_empty());
     }
 
     #[test]
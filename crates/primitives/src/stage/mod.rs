@@ -7,5 +7,5 @@ mod checkpoints;
 pub use checkpoints::{
     AccountHashingCheckpoint, CheckpointBlockRange, EntitiesCheckpoint, ExecutionCheckpoint,
     HeadersCheckpoint, IndexHistoryCheckpoint, MerkleCheckpoint, StageCheckpoint,
-    StageUnitCheckpoint, StorageHashingCheckpoint,
+    StageUnitCheckpoint, StorageHashingCheckpoint, StorageRootMerkleCheckpoint,
 };
@@ -53,6 +53,7 @@ pub struct HashBuilder {
     stored_in_database: bool,
 
     updated_branch_nodes: Option<HashMap<Nibbles, BranchNodeCompact>>,
+    retained_depth: Option<usize>,
     proof_retainer: Option<ProofRetainer>,
 
     rlp_buf: Vec<u8>,
@@ -69,6 +70,7 @@ impl From<HashBuilderState> for HashBuilder {
             hash_masks: state.hash_masks,
             stored_in_database: state.stored_in_database,
             updated_branch_nodes: None,
+            retained_depth: None,
             proof_retainer: None,
             rlp_buf: Vec::with_capacity(32),
         }
@@ -98,6 +100,22 @@ impl HashBuilder {
         self
     }
 
+    /// Limits the branch nodes that are stored to the ones with a path of at most `depth` nibbles.
+    ///
+    /// See [HashBuilder::set_retained_depth].
+    pub fn with_retained_depth(mut self, depth: Option<usize>) -> Self {
+        self.set_retained_depth(depth);
+        self
+    }
+
+    /// Limits the branch nodes that are stored to the ones with a path of at most `depth` nibbles.
+    ///
+    /// Deeper branch nodes are still hashed, but they are neither emitted as updates nor marked as
+    /// stored in the tree mask of their parent. If `None`, all branch nodes are stored.
+    pub fn set_retained_depth(&mut self, depth: Option<usize>) {
+        self.retained_depth = depth;
+    }
+
     /// Enable proof retainer for the specified target nibbles.
     pub fn with_proof_retainer(mut self, targets: Vec<Nibbles>) -> Self {
         self.proof_retainer = Some(ProofRetainer::new(targets));
@@ -124,6 +142,15 @@ impl HashBuilder {
         self.proof_retainer.take().map(ProofRetainer::into_proofs).unwrap_or_default()
     }
 
+    /// Take and return the retained proofs whose keys start with the given prefix, keeping the
+    /// proof retainer for the following nodes.
+    pub fn take_proofs_with_prefix(&mut self, prefix: &Nibbles) -> BTreeMap<Nibbles, Bytes> {
+        self.proof_retainer
+            .as_mut()
+            .map(|proof_retainer| proof_retainer.take_proofs_with_prefix(prefix))
+            .unwrap_or_default()
+    }
+
     /// The number of total updates accrued.
     /// Returns `0` if [Self::with_updates] was not called.
     pub fn updates_len(&self) -> usize {
@@ -380,7 +407,9 @@ impl HashBuilder {
             self.hash_masks[parent_index] |= TrieMask::from_nibble(current[parent_index]);
         }
 
-        let store_in_db_trie = !self.tree_masks[len].is_empty() || !self.hash_masks[len].is_empty();
+        let store_in_db_trie = (!self.tree_masks[len].is_empty() ||
+            !self.hash_masks[len].is_empty()) &&
+            self.retained_depth.map_or(true, |depth| len <= depth);
         if store_in_db_trie {
             if len > 0 {
                 let parent_index = len - 1;
@@ -571,6 +600,42 @@ mod tests {
         assert_eq!(root, trie_root(data));
     }
 
+    #[test]
+    fn test_retained_depth() {
+        let data = [
+            (0x10, 0x00),
+            (0x11, 0x00),
+            (0x11, 0x10),
+            (0x12, 0x00),
+            (0x12, 0x20),
+            (0x13, 0x20),
+            (0x20, 0x00),
+        ]
+        .map(|(first, second)| {
+            let mut key = [0u8; 32];
+            key[..2].copy_from_slice(&[first, second]);
+            (key.to_vec(), Vec::new())
+        });
+        let build = |depth| {
+            let mut hb = HashBuilder::default().with_updates(true).with_retained_depth(depth);
+            for (key, val) in &data {
+                hb.add_leaf(Nibbles::unpack(key), val.as_ref());
+            }
+            let root = hb.root();
+            (root, hb.split().1)
+        };
+
+        let (root, updates) = build(None);
+        assert!(updates.contains_key(&Nibbles::from(hex!("01").as_slice())));
+        assert_eq!(updates[&Nibbles::default()].tree_mask, TrieMask::new(0b10));
+
+        // the deeper node is hashed, but neither stored nor referenced by the root node
+        let (limited_root, limited_updates) = build(Some(0));
+        assert_eq!(limited_root, root);
+        assert_eq!(limited_updates.keys().collect::<Vec<_>>(), [&Nibbles::default()]);
+        assert_eq!(limited_updates[&Nibbles::default()].tree_mask, TrieMask::new(0));
+    }
+
     #[test]
     fn test_root_raw_data() {
         let data = vec![
@@ -23,6 +23,20 @@ impl ProofRetainer {
         self.targets.iter().any(|target| target.starts_with(prefix))
     }
 
+    /// Removes and returns the collected proofs whose keys start with the given prefix.
+    pub fn take_proofs_with_prefix(&mut self, prefix: &Nibbles) -> BTreeMap<Nibbles, Bytes> {
+        let keys = self
+            .proofs
+            .range(prefix.clone()..)
+            .map(|(key, _)| key)
+            .take_while(|key| key.has_prefix(prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.into_iter()
+            .filter_map(|key| self.proofs.remove(&key).map(|proof| (key, proof)))
+            .collect()
+    }
+
     /// Returns all collected proofs.
     pub fn into_proofs(self) -> BTreeMap<Nibbles, Bytes> {
         self.proofs
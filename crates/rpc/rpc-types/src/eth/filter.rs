@@ -121,6 +121,21 @@ impl From<U256> for Topic {
     }
 }
 
+/// The signature of the `Transfer` event shared by ERC-20 and ERC-721 tokens.
+pub const TRANSFER_EVENT_SIGNATURE: &str = "Transfer(address,address,uint256)";
+
+/// Converts an address into its indexed topic representation, left-padded to 32 bytes.
+fn address_to_topic(address: Address) -> B256 {
+    let mut topic = B256::ZERO;
+    topic[12..].copy_from_slice(address.as_slice());
+    topic
+}
+
+/// Returns `true` if the topic is a valid left-padded address.
+fn is_address_topic(topic: &B256) -> bool {
+    topic[..12].iter().all(|byte| *byte == 0)
+}
+
 /// Represents the target range of blocks for the filter
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum FilterBlockOption {
@@ -437,6 +452,80 @@ impl Filter {
         self
     }
 
+    /// Sets the event signature (topic0) and the positional indexed arguments of the event.
+    ///
+    /// The first indexed value is matched against topic1, the second against topic2 and so on.
+    /// `None` acts as a wildcard for that position.
+    ///
+    /// # Panics
+    ///
+    /// If more than three indexed arguments are provided.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use alloy_primitives::B256;
+    /// # use reth_rpc_types::Filter;
+    /// # fn main() {
+    /// let owner = B256::with_last_byte(1);
+    /// let filter = Filter::new()
+    ///     .event_with_indexed_args("Approval(address,address,uint256)", [Some(owner), None]);
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn event_with_indexed_args<I>(mut self, event_signature: &str, indexed: I) -> Self
+    where
+        I: IntoIterator<Item = Option<B256>>,
+    {
+        self = self.event(event_signature);
+        for (idx, arg) in indexed.into_iter().enumerate() {
+            assert!(idx < 3, "an event has at most three indexed arguments");
+            self.topics[idx + 1] = arg.map(Into::into).unwrap_or_default();
+        }
+        self
+    }
+
+    /// Matches ERC-20 `Transfer(address,address,uint256)` events, optionally restricted to the
+    /// given sender and recipient.
+    #[must_use]
+    pub fn erc20_transfer(self, from: Option<Address>, to: Option<Address>) -> Self {
+        self.event_with_indexed_args(
+            TRANSFER_EVENT_SIGNATURE,
+            [from.map(address_to_topic), to.map(address_to_topic)],
+        )
+    }
+
+    /// Matches ERC-721 `Transfer(address,address,uint256)` events, optionally restricted to the
+    /// given sender, recipient and token id.
+    ///
+    /// Unlike ERC-20, the token id of an ERC-721 transfer is indexed and stored in topic3.
+    #[must_use]
+    pub fn erc721_transfer(
+        self,
+        from: Option<Address>,
+        to: Option<Address>,
+        token_id: Option<U256>,
+    ) -> Self {
+        self.event_with_indexed_args(
+            TRANSFER_EVENT_SIGNATURE,
+            [from.map(address_to_topic), to.map(address_to_topic), token_id.map(B256::from)],
+        )
+    }
+
+    /// Returns `true` if the filter targets `Transfer(address,address,uint256)` events, but
+    /// the values for the indexed `from` or `to` arguments can never match an address.
+    ///
+    /// Indexed addresses are stored as 32 byte words left-padded with zeroes, so any topic value
+    /// with a non-zero upper 12 bytes is a malformed address argument, commonly caused by passing
+    /// the arguments in the wrong position.
+    pub fn has_malformed_transfer_topics(&self) -> bool {
+        let transfer = keccak256(TRANSFER_EVENT_SIGNATURE);
+        if self.topics[0].is_empty() || !self.topics[0].0.iter().all(|topic| *topic == transfer) {
+            return false
+        }
+        self.topics[1..3].iter().any(|topic| topic.0.iter().any(|value| !is_address_topic(value)))
+    }
+
     /// Returns true if this is a range filter and has a from block
     pub fn is_paginatable(&self) -> bool {
         self.get_from_block().is_some()
@@ -695,11 +784,48 @@ where
     }
 }
 
+/// Reth specific refinements of a [Filter] that are not part of the standard filter object.
+///
+/// Both are checked after the logs of a block are fetched, the block blooms can't rule out blocks
+/// based on them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LogFilterOptions {
+    /// The exact number of topics of the matching logs, if restricted.
+    pub exact_topic_count: Option<usize>,
+    /// The receipt status of the transactions whose logs match, if restricted.
+    ///
+    /// Reverted transactions don't emit any logs, so in practice only `Some(true)` or `None`
+    /// return logs.
+    pub transaction_success: Option<bool>,
+}
+
+impl LogFilterOptions {
+    /// Only matches logs with exactly the given number of topics, e.g. to tell apart events whose
+    /// signatures collide but that index a different number of arguments.
+    #[must_use]
+    pub fn exact_topic_count(mut self, count: usize) -> Self {
+        self.exact_topic_count = Some(count);
+        self
+    }
+
+    /// Only matches logs of transactions with the given receipt status.
+    ///
+    /// Logs are currently only emitted by successful transactions, so filtering for reverted
+    /// transactions matches nothing.
+    #[must_use]
+    pub fn transaction_success(mut self, success: bool) -> Self {
+        self.transaction_success = Some(success);
+        self
+    }
+}
+
 /// Support for matching [Filter]s
 #[derive(Debug, Default)]
 pub struct FilteredParams {
     /// The original filter, if any
     pub filter: Option<Filter>,
+    /// The reth specific refinements of the filter
+    pub options: LogFilterOptions,
 }
 
 impl FilteredParams {
@@ -707,12 +833,18 @@ impl FilteredParams {
     /// for matching
     pub fn new(filter: Option<Filter>) -> Self {
         if let Some(filter) = filter {
-            FilteredParams { filter: Some(filter) }
+            FilteredParams { filter: Some(filter), options: Default::default() }
         } else {
             Default::default()
         }
     }
 
+    /// Applies the given reth specific refinements on top of the filter
+    pub fn with_options(mut self, options: LogFilterOptions) -> Self {
+        self.options = options;
+        self
+    }
+
     /// Returns the [BloomFilter] for the given address
     pub fn address_filter(address: &FilterSet<Address>) -> BloomFilter {
         address.to_bloom_filter()
@@ -791,6 +923,18 @@ impl FilteredParams {
         self.filter.as_ref().map(|f| f.address.matches(&log.address)).unwrap_or(true)
     }
 
+    /// Returns `true` if the number of topics of a log matches the exact topic count of the
+    /// options, if any
+    pub fn filter_topic_count(&self, topic_count: usize) -> bool {
+        self.options.exact_topic_count.map_or(true, |count| count == topic_count)
+    }
+
+    /// Returns `true` if the receipt status of a log's transaction matches the transaction status
+    /// of the options, if any
+    pub fn filter_transaction_success(&self, success: bool) -> bool {
+        self.options.transaction_success.map_or(true, |expected| expected == success)
+    }
+
     /// Returns `true` if the log matches the filter's topics
     pub fn filter_topics(&self, log: &Log) -> bool {
         let topics = match self.filter.as_ref() {
@@ -821,6 +965,66 @@ impl FilteredParams {
     }
 }
 
+/// A bloom gate over several [Filter]s that rejects blocks that can't match any of them.
+///
+/// The address and topic bloom matchers of the filters that restrict the same positions are
+/// combined, so a block that passes the gate may still match none of the filters and has to be
+/// checked against each of them. A block that fails the gate never matches any of them.
+///
+/// A position only one filter leaves open doesn't open it for the others, so mixing address-only
+/// and topic-only filters still rejects blocks.
+#[derive(Debug, Default)]
+pub struct UnionFilterParams {
+    /// The combined address and topic blooms of every group of filters restricting the same
+    /// positions, empty for an open position. Nothing matches if there are no groups.
+    groups: Vec<(BloomFilter, Vec<BloomFilter>)>,
+}
+
+impl UnionFilterParams {
+    /// Combines the bloom matchers of the given filters.
+    pub fn new<'a>(filters: impl IntoIterator<Item = &'a Filter>) -> Self {
+        // the restricted positions of a group, and its address and topic blooms
+        let mut groups: Vec<((bool, [bool; 4]), Vec<Bloom>, [Vec<Bloom>; 4])> = Vec::new();
+
+        for filter in filters {
+            let mut restricted_topics = [false; 4];
+            for (idx, topic) in filter.topics.iter().enumerate() {
+                restricted_topics[idx] = !topic.is_empty();
+            }
+            let shape = (!filter.address.is_empty(), restricted_topics);
+
+            let idx = match groups.iter().position(|(group_shape, ..)| *group_shape == shape) {
+                Some(idx) => idx,
+                None => {
+                    groups.push((shape, Vec::new(), Default::default()));
+                    groups.len() - 1
+                }
+            };
+            let (_, address_blooms, topic_blooms) = &mut groups[idx];
+            address_blooms.extend(filter.address.to_bloom_filter().0);
+            for (idx, topic) in filter.topics.iter().enumerate() {
+                topic_blooms[idx].extend(topic.to_bloom_filter().0);
+            }
+        }
+
+        let groups = groups
+            .into_iter()
+            .map(|(_, address_blooms, topic_blooms)| {
+                (address_blooms.into(), topic_blooms.into_iter().map(Into::into).collect())
+            })
+            .collect();
+        Self { groups }
+    }
+
+    /// Returns `false` if a block with the given bloom can't match any of the filters.
+    pub fn matches_bloom(&self, bloom: Bloom) -> bool {
+        self.groups.iter().any(|(address_filter, topics_filter)| {
+            FilteredParams::matches_address(bloom, address_filter) &&
+                FilteredParams::matches_topics(bloom, topics_filter)
+        })
+    }
+}
+
 /// Response of the `eth_getFilterChanges` RPC.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum FilterChanges {
@@ -1060,6 +1264,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn can_build_transfer_filters() {
+        let from = Address::random();
+        let to = Address::random();
+
+        let filter = Filter::new().erc20_transfer(Some(from), None);
+        assert_eq!(filter.topics[0], keccak256(TRANSFER_EVENT_SIGNATURE).into());
+        assert_eq!(filter.topics[1], address_to_topic(from).into());
+        assert!(filter.topics[2].is_empty());
+        assert!(filter.topics[3].is_empty());
+        assert!(!filter.has_malformed_transfer_topics());
+
+        let filter = Filter::new().erc721_transfer(None, Some(to), Some(U256::from(7)));
+        assert!(filter.topics[1].is_empty());
+        assert_eq!(filter.topics[2], address_to_topic(to).into());
+        assert_eq!(filter.topics[3], U256::from(7).into());
+        assert!(!filter.has_malformed_transfer_topics());
+    }
+
+    #[test]
+    fn can_detect_malformed_transfer_filter() {
+        // A random word is not a left-padded address
+        let filter = Filter::new()
+            .event(TRANSFER_EVENT_SIGNATURE)
+            .topic1(B256::repeat_byte(0xff))
+            .topic2(address_to_topic(Address::random()));
+        assert!(filter.has_malformed_transfer_topics());
+
+        // The same topics are fine for other events
+        let filter = Filter::new()
+            .event("Deposit(address,uint256)")
+            .topic1(B256::repeat_byte(0xff))
+            .topic2(address_to_topic(Address::random()));
+        assert!(!filter.has_malformed_transfer_topics());
+    }
+
     #[test]
     fn can_detect_different_topics() {
         let topic1 = B256::random();
@@ -1215,6 +1455,94 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn union_filter_has_no_false_negatives() {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let filters = (0..3)
+                .map(|_| {
+                    let mut filter = Filter::new();
+                    if rng.gen_bool(0.7) {
+                        filter = filter.address(vec![Address::random(), Address::random()]);
+                    }
+                    if rng.gen_bool(0.7) {
+                        filter = filter.event_signature(B256::random());
+                    }
+                    if rng.gen_bool(0.5) {
+                        filter = filter.topic1(vec![B256::random(), B256::random()]);
+                    }
+                    filter
+                })
+                .collect::<Vec<_>>();
+            let union = UnionFilterParams::new(&filters);
+
+            // a bloom with items of one of the filters and random ones
+            let source = &filters[rng.gen_range(0..filters.len())];
+            let mut bloom = build_bloom(Address::random(), B256::random(), B256::random());
+            for address in source.address.0.iter().take(1) {
+                bloom.accrue(BloomInput::Raw(&address[..]));
+            }
+            for topic in source.topics.iter().filter_map(|topic| topic.0.iter().next()) {
+                bloom.accrue(BloomInput::Raw(&topic[..]));
+            }
+
+            let matches_any = filters.iter().any(|filter| {
+                FilteredParams::matches_address(
+                    bloom,
+                    &FilteredParams::address_filter(&filter.address),
+                ) && FilteredParams::matches_topics(
+                    bloom,
+                    &FilteredParams::topics_filter(&filter.topics),
+                )
+            });
+            assert!(matches_any);
+            assert!(union.matches_bloom(bloom));
+        }
+    }
+
+    #[test]
+    fn union_filter_rejects_unrelated_blocks() {
+        let filters = [
+            Filter::new().address(Address::random()),
+            Filter::new().address(Address::random()),
+            Filter::new().event_signature(B256::random()).topic1(B256::random()),
+        ];
+        let union = UnionFilterParams::new(&filters);
+
+        // an address-only and a topic-only filter don't open each other's positions
+        assert!(!union.matches_bloom(build_bloom(
+            Address::random(),
+            B256::random(),
+            B256::random()
+        )));
+
+        // the addresses of the filters restricting the same positions are combined
+        for filter in &filters[..2] {
+            assert!(union.matches_bloom(build_bloom(
+                filter.address.0.iter().next().copied().unwrap(),
+                B256::random(),
+                B256::random(),
+            )));
+        }
+        assert!(union.matches_bloom(build_bloom(
+            Address::random(),
+            filters[2].topics[0].0.iter().next().copied().unwrap(),
+            filters[2].topics[1].0.iter().next().copied().unwrap(),
+        )));
+
+        // a topic of the topic-only filter alone doesn't pass
+        assert!(!union.matches_bloom(build_bloom(
+            Address::random(),
+            filters[2].topics[0].0.iter().next().copied().unwrap(),
+            B256::random(),
+        )));
+
+        // nothing matches an empty union
+        assert!(!UnionFilterParams::new(std::iter::empty()).matches_bloom(Bloom::default()));
+    }
+
     #[test]
     fn can_convert_to_ethers_filter() {
         let json = json!(
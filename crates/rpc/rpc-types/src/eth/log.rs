@@ -1,4 +1,4 @@
-use alloy_primitives::{Address, Bytes, B256, U256};
+use alloy_primitives::{Address, Bytes, B256, U128, U256};
 use serde::{Deserialize, Serialize};
 
 /// Ethereum Log emitted by a transaction
@@ -26,6 +26,45 @@ pub struct Log {
     pub removed: bool,
 }
 
+/// A [Log] together with context about the transaction that emitted it.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogWithTxContext {
+    /// The log.
+    #[serde(flatten)]
+    pub log: Log,
+    /// Whether the transaction that emitted the log created a contract, i.e. has no `to` address.
+    pub is_contract_creation: bool,
+}
+
+/// A [Log] together with the effective gas price of the transaction that emitted it.
+///
+/// This is a non-standard enrichment for fee analysis, which saves a transaction lookup per log.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogWithGasPrice {
+    /// The log.
+    #[serde(flatten)]
+    pub log: Log,
+    /// The price per unit of gas the transaction paid, including the priority fee.
+    pub effective_gas_price: U128,
+}
+
+/// A [Log] together with its position in the result of the query that returned it.
+///
+/// Unlike the `logIndex`, which restarts in every block, the result index counts up from zero
+/// across the whole result. It is only meaningful within the ordering of that single query and
+/// can't be compared across queries with different filters or ranges.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedLog {
+    /// The log.
+    #[serde(flatten)]
+    pub log: Log,
+    /// Position of the log in the query result.
+    pub result_index: U256,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -26,7 +26,7 @@ pub use engine::{ExecutionPayload, ExecutionPayloadV1, ExecutionPayloadV2, Paylo
 pub use fee::{FeeHistory, TxGasAndReward};
 pub use filter::*;
 pub use index::Index;
-pub use log::Log;
+pub use log::{IndexedLog, Log, LogWithGasPrice, LogWithTxContext};
 pub use raw_log::{logs_bloom, Log as RawLog};
 pub use syncing::*;
 pub use transaction::*;
@@ -14,6 +14,26 @@ pub struct Log {
     pub data: Bytes,
 }
 
+/// A [Log] together with its position in the chain, the compact binary counterpart of the JSON
+/// log returned by `eth_getLogs`.
+#[derive(Clone, Debug, PartialEq, Eq, RlpDecodable, RlpEncodable, Default)]
+pub struct RawBlockLog {
+    /// The log.
+    pub log: Log,
+    /// Hash of the block the transaction that emitted this log was mined in.
+    pub block_hash: B256,
+    /// Number of the block the transaction that emitted this log was mined in.
+    pub block_number: u64,
+    /// Hash of the transaction that emitted this log.
+    pub transaction_hash: B256,
+    /// Index of the transaction in the block.
+    pub transaction_index: u64,
+    /// Index of the log in the block.
+    pub log_index: u64,
+    /// Whether this log was removed by a reorg.
+    pub removed: bool,
+}
+
 /// Calculate receipt logs bloom.
 pub fn logs_bloom<'a, It>(logs: It) -> Bloom
 where
@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use reth_primitives::{Address, Header, B256};
+use reth_provider::test_utils::MockEthProvider;
+use reth_rpc::{eth::cache::EthStateCache, EthFilter, EthFilterConfig};
+use reth_rpc_api::EthFilterApiServer;
+use reth_rpc_types::Filter;
+use reth_tasks::TokioTaskExecutor;
+use reth_transaction_pool::test_utils::testing_pool;
+use std::time::Duration;
+
+/// Benchmarks polling a log filter for changes when a single new block arrived since the last
+/// poll, as done by clients that poll every block.
+pub fn poll_log_filter(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+
+    let provider = MockEthProvider::default();
+    provider.add_header(B256::random(), Header::default());
+    let eth_filter = EthFilter::with_config(
+        provider.clone(),
+        testing_pool(),
+        EthStateCache::spawn(provider.clone(), Default::default()),
+        EthFilterConfig::new(10_000, Duration::from_secs(60)),
+        Box::<TokioTaskExecutor>::default(),
+    );
+
+    let mut number = 0;
+    for num_addresses in [1, 100] {
+        let addresses = (0..num_addresses).map(|_| Address::random()).collect::<Vec<_>>();
+        let filter = Filter::new()
+            .address(addresses)
+            .events(["Transfer(address,address,uint256)", "Approval(address,address,uint256)"]);
+        let id = runtime.block_on(eth_filter.new_filter(filter)).unwrap();
+
+        c.bench_function(&format!("poll log filter | addresses: {num_addresses}"), |b| {
+            b.to_async(&runtime).iter_batched(
+                || {
+                    // replace the head with the next block, its logs bloom doesn't match
+                    number += 1;
+                    provider.headers.lock().clear();
+                    provider.add_header(B256::random(), Header { number, ..Default::default() });
+                },
+                |_| async { eth_filter.filter_changes(id.clone()).await.unwrap() },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+criterion_group!(filter_poll, poll_log_filter);
+criterion_main!(filter_poll);
@@ -8,31 +8,62 @@ use crate::{
     EthSubscriptionIdProvider,
 };
 use alloy_primitives::B256;
+use alloy_rlp::Encodable;
 use async_trait::async_trait;
 use jsonrpsee::{core::RpcResult, server::IdProvider};
-use reth_interfaces::RethError;
-use reth_primitives::{BlockHashOrNumber, Receipt, SealedBlock, TxHash};
-use reth_provider::{BlockIdReader, BlockReader, EvmEnvProvider};
+use reth_interfaces::{db::DatabaseError, provider::ProviderError, RethError};
+use reth_primitives::{
+    Address, BlockHashOrNumber, BlockNumHash, BlockNumberOrTag, Bloom, Bytes, ChainInfo, PruneMode,
+    Receipt, SealedBlock, TxHash, U256,
+};
+use reth_provider::{
+    BlockHashReader, BlockIdReader, BlockNumReader, BlockReader, BlockReaderIdExt, BlockSource,
+    EvmEnvProvider, HeaderProvider, ReceiptProviderIdExt, TransactionsProvider,
+};
 use reth_rpc_api::EthFilterApiServer;
-use reth_rpc_types::{Filter, FilterBlockOption, FilterChanges, FilterId, FilteredParams, Log};
+use reth_rpc_types::{
+    raw_log::RawBlockLog, BloomFilter, Filter, FilterBlockOption, FilterChanges, FilterId,
+    FilteredParams, IndexedLog, Log, LogFilterOptions, LogWithGasPrice, LogWithTxContext,
+    UnionFilterParams,
+};
 use reth_tasks::TaskSpawner;
-use reth_transaction_pool::TransactionPool;
+use reth_transaction_pool::{PoolTransaction, TransactionPool, ValidPoolTransaction};
 use std::{
     collections::HashMap,
+    future::Future,
     iter::StepBy,
     ops::RangeInclusive,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 use tokio::{
-    sync::{mpsc::Receiver, Mutex},
+    sync::{
+        mpsc::{
+            self,
+            error::{TryRecvError, TrySendError},
+            Receiver, Sender,
+        },
+        Mutex, Notify, OwnedSemaphorePermit, Semaphore,
+    },
     time::MissedTickBehavior,
 };
-use tracing::trace;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, trace};
 
 /// The maximum number of headers we read at once when handling a range filter.
 const MAX_HEADERS_RANGE: u64 = 1_000; // with ~530bytes per header this is ~500kb
 
+/// The number of pending transactions buffered for a pending transaction filter between polls,
+/// the same as the buffer of a listener of the pool.
+const PENDING_TX_BUFFER_SIZE: usize = 2048;
+
+/// The maximum number of orphaned blocks walked back to find the common ancestor of a reorg since
+/// the last poll of a filter, see [detect_reorg].
+const MAX_REORG_DEPTH: usize = 256;
+
 /// `Eth` filter RPC implementation.
 pub struct EthFilter<Provider, Pool> {
     /// All nested fields bundled together.
@@ -60,16 +91,41 @@ where
         task_spawner: Box<dyn TaskSpawner>,
         stale_filter_ttl: Duration,
     ) -> Self {
+        Self::with_config(
+            provider,
+            pool,
+            eth_cache,
+            EthFilterConfig::new(max_logs_per_response, stale_filter_ttl),
+            task_spawner,
+        )
+    }
+
+    /// Creates a new, shareable instance with the given [EthFilterConfig].
+    ///
+    /// This also spawns a task that periodically clears stale filters.
+    pub fn with_config(
+        provider: Provider,
+        pool: Pool,
+        eth_cache: EthStateCache,
+        config: EthFilterConfig,
+        task_spawner: Box<dyn TaskSpawner>,
+    ) -> Self {
+        // a limit of zero would make every scan wait forever, at least one scan always runs
+        let log_scan_permits =
+            config.max_concurrent_log_scans.map(|n| Arc::new(Semaphore::new(n.max(1))));
         let inner = EthFilterInner {
             provider,
             active_filters: Default::default(),
             pool,
             id_provider: Arc::new(EthSubscriptionIdProvider::default()),
-            max_logs_per_response,
             eth_cache,
             max_headers_range: MAX_HEADERS_RANGE,
             task_spawner,
-            stale_filter_ttl,
+            log_scan_permits,
+            config,
+            last_stale_filter_clean: Default::default(),
+            stale_cleanup_paused: AtomicBool::new(false),
+            pending_txs_fanout: Default::default(),
         };
 
         let eth_filter = Self { inner: Arc::new(inner) };
@@ -90,66 +146,329 @@ where
         &self.inner.active_filters
     }
 
-    /// Endless future that [Self::clear_stale_filters] every `stale_filter_ttl` interval.
+    /// Pauses the eviction of stale filters until [Self::resume_stale_cleanup] is called.
+    ///
+    /// The task clearing stale filters keeps running, but skips its sweeps, so filters that are
+    /// not polled within the `stale_filter_ttl` are kept. This is useful during maintenance or an
+    /// incident, when clients are expected to poll their filters again shortly.
+    pub fn pause_stale_cleanup(&self) {
+        self.inner.stale_cleanup_paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Resumes the eviction of stale filters paused by [Self::pause_stale_cleanup].
+    ///
+    /// Filters that became stale while paused are evicted by the next sweep.
+    pub fn resume_stale_cleanup(&self) {
+        self.inner.stale_cleanup_paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the eviction of stale filters is paused.
+    pub fn is_stale_cleanup_paused(&self) -> bool {
+        self.inner.stale_cleanup_paused.load(Ordering::Relaxed)
+    }
+
+    /// Endless future that [Self::clear_stale_filters] every `stale_filter_ttl` interval, unless
+    /// the cleanup is paused.
     async fn watch_and_clear_stale_filters(&self) {
-        let mut interval = tokio::time::interval(self.inner.stale_filter_ttl);
+        let mut interval = tokio::time::interval(self.inner.config.stale_filter_ttl);
         interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
         loop {
             interval.tick().await;
-            self.clear_stale_filters(Instant::now()).await;
+            *self.inner.last_stale_filter_clean.lock().unwrap() = Some(Instant::now());
+            if self.is_stale_cleanup_paused() {
+                trace!(target: "rpc::eth", "stale filter cleanup is paused");
+                continue
+            }
+            let stats = self.clear_stale_filters(Instant::now()).await;
+            debug!(
+                target: "rpc::eth",
+                examined = stats.examined,
+                evicted = stats.evicted,
+                oldest_surviving_age = ?stats.oldest_surviving_age,
+                "cleared stale filters"
+            );
         }
     }
 
     /// Clears all filters that have not been polled for longer than the configured
     /// `stale_filter_ttl` at the given instant.
-    pub async fn clear_stale_filters(&self, now: Instant) {
+    ///
+    /// The eviction callbacks of the cleared filters are invoked after the filters are released.
+    ///
+    /// Returns stats about the filters that were examined.
+    pub async fn clear_stale_filters(&self, now: Instant) -> StaleFilterStats {
         trace!(target: "rpc::eth", "clear stale filters");
+        let mut stats = StaleFilterStats::default();
+        let mut eviction_callbacks = Vec::new();
         self.active_filters().inner.lock().await.retain(|id, filter| {
-            let is_valid = (now - filter.last_poll_timestamp) < self.inner.stale_filter_ttl;
+            let age = now.saturating_duration_since(filter.last_poll_timestamp);
+            let never_matched = self
+                .inner
+                .config
+                .max_polls_without_matches
+                .map_or(false, |max_polls| filter.never_matched(max_polls));
+            let is_valid = age < self.inner.config.stale_filter_ttl && !never_matched;
 
-            if !is_valid {
-                trace!(target: "rpc::eth", "evict filter with id: {:?}", id);
+            stats.examined += 1;
+            if is_valid {
+                stats.oldest_surviving_age = stats.oldest_surviving_age.max(Some(age));
+            } else {
+                stats.evicted += 1;
+                trace!(target: "rpc::eth", never_matched, "evict filter with id: {:?}", id);
+                if let Some(on_evict) = filter.on_evict.take() {
+                    eviction_callbacks.push((id.clone(), on_evict));
+                }
             }
 
             is_valid
-        })
+        });
+
+        // the lock is released, so the callbacks are free to use the filters again
+        for (id, on_evict) in eviction_callbacks {
+            (on_evict.0)(id);
+        }
+        stats
+    }
+
+    /// Returns a snapshot of the health of the filter subsystem, for liveness and readiness
+    /// probes.
+    ///
+    /// The task clearing stale filters is considered alive if it ran within the last two
+    /// `stale_filter_ttl` intervals.
+    pub async fn health(&self) -> FilterHealth {
+        let now = Instant::now();
+        let last_clean = *self.inner.last_stale_filter_clean.lock().unwrap();
+        let stale_filter_cleaner_alive = last_clean.map_or(false, |last_clean| {
+            now.saturating_duration_since(last_clean) < self.inner.config.stale_filter_ttl * 2
+        });
+
+        let (active_filters, oldest_filter_age, pending_receivers) = {
+            let filters = self.active_filters().inner.lock().await;
+            let oldest_filter_age = filters
+                .values()
+                .map(|filter| now.saturating_duration_since(filter.installed_at))
+                .max();
+            let pending_receivers = filters
+                .values()
+                .filter_map(|filter| match &filter.kind {
+                    FilterKind::PendingTransaction(receiver) => Some(receiver.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            (filters.len(), oldest_filter_age, pending_receivers)
+        };
+
+        // the receivers are checked without the lock, so polls aren't blocked by a busy channel
+        let mut pending_transactions_connected = true;
+        for receiver in pending_receivers {
+            pending_transactions_connected &= receiver.is_connected().await;
+        }
+
+        FilterHealth {
+            active_filters,
+            stale_filter_cleaner_alive,
+            oldest_filter_age,
+            pending_transactions_connected,
+        }
+    }
+}
+
+/// A snapshot of the health of the filter subsystem, see [EthFilter::health].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilterHealth {
+    /// Number of installed filters.
+    pub active_filters: usize,
+    /// Whether the task clearing stale filters is still running.
+    pub stale_filter_cleaner_alive: bool,
+    /// Time since the oldest filter was installed, if any are installed.
+    pub oldest_filter_age: Option<Duration>,
+    /// Whether the channels of all pending transaction filters are still connected to the pool.
+    pub pending_transactions_connected: bool,
+}
+
+/// Stats of a single [EthFilter::clear_stale_filters] pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StaleFilterStats {
+    /// Number of filters that were examined.
+    pub examined: usize,
+    /// Number of filters that were evicted.
+    pub evicted: usize,
+    /// Time since the least recently polled remaining filter was last polled, if any remain.
+    pub oldest_surviving_age: Option<Duration>,
+}
+
+/// The block range resolved by a poll of a log filter, to reconstruct whether polls skipped or
+/// rescanned blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilterPollRange {
+    /// The first block that was not covered by the previous polls.
+    pub start_block: u64,
+    /// The best block at the time of the poll.
+    pub best_number: u64,
+    /// The first block of the scanned range.
+    pub from_block: u64,
+    /// The last block of the scanned range.
+    pub to_block: u64,
+}
+
+/// The result of a best-effort log query, see [EthFilter::logs_best_effort].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BestEffortLogs {
+    /// The matching logs of the blocks that could be scanned.
+    pub logs: Vec<Log>,
+    /// The numbers of the blocks that could not be scanned, in ascending order.
+    pub failed_blocks: Vec<u64>,
+}
+
+/// The criteria of a pending transaction filter, see
+/// [EthFilter::new_pending_transaction_filter_with_criteria].
+///
+/// A transaction matches if it satisfies all of the set criteria.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PendingTransactionCriteria {
+    /// The sender the transactions must be from, if any.
+    pub sender: Option<Address>,
+    /// The minimum max fee per gas of the transactions, the gas price of legacy transactions, if
+    /// any.
+    pub min_gas_price: Option<u128>,
+}
+
+impl PendingTransactionCriteria {
+    /// Only match the transactions from the given sender.
+    pub const fn sender(mut self, sender: Address) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    /// Only match the transactions with a max fee per gas of at least the given gas price.
+    pub const fn min_gas_price(mut self, min_gas_price: u128) -> Self {
+        self.min_gas_price = Some(min_gas_price);
+        self
+    }
+
+    /// Returns `true` if the given pool transaction matches the criteria.
+    pub fn matches<T: PoolTransaction>(&self, transaction: &ValidPoolTransaction<T>) -> bool {
+        self.sender.map_or(true, |sender| transaction.sender() == sender) &&
+            self.min_gas_price
+                .map_or(true, |min_gas_price| transaction.max_fee_per_gas() >= min_gas_price)
+    }
+}
+
+/// A block range of a log filter expressed as offsets below the best block, resolved against the
+/// best block at every poll.
+///
+/// The window `head - from_offset ..= head - to_offset` slides forward with the chain: each poll
+/// only reports the blocks of the current window that previous polls didn't cover yet, and blocks
+/// that fell out of the window before they were polled are never reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadRelativeRange {
+    /// How many blocks below the best block the window starts.
+    pub from_offset: u64,
+    /// How many blocks below the best block the window ends.
+    pub to_offset: u64,
+}
+
+impl HeadRelativeRange {
+    /// Creates a window of the blocks `head - from_offset ..= head - to_offset`.
+    pub const fn new(from_offset: u64, to_offset: u64) -> Self {
+        Self { from_offset, to_offset }
+    }
+
+    /// Returns the first block of the window for the given best block.
+    pub const fn from_block(&self, best_number: u64) -> u64 {
+        best_number.saturating_sub(self.from_offset)
+    }
+
+    /// Returns the last block of the window for the given best block.
+    pub const fn to_block(&self, best_number: u64) -> u64 {
+        best_number.saturating_sub(self.to_offset)
     }
 }
 
 impl<Provider, Pool> EthFilter<Provider, Pool>
 where
-    Provider: BlockReader + BlockIdReader + EvmEnvProvider + 'static,
+    Provider: BlockReaderIdExt + EvmEnvProvider + 'static,
     Pool: TransactionPool + 'static,
 {
+    /// Returns the block range scanned by the last poll of the log filter with the given id, if it
+    /// was polled.
+    pub async fn last_poll_range(&self, id: &FilterId) -> Option<FilterPollRange> {
+        self.inner.active_filters.inner.lock().await.get(id)?.last_poll_range
+    }
+
     /// Returns all the filter changes for the given id, if any
     pub async fn filter_changes(&self, id: FilterId) -> Result<FilterChanges, FilterError> {
         let info = self.inner.provider.chain_info()?;
         let best_number = info.best_number;
 
+        // if the chain reorganized since the last poll, the changes are fetched again from the
+        // first block after the common ancestor, so that the blocks replacing the orphaned ones are
+        // reported. The orphaned blocks are walked without holding the lock of the filters.
+        let (last_tip, is_pending_tx) = {
+            let filters = self.inner.active_filters.inner.lock().await;
+            let filter = filters.get(&id).ok_or(FilterError::FilterNotFound(id.clone()))?;
+            (filter.last_tip, matches!(filter.kind, FilterKind::PendingTransaction(_)))
+        };
+        let reorg = if is_pending_tx {
+            None
+        } else {
+            detect_reorg(&self.inner.provider, last_tip, MAX_REORG_DEPTH)?
+        };
+
         // start_block is the block from which we should start fetching changes, the next block from
         // the last time changes were polled, in other words the best block at last poll + 1
-        let (start_block, kind) = {
+        let (start_block, kind, window) = {
             let mut filters = self.inner.active_filters.inner.lock().await;
-            let filter = filters.get_mut(&id).ok_or(FilterError::FilterNotFound(id))?;
+            let filter = filters.get_mut(&id).ok_or(FilterError::FilterNotFound(id.clone()))?;
+            filter.polls += 1;
 
-            if filter.block > best_number {
+            // a concurrent poll that moved the tip already applied the reorg
+            if let Some(reorg) = reorg.filter(|_| filter.last_tip == last_tip) {
+                debug!(target: "rpc::eth::filter", ?id, orphaned=reorg.orphaned.len(), ancestor=?reorg.common_ancestor, "chain reorganized since last poll");
+                if let Some(ancestor) = reorg.common_ancestor {
+                    filter.block = filter.block.min(ancestor.number + 1);
+                }
+            }
+
+            // a head-relative window only covers the blocks up to its end, the blocks after it are
+            // reported by later polls once the window slid over them
+            let last_block =
+                filter.head_relative.map_or(best_number, |window| window.to_block(best_number));
+
+            // pending transactions arrive independently of new blocks
+            if filter.block > last_block &&
+                !matches!(filter.kind, FilterKind::PendingTransaction(_))
+            {
                 // no new blocks since the last poll
                 return Ok(FilterChanges::Empty)
             }
 
             // update filter
-            // we fetch all changes from [filter.block..best_block], so we advance the filter's
-            // block to `best_block +1`, the next from which we should start fetching changes again
-            let mut block = best_number + 1;
+            // we fetch all changes from [filter.block..last_block], so we advance the filter's
+            // block to `last_block +1`, the next from which we should start fetching changes again
+            let mut block = last_block + 1;
             std::mem::swap(&mut filter.block, &mut block);
+            filter.last_tip = BlockNumHash::new(best_number, info.best_hash);
             filter.last_poll_timestamp = Instant::now();
 
-            (block, filter.kind.clone())
+            (block, filter.kind.clone(), filter.head_relative)
         };
 
         match kind {
             FilterKind::PendingTransaction(receiver) => {
-                let pending_txs = receiver.drain().await;
+                let mut pending_txs =
+                    receiver.drain(self.inner.config.max_pending_transactions_per_poll).await?;
+                if let Some(criteria) = receiver.criteria {
+                    // transactions that already left the pool can't be resolved and are skipped
+                    pending_txs = self
+                        .inner
+                        .pool
+                        .get_all(pending_txs)
+                        .into_iter()
+                        .filter(|transaction| criteria.matches(transaction))
+                        .map(|transaction| *transaction.hash())
+                        .collect();
+                }
                 Ok(FilterChanges::Hashes(pending_txs))
             }
             FilterKind::Block => {
@@ -164,19 +483,27 @@ where
                 Ok(FilterChanges::Hashes(block_hashes))
             }
             FilterKind::Log(filter) => {
-                let (from_block_number, to_block_number) = match filter.block_option {
-                    FilterBlockOption::Range { from_block, to_block } => {
-                        let from = from_block
-                            .map(|num| self.inner.provider.convert_block_number(num))
-                            .transpose()?
-                            .flatten();
-                        let to = to_block
-                            .map(|num| self.inner.provider.convert_block_number(num))
-                            .transpose()?
-                            .flatten();
-                        logs_utils::get_filter_block_range(from, to, start_block, info)
+                let block_option = filter.filter().block_option;
+                let (from_block_number, to_block_number) = match (window, block_option) {
+                    (Some(window), _) => {
+                        // the window is resolved against the current best block, without the
+                        // blocks that previous polls already reported
+                        let earliest = self.inner.config.earliest_available_block(best_number);
+                        let from = window.from_block(best_number).max(start_block).max(earliest);
+                        (from, window.to_block(best_number))
                     }
-                    FilterBlockOption::AtBlockHash(_) => {
+                    (None, FilterBlockOption::Range { from_block, to_block }) => {
+                        let from = self.inner.resolve_filter_block(from_block)?;
+                        let to = self.inner.resolve_filter_block(to_block)?;
+                        let (from, to) =
+                            logs_utils::get_filter_block_range(from, to, start_block, info);
+                        // the blocks before `start_block` were covered by the previous polls, so
+                        // only the blocks that arrived since are scanned, even if the filter's
+                        // `fromBlock` lies further in the past
+                        let earliest = self.inner.config.earliest_available_block(best_number);
+                        (from.max(start_block).max(earliest), to)
+                    }
+                    (None, FilterBlockOption::AtBlockHash(_)) => {
                         // blockHash is equivalent to fromBlock = toBlock = the block number with
                         // hash blockHash
                         // get_logs_in_block_range is inclusive
@@ -184,64 +511,375 @@ where
                     }
                 };
 
+                let poll_range = FilterPollRange {
+                    start_block,
+                    best_number,
+                    from_block: from_block_number,
+                    to_block: to_block_number,
+                };
+                trace!(target: "rpc::eth::filter", ?id, ?poll_range, "polling log filter");
+                if let Some(filter) = self.inner.active_filters.inner.lock().await.get_mut(&id) {
+                    filter.last_poll_range = Some(poll_range);
+                }
+
                 let logs = self
                     .inner
-                    .get_logs_in_block_range(&filter, from_block_number, to_block_number)
+                    .get_logs_in_block_range(
+                        &filter,
+                        from_block_number,
+                        to_block_number,
+                        append_logs,
+                        None,
+                        None,
+                    )
                     .await?;
+                if !logs.is_empty() {
+                    let mut filters = self.inner.active_filters.inner.lock().await;
+                    if let Some(filter) = filters.get_mut(&id) {
+                        filter.matched_logs += logs.len() as u64;
+                    }
+                }
                 Ok(FilterChanges::Logs(logs))
             }
         }
     }
 
+    /// Moves the start of the next poll of a block or log filter forward to the given block,
+    /// skipping the blocks between the last poll and `block`.
+    ///
+    /// This is a recovery path for clients that fell far behind on polling, e.g. after a long
+    /// disconnect, and explicitly accept missing the changes of the skipped blocks instead of
+    /// triggering a scan of the whole gap. The block must lie between the start of the next poll
+    /// and the block after the best block, which skips all blocks up to the current tip. Like a
+    /// poll, this keeps the filter from being evicted as stale.
+    ///
+    /// Returns an error if the filter doesn't exist, is a pending transaction filter, or the
+    /// block is out of range.
+    ///
+    /// This is not served over RPC: the `eth` filter namespace is kept to the standard methods,
+    /// like the other extensions of this type, and the `reth` namespace doesn't hold the installed
+    /// filters.
+    pub async fn reanchor_filter(&self, id: FilterId, block: u64) -> Result<(), FilterError> {
+        let best_number = self.inner.provider.best_block_number()?;
+        let mut filters = self.inner.active_filters.inner.lock().await;
+        let filter = filters.get_mut(&id).ok_or(FilterError::FilterNotFound(id.clone()))?;
+        if matches!(filter.kind, FilterKind::PendingTransaction(_)) {
+            return Err(FilterError::InvalidFilter(
+                "pending transaction filters can't be re-anchored",
+            ))
+        }
+        if block < filter.block || block > best_number + 1 {
+            return Err(FilterError::InvalidFilter(
+                "re-anchor block must be between the next polled block and the block after the best block",
+            ))
+        }
+
+        debug!(target: "rpc::eth::filter", ?id, from=filter.block, to=block, "re-anchoring filter");
+        filter.block = block;
+        filter.last_poll_timestamp = Instant::now();
+        Ok(())
+    }
+
     /// Returns an array of all logs matching filter with given id.
     ///
     /// Returns an error if no matching log filter exists.
     ///
     /// Handler for `eth_getFilterLogs`
     pub async fn filter_logs(&self, id: FilterId) -> Result<FilterChanges, FilterError> {
-        let filter = {
-            let filters = self.inner.active_filters.inner.lock().await;
-            if let FilterKind::Log(ref filter) =
-                filters.get(&id).ok_or_else(|| FilterError::FilterNotFound(id.clone()))?.kind
-            {
-                *filter.clone()
-            } else {
-                // Not a log filter
-                return Err(FilterError::FilterNotFound(id))
-            }
-        };
+        let filter = self.inner.log_filter(id).await?;
+        let logs = self.inner.logs_for_filter(&filter, append_logs, None).await?;
+        Ok(FilterChanges::Logs(logs))
+    }
 
-        let logs = self.inner.logs_for_filter(filter).await?;
+    /// Same as [Self::filter_logs], but stops scanning and returns [FilterError::Cancelled] as
+    /// soon as the given token is cancelled, for example because the client disconnected.
+    pub async fn filter_logs_with_cancellation(
+        &self,
+        id: FilterId,
+        cancel: &CancellationToken,
+    ) -> Result<FilterChanges, FilterError> {
+        let filter = self.inner.log_filter(id).await?;
+        let logs = self.inner.logs_for_filter(&filter, append_logs, Some(cancel)).await?;
         Ok(FilterChanges::Logs(logs))
     }
+
+    /// Returns logs matching given filter object, tagged with whether the transaction that emitted
+    /// them created a contract.
+    ///
+    /// Same as `eth_getLogs`, but with the additional transaction context.
+    pub async fn logs_with_tx_context(
+        &self,
+        filter: Filter,
+    ) -> Result<Vec<LogWithTxContext>, FilterError> {
+        self.inner.logs_for_filter(&LogFilter::new(filter), append_logs_with_tx_context, None).await
+    }
+
+    /// Returns logs matching given filter object, each with the effective gas price of the
+    /// transaction that emitted it.
+    ///
+    /// Same as `eth_getLogs`, but with a non-standard enrichment for fee analysis: the gas price is
+    /// derived from the transaction and the base fee of its block, so clients don't need to look up
+    /// every transaction.
+    pub async fn logs_with_effective_gas_price(
+        &self,
+        filter: Filter,
+    ) -> Result<Vec<LogWithGasPrice>, FilterError> {
+        self.inner.logs_for_filter(&LogFilter::new(filter), append_logs_with_gas_price, None).await
+    }
+
+    /// Returns logs matching given filter object and the given reth specific refinements.
+    ///
+    /// Same as `eth_getLogs`, but the logs are additionally matched against the
+    /// [LogFilterOptions], which are checked after the logs of a block are fetched.
+    pub async fn logs_with_options(
+        &self,
+        filter: Filter,
+        options: LogFilterOptions,
+    ) -> Result<Vec<Log>, FilterError> {
+        let filter = LogFilter::new(filter).with_options(options);
+        self.inner.logs_for_filter(&filter, append_logs, None).await
+    }
+
+    /// Returns logs matching given filter object, each with its position in the result.
+    ///
+    /// Same as `eth_getLogs`, but the [IndexedLog::result_index] gives clients a single cursor
+    /// across all blocks of the result, for example to resume streaming or to deduplicate. The
+    /// index is only meaningful within the ordering of this query.
+    pub async fn indexed_logs(&self, filter: Filter) -> Result<Vec<IndexedLog>, FilterError> {
+        self.inner.logs_for_filter(&LogFilter::new(filter), append_indexed_logs, None).await
+    }
+
+    /// Returns logs matching given filter object, starting at the given position in the result.
+    ///
+    /// Same as `eth_getLogs`, but the first `start_index` matching logs in canonical order are
+    /// skipped, so a client can resume a query at the [IndexedLog::result_index] of the first log
+    /// it hasn't processed yet. The index is only meaningful for the same filter and block range,
+    /// and only if the range is not affected by a reorg in the meantime.
+    ///
+    /// The skipped logs still have to be scanned, but they don't count towards the
+    /// `max_logs_per_response` and `max_response_bytes` limits, so a query rejected for exceeding
+    /// them can be retried from a later index with a narrower block range instead.
+    pub async fn logs_from_index(
+        &self,
+        filter: Filter,
+        start_index: usize,
+    ) -> Result<Vec<Log>, FilterError> {
+        let filter = LogFilter::new(filter).with_start_index(start_index);
+        self.inner.logs_for_filter(&filter, append_logs, None).await
+    }
+
+    /// Returns logs matching given filter object, skipping the blocks that can't be read.
+    ///
+    /// Same as `eth_getLogs`, but a block whose headers or receipts are unavailable, even after
+    /// retrying transient errors, doesn't fail the whole query. The block is reported in
+    /// [BestEffortLogs::failed_blocks] instead, so the client can retry just those blocks. Errors
+    /// of a `blockHash` filter are not skipped.
+    pub async fn logs_best_effort(&self, filter: Filter) -> Result<BestEffortLogs, FilterError> {
+        self.inner.logs_for_filter_best_effort(&LogFilter::new(filter)).await
+    }
+
+    /// Returns the numbers of the blocks in the given _inclusive_ range whose logs bloom matches
+    /// the filter, ignoring the filter's own block range.
+    ///
+    /// Only the headers are read, so this is much cheaper than fetching the logs, for clients that
+    /// keep their own log store and only need to know which blocks to fetch. A bloom has no false
+    /// negatives, so every block with a matching log is returned, but it may have false positives,
+    /// so a returned block may have no matching log at all.
+    pub async fn candidate_blocks(
+        &self,
+        filter: &Filter,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<u64>, FilterError> {
+        let filter = LogFilter::new(filter.clone());
+        self.inner.get_candidate_blocks_in_range(&filter, from_block, to_block).await
+    }
+
+    /// Returns the logs matching the given filter object as an RLP encoded list of
+    /// [RawBlockLog]s.
+    ///
+    /// This is a non-standard variant of `eth_getLogs` for clients behind a binary transport that
+    /// parse the logs themselves. The logs are encoded straight from the receipts, without building
+    /// the JSON [Log]s. The response limits apply to the encoded size of the logs.
+    pub async fn raw_logs(&self, filter: Filter) -> Result<Bytes, FilterError> {
+        let logs =
+            self.inner.logs_for_filter(&LogFilter::new(filter), append_raw_logs, None).await?;
+        let mut encoded = Vec::with_capacity(logs.length());
+        logs.encode(&mut encoded);
+        Ok(encoded.into())
+    }
+
+    /// Returns the first log matching the given filter object of every block that has any.
+    ///
+    /// This is a non-standard, lossy variant of `eth_getLogs` for monitors that only need to know
+    /// whether a block contains a matching log. The remaining logs of a block are not returned.
+    pub async fn first_log_per_block(&self, filter: Filter) -> Result<Vec<Log>, FilterError> {
+        self.inner.logs_for_filter(&LogFilter::new(filter), append_first_log, None).await
+    }
+
+    /// Returns the logs matching the given filter object that were emitted after the transaction
+    /// with the given hash.
+    ///
+    /// This is a non-standard variant of `eth_getLogs` for indexers that track their progress by
+    /// the last transaction they processed. The range starts with the logs of the transactions
+    /// that follow the anchor transaction in its block and ends at the `toBlock` of the filter.
+    /// The `fromBlock` of the filter is ignored and block hash filters are rejected.
+    ///
+    /// Returns [EthApiError::TransactionNotFound] if the transaction is not part of the chain.
+    pub async fn logs_since_tx(
+        &self,
+        filter: Filter,
+        tx_hash: TxHash,
+    ) -> Result<Vec<Log>, FilterError> {
+        self.inner.logs_since_tx(&LogFilter::new(filter), tx_hash).await
+    }
+
+    /// Same as `eth_newFilter`, but invokes `on_evict` with the id of the filter if it is evicted
+    /// for not being polled within the `stale_filter_ttl`.
+    ///
+    /// This lets transports with server-initiated messages tell the client to reinstall its
+    /// filter. The callback is not invoked if the filter is uninstalled by the client.
+    pub async fn new_filter_with_eviction_callback(
+        &self,
+        filter: Filter,
+        on_evict: impl FnOnce(FilterId) + Send + 'static,
+    ) -> RpcResult<FilterId> {
+        let kind = FilterKind::Log(Arc::new(LogFilter::new(filter)));
+        self.inner.install_filter(kind, Some(EvictionCallback(Box::new(on_evict)))).await
+    }
+
+    /// Installs a log filter and returns its id together with the logs matching it up to the best
+    /// block at installation.
+    ///
+    /// This is `eth_newFilter` followed by `eth_getFilterLogs`, without the race between the two
+    /// calls: the backfilled logs and the first `eth_getFilterChanges` poll are split at the same
+    /// best block, so no block is missed or reported twice, regardless of
+    /// [EthFilterConfig::report_install_block]. The filter is uninstalled again if the backfill
+    /// fails.
+    pub async fn new_filter_with_backfill(
+        &self,
+        filter: Filter,
+    ) -> RpcResult<(FilterId, Vec<Log>)> {
+        self.inner.install_filter_with_backfill(filter).await
+    }
+
+    /// Installs a log filter that matches the logs in a window of blocks relative to the best
+    /// block, e.g. the last 100 blocks, instead of the filter's own block range.
+    ///
+    /// The window is resolved against the best block on every `eth_getFilterChanges` poll, see
+    /// [HeadRelativeRange] for how it interacts with the incremental polling: the first poll
+    /// reports the whole window, later polls only the blocks the window slid over since.
+    ///
+    /// Returns an error if the window ends before it starts.
+    pub async fn new_head_relative_filter(
+        &self,
+        filter: Filter,
+        window: HeadRelativeRange,
+    ) -> RpcResult<FilterId> {
+        if window.to_offset > window.from_offset {
+            return Err(FilterError::InvalidFilter("window ends before it starts").into())
+        }
+        let kind = FilterKind::Log(Arc::new(LogFilter::new(filter)));
+        self.inner.install_filter_with_window(kind, None, Some(window)).await
+    }
+
+    /// Same as `eth_newPendingTransactionFilter`, but only reports the pending transactions that
+    /// match the given criteria, e.g. the transactions of a single sender.
+    ///
+    /// Every new pending transaction is looked up in the pool when the filter is polled, which
+    /// costs a read lock of the pool per poll and a lookup per transaction, so polling this filter
+    /// is more expensive than polling an unfiltered one. Transactions that left the pool before the
+    /// poll are not reported. [EthFilterConfig::max_pending_transactions_per_poll] limits the
+    /// transactions that are checked per poll, not the matching ones.
+    pub async fn new_pending_transaction_filter_with_criteria(
+        &self,
+        criteria: PendingTransactionCriteria,
+    ) -> RpcResult<FilterId> {
+        let receiver = self.inner.pending_transactions_receiver().with_criteria(criteria);
+        self.inner.install_filter(FilterKind::PendingTransaction(receiver), None).await
+    }
+
+    /// Same as `eth_newBlockFilter`, but invokes `on_evict` with the id of the filter if it is
+    /// evicted, see [Self::new_filter_with_eviction_callback].
+    pub async fn new_block_filter_with_eviction_callback(
+        &self,
+        on_evict: impl FnOnce(FilterId) + Send + 'static,
+    ) -> RpcResult<FilterId> {
+        let on_evict = EvictionCallback(Box::new(on_evict));
+        self.inner.install_filter(FilterKind::Block, Some(on_evict)).await
+    }
+
+    /// Returns the most recent logs matching the given filter object, newest first.
+    ///
+    /// Unlike `eth_getLogs`, a range with more than [EthFilterConfig::max_logs_per_response]
+    /// matches is not an error. The range is walked from its end and the scan stops once the limit
+    /// is reached, so "last N events" queries only touch the most recent blocks.
+    pub async fn logs_newest_first(&self, filter: Filter) -> Result<Vec<Log>, FilterError> {
+        self.inner.logs_for_filter_newest_first(&LogFilter::new(filter)).await
+    }
+
+    /// Returns the logs matching each of the given filter objects in the given _inclusive_ block
+    /// range, walking the range only once.
+    ///
+    /// The block options of the filters are ignored. The result contains the index of every filter
+    /// that matched any logs, together with its logs, in the order of the given filters. The
+    /// response limits apply to each filter individually.
+    pub async fn combined_logs(
+        &self,
+        filters: Vec<Filter>,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<(usize, Vec<Log>)>, FilterError> {
+        let filters = filters.into_iter().map(LogFilter::new).collect::<Vec<_>>();
+        let cancels = vec![None; filters.len()];
+        self.inner.get_combined_logs_in_block_range(&filters, &cancels, from_block, to_block).await
+    }
+
+    /// Same as [Self::combined_logs], but every filter comes with a token that drops the filter
+    /// from the scan once it is cancelled, for example because its client disconnected.
+    ///
+    /// The scan continues for the remaining filters. The logs collected for a dropped filter are
+    /// discarded and it can no longer fail the scan by exceeding the response limits. The result
+    /// only contains the filters that were not cancelled, with their index in the given filters.
+    /// The scan stops early once all filters are cancelled.
+    pub async fn combined_logs_with_cancellation(
+        &self,
+        filters: Vec<(Filter, CancellationToken)>,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<(usize, Vec<Log>)>, FilterError> {
+        let (filters, cancels): (Vec<_>, Vec<_>) = filters
+            .into_iter()
+            .map(|(filter, cancel)| (LogFilter::new(filter), Some(cancel)))
+            .unzip();
+        self.inner.get_combined_logs_in_block_range(&filters, &cancels, from_block, to_block).await
+    }
 }
 
 #[async_trait]
 impl<Provider, Pool> EthFilterApiServer for EthFilter<Provider, Pool>
 where
-    Provider: BlockReader + BlockIdReader + EvmEnvProvider + 'static,
+    Provider: BlockReaderIdExt + EvmEnvProvider + 'static,
     Pool: TransactionPool + 'static,
 {
     /// Handler for `eth_newFilter`
     async fn new_filter(&self, filter: Filter) -> RpcResult<FilterId> {
         trace!(target: "rpc::eth", "Serving eth_newFilter");
-        self.inner.install_filter(FilterKind::Log(Box::new(filter))).await
+        self.inner.install_filter(FilterKind::Log(Arc::new(LogFilter::new(filter))), None).await
     }
 
     /// Handler for `eth_newBlockFilter`
     async fn new_block_filter(&self) -> RpcResult<FilterId> {
         trace!(target: "rpc::eth", "Serving eth_newBlockFilter");
-        self.inner.install_filter(FilterKind::Block).await
+        self.inner.install_filter(FilterKind::Block, None).await
     }
 
     /// Handler for `eth_newPendingTransactionFilter`
     async fn new_pending_transaction_filter(&self) -> RpcResult<FilterId> {
         trace!(target: "rpc::eth", "Serving eth_newPendingTransactionFilter");
-        let receiver = self.inner.pool.pending_transactions_listener();
+        let pending_txs_receiver = self.inner.pending_transactions_receiver();
 
-        let pending_txs_receiver = PendingTransactionsReceiver::new(receiver);
-
-        self.inner.install_filter(FilterKind::PendingTransaction(pending_txs_receiver)).await
+        self.inner.install_filter(FilterKind::PendingTransaction(pending_txs_receiver), None).await
     }
 
     /// Handler for `eth_getFilterChanges`
@@ -277,7 +915,7 @@ where
     /// Handler for `eth_getLogs`
     async fn logs(&self, filter: Filter) -> RpcResult<Vec<Log>> {
         trace!(target: "rpc::eth", "Serving eth_getLogs");
-        Ok(self.inner.logs_for_filter(filter).await?)
+        Ok(self.inner.logs_for_filter(&LogFilter::new(filter), append_logs, None).await?)
     }
 }
 
@@ -304,301 +942,3339 @@ struct EthFilterInner<Provider, Pool> {
     active_filters: ActiveFilters,
     /// Provides ids to identify filters
     id_provider: Arc<dyn IdProvider>,
-    /// Maximum number of logs that can be returned in a response
-    max_logs_per_response: usize,
     /// The async cache frontend for eth related data
     eth_cache: EthStateCache,
     /// maximum number of headers to read at once for range filter
     max_headers_range: u64,
     /// The type that can spawn tasks.
     task_spawner: Box<dyn TaskSpawner>,
-    /// Duration since the last filter poll, after which the filter is considered stale
-    stale_filter_ttl: Duration,
+    /// Limits the number of concurrent log range scans, if configured.
+    log_scan_permits: Option<Arc<Semaphore>>,
+    /// The filter settings.
+    config: EthFilterConfig,
+    /// When the task clearing stale filters last ran, if it ran yet.
+    last_stale_filter_clean: std::sync::Mutex<Option<Instant>>,
+    /// Whether the task clearing stale filters skips its sweeps.
+    stale_cleanup_paused: AtomicBool,
+    /// Feeds the pending transaction filters from a single listener of the pool.
+    pending_txs_fanout: PendingTransactionsFanout,
 }
 
 impl<Provider, Pool> EthFilterInner<Provider, Pool>
 where
-    Provider: BlockReader + BlockIdReader + EvmEnvProvider + 'static,
+    Provider: BlockReaderIdExt + EvmEnvProvider + 'static,
     Pool: TransactionPool + 'static,
 {
     /// Returns logs matching given filter object.
-    async fn logs_for_filter(&self, filter: Filter) -> Result<Vec<Log>, FilterError> {
-        match filter.block_option {
+    ///
+    /// The matching logs of each block are collected with `append`.
+    ///
+    /// If a cancellation token is given, the scan stops with [FilterError::Cancelled] once it is
+    /// cancelled.
+    async fn logs_for_filter<T: LogResponseSize>(
+        &self,
+        filter: &LogFilter,
+        append: AppendBlockLogs<T>,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<Vec<T>, FilterError> {
+        match filter.filter().block_option {
             FilterBlockOption::AtBlockHash(block_hash) => {
-                let mut all_logs = Vec::new();
-                // all matching logs in the block, if it exists
-                if let Some((block, receipts)) =
-                    self.eth_cache.get_block_and_receipts(block_hash).await?
-                {
-                    let filter = FilteredParams::new(Some(filter));
-                    logs_utils::append_matching_block_logs(
-                        &mut all_logs,
-                        &filter,
-                        (block_hash, block.number).into(),
-                        block.body.into_iter().map(|tx| tx.hash()).zip(receipts),
-                        false,
-                    );
-                }
-                Ok(all_logs)
+                self.logs_at_block_hash(filter, block_hash, append, cancel).await
             }
             FilterBlockOption::Range { from_block, to_block } => {
-                // compute the range
-                let info = self.provider.chain_info()?;
-
-                // we start at the most recent block if unset in filter
-                let start_block = info.best_number;
-                let from = from_block
-                    .map(|num| self.provider.convert_block_number(num))
-                    .transpose()?
-                    .flatten();
-                let to = to_block
-                    .map(|num| self.provider.convert_block_number(num))
-                    .transpose()?
-                    .flatten();
                 let (from_block_number, to_block_number) =
-                    logs_utils::get_filter_block_range(from, to, start_block, info);
-                self.get_logs_in_block_range(&filter, from_block_number, to_block_number).await
+                    self.filter_block_range(from_block, to_block)?;
+                self.get_logs_in_block_range(
+                    filter,
+                    from_block_number,
+                    to_block_number,
+                    append,
+                    cancel,
+                    None,
+                )
+                .await
             }
         }
     }
 
-    /// Installs a new filter and returns the new identifier.
-    async fn install_filter(&self, kind: FilterKind) -> RpcResult<FilterId> {
-        let last_poll_block_number = self.provider.best_block_number().to_rpc_result()?;
-        let id = FilterId::from(self.id_provider.next_id());
-        let mut filters = self.active_filters.inner.lock().await;
-        filters.insert(
-            id.clone(),
-            ActiveFilter {
-                block: last_poll_block_number,
-                last_poll_timestamp: Instant::now(),
-                kind,
-            },
-        );
-        Ok(id)
-    }
-
-    /// Fetches both receipts and block for the given block number.
-    async fn block_and_receipts_by_number(
+    /// Returns the logs matching the given filter, skipping the blocks that can't be read, see
+    /// [EthFilter::logs_best_effort].
+    async fn logs_for_filter_best_effort(
         &self,
-        hash_or_number: BlockHashOrNumber,
-    ) -> EthResult<Option<(SealedBlock, Vec<Receipt>)>> {
-        let block_hash = match self.provider.convert_block_hash(hash_or_number)? {
-            Some(hash) => hash,
-            None => return Ok(None),
+        filter: &LogFilter,
+    ) -> Result<BestEffortLogs, FilterError> {
+        let mut failed_blocks = Vec::new();
+        let logs = match filter.filter().block_option {
+            FilterBlockOption::AtBlockHash(block_hash) => {
+                self.logs_at_block_hash(filter, block_hash, append_logs, None).await?
+            }
+            FilterBlockOption::Range { from_block, to_block } => {
+                let (from, to) = self.filter_block_range(from_block, to_block)?;
+                self.get_logs_in_block_range(
+                    filter,
+                    from,
+                    to,
+                    append_logs,
+                    None,
+                    Some(&mut failed_blocks),
+                )
+                .await?
+            }
         };
-
-        Ok(self.eth_cache.get_block_and_receipts(block_hash).await?)
+        Ok(BestEffortLogs { logs, failed_blocks })
     }
 
-    /// Returns all logs in the given _inclusive_ range that match the filter
+    /// Returns the matching logs of the block with the given hash, collected with `append`.
     ///
-    /// Returns an error if:
-    ///  - underlying database error
-    ///  - amount of matches exceeds configured limit
-    async fn get_logs_in_block_range(
+    /// If the block is not canonical, but still known to the provider together with its receipts,
+    /// for example a block of a side chain that was never made canonical, its logs are returned
+    /// flagged as `removed`. Whether such blocks are available depends on the node, they are only
+    /// kept in memory until they fall below the finalized block.
+    async fn logs_at_block_hash<T>(
         &self,
-        filter: &Filter,
-        from_block: u64,
-        to_block: u64,
-    ) -> Result<Vec<Log>, FilterError> {
-        trace!(target: "rpc::eth::filter", from=from_block, to=to_block, ?filter, "finding logs in range");
-
+        filter: &LogFilter,
+        block_hash: B256,
+        append: AppendBlockLogs<T>,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<Vec<T>, FilterError> {
         let mut all_logs = Vec::new();
-        let filter_params = FilteredParams::new(Some(filter.clone()));
-
-        // derive bloom filters from filter input
-        let address_filter = FilteredParams::address_filter(&filter.address);
-        let topics_filter = FilteredParams::topics_filter(&filter.topics);
-
-        let is_multi_block_range = from_block != to_block;
+        // all matching logs in the block, if it exists
+        if let Some((block, receipts)) =
+            until_cancelled(cancel, self.eth_cache.get_block_and_receipts(block_hash)).await??
+        {
+            append(&mut all_logs, &filter.params, block, receipts, false);
+        } else if let Some(block) =
+            self.provider.find_block_by_hash(block_hash, BlockSource::Pending)?
+        {
+            if let Some(receipts) = self.provider.receipts_by_block_id(block_hash.into())? {
+                append(&mut all_logs, &filter.params, block.seal(block_hash), receipts, true);
+            }
+        }
+        all_logs.drain(..filter.start_index.min(all_logs.len()));
+        Ok(all_logs)
+    }
 
-        // loop over the range of new blocks and check logs if the filter matches the log's bloom
-        // filter
-        for (from, to) in
-            BlockRangeInclusiveIter::new(from_block..=to_block, self.max_headers_range)
+    /// Returns the installed log filter with the given id.
+    async fn log_filter(&self, id: FilterId) -> Result<Arc<LogFilter>, FilterError> {
+        let filters = self.active_filters.inner.lock().await;
+        if let FilterKind::Log(ref filter) =
+            filters.get(&id).ok_or_else(|| FilterError::FilterNotFound(id.clone()))?.kind
         {
-            let headers = self.provider.headers_range(from..=to)?;
+            Ok(filter.clone())
+        } else {
+            // Not a log filter
+            Err(FilterError::FilterNotFound(id))
+        }
+    }
 
-            for (idx, header) in headers.iter().enumerate() {
-                // these are consecutive headers, so we can use the parent hash of the next block to
-                // get the current header's hash
-                let num_hash: BlockHashOrNumber = headers
-                    .get(idx + 1)
-                    .map(|h| h.parent_hash.into())
-                    .unwrap_or_else(|| header.number.into());
-
-                // only if filter matches
-                if FilteredParams::matches_address(header.logs_bloom, &address_filter) &&
-                    FilteredParams::matches_topics(header.logs_bloom, &topics_filter)
-                {
-                    if let Some((block, receipts)) =
-                        self.block_and_receipts_by_number(num_hash).await?
-                    {
-                        let block_hash = block.hash;
+    /// Returns the logs matching the given filter newest-first, see [EthFilter::logs_newest_first].
+    async fn logs_for_filter_newest_first(
+        &self,
+        filter: &LogFilter,
+    ) -> Result<Vec<Log>, FilterError> {
+        match filter.filter().block_option {
+            FilterBlockOption::AtBlockHash(block_hash) => {
+                let mut all_logs =
+                    self.logs_at_block_hash(filter, block_hash, append_logs, None).await?;
+                all_logs.reverse();
+                Ok(all_logs)
+            }
+            FilterBlockOption::Range { from_block, to_block } => {
+                let (from_block_number, to_block_number) =
+                    self.filter_block_range(from_block, to_block)?;
+                self.get_logs_in_block_range_newest_first(
+                    filter,
+                    from_block_number,
+                    to_block_number,
+                )
+                .await
+            }
+        }
+    }
 
-                        logs_utils::append_matching_block_logs(
-                            &mut all_logs,
-                            &filter_params,
-                            (block.number, block_hash).into(),
-                            block.body.into_iter().map(|tx| tx.hash()).zip(receipts),
-                            false,
-                        );
+    /// Returns the logs matching the given filter after the given transaction, see
+    /// [EthFilter::logs_since_tx].
+    ///
+    /// The remaining logs of the anchor block are collected first and the rest of the range is
+    /// scanned as usual. The response limits apply to the logs of both parts.
+    async fn logs_since_tx(
+        &self,
+        filter: &LogFilter,
+        tx_hash: TxHash,
+    ) -> Result<Vec<Log>, FilterError> {
+        let FilterBlockOption::Range { to_block, .. } = filter.filter().block_option else {
+            return Err(FilterError::InvalidFilter(
+                "a transaction anchor can't be combined with a block hash",
+            ))
+        };
+        let (_, meta) = self
+            .provider
+            .transaction_by_hash_with_meta(tx_hash)?
+            .ok_or(EthApiError::TransactionNotFound)?;
+        let (from_block, to_block) =
+            self.filter_block_range(Some(meta.block_number.into()), to_block)?;
 
-                        // size check but only if range is multiple blocks, so we always return all
-                        // logs of a single block
-                        if is_multi_block_range && all_logs.len() > self.max_logs_per_response {
-                            return Err(FilterError::QueryExceedsMaxResults(
-                                self.max_logs_per_response,
-                            ))
-                        }
-                    }
-                }
+        let mut all_logs = Vec::new();
+        // the anchor block is pruned if the range starts after it
+        let mut next_block = from_block;
+        if from_block == meta.block_number {
+            if let Some((block, receipts)) =
+                self.with_retry(|| self.block_and_receipts_by_number(from_block.into())).await?
+            {
+                append_logs(&mut all_logs, &filter.params, block, receipts, false);
+                let anchor_index = U256::from(meta.index);
+                all_logs
+                    .retain(|log| log.transaction_index.map_or(false, |idx| idx > anchor_index));
             }
+            next_block += 1;
+        }
+        if next_block > to_block {
+            return Ok(all_logs)
         }
 
+        all_logs.extend(
+            self.get_logs_in_block_range(filter, next_block, to_block, append_logs, None, None)
+                .await?,
+        );
+        if all_logs.len() > self.config.max_logs_per_response {
+            return Err(FilterError::QueryExceedsMaxResults(self.config.max_logs_per_response))
+        }
+        if let Some(max_bytes) = self.config.max_response_bytes {
+            let response_bytes =
+                all_logs.iter().map(LogResponseSize::estimated_response_size).sum::<usize>();
+            if response_bytes > max_bytes {
+                return Err(FilterError::QueryExceedsMaxResponseBytes(max_bytes))
+            }
+        }
         Ok(all_logs)
     }
-}
-
-/// All active filters
-#[derive(Debug, Clone, Default)]
-pub struct ActiveFilters {
-    inner: Arc<Mutex<HashMap<FilterId, ActiveFilter>>>,
-}
 
-/// An installed filter
-#[derive(Debug)]
-struct ActiveFilter {
-    /// At which block the filter was polled last.
-    block: u64,
-    /// Last time this filter was polled.
-    last_poll_timestamp: Instant,
-    /// What kind of filter it is.
-    kind: FilterKind,
-}
+    /// Computes the _inclusive_ block range of a range filter.
+    fn filter_block_range(
+        &self,
+        from_block: Option<BlockNumberOrTag>,
+        to_block: Option<BlockNumberOrTag>,
+    ) -> Result<(u64, u64), FilterError> {
+        let info = self.provider.chain_info()?;
 
-/// A receiver for pending transactions that returns all new transactions since the last poll.
-#[derive(Debug, Clone)]
-struct PendingTransactionsReceiver {
-    txs_receiver: Arc<Mutex<Receiver<TxHash>>>,
-}
+        // we start at the most recent block if unset in filter
+        let start_block = info.best_number;
+        let from = self.resolve_filter_block(from_block)?;
+        let to = self.resolve_filter_block(to_block)?;
+        let (from, to) = logs_utils::get_filter_block_range(from, to, start_block, info);
+        // the receipts below the earliest available block are pruned
+        Ok((from.max(self.config.earliest_available_block(info.best_number)), to))
+    }
 
-impl PendingTransactionsReceiver {
-    fn new(receiver: Receiver<TxHash>) -> Self {
-        PendingTransactionsReceiver { txs_receiver: Arc::new(Mutex::new(receiver)) }
+    /// Resolves the `fromBlock` or `toBlock` of a range filter to a block number.
+    ///
+    /// Returns `None` if the block is unset, in which case the range defaults apply. Returns
+    /// [FilterError::UnresolvableBlockTag] if the block is set but can't be resolved, for example
+    /// `finalized` before the chain has finalized any block.
+    ///
+    /// `earliest` resolves to the lowest block whose receipts are available, see
+    /// [EthFilterConfig::receipts_prune_mode].
+    fn resolve_filter_block(
+        &self,
+        block: Option<BlockNumberOrTag>,
+    ) -> Result<Option<u64>, FilterError> {
+        let Some(block) = block else { return Ok(None) };
+        if block.is_earliest() {
+            let best_number = self.provider.best_block_number()?;
+            return Ok(Some(self.config.earliest_available_block(best_number)))
+        }
+        match self.provider.convert_block_number(block) {
+            Ok(Some(number)) => Ok(Some(number)),
+            Ok(None) |
+            Err(RethError::Provider(
+                ProviderError::FinalizedBlockNotFound | ProviderError::SafeBlockNotFound,
+            )) => Err(FilterError::UnresolvableBlockTag(block)),
+            Err(err) => Err(err.into()),
+        }
     }
 
-    /// Returns all new pending transactions received since the last poll.
-    async fn drain(&self) -> Vec<B256> {
-        let mut pending_txs = Vec::new();
-        let mut prepared_stream = self.txs_receiver.lock().await;
+    /// Returns a new receiver of the pending transactions of the pool, fed by the shared listener.
+    fn pending_transactions_receiver(&self) -> PendingTransactionsReceiver {
+        PendingTransactionsReceiver::subscribed(
+            self.pending_txs_fanout
+                .subscribe(|| self.pool.pending_transactions_listener(), &*self.task_spawner),
+        )
+    }
 
-        while let Ok(tx_hash) = prepared_stream.try_recv() {
-            pending_txs.push(tx_hash);
-        }
-        pending_txs
+    /// Installs a new filter and returns the new identifier.
+    ///
+    /// The optional callback is invoked if the filter is evicted for being stale.
+    ///
+    /// Returns an error if the filter is malformed, see [Filter::has_malformed_transfer_topics].
+    async fn install_filter(
+        &self,
+        kind: FilterKind,
+        on_evict: Option<EvictionCallback>,
+    ) -> RpcResult<FilterId> {
+        self.install_filter_with_window(kind, on_evict, None).await
     }
-}
 
-#[derive(Clone, Debug)]
-enum FilterKind {
-    Log(Box<Filter>),
-    Block,
-    PendingTransaction(PendingTransactionsReceiver),
-}
+    /// Installs a log filter and collects its logs up to the best block at installation, see
+    /// [EthFilter::new_filter_with_backfill].
+    async fn install_filter_with_backfill(
+        &self,
+        filter: Filter,
+    ) -> RpcResult<(FilterId, Vec<Log>)> {
+        let filter = Arc::new(LogFilter::new(filter));
+        let id = self.install_filter(FilterKind::Log(filter.clone()), None).await?;
 
-/// Errors that can occur in the handler implementation
-#[derive(Debug, thiserror::Error)]
-pub enum FilterError {
-    #[error("filter not found")]
-    FilterNotFound(FilterId),
-    #[error("query exceeds max results {0}")]
-    QueryExceedsMaxResults(usize),
-    #[error(transparent)]
-    EthAPIError(#[from] EthApiError),
-    /// Error thrown when a spawned task failed to deliver a response.
-    #[error("internal filter error")]
-    InternalError,
-}
+        // the chain at installation splits the backfill from the first poll
+        let tip = {
+            let mut filters = self.active_filters.inner.lock().await;
+            let Some(active) = filters.get_mut(&id) else {
+                return Err(FilterError::FilterNotFound(id).into())
+            };
+            active.block = active.last_tip.number + 1;
+            active.last_tip
+        };
 
-// convert the error
-impl From<FilterError> for jsonrpsee::types::error::ErrorObject<'static> {
-    fn from(err: FilterError) -> Self {
-        match err {
-            FilterError::FilterNotFound(_) => rpc_error_with_code(
-                jsonrpsee::types::error::INVALID_PARAMS_CODE,
-                "filter not found",
-            ),
-            err @ FilterError::InternalError => {
-                rpc_error_with_code(jsonrpsee::types::error::INTERNAL_ERROR_CODE, err.to_string())
+        match self.backfill_logs(&filter, tip).await {
+            Ok(logs) => Ok((id, logs)),
+            Err(err) => {
+                self.active_filters.inner.lock().await.remove(&id);
+                Err(err.into())
             }
-            FilterError::EthAPIError(err) => err.into(),
-            err @ FilterError::QueryExceedsMaxResults(_) => {
-                rpc_error_with_code(jsonrpsee::types::error::INVALID_PARAMS_CODE, err.to_string())
+        }
+    }
+
+    /// Returns the logs matching the filter up to the given tip, see
+    /// [EthFilter::new_filter_with_backfill].
+    async fn backfill_logs(
+        &self,
+        filter: &LogFilter,
+        tip: BlockNumHash,
+    ) -> Result<Vec<Log>, FilterError> {
+        match filter.filter().block_option {
+            FilterBlockOption::AtBlockHash(block_hash) => {
+                self.logs_at_block_hash(filter, block_hash, append_logs, None).await
+            }
+            FilterBlockOption::Range { from_block, to_block } => {
+                // the range is resolved against the tip, even if the chain advanced since
+                let info = ChainInfo { best_hash: tip.hash, best_number: tip.number };
+                let from = self.resolve_filter_block(from_block)?;
+                let to = self.resolve_filter_block(to_block)?;
+                let (from, to) = logs_utils::get_filter_block_range(from, to, tip.number, info);
+                let from = from.max(self.config.earliest_available_block(tip.number));
+                let to = to.min(tip.number);
+                self.get_logs_in_block_range(filter, from, to, append_logs, None, None).await
             }
         }
     }
-}
 
-impl From<RethError> for FilterError {
-    fn from(err: RethError) -> Self {
-        FilterError::EthAPIError(err.into())
+    /// Installs a filter, optionally with a block window relative to the best block.
+    async fn install_filter_with_window(
+        &self,
+        kind: FilterKind,
+        on_evict: Option<EvictionCallback>,
+        head_relative: Option<HeadRelativeRange>,
+    ) -> RpcResult<FilterId> {
+        if let FilterKind::Log(ref filter) = kind {
+            if filter.filter().has_malformed_transfer_topics() {
+                return Err(FilterError::InvalidFilter(
+                    "indexed transfer addresses must be left-padded to 32 bytes",
+                )
+                .into())
+            }
+        }
+
+        let info = self.provider.chain_info().to_rpc_result()?;
+        let id = FilterId::from(self.id_provider.next_id());
+        let now = Instant::now();
+        let mut filters = self.active_filters.inner.lock().await;
+        filters.insert(
+            id.clone(),
+            ActiveFilter {
+                // the first block the first poll reports, a head-relative window is reported as a
+                // whole by the first poll
+                block: if head_relative.is_some() {
+                    0
+                } else {
+                    info.best_number + u64::from(!self.config.report_install_block)
+                },
+                last_tip: BlockNumHash::new(info.best_number, info.best_hash),
+                last_poll_timestamp: now,
+                installed_at: now,
+                kind,
+                on_evict,
+                polls: 0,
+                matched_logs: 0,
+                last_poll_range: None,
+                head_relative,
+            },
+        );
+        Ok(id)
     }
-}
 
-/// An iterator that yields _inclusive_ block ranges of a given step size
-#[derive(Debug)]
-struct BlockRangeInclusiveIter {
-    iter: StepBy<RangeInclusive<u64>>,
-    step: u64,
-    end: u64,
-}
+    /// Acquires a permit to run a log range scan, if the number of concurrent scans is limited.
+    ///
+    /// Waits at most [EthFilterConfig::log_scan_queue_timeout] for a permit and returns
+    /// [FilterError::TooBusy] if none became available.
+    async fn acquire_log_scan_permit(&self) -> Result<Option<OwnedSemaphorePermit>, FilterError> {
+        let Some(permits) = self.log_scan_permits.clone() else { return Ok(None) };
 
-impl BlockRangeInclusiveIter {
-    fn new(range: RangeInclusive<u64>, step: u64) -> Self {
-        Self { end: *range.end(), iter: range.step_by(step as usize + 1), step }
+        let permit = match self.config.log_scan_queue_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, permits.acquire_owned())
+                .await
+                .ok()
+                .and_then(Result::ok),
+            None => permits.try_acquire_owned().ok(),
+        };
+
+        permit.map(Some).ok_or(FilterError::TooBusy)
     }
-}
 
-impl Iterator for BlockRangeInclusiveIter {
-    type Item = (u64, u64);
+    /// Fetches both receipts and block for the given block number.
+    async fn block_and_receipts_by_number(
+        &self,
+        hash_or_number: BlockHashOrNumber,
+    ) -> EthResult<Option<(SealedBlock, Vec<Receipt>)>> {
+        let block_hash = match self.provider.convert_block_hash(hash_or_number)? {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let start = self.iter.next()?;
-        let end = (start + self.step).min(self.end);
-        if start > end {
-            return None
+        Ok(self.eth_cache.get_block_and_receipts(block_hash).await?)
+    }
+
+    /// Runs the provider read and retries it if it fails with a transient error, as configured by
+    /// [EthFilterConfig::log_scan_retry].
+    ///
+    /// Returns the error of the last attempt once the retries are exhausted.
+    async fn with_retry<T, F, Fut>(&self, mut read: F) -> EthResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = EthResult<T>>,
+    {
+        let policy = self.config.log_scan_retry;
+        let mut retry = 0;
+        loop {
+            match read().await {
+                Err(err)
+                    if retry < policy.max_retries && LogScanRetryPolicy::is_transient(&err) =>
+                {
+                    let backoff = policy.backoff(retry);
+                    debug!(target: "rpc::eth::filter", %err, retry, ?backoff, "retrying transient provider error");
+                    tokio::time::sleep(backoff).await;
+                    retry += 1;
+                }
+                res => return res,
+            }
         }
-        Some((start, end))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rand::{thread_rng, Rng};
+    /// Returns all logs in the given _inclusive_ range that match the filter
+    ///
+    /// The logs are in canonical order: by block, then by transaction index and then by log index,
+    /// since the blocks are scanned in ascending order and the logs of a block are appended in the
+    /// order of its receipts.
+    ///
+    /// If `failed_blocks` is given, the scan is best effort: blocks whose headers or receipts can't
+    /// be read, even after retrying, are skipped and their numbers appended to `failed_blocks`
+    /// instead of failing the scan.
+    ///
+    /// The first [LogFilter::start_index] matching logs are counted, but dropped instead of being
+    /// returned, so they don't count towards the response limits.
+    ///
+    /// Returns an error if:
+    ///  - underlying database error, unless the scan is best effort
+    ///  - amount of matches exceeds configured limit
+    ///  - estimated size of the matches exceeds configured limit
+    ///  - too many scans are already in progress
+    ///  - the given cancellation token is cancelled
+    async fn get_logs_in_block_range<T: LogResponseSize>(
+        &self,
+        filter: &LogFilter,
+        from_block: u64,
+        to_block: u64,
+        append: AppendBlockLogs<T>,
+        cancel: Option<&CancellationToken>,
+        mut failed_blocks: Option<&mut Vec<u64>>,
+    ) -> Result<Vec<T>, FilterError> {
+        trace!(target: "rpc::eth::filter", from=from_block, to=to_block, filter=?filter.filter(), "finding logs in range");
 
-    #[test]
-    fn test_block_range_iter() {
-        for _ in 0..100 {
-            let mut rng = thread_rng();
-            let start = rng.gen::<u32>() as u64;
-            let end = start.saturating_add(rng.gen::<u32>() as u64);
-            let step = rng.gen::<u16>() as u64;
-            let range = start..=end;
-            let mut iter = BlockRangeInclusiveIter::new(range.clone(), step);
-            let (from, mut end) = iter.next().unwrap();
-            assert_eq!(from, start);
-            assert_eq!(end, (from + step).min(*range.end()));
+        let _permit = until_cancelled(cancel, self.acquire_log_scan_permit()).await??;
 
-            for (next_from, next_end) in iter {
-                // ensure range starts with previous end + 1
-                assert_eq!(next_from, end + 1);
-                end = next_end;
+        let mut all_logs = Vec::new();
+        let mut response_bytes = 0;
+        let mut logs_to_skip = filter.start_index;
+
+        let is_multi_block_range = from_block != to_block;
+
+        // loop over the range of new blocks and check logs if the filter matches the log's bloom
+        // filter
+        for (from, to) in
+            BlockRangeInclusiveIter::new(from_block..=to_block, self.max_headers_range)
+        {
+            if cancel.map_or(false, CancellationToken::is_cancelled) {
+                return Err(FilterError::Cancelled)
             }
+            let headers = match until_cancelled(
+                cancel,
+                self.with_retry(|| async {
+                    self.provider.headers_range(from..=to).map_err(EthApiError::from)
+                }),
+            )
+            .await?
+            {
+                Ok(headers) => headers,
+                Err(err) => match failed_blocks.as_deref_mut() {
+                    Some(failed_blocks) => {
+                        debug!(target: "rpc::eth::filter", %err, from, to, "skipping unreadable headers");
+                        failed_blocks.extend(from..=to);
+                        continue
+                    }
+                    None => return Err(err.into()),
+                },
+            };
 
-            assert_eq!(end, *range.end());
+            for (idx, header) in headers.iter().enumerate() {
+                // these are consecutive headers, so we can use the parent hash of the next block to
+                // get the current header's hash
+                let num_hash: BlockHashOrNumber = headers
+                    .get(idx + 1)
+                    .map(|h| h.parent_hash.into())
+                    .unwrap_or_else(|| header.number.into());
+
+                // only if filter matches
+                if filter.matches_bloom(header.logs_bloom) {
+                    let block_and_receipts = match until_cancelled(
+                        cancel,
+                        self.with_retry(|| self.block_and_receipts_by_number(num_hash)),
+                    )
+                    .await?
+                    {
+                        Ok(block_and_receipts) => block_and_receipts,
+                        Err(err) => match failed_blocks.as_deref_mut() {
+                            Some(failed_blocks) => {
+                                debug!(target: "rpc::eth::filter", %err, block=header.number, "skipping unreadable block");
+                                failed_blocks.push(header.number);
+                                continue
+                            }
+                            None => return Err(err.into()),
+                        },
+                    };
+                    if let Some((block, receipts)) = block_and_receipts {
+                        let num_logs = all_logs.len();
+                        append(&mut all_logs, &filter.params, block, receipts, false);
+                        if logs_to_skip > 0 {
+                            let skipped = logs_to_skip.min(all_logs.len() - num_logs);
+                            all_logs.drain(num_logs..num_logs + skipped);
+                            logs_to_skip -= skipped;
+                        }
+                        response_bytes += all_logs[num_logs..]
+                            .iter()
+                            .map(LogResponseSize::estimated_response_size)
+                            .sum::<usize>();
+
+                        // size checks but only if range is multiple blocks, so we always return all
+                        // logs of a single block
+                        if is_multi_block_range {
+                            if all_logs.len() > self.config.max_logs_per_response {
+                                return Err(FilterError::QueryExceedsMaxResults(
+                                    self.config.max_logs_per_response,
+                                ))
+                            }
+                            if let Some(max_bytes) = self.config.max_response_bytes {
+                                if response_bytes > max_bytes {
+                                    return Err(FilterError::QueryExceedsMaxResponseBytes(max_bytes))
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(all_logs)
+    }
+
+    /// Returns the numbers of the blocks in the given _inclusive_ range whose bloom matches the
+    /// filter, without fetching their bodies or receipts.
+    ///
+    /// Returns an error if:
+    ///  - underlying database error
+    ///  - too many scans are already in progress
+    async fn get_candidate_blocks_in_range(
+        &self,
+        filter: &LogFilter,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<u64>, FilterError> {
+        trace!(target: "rpc::eth::filter", from=from_block, to=to_block, filter=?filter.filter(), "finding candidate blocks in range");
+
+        let _permit = self.acquire_log_scan_permit().await?;
+
+        let mut candidates = Vec::new();
+        for (from, to) in
+            BlockRangeInclusiveIter::new(from_block..=to_block, self.max_headers_range)
+        {
+            let headers = self
+                .with_retry(|| async {
+                    self.provider.headers_range(from..=to).map_err(EthApiError::from)
+                })
+                .await?;
+            candidates.extend(
+                headers
+                    .iter()
+                    .filter(|header| filter.matches_bloom(header.logs_bloom))
+                    .map(|header| header.number),
+            );
         }
+
+        Ok(candidates)
+    }
+
+    /// Returns the most recent logs in the given _inclusive_ range that match the filter, newest
+    /// first.
+    ///
+    /// The range is walked from `to_block` down to `from_block` and the walk stops as soon as
+    /// [EthFilterConfig::max_logs_per_response] logs are found, so the oldest matches are dropped
+    /// instead of failing the request.
+    ///
+    /// Returns an error if:
+    ///  - underlying database error
+    ///  - estimated size of the matches exceeds configured limit
+    ///  - too many scans are already in progress
+    async fn get_logs_in_block_range_newest_first(
+        &self,
+        filter: &LogFilter,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<Log>, FilterError> {
+        trace!(target: "rpc::eth::filter", from=from_block, to=to_block, filter=?filter.filter(), "finding newest logs in range");
+
+        let _permit = self.acquire_log_scan_permit().await?;
+
+        let max_logs = self.config.max_logs_per_response;
+        let mut all_logs = Vec::new();
+        let mut block_logs = Vec::new();
+        let mut response_bytes = 0;
+
+        let is_multi_block_range = from_block != to_block;
+
+        for (from, to) in
+            BlockRangeInclusiveRevIter::new(from_block..=to_block, self.max_headers_range)
+        {
+            let headers = self
+                .with_retry(|| async {
+                    self.provider.headers_range(from..=to).map_err(EthApiError::from)
+                })
+                .await?;
+
+            for (idx, header) in headers.iter().enumerate().rev() {
+                if !filter.matches_bloom(header.logs_bloom) {
+                    continue
+                }
+
+                let num_hash: BlockHashOrNumber = headers
+                    .get(idx + 1)
+                    .map(|h| h.parent_hash.into())
+                    .unwrap_or_else(|| header.number.into());
+
+                if let Some((block, receipts)) =
+                    self.with_retry(|| self.block_and_receipts_by_number(num_hash)).await?
+                {
+                    block_logs.clear();
+                    append_logs(&mut block_logs, &filter.params, block, receipts, false);
+                    // The newest logs of the block are kept if the limit splits it.
+                    block_logs.reverse();
+                    block_logs.truncate(max_logs - all_logs.len());
+                    response_bytes += block_logs
+                        .iter()
+                        .map(LogResponseSize::estimated_response_size)
+                        .sum::<usize>();
+                    all_logs.append(&mut block_logs);
+
+                    if is_multi_block_range {
+                        if let Some(max_bytes) = self.config.max_response_bytes {
+                            if response_bytes > max_bytes {
+                                return Err(FilterError::QueryExceedsMaxResponseBytes(max_bytes))
+                            }
+                        }
+                    }
+
+                    if all_logs.len() >= max_logs {
+                        return Ok(all_logs)
+                    }
+                }
+            }
+        }
+
+        Ok(all_logs)
+    }
+
+    /// Returns the logs of every filter in the given _inclusive_ range, see
+    /// [EthFilter::combined_logs].
+    ///
+    /// A block and its receipts are fetched at most once, if its bloom matches any of the filters.
+    /// The blooms are first checked against the union of the filters, see [UnionFilterParams].
+    ///
+    /// A filter whose token in `cancels` is cancelled is pruned before the next block is checked
+    /// and is missing from the result.
+    async fn get_combined_logs_in_block_range(
+        &self,
+        filters: &[LogFilter],
+        cancels: &[Option<CancellationToken>],
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<(usize, Vec<Log>)>, FilterError> {
+        trace!(target: "rpc::eth::filter", from=from_block, to=to_block, filters=filters.len(), "finding combined logs in range");
+
+        let _permit = self.acquire_log_scan_permit().await?;
+
+        let is_cancelled =
+            |idx: usize| cancels[idx].as_ref().map_or(false, CancellationToken::is_cancelled);
+
+        // the logs and the estimated response size of each filter
+        let mut all_logs = vec![(Vec::new(), 0); filters.len()];
+        // the filters that were not cancelled yet
+        let mut active = (0..filters.len()).collect::<Vec<_>>();
+        let mut matching = Vec::with_capacity(filters.len());
+        let mut union = UnionFilterParams::new(filters.iter().map(LogFilter::filter));
+
+        let is_multi_block_range = from_block != to_block;
+
+        for (from, to) in
+            BlockRangeInclusiveIter::new(from_block..=to_block, self.max_headers_range)
+        {
+            let headers = self
+                .with_retry(|| async {
+                    self.provider.headers_range(from..=to).map_err(EthApiError::from)
+                })
+                .await?;
+
+            for (idx, header) in headers.iter().enumerate() {
+                // prune the cancelled filters and drop their logs
+                if active.iter().any(|&filter_idx| is_cancelled(filter_idx)) {
+                    active.retain(|&filter_idx| {
+                        if is_cancelled(filter_idx) {
+                            all_logs[filter_idx] = (Vec::new(), 0);
+                            return false
+                        }
+                        true
+                    });
+                    if active.is_empty() {
+                        return Ok(Vec::new())
+                    }
+                    union = UnionFilterParams::new(
+                        active.iter().map(|&filter_idx| filters[filter_idx].filter()),
+                    );
+                }
+
+                // cheaply skip blocks that can't match any filter
+                if !union.matches_bloom(header.logs_bloom) {
+                    continue
+                }
+
+                matching.clear();
+                matching.extend(
+                    active
+                        .iter()
+                        .copied()
+                        .filter(|&filter_idx| filters[filter_idx].matches_bloom(header.logs_bloom)),
+                );
+                if matching.is_empty() {
+                    continue
+                }
+
+                let num_hash: BlockHashOrNumber = headers
+                    .get(idx + 1)
+                    .map(|h| h.parent_hash.into())
+                    .unwrap_or_else(|| header.number.into());
+
+                let Some((block, receipts)) =
+                    self.with_retry(|| self.block_and_receipts_by_number(num_hash)).await?
+                else {
+                    continue
+                };
+                let tx_hashes = block.body.iter().map(|tx| tx.hash()).collect::<Vec<_>>();
+
+                for &filter_idx in &matching {
+                    // the filter may have been cancelled while the block was fetched
+                    if is_cancelled(filter_idx) {
+                        continue
+                    }
+                    let (logs, response_bytes) = &mut all_logs[filter_idx];
+                    let num_logs = logs.len();
+                    logs_utils::append_matching_block_logs(
+                        logs,
+                        &filters[filter_idx].params,
+                        (block.number, block.hash).into(),
+                        tx_hashes.iter().copied().zip(receipts.iter().cloned()),
+                        false,
+                    );
+                    *response_bytes += logs[num_logs..]
+                        .iter()
+                        .map(LogResponseSize::estimated_response_size)
+                        .sum::<usize>();
+
+                    // same size checks as for a single filter, but per filter
+                    if is_multi_block_range {
+                        if logs.len() > self.config.max_logs_per_response {
+                            return Err(FilterError::QueryExceedsMaxResults(
+                                self.config.max_logs_per_response,
+                            ))
+                        }
+                        if let Some(max_bytes) = self.config.max_response_bytes {
+                            if *response_bytes > max_bytes {
+                                return Err(FilterError::QueryExceedsMaxResponseBytes(max_bytes))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(all_logs
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, (logs, _))| !logs.is_empty() && !is_cancelled(*idx))
+            .map(|(idx, (logs, _))| (idx, logs))
+            .collect())
+    }
+}
+
+/// Approximate size of the serialized fields of a log, excluding its topics and data.
+const LOG_RESPONSE_OVERHEAD: usize = 400;
+
+/// Approximate size of a serialized topic.
+const TOPIC_RESPONSE_SIZE: usize = 70;
+
+/// Estimates how many bytes a log adds to a response.
+trait LogResponseSize {
+    /// Returns the approximate size of the serialized log.
+    fn estimated_response_size(&self) -> usize;
+}
+
+impl LogResponseSize for Log {
+    fn estimated_response_size(&self) -> usize {
+        // data is hex encoded
+        LOG_RESPONSE_OVERHEAD + self.topics.len() * TOPIC_RESPONSE_SIZE + self.data.len() * 2
+    }
+}
+
+impl LogResponseSize for LogWithTxContext {
+    fn estimated_response_size(&self) -> usize {
+        self.log.estimated_response_size()
+    }
+}
+
+impl LogResponseSize for LogWithGasPrice {
+    fn estimated_response_size(&self) -> usize {
+        self.log.estimated_response_size()
+    }
+}
+
+impl LogResponseSize for IndexedLog {
+    fn estimated_response_size(&self) -> usize {
+        self.log.estimated_response_size()
+    }
+}
+
+impl LogResponseSize for RawBlockLog {
+    fn estimated_response_size(&self) -> usize {
+        self.length()
+    }
+}
+
+/// Appends the matching logs of a block and its receipts to the response.
+///
+/// The logs are flagged as `removed` if the block is not canonical.
+type AppendBlockLogs<T> = fn(&mut Vec<T>, &FilteredParams, SealedBlock, Vec<Receipt>, bool);
+
+/// Appends the matching logs of a block as plain [Log]s.
+fn append_logs(
+    all_logs: &mut Vec<Log>,
+    filter: &FilteredParams,
+    block: SealedBlock,
+    receipts: Vec<Receipt>,
+    removed: bool,
+) {
+    logs_utils::append_matching_block_logs(
+        all_logs,
+        filter,
+        (block.number, block.hash).into(),
+        block.body.into_iter().map(|tx| tx.hash()).zip(receipts),
+        removed,
+    );
+}
+
+/// Appends the matching logs of a block as [IndexedLog]s, numbered from the logs appended before.
+fn append_indexed_logs(
+    all_logs: &mut Vec<IndexedLog>,
+    filter: &FilteredParams,
+    block: SealedBlock,
+    receipts: Vec<Receipt>,
+    removed: bool,
+) {
+    logs_utils::append_matching_block_indexed_logs(
+        all_logs,
+        filter,
+        (block.number, block.hash).into(),
+        block.body.into_iter().map(|tx| tx.hash()).zip(receipts),
+        removed,
+    );
+}
+
+/// Appends only the first matching log of a block.
+fn append_first_log(
+    all_logs: &mut Vec<Log>,
+    filter: &FilteredParams,
+    block: SealedBlock,
+    receipts: Vec<Receipt>,
+    removed: bool,
+) {
+    all_logs.extend(logs_utils::first_matching_block_log(
+        filter,
+        (block.number, block.hash).into(),
+        block.body.into_iter().map(|tx| tx.hash()).zip(receipts),
+        removed,
+    ));
+}
+
+/// Appends the matching logs of a block as [RawBlockLog]s.
+fn append_raw_logs(
+    all_logs: &mut Vec<RawBlockLog>,
+    filter: &FilteredParams,
+    block: SealedBlock,
+    receipts: Vec<Receipt>,
+    removed: bool,
+) {
+    logs_utils::append_matching_block_raw_logs(
+        all_logs,
+        filter,
+        (block.number, block.hash).into(),
+        block.body.into_iter().map(|tx| tx.hash()).zip(receipts),
+        removed,
+    );
+}
+
+/// Appends the matching logs of a block together with the context of their transaction.
+fn append_logs_with_tx_context(
+    all_logs: &mut Vec<LogWithTxContext>,
+    filter: &FilteredParams,
+    block: SealedBlock,
+    receipts: Vec<Receipt>,
+    removed: bool,
+) {
+    logs_utils::append_matching_block_logs_with_tx_context(
+        all_logs,
+        filter,
+        (block.number, block.hash).into(),
+        block.body.into_iter().zip(receipts),
+        removed,
+    );
+}
+
+/// Appends the matching logs of a block together with the effective gas price of their transaction.
+fn append_logs_with_gas_price(
+    all_logs: &mut Vec<LogWithGasPrice>,
+    filter: &FilteredParams,
+    block: SealedBlock,
+    receipts: Vec<Receipt>,
+    removed: bool,
+) {
+    let base_fee = block.base_fee_per_gas;
+    logs_utils::append_matching_block_logs_with_gas_price(
+        all_logs,
+        filter,
+        (block.number, block.hash).into(),
+        block.body.into_iter().zip(receipts),
+        base_fee,
+        removed,
+    );
+}
+
+/// Settings for the [EthFilter] handler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EthFilterConfig {
+    /// Maximum number of logs that can be returned in a single response in `eth_getLogs` calls.
+    pub max_logs_per_response: usize,
+    /// Duration since the last filter poll, after which the filter is considered stale.
+    pub stale_filter_ttl: Duration,
+    /// Maximum number of log range scans that can run concurrently, a limit of zero is raised to
+    /// one.
+    ///
+    /// Defaults to `None`, which does not limit concurrent scans.
+    pub max_concurrent_log_scans: Option<usize>,
+    /// How long a log range scan waits for a free slot if
+    /// [EthFilterConfig::max_concurrent_log_scans] is reached.
+    ///
+    /// Defaults to `None`, which rejects the scan immediately.
+    pub log_scan_queue_timeout: Option<Duration>,
+    /// Maximum estimated size in bytes of the logs returned in a single response in
+    /// `eth_getLogs` calls.
+    ///
+    /// Defaults to `None`, which only limits the number of logs.
+    pub max_response_bytes: Option<usize>,
+    /// Number of polls after which a log filter that never matched any logs is evicted by
+    /// [EthFilter::clear_stale_filters], regardless of how recently it was polled.
+    ///
+    /// Defaults to `None`, which keeps such filters as long as they are polled, since they may be
+    /// watching for rare events.
+    pub max_polls_without_matches: Option<u64>,
+    /// Maximum number of transaction hashes a single poll of a pending transaction filter
+    /// returns. The remaining transactions are kept for the next polls.
+    ///
+    /// Defaults to `None`, which returns all transactions received since the last poll.
+    pub max_pending_transactions_per_poll: Option<usize>,
+    /// Whether the first poll of a block or log filter includes the best block at the time the
+    /// filter was installed. Every later poll starts at the block after the best block of the
+    /// previous poll, so each block is reported once either way.
+    ///
+    /// Defaults to `true`.
+    pub report_install_block: bool,
+    /// The prune mode of the receipts of the node, if they are pruned.
+    ///
+    /// Range filters never scan below the lowest block whose receipts are kept, and `earliest`
+    /// resolves to that block instead of genesis.
+    ///
+    /// Defaults to `None`, which assumes the full history is available.
+    pub receipts_prune_mode: Option<PruneMode>,
+    /// How log range scans retry provider reads that failed with a transient error.
+    ///
+    /// Defaults to no retries, so any error aborts the scan.
+    pub log_scan_retry: LogScanRetryPolicy,
+}
+
+impl EthFilterConfig {
+    /// Creates a new config with the given response limit and stale filter ttl.
+    pub fn new(max_logs_per_response: usize, stale_filter_ttl: Duration) -> Self {
+        Self {
+            max_logs_per_response,
+            stale_filter_ttl,
+            max_concurrent_log_scans: None,
+            log_scan_queue_timeout: None,
+            max_response_bytes: None,
+            max_polls_without_matches: None,
+            max_pending_transactions_per_poll: None,
+            report_install_block: true,
+            receipts_prune_mode: None,
+            log_scan_retry: LogScanRetryPolicy::default(),
+        }
+    }
+
+    /// Configures the maximum number of concurrent log range scans, at least one.
+    pub fn max_concurrent_log_scans(mut self, max_scans: usize) -> Self {
+        self.max_concurrent_log_scans = Some(max_scans.max(1));
+        self
+    }
+
+    /// Configures how long a log range scan waits for a free slot before it is rejected.
+    pub fn log_scan_queue_timeout(mut self, timeout: Duration) -> Self {
+        self.log_scan_queue_timeout = Some(timeout);
+        self
+    }
+
+    /// Configures the maximum estimated size in bytes of a single `eth_getLogs` response.
+    pub fn max_response_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_response_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Configures the number of polls after which log filters that never matched are evicted.
+    pub fn max_polls_without_matches(mut self, max_polls: u64) -> Self {
+        self.max_polls_without_matches = Some(max_polls);
+        self
+    }
+
+    /// Configures the maximum number of transaction hashes returned by a single poll of a pending
+    /// transaction filter.
+    pub fn max_pending_transactions_per_poll(mut self, max_txs: usize) -> Self {
+        self.max_pending_transactions_per_poll = Some(max_txs);
+        self
+    }
+
+    /// Configures whether the first poll of a filter includes the block it was installed at.
+    pub fn report_install_block(mut self, report: bool) -> Self {
+        self.report_install_block = report;
+        self
+    }
+
+    /// Configures the prune mode of the receipts, which bounds the scanned block ranges.
+    pub fn receipts_prune_mode(mut self, mode: PruneMode) -> Self {
+        self.receipts_prune_mode = Some(mode);
+        self
+    }
+
+    /// Configures how log range scans retry transient provider errors.
+    pub fn log_scan_retry(mut self, policy: LogScanRetryPolicy) -> Self {
+        self.log_scan_retry = policy;
+        self
+    }
+
+    /// Returns the lowest block whose receipts are available at the given best block.
+    fn earliest_available_block(&self, best_number: u64) -> u64 {
+        match self.receipts_prune_mode {
+            None => 0,
+            Some(PruneMode::Full) => best_number + 1,
+            Some(PruneMode::Distance(distance)) => best_number.saturating_sub(distance),
+            Some(PruneMode::Before(block)) => block,
+        }
+    }
+}
+
+/// The retry policy of the provider reads of a log range scan.
+///
+/// Only transient errors, e.g. a read that failed because the database was busy, are retried. The
+/// backoff doubles after every retry, up to [LogScanRetryPolicy::max_backoff].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogScanRetryPolicy {
+    /// Maximum number of retries of a single read, `0` disables retries.
+    pub max_retries: u32,
+    /// Backoff before the first retry.
+    pub initial_backoff: Duration,
+    /// Maximum backoff between two retries.
+    pub max_backoff: Duration,
+}
+
+impl LogScanRetryPolicy {
+    /// Creates a new policy with the given number of retries and initial backoff.
+    pub fn new(max_retries: u32, initial_backoff: Duration) -> Self {
+        Self { max_retries, initial_backoff, ..Default::default() }
+    }
+
+    /// Configures the maximum backoff between two retries.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Returns the backoff before the given retry, starting at `0`.
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff.saturating_mul(1 << retry.min(16)).min(self.max_backoff)
+    }
+
+    /// Returns `true` if a read that failed with the given error may succeed if it is retried.
+    fn is_transient(err: &EthApiError) -> bool {
+        let EthApiError::Internal(err) = err else { return false };
+        let err = match err {
+            RethError::Database(err) | RethError::Provider(ProviderError::Database(err)) => err,
+            _ => return false,
+        };
+        matches!(
+            err,
+            DatabaseError::Read(_) | DatabaseError::InitTx(_) | DatabaseError::InitCursor(_)
+        )
+    }
+}
+
+impl Default for LogScanRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// All active filters
+#[derive(Debug, Clone, Default)]
+pub struct ActiveFilters {
+    inner: Arc<Mutex<HashMap<FilterId, ActiveFilter>>>,
+}
+
+/// An installed filter
+#[derive(Debug)]
+struct ActiveFilter {
+    /// At which block the filter was polled last.
+    block: u64,
+    /// The best block when the filter was installed or polled last.
+    last_tip: BlockNumHash,
+    /// Last time this filter was polled.
+    last_poll_timestamp: Instant,
+    /// When the filter was installed.
+    installed_at: Instant,
+    /// What kind of filter it is.
+    kind: FilterKind,
+    /// Invoked with the id of the filter if it is evicted.
+    on_evict: Option<EvictionCallback>,
+    /// How often the filter was polled for changes.
+    polls: u64,
+    /// How many logs the polls of a log filter returned in total.
+    matched_logs: u64,
+    /// The block range scanned by the last poll of a log filter.
+    last_poll_range: Option<FilterPollRange>,
+    /// The window relative to the best block a log filter matches, instead of its block range.
+    head_relative: Option<HeadRelativeRange>,
+}
+
+impl ActiveFilter {
+    /// Returns `true` if this is a log filter that was polled at least `max_polls` times without
+    /// ever returning a log.
+    fn never_matched(&self, max_polls: u64) -> bool {
+        matches!(self.kind, FilterKind::Log(_)) && self.matched_logs == 0 && self.polls >= max_polls
+    }
+}
+
+/// A callback that is notified when its filter is evicted for being stale.
+struct EvictionCallback(Box<dyn FnOnce(FilterId) + Send>);
+
+impl std::fmt::Debug for EvictionCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EvictionCallback").finish_non_exhaustive()
+    }
+}
+
+/// A receiver for pending transactions that returns all new transactions since the last poll.
+#[derive(Debug, Clone)]
+struct PendingTransactionsReceiver {
+    txs_receiver: Arc<Mutex<PendingTransactionsChannel>>,
+    /// The criteria the reported transactions have to match, if any.
+    criteria: Option<PendingTransactionCriteria>,
+}
+
+/// The receiving half of the pending transactions channel.
+#[derive(Debug)]
+struct PendingTransactionsChannel {
+    receiver: Receiver<TxHash>,
+    /// A transaction received by a connection check, returned first by the next poll.
+    peeked: Option<TxHash>,
+    /// The registration with the shared listener of the pool, if any.
+    ///
+    /// Declared after the receiver, so the channel is closed by the time it's released.
+    _subscription: Option<FanoutSubscription>,
+}
+
+impl PendingTransactionsReceiver {
+    fn new(receiver: Receiver<TxHash>) -> Self {
+        Self::with_subscription(receiver, None)
+    }
+
+    /// Creates a receiver fed by the [PendingTransactionsFanout], releasing the subscription once
+    /// the last clone is dropped.
+    fn subscribed((receiver, subscription): (Receiver<TxHash>, FanoutSubscription)) -> Self {
+        Self::with_subscription(receiver, Some(subscription))
+    }
+
+    fn with_subscription(
+        receiver: Receiver<TxHash>,
+        subscription: Option<FanoutSubscription>,
+    ) -> Self {
+        let channel =
+            PendingTransactionsChannel { receiver, peeked: None, _subscription: subscription };
+        PendingTransactionsReceiver { txs_receiver: Arc::new(Mutex::new(channel)), criteria: None }
+    }
+
+    /// Only report the transactions matching the given criteria.
+    fn with_criteria(mut self, criteria: PendingTransactionCriteria) -> Self {
+        self.criteria = Some(criteria);
+        self
+    }
+
+    /// Returns `false` once the sender half is gone and all transactions are drained.
+    ///
+    /// The channel can't be checked without receiving, so a received transaction is kept for the
+    /// next poll.
+    async fn is_connected(&self) -> bool {
+        let mut channel = self.txs_receiver.lock().await;
+        if channel.peeked.is_some() {
+            return true
+        }
+        match channel.receiver.try_recv() {
+            Ok(tx_hash) => {
+                channel.peeked = Some(tx_hash);
+                true
+            }
+            Err(TryRecvError::Empty) => true,
+            Err(TryRecvError::Disconnected) => false,
+        }
+    }
+
+    /// Returns the new pending transactions received since the last poll, at most `max_txs` if
+    /// set. Transactions beyond the limit stay queued in order for the next poll.
+    ///
+    /// Returns [FilterError::PendingTransactionStreamEnded] once all transactions are drained and
+    /// the sender half is gone, for example because the pool shut down.
+    async fn drain(&self, max_txs: Option<usize>) -> Result<Vec<B256>, FilterError> {
+        let mut pending_txs = Vec::new();
+        let mut channel = self.txs_receiver.lock().await;
+
+        while max_txs.map_or(true, |max_txs| pending_txs.len() < max_txs) {
+            let next = match channel.peeked.take() {
+                Some(tx_hash) => Ok(tx_hash),
+                None => channel.receiver.try_recv(),
+            };
+            match next {
+                Ok(tx_hash) => pending_txs.push(tx_hash),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // return the remaining transactions first, the next poll reports the error
+                    if pending_txs.is_empty() {
+                        return Err(FilterError::PendingTransactionStreamEnded)
+                    }
+                    break
+                }
+            }
+        }
+        Ok(pending_txs)
+    }
+}
+
+/// Shares a single listener of the pool between all pending transaction filters.
+///
+/// Every filter gets its own buffered channel, fed by a task that forwards the hashes received
+/// from the pool. The listener is only registered with the pool while there are filters to feed:
+/// the task exits as soon as the channel of the last filter is dropped, and the next filter
+/// registers a new listener.
+#[derive(Debug, Default)]
+struct PendingTransactionsFanout {
+    /// The subscribers of the current listener, `None` if there is no listener.
+    subscribers: Arc<std::sync::Mutex<Option<FanoutSubscribers>>>,
+}
+
+/// The filters fed by a listener of the pool.
+#[derive(Debug)]
+struct FanoutSubscribers {
+    /// The senders of the channels of the installed filters.
+    senders: Vec<Sender<TxHash>>,
+    /// Stops the task forwarding the transactions of the listener.
+    shutdown: Arc<Notify>,
+}
+
+impl PendingTransactionsFanout {
+    /// Returns a new channel of pending transactions for a filter, and the subscription that
+    /// releases the listener once it's dropped after the channel.
+    ///
+    /// If there is no listener yet, one is created with `listen` and the task forwarding its
+    /// transactions is spawned.
+    fn subscribe(
+        &self,
+        listen: impl FnOnce() -> Receiver<TxHash>,
+        task_spawner: &dyn TaskSpawner,
+    ) -> (Receiver<TxHash>, FanoutSubscription) {
+        let (sender, receiver) = mpsc::channel(PENDING_TX_BUFFER_SIZE);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        match subscribers.as_mut() {
+            Some(subscribers) => subscribers.senders.push(sender),
+            None => {
+                let shutdown = Arc::new(Notify::new());
+                *subscribers =
+                    Some(FanoutSubscribers { senders: vec![sender], shutdown: shutdown.clone() });
+                let listener = listen();
+                task_spawner.spawn(Box::pin(Self::forward(
+                    listener,
+                    self.subscribers.clone(),
+                    shutdown,
+                )));
+            }
+        }
+        (receiver, FanoutSubscription { subscribers: self.subscribers.clone() })
+    }
+
+    /// Forwards the transactions of the listener to all filters until the pool goes away or the
+    /// last filter is gone.
+    ///
+    /// As with the listeners of the pool, a transaction is dropped for a filter whose buffer is
+    /// full instead of blocking the other filters.
+    async fn forward(
+        mut listener: Receiver<TxHash>,
+        subscribers: Arc<std::sync::Mutex<Option<FanoutSubscribers>>>,
+        shutdown: Arc<Notify>,
+    ) {
+        loop {
+            let tx_hash = tokio::select! {
+                tx_hash = listener.recv() => tx_hash,
+                // dropping the listener unregisters it from the pool
+                _ = shutdown.notified() => return,
+            };
+            let mut guard = subscribers.lock().unwrap();
+            // the subscribers may already belong to the next listener
+            let Some(current) =
+                guard.as_mut().filter(|current| Arc::ptr_eq(&current.shutdown, &shutdown))
+            else {
+                return
+            };
+            let Some(tx_hash) = tx_hash else {
+                // the pool is gone, dropping the senders ends the streams of the filters
+                *guard = None;
+                return
+            };
+            current.senders.retain(|sender| match sender.try_send(tx_hash) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    debug!(target: "rpc::eth::filter", ?tx_hash, "failed to send pending tx; channel full");
+                    true
+                }
+                Err(TrySendError::Closed(_)) => false,
+            });
+            if current.senders.is_empty() {
+                *guard = None;
+                return
+            }
+        }
+    }
+}
+
+/// The registration of a filter with the [PendingTransactionsFanout].
+///
+/// Dropping the subscription after the receiver of the filter removes its closed channel, and
+/// stops the listener if it was the last one.
+#[derive(Debug)]
+struct FanoutSubscription {
+    subscribers: Arc<std::sync::Mutex<Option<FanoutSubscribers>>>,
+}
+
+impl Drop for FanoutSubscription {
+    fn drop(&mut self) {
+        let mut guard = self.subscribers.lock().unwrap();
+        let Some(current) = guard.as_mut() else { return };
+        current.senders.retain(|sender| !sender.is_closed());
+        if current.senders.is_empty() {
+            current.shutdown.notify_one();
+            *guard = None;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum FilterKind {
+    Log(Arc<LogFilter>),
+    Block,
+    PendingTransaction(PendingTransactionsReceiver),
+}
+
+/// A log filter together with the matchers derived from it.
+///
+/// The matchers are derived once and reused for every poll of an installed filter. The filter
+/// can't be modified after construction, so they always match its definition.
+#[derive(Debug)]
+struct LogFilter {
+    /// The filter definition.
+    filter: Filter,
+    /// Matcher for the logs of a block.
+    params: FilteredParams,
+    /// Bloom filter of the filter addresses.
+    address_filter: BloomFilter,
+    /// Bloom filters of the filter topics.
+    topics_filter: Vec<BloomFilter>,
+    /// The number of leading matching logs of the query to skip, in canonical order.
+    start_index: usize,
+}
+
+impl LogFilter {
+    fn new(filter: Filter) -> Self {
+        let address_filter = FilteredParams::address_filter(&filter.address);
+        let topics_filter = FilteredParams::topics_filter(&filter.topics);
+        let params = FilteredParams::new(Some(filter.clone()));
+        Self { filter, params, address_filter, topics_filter, start_index: 0 }
+    }
+
+    /// Refines the matchers with the given reth specific options.
+    fn with_options(mut self, options: LogFilterOptions) -> Self {
+        self.params.options = options;
+        self
+    }
+
+    /// Skips the matching logs of the query before the given index, see
+    /// [EthFilter::logs_from_index].
+    ///
+    /// Only the ascending scans of [EthFilterInner::logs_for_filter] honor the start index.
+    fn with_start_index(mut self, start_index: usize) -> Self {
+        self.start_index = start_index;
+        self
+    }
+
+    /// Returns the filter definition.
+    fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    /// Returns `true` if a block with the given logs bloom may contain matching logs.
+    fn matches_bloom(&self, bloom: Bloom) -> bool {
+        FilteredParams::matches_address(bloom, &self.address_filter) &&
+            FilteredParams::matches_topics(bloom, &self.topics_filter)
+    }
+}
+
+/// Errors that can occur in the handler implementation
+#[derive(Debug, thiserror::Error)]
+pub enum FilterError {
+    #[error("filter not found")]
+    FilterNotFound(FilterId),
+    #[error("query exceeds max results {0}")]
+    QueryExceedsMaxResults(usize),
+    /// Error thrown when the estimated size of the matching logs exceeds the configured limit.
+    #[error("query exceeds max response size of {0} bytes")]
+    QueryExceedsMaxResponseBytes(usize),
+    #[error("invalid filter: {0}")]
+    InvalidFilter(&'static str),
+    /// Error thrown when a block tag of the filter range can't be resolved to a block.
+    #[error("block `{0}` of the filter range can't be resolved")]
+    UnresolvableBlockTag(BlockNumberOrTag),
+    /// Error thrown when the maximum number of concurrent log scans is reached.
+    #[error("too many concurrent log queries, try again later")]
+    TooBusy,
+    /// Error thrown when the pending transaction stream of a filter ended.
+    #[error("pending transaction stream ended, the filter must be reinstalled")]
+    PendingTransactionStreamEnded,
+    #[error(transparent)]
+    EthAPIError(#[from] EthApiError),
+    /// Error thrown when a spawned task failed to deliver a response.
+    #[error("internal filter error")]
+    InternalError,
+    /// Error thrown when a log query was cancelled before it completed.
+    #[error("log query was cancelled")]
+    Cancelled,
+}
+
+// convert the error
+impl From<FilterError> for jsonrpsee::types::error::ErrorObject<'static> {
+    fn from(err: FilterError) -> Self {
+        match err {
+            FilterError::FilterNotFound(_) => rpc_error_with_code(
+                jsonrpsee::types::error::INVALID_PARAMS_CODE,
+                "filter not found",
+            ),
+            err @ FilterError::InternalError => {
+                rpc_error_with_code(jsonrpsee::types::error::INTERNAL_ERROR_CODE, err.to_string())
+            }
+            err @ FilterError::PendingTransactionStreamEnded => {
+                rpc_error_with_code(jsonrpsee::types::error::INTERNAL_ERROR_CODE, err.to_string())
+            }
+            err @ FilterError::Cancelled => {
+                rpc_error_with_code(jsonrpsee::types::error::INTERNAL_ERROR_CODE, err.to_string())
+            }
+            err @ FilterError::TooBusy => {
+                rpc_error_with_code(jsonrpsee::types::error::SERVER_IS_BUSY_CODE, err.to_string())
+            }
+            FilterError::EthAPIError(err) => err.into(),
+            err @ FilterError::QueryExceedsMaxResults(_) => {
+                rpc_error_with_code(jsonrpsee::types::error::INVALID_PARAMS_CODE, err.to_string())
+            }
+            err @ FilterError::QueryExceedsMaxResponseBytes(_) => {
+                rpc_error_with_code(jsonrpsee::types::error::INVALID_PARAMS_CODE, err.to_string())
+            }
+            err @ FilterError::InvalidFilter(_) => {
+                rpc_error_with_code(jsonrpsee::types::error::INVALID_PARAMS_CODE, err.to_string())
+            }
+            err @ FilterError::UnresolvableBlockTag(_) => {
+                rpc_error_with_code(jsonrpsee::types::error::INVALID_PARAMS_CODE, err.to_string())
+            }
+        }
+    }
+}
+
+impl From<RethError> for FilterError {
+    fn from(err: RethError) -> Self {
+        FilterError::EthAPIError(err.into())
+    }
+}
+
+/// A reorganization of the chain since a filter was last polled, see [detect_reorg].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ReorgInfo {
+    /// The most recent block that both the orphaned and the canonical chain contain, or `None` if
+    /// the ancestry of the orphaned blocks is no longer available.
+    common_ancestor: Option<BlockNumHash>,
+    /// The hashes of the orphaned blocks, from the old tip downwards.
+    orphaned: Vec<B256>,
+}
+
+/// Checks whether the given tip of the last poll is still part of the canonical chain.
+///
+/// If it isn't, the orphaned blocks are walked back by their parent hashes until a block of the
+/// canonical chain is found, the common ancestor. The walk gives up after `max_depth` orphaned
+/// blocks and reports the common ancestor as unknown.
+fn detect_reorg<Provider>(
+    provider: &Provider,
+    last_tip: BlockNumHash,
+    max_depth: usize,
+) -> Result<Option<ReorgInfo>, FilterError>
+where
+    Provider: BlockHashReader + HeaderProvider,
+{
+    let mut orphaned = Vec::new();
+    let mut block = last_tip;
+    let common_ancestor = loop {
+        if provider.block_hash(block.number)? == Some(block.hash) {
+            break Some(block)
+        }
+        if orphaned.len() == max_depth {
+            break None
+        }
+        orphaned.push(block.hash);
+
+        let Some(header) = provider.header(&block.hash)? else { break None };
+        if block.number == 0 {
+            break None
+        }
+        block = BlockNumHash::new(block.number - 1, header.parent_hash);
+    };
+
+    if orphaned.is_empty() {
+        return Ok(None)
+    }
+    Ok(Some(ReorgInfo { common_ancestor, orphaned }))
+}
+
+/// Awaits the future, unless the given token is cancelled first.
+async fn until_cancelled<F: Future>(
+    cancel: Option<&CancellationToken>,
+    fut: F,
+) -> Result<F::Output, FilterError> {
+    let Some(cancel) = cancel else { return Ok(fut.await) };
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(FilterError::Cancelled),
+        output = fut => Ok(output),
+    }
+}
+
+/// An iterator that yields _inclusive_ block ranges of a given step size
+#[derive(Debug)]
+struct BlockRangeInclusiveIter {
+    iter: StepBy<RangeInclusive<u64>>,
+    step: u64,
+    end: u64,
+}
+
+impl BlockRangeInclusiveIter {
+    fn new(range: RangeInclusive<u64>, step: u64) -> Self {
+        Self { end: *range.end(), iter: range.step_by(step as usize + 1), step }
+    }
+}
+
+impl Iterator for BlockRangeInclusiveIter {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.iter.next()?;
+        let end = (start + self.step).min(self.end);
+        if start > end {
+            return None
+        }
+        Some((start, end))
+    }
+}
+
+/// An iterator that yields _inclusive_ block ranges of a given step size in descending order,
+/// starting at the end of the range.
+#[derive(Debug)]
+struct BlockRangeInclusiveRevIter {
+    start: u64,
+    next_end: Option<u64>,
+    step: u64,
+}
+
+impl BlockRangeInclusiveRevIter {
+    fn new(range: RangeInclusive<u64>, step: u64) -> Self {
+        let next_end = (!range.is_empty()).then_some(*range.end());
+        Self { start: *range.start(), next_end, step }
+    }
+}
+
+impl Iterator for BlockRangeInclusiveRevIter {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let end = self.next_end?;
+        let start = end.saturating_sub(self.step).max(self.start);
+        self.next_end = (start > self.start).then(|| start - 1);
+        Some((start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_rlp::Decodable;
+    use rand::{thread_rng, Rng};
+    use reth_primitives::{
+        logs_bloom, Address, Block, Header, Signature, Transaction, TransactionSigned, TxEip1559,
+        TxLegacy, U128, U256,
+    };
+    use reth_provider::{test_utils::MockEthProvider, AccountReader, ReceiptProvider};
+    use reth_rpc_types::TRANSFER_EVENT_SIGNATURE;
+    use reth_tasks::TokioTaskExecutor;
+    use reth_transaction_pool::{
+        test_utils::{testing_pool, MockTransaction, MockTransactionFactory, TestPool},
+        TransactionOrigin,
+    };
+
+    fn test_filter_config() -> EthFilterConfig {
+        EthFilterConfig::new(10_000, Duration::from_secs(5 * 60))
+    }
+
+    fn build_test_eth_filter(
+        provider: MockEthProvider,
+        config: EthFilterConfig,
+    ) -> EthFilter<MockEthProvider, TestPool> {
+        let cache = EthStateCache::spawn(provider.clone(), Default::default());
+        EthFilter::with_config(
+            provider,
+            testing_pool(),
+            cache,
+            config,
+            Box::<TokioTaskExecutor>::default(),
+        )
+    }
+
+    fn mock_provider_with_head(number: u64) -> MockEthProvider {
+        let provider = MockEthProvider::default();
+        provider.add_header(B256::random(), Header { number, ..Default::default() });
+        provider
+    }
+
+    /// Returns a provider with the blocks `1..=num_blocks`, each with `logs_per_block`
+    /// transactions that emitted a single log with `data_len` bytes of data.
+    fn mock_provider_with_logs(
+        num_blocks: u64,
+        logs_per_block: usize,
+        data_len: usize,
+    ) -> MockEthProvider {
+        let provider = MockEthProvider::default();
+        let mut parent_hash = B256::ZERO;
+        for number in 1..=num_blocks {
+            let hash = B256::random();
+            let receipts: Vec<Receipt> = (0..logs_per_block)
+                .map(|_| {
+                    let log = reth_primitives::Log {
+                        address: Address::random(),
+                        topics: vec![B256::random()],
+                        data: vec![0xff; data_len].into(),
+                    };
+                    Receipt { logs: vec![log], ..Default::default() }
+                })
+                .collect();
+            let logs_bloom = logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs));
+            let header = Header { number, parent_hash, logs_bloom, ..Default::default() };
+            let body = vec![TransactionSigned::default(); logs_per_block];
+            provider.add_block(hash, Block { header, body, ..Default::default() });
+            provider.add_receipts(hash, receipts);
+            parent_hash = hash;
+        }
+        provider
+    }
+
+    #[tokio::test]
+    async fn test_install_transfer_filters() {
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), test_filter_config());
+
+        let filter = Filter::new().erc20_transfer(Some(Address::random()), None);
+        assert!(eth_filter.new_filter(filter).await.is_ok());
+
+        // topic1 can never match an address
+        let filter = Filter::new().event(TRANSFER_EVENT_SIGNATURE).topic1(B256::repeat_byte(0xff));
+        assert!(eth_filter.new_filter(filter).await.is_err());
+        assert_eq!(eth_filter.active_filters().inner.lock().await.len(), 1);
+    }
+
+    #[test]
+    fn test_block_range_iter() {
+        for _ in 0..100 {
+            let mut rng = thread_rng();
+            let start = rng.gen::<u32>() as u64;
+            let end = start.saturating_add(rng.gen::<u32>() as u64);
+            let step = rng.gen::<u16>() as u64;
+            let range = start..=end;
+            let mut iter = BlockRangeInclusiveIter::new(range.clone(), step);
+            let (from, mut end) = iter.next().unwrap();
+            assert_eq!(from, start);
+            assert_eq!(end, (from + step).min(*range.end()));
+
+            for (next_from, next_end) in iter {
+                // ensure range starts with previous end + 1
+                assert_eq!(next_from, end + 1);
+                end = next_end;
+            }
+
+            assert_eq!(end, *range.end());
+        }
+    }
+
+    #[test]
+    fn test_block_range_rev_iter() {
+        for _ in 0..100 {
+            let mut rng = thread_rng();
+            let start = rng.gen::<u32>() as u64;
+            let end = start.saturating_add(rng.gen::<u32>() as u64);
+            let step = rng.gen::<u16>() as u64;
+            let range = start..=end;
+            let mut iter = BlockRangeInclusiveRevIter::new(range.clone(), step);
+            let (mut start, to) = iter.next().unwrap();
+            assert_eq!(to, end);
+            assert_eq!(start, to.saturating_sub(step).max(*range.start()));
+
+            for (next_from, next_end) in iter {
+                // ensure range ends with previous start - 1
+                assert_eq!(next_end + 1, start);
+                start = next_from;
+            }
+
+            assert_eq!(start, *range.start());
+        }
+
+        assert_eq!(BlockRangeInclusiveRevIter::new(0..=0, 0).collect::<Vec<_>>(), [(0, 0)]);
+        assert_eq!(BlockRangeInclusiveRevIter::new(1..=0, 0).next(), None);
+    }
+
+    #[tokio::test]
+    async fn test_logs_newest_first() {
+        let config = EthFilterConfig::new(3, Duration::from_secs(5 * 60));
+        let eth_filter = build_test_eth_filter(mock_provider_with_logs(5, 2, 0), config);
+
+        let filter = Filter::new().from_block(1).to_block(5);
+        let logs = eth_filter.logs_newest_first(filter.clone()).await.unwrap();
+        let positions = logs
+            .iter()
+            .map(|log| (log.block_number.unwrap(), log.log_index.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                (U256::from(5), U256::from(1)),
+                (U256::from(5), U256::ZERO),
+                (U256::from(4), U256::from(1)),
+            ]
+        );
+
+        // the standard query exceeds the limit
+        assert!(matches!(
+            eth_filter.inner.logs_for_filter(&LogFilter::new(filter), append_logs, None).await,
+            Err(FilterError::QueryExceedsMaxResults(3))
+        ));
+
+        // fewer matches than the limit are all returned
+        let logs =
+            eth_filter.logs_newest_first(Filter::new().from_block(1).to_block(1)).await.unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].log_index, Some(U256::from(1)));
+    }
+
+    #[tokio::test]
+    async fn test_logs_newest_first_split_block() {
+        // the limit splits the second newest block
+        let config = EthFilterConfig::new(4, Duration::from_secs(5 * 60));
+        let eth_filter = build_test_eth_filter(mock_provider_with_logs(5, 3, 0), config);
+
+        let logs =
+            eth_filter.logs_newest_first(Filter::new().from_block(1).to_block(5)).await.unwrap();
+        let positions = logs
+            .iter()
+            .map(|log| (log.block_number.unwrap(), log.log_index.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                (U256::from(5), U256::from(2)),
+                (U256::from(5), U256::from(1)),
+                (U256::from(5), U256::ZERO),
+                (U256::from(4), U256::from(2)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unresolvable_block_tags() {
+        // the chain hasn't finalized any block yet
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(10), test_filter_config());
+
+        for tag in [BlockNumberOrTag::Finalized, BlockNumberOrTag::Safe] {
+            let filter = Filter::new().from_block(tag);
+            assert!(matches!(
+                eth_filter.inner.logs_for_filter(&LogFilter::new(filter), append_logs, None).await,
+                Err(FilterError::UnresolvableBlockTag(t)) if t == tag
+            ));
+
+            let filter = Filter::new().to_block(tag);
+            let id = eth_filter.new_filter(filter).await.unwrap();
+            assert!(matches!(
+                eth_filter.filter_changes(id).await,
+                Err(FilterError::UnresolvableBlockTag(t)) if t == tag
+            ));
+        }
+
+        // unset blocks default to the latest block
+        assert!(eth_filter
+            .inner
+            .logs_for_filter(&LogFilter::new(Filter::new()), append_logs, None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pending_transactions_per_poll() {
+        let config = test_filter_config().max_pending_transactions_per_poll(2);
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), config);
+
+        let (tx, rx) = tokio::sync::mpsc::channel(8);
+        let id = eth_filter
+            .inner
+            .install_filter(
+                FilterKind::PendingTransaction(PendingTransactionsReceiver::new(rx)),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let hashes = (0..5).map(|_| B256::random()).collect::<Vec<_>>();
+        for hash in &hashes {
+            tx.send(*hash).await.unwrap();
+        }
+
+        // the transactions are delivered in order over multiple polls
+        for chunk in hashes.chunks(2).chain([&[][..]]) {
+            assert_eq!(
+                eth_filter.filter_changes(id.clone()).await.unwrap(),
+                FilterChanges::Hashes(chunk.to_vec())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_transactions_fanout() {
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), test_filter_config());
+
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(
+                EthFilterApiServer::new_pending_transaction_filter(&eth_filter).await.unwrap(),
+            );
+        }
+        // all filters share a single listener of the pool
+        let subscribers = eth_filter.inner.pending_txs_fanout.subscribers.clone();
+        assert_eq!(
+            subscribers.lock().unwrap().as_ref().map(|subscribers| subscribers.senders.len()),
+            Some(3)
+        );
+
+        let mut tx_factory = MockTransactionFactory::default();
+        let mut hashes = Vec::new();
+        for _ in 0..3 {
+            let transaction = tx_factory.create_eip1559().transaction;
+            hashes.push(
+                eth_filter
+                    .inner
+                    .pool
+                    .add_transaction(TransactionOrigin::External, transaction)
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        // every filter receives all transactions
+        for id in &ids {
+            let mut received = Vec::new();
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while received.len() < hashes.len() {
+                    let FilterChanges::Hashes(new_hashes) =
+                        eth_filter.filter_changes(id.clone()).await.unwrap()
+                    else {
+                        panic!("expected hashes")
+                    };
+                    received.extend(new_hashes);
+                    tokio::task::yield_now().await;
+                }
+            })
+            .await
+            .unwrap();
+            assert_eq!(received, hashes);
+        }
+
+        // the listener is released once all filters are uninstalled
+        for id in ids {
+            assert!(EthFilterApiServer::uninstall_filter(&eth_filter, id).await.unwrap());
+        }
+        assert!(subscribers.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pending_transactions_fanout_stops_without_new_transactions() {
+        let fanout = PendingTransactionsFanout::default();
+        let (pool_tx, pool_rx) = mpsc::channel(1);
+        let mut pool_rx = Some(pool_rx);
+        let receivers = (0..2)
+            .map(|_| {
+                PendingTransactionsReceiver::subscribed(
+                    fanout.subscribe(|| pool_rx.take().unwrap(), &TokioTaskExecutor::default()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        drop(receivers);
+        assert!(fanout.subscribers.lock().unwrap().is_none());
+        // the task drops the listener even though the pool never sends another transaction
+        tokio::time::timeout(Duration::from_secs(5), pool_tx.closed()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pending_transaction_criteria() {
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), test_filter_config());
+
+        let all = EthFilterApiServer::new_pending_transaction_filter(&eth_filter).await.unwrap();
+        let tx_a = MockTransaction::eip1559();
+        let by_sender = eth_filter
+            .new_pending_transaction_filter_with_criteria(
+                PendingTransactionCriteria::default().sender(tx_a.sender()),
+            )
+            .await
+            .unwrap();
+        let by_gas_price = eth_filter
+            .new_pending_transaction_filter_with_criteria(
+                PendingTransactionCriteria::default().min_gas_price(50),
+            )
+            .await
+            .unwrap();
+
+        // `tx_a` and `tx_c` are from the same sender, only `tx_b` pays at least the gas price
+        let tx_b = MockTransaction::eip1559().with_max_fee(100);
+        let tx_c = tx_a.next();
+        let mut hashes = Vec::new();
+        for transaction in [tx_a, tx_b, tx_c] {
+            hashes.push(
+                eth_filter
+                    .inner
+                    .pool
+                    .add_transaction(TransactionOrigin::External, transaction)
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        // once the unfiltered filter received all transactions, so did the others
+        let mut received = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while received.len() < hashes.len() {
+                let FilterChanges::Hashes(new_hashes) =
+                    eth_filter.filter_changes(all.clone()).await.unwrap()
+                else {
+                    panic!("expected hashes")
+                };
+                received.extend(new_hashes);
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(received, hashes);
+
+        assert_eq!(
+            eth_filter.filter_changes(by_sender).await.unwrap(),
+            FilterChanges::Hashes(vec![hashes[0], hashes[2]])
+        );
+        assert_eq!(
+            eth_filter.filter_changes(by_gas_price).await.unwrap(),
+            FilterChanges::Hashes(vec![hashes[1]])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pending_transaction_stream_ended() {
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), test_filter_config());
+
+        let (tx, rx) = tokio::sync::mpsc::channel(8);
+        let id = eth_filter
+            .inner
+            .install_filter(
+                FilterKind::PendingTransaction(PendingTransactionsReceiver::new(rx)),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let hash = B256::random();
+        tx.send(hash).await.unwrap();
+        drop(tx);
+
+        // transactions received before the pool went away are still returned
+        assert!(matches!(
+            eth_filter.filter_changes(id.clone()).await,
+            Ok(FilterChanges::Hashes(hashes)) if hashes == vec![hash]
+        ));
+        assert!(matches!(
+            eth_filter.filter_changes(id).await,
+            Err(FilterError::PendingTransactionStreamEnded)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_clear_stale_filters_stats() {
+        let ttl = Duration::from_secs(60);
+        let config = EthFilterConfig::new(10_000, ttl);
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), config);
+
+        for _ in 0..3 {
+            eth_filter.new_block_filter().await.unwrap();
+        }
+        let now = Instant::now();
+        {
+            let mut filters = eth_filter.active_filters().inner.lock().await;
+            for (filter, age) in filters.values_mut().zip([10, 20, 120]) {
+                filter.last_poll_timestamp = now - Duration::from_secs(age);
+            }
+        }
+
+        let stats = eth_filter.clear_stale_filters(now).await;
+        assert_eq!(
+            stats,
+            StaleFilterStats {
+                examined: 3,
+                evicted: 1,
+                oldest_surviving_age: Some(Duration::from_secs(20)),
+            }
+        );
+        assert_eq!(eth_filter.active_filters().inner.lock().await.len(), 2);
+
+        // nothing left to evict
+        let stats = eth_filter.clear_stale_filters(now).await;
+        assert_eq!((stats.examined, stats.evicted), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_pause_stale_cleanup() {
+        let ttl = Duration::from_millis(50);
+        let eth_filter =
+            build_test_eth_filter(mock_provider_with_head(0), EthFilterConfig::new(10_000, ttl));
+        eth_filter.pause_stale_cleanup();
+        assert!(eth_filter.is_stale_cleanup_paused());
+        let id = eth_filter.new_block_filter().await.unwrap();
+
+        // several sweeps are skipped although the filter is stale
+        tokio::time::sleep(ttl * 5).await;
+        assert!(eth_filter.active_filters().inner.lock().await.contains_key(&id));
+        assert!(eth_filter.health().await.stale_filter_cleaner_alive);
+
+        // the next sweep evicts the filter
+        eth_filter.resume_stale_cleanup();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while eth_filter.active_filters().inner.lock().await.contains_key(&id) {
+                tokio::time::sleep(ttl).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evict_filters_without_matches() {
+        let config = test_filter_config().max_polls_without_matches(3);
+        let eth_filter = build_test_eth_filter(mock_provider_with_logs(3, 1, 0), config);
+
+        let matching = eth_filter.new_filter(Filter::new()).await.unwrap();
+        let never_matching =
+            eth_filter.new_filter(Filter::new().address(Address::random())).await.unwrap();
+        let blocks = eth_filter.new_block_filter().await.unwrap();
+
+        for _ in 0..2 {
+            for id in [&matching, &never_matching, &blocks] {
+                eth_filter.filter_changes(id.clone()).await.unwrap();
+            }
+        }
+        assert_eq!(eth_filter.clear_stale_filters(Instant::now()).await.evicted, 0);
+
+        for id in [&matching, &never_matching, &blocks] {
+            eth_filter.filter_changes(id.clone()).await.unwrap();
+        }
+        assert_eq!(eth_filter.clear_stale_filters(Instant::now()).await.evicted, 1);
+
+        let filters = eth_filter.active_filters().inner.lock().await;
+        assert!(filters.contains_key(&matching));
+        assert!(!filters.contains_key(&never_matching));
+        assert!(filters.contains_key(&blocks));
+    }
+
+    #[tokio::test]
+    async fn test_eviction_callback() {
+        let ttl = Duration::from_secs(60);
+        let config = EthFilterConfig::new(10_000, ttl);
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), config);
+
+        let (evicted_tx, mut evicted_rx) = tokio::sync::mpsc::unbounded_channel();
+        let stale = {
+            let evicted_tx = evicted_tx.clone();
+            eth_filter
+                .new_block_filter_with_eviction_callback(move |id| evicted_tx.send(id).unwrap())
+                .await
+                .unwrap()
+        };
+        let active = eth_filter
+            .new_filter_with_eviction_callback(Filter::new(), move |id| {
+                evicted_tx.send(id).unwrap()
+            })
+            .await
+            .unwrap();
+
+        let now = Instant::now();
+        eth_filter
+            .active_filters()
+            .inner
+            .lock()
+            .await
+            .get_mut(&stale)
+            .unwrap()
+            .last_poll_timestamp = now - ttl;
+
+        let stats = eth_filter.clear_stale_filters(now).await;
+        assert_eq!(stats.evicted, 1);
+        assert_eq!(evicted_rx.try_recv(), Ok(stale));
+        assert!(evicted_rx.try_recv().is_err());
+
+        // uninstalling doesn't notify
+        assert!(eth_filter.uninstall_filter(active).await.unwrap());
+        assert!(evicted_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(1), test_filter_config());
+        let health = eth_filter.health().await;
+        assert_eq!(health.active_filters, 0);
+        assert_eq!(health.oldest_filter_age, None);
+        assert!(health.pending_transactions_connected);
+
+        // the cleaner task runs once it is scheduled
+        tokio::task::yield_now().await;
+        assert!(eth_filter.health().await.stale_filter_cleaner_alive);
+
+        let log_filter = eth_filter.new_filter(Filter::new()).await.unwrap();
+        let block_filter = eth_filter.new_block_filter().await.unwrap();
+        eth_filter.new_pending_transaction_filter().await.unwrap();
+        let health = eth_filter.health().await;
+        assert_eq!(health.active_filters, 3);
+        assert!(health.oldest_filter_age.is_some());
+        assert!(health.pending_transactions_connected);
+
+        assert!(eth_filter.uninstall_filter(log_filter).await.unwrap());
+        assert_eq!(eth_filter.health().await.active_filters, 2);
+        assert!(eth_filter.uninstall_filter(block_filter.clone()).await.unwrap());
+        assert!(!eth_filter.uninstall_filter(block_filter).await.unwrap());
+        assert_eq!(eth_filter.health().await.active_filters, 1);
+    }
+
+    #[tokio::test]
+    async fn test_max_response_bytes() {
+        let provider = mock_provider_with_logs(2, 1, 2048);
+
+        // far below the count limit, but each log exceeds the byte limit
+        let config = test_filter_config().max_response_bytes(4096);
+        let eth_filter = build_test_eth_filter(provider.clone(), config);
+        assert!(matches!(
+            eth_filter
+                .inner
+                .get_logs_in_block_range(
+                    &LogFilter::new(Filter::new()),
+                    1,
+                    2,
+                    append_logs,
+                    None,
+                    None
+                )
+                .await,
+            Err(FilterError::QueryExceedsMaxResponseBytes(4096))
+        ));
+
+        // all logs of a single block are always returned
+        let logs = eth_filter
+            .inner
+            .get_logs_in_block_range(&LogFilter::new(Filter::new()), 2, 2, append_logs, None, None)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+        let logs = eth_filter
+            .inner
+            .get_logs_in_block_range(&LogFilter::new(Filter::new()), 1, 2, append_logs, None, None)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_first_log_per_block() {
+        let eth_filter =
+            build_test_eth_filter(mock_provider_with_logs(3, 3, 0), test_filter_config());
+
+        let filter = Filter::new().from_block(1).to_block(3);
+        let logs = eth_filter.first_log_per_block(filter.clone()).await.unwrap();
+        assert_eq!(logs.len(), 3);
+        for (log, number) in logs.iter().zip(1u64..) {
+            assert_eq!(log.block_number, Some(U256::from(number)));
+            assert_eq!(log.log_index, Some(U256::ZERO));
+        }
+
+        // the standard query returns all logs
+        let logs =
+            eth_filter.inner.logs_for_filter(&LogFilter::new(filter), append_logs, None).await;
+        assert_eq!(logs.unwrap().len(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_indexed_logs() {
+        let eth_filter =
+            build_test_eth_filter(mock_provider_with_logs(3, 3, 0), test_filter_config());
+
+        let filter = Filter::new().from_block(1).to_block(3);
+        let logs = eth_filter.indexed_logs(filter.clone()).await.unwrap();
+        assert_eq!(logs.len(), 9);
+        for (log, index) in logs.iter().zip(0u64..) {
+            assert_eq!(log.result_index, U256::from(index));
+            assert_eq!(log.log.block_number, Some(U256::from(index / 3 + 1)));
+            assert_eq!(log.log.log_index, Some(U256::from(index % 3)));
+        }
+
+        // the indices start over in every query
+        let logs = eth_filter.indexed_logs(filter.from_block(3)).await.unwrap();
+        let indices = logs.iter().map(|log| log.result_index).collect::<Vec<_>>();
+        assert_eq!(indices, [U256::ZERO, U256::from(1), U256::from(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_logs_from_index() {
+        let eth_filter =
+            build_test_eth_filter(mock_provider_with_logs(3, 3, 0), test_filter_config());
+
+        let filter = Filter::new().from_block(1).to_block(3);
+        let indexed = eth_filter.indexed_logs(filter.clone()).await.unwrap();
+
+        // resuming mid-block returns the suffix of the full result
+        let logs = eth_filter.logs_from_index(filter.clone(), 4).await.unwrap();
+        let expected = indexed[4..].iter().map(|log| log.log.clone()).collect::<Vec<_>>();
+        assert_eq!(logs, expected);
+        assert_eq!(logs[0].block_number, Some(U256::from(2)));
+        assert_eq!(logs[0].log_index, Some(U256::from(1)));
+
+        assert!(eth_filter.logs_from_index(filter.clone(), 9).await.unwrap().is_empty());
+        assert!(eth_filter.logs_from_index(filter.clone(), 100).await.unwrap().is_empty());
+
+        // the skipped logs don't count towards the response limit
+        let eth_filter = build_test_eth_filter(
+            mock_provider_with_logs(3, 3, 0),
+            EthFilterConfig::new(5, Duration::from_secs(5 * 60)),
+        );
+        assert!(matches!(
+            eth_filter.logs_from_index(filter.clone(), 3).await,
+            Err(FilterError::QueryExceedsMaxResults(5))
+        ));
+        let logs = eth_filter.logs_from_index(filter, 4).await.unwrap();
+        assert_eq!(logs, expected);
+    }
+
+    #[tokio::test]
+    async fn test_side_chain_block_logs() {
+        let provider = mock_provider_with_logs(2, 2, 0);
+        let canonical_hash = provider.block_hash(2).unwrap().unwrap();
+
+        // a block of a side chain at the height of the canonical head
+        let receipts = vec![Receipt {
+            logs: vec![reth_primitives::Log {
+                address: Address::random(),
+                topics: vec![B256::random()],
+                data: Default::default(),
+            }],
+            ..Default::default()
+        }];
+        let header = Header {
+            number: 2,
+            logs_bloom: logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs)),
+            ..Default::default()
+        };
+        let side_chain_hash = B256::random();
+        let body = vec![TransactionSigned::default()];
+        provider.add_side_chain_block(
+            side_chain_hash,
+            Block { header, body, ..Default::default() },
+            receipts,
+        );
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+
+        let logs_at = |hash: B256| {
+            let filter = LogFilter::new(Filter::new().at_block_hash(hash));
+            let eth_filter = eth_filter.clone();
+            async move { eth_filter.inner.logs_for_filter(&filter, append_logs, None).await }
+        };
+
+        let logs = logs_at(canonical_hash).await.unwrap();
+        assert_eq!(logs.len(), 2);
+        assert!(logs.iter().all(|log| !log.removed));
+
+        // the logs of the side chain block are flagged as removed
+        let logs = logs_at(side_chain_hash).await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].removed);
+        assert_eq!(logs[0].block_hash, Some(side_chain_hash));
+        assert_eq!(logs[0].block_number, Some(U256::from(2)));
+
+        assert_eq!(logs_at(B256::random()).await.unwrap(), Vec::new());
+    }
+
+    #[tokio::test]
+    async fn test_logs_with_effective_gas_price() {
+        let provider = MockEthProvider::default();
+        let body = [
+            Transaction::Legacy(TxLegacy { gas_price: 20, ..Default::default() }),
+            // the priority fee is capped by the max fee
+            Transaction::Eip1559(TxEip1559 {
+                max_fee_per_gas: 30,
+                max_priority_fee_per_gas: 5,
+                ..Default::default()
+            }),
+            Transaction::Eip1559(TxEip1559 {
+                max_fee_per_gas: 12,
+                max_priority_fee_per_gas: 5,
+                ..Default::default()
+            }),
+        ]
+        .map(|tx| TransactionSigned::from_transaction_and_signature(tx, Signature::default()));
+        let receipts = body
+            .iter()
+            .map(|_| {
+                let log = reth_primitives::Log {
+                    address: Address::random(),
+                    topics: vec![B256::random()],
+                    data: Default::default(),
+                };
+                Receipt { logs: vec![log], ..Default::default() }
+            })
+            .collect::<Vec<_>>();
+        let logs_bloom = logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs));
+        let header =
+            Header { number: 1, base_fee_per_gas: Some(10), logs_bloom, ..Default::default() };
+        let hash = B256::random();
+        provider.add_block(hash, Block { header, body: body.to_vec(), ..Default::default() });
+        provider.add_receipts(hash, receipts);
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+
+        let filter = Filter::new().from_block(1).to_block(1);
+        let logs = eth_filter.logs_with_effective_gas_price(filter).await.unwrap();
+        let gas_prices = logs
+            .iter()
+            .map(|log| (log.log.transaction_hash.unwrap(), log.effective_gas_price))
+            .collect::<Vec<_>>();
+        let expected =
+            body.iter().map(TransactionSigned::hash).zip([20u64, 15, 12].map(U128::from));
+        assert_eq!(gas_prices, expected.collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_logs_since_tx() {
+        // three blocks with three transactions that emit a log each
+        let provider = MockEthProvider::default();
+        let mut parent_hash = B256::ZERO;
+        let mut tx_hashes = Vec::new();
+        for number in 1..=3u64 {
+            let body = (0..3)
+                .map(|idx| {
+                    let tx = TxLegacy { nonce: number * 3 + idx, ..Default::default() };
+                    TransactionSigned::from_transaction_and_signature(
+                        Transaction::Legacy(tx),
+                        Signature::default(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            tx_hashes.push(body.iter().map(|tx| tx.hash()).collect::<Vec<_>>());
+            let receipts: Vec<Receipt> = body
+                .iter()
+                .map(|_| {
+                    let log = reth_primitives::Log {
+                        address: Address::random(),
+                        topics: vec![B256::random()],
+                        data: Default::default(),
+                    };
+                    Receipt { logs: vec![log], ..Default::default() }
+                })
+                .collect();
+            let logs_bloom = logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs));
+            let header = Header { number, parent_hash, logs_bloom, ..Default::default() };
+            let hash = B256::random();
+            provider.add_block(hash, Block { header, body, ..Default::default() });
+            provider.add_receipts(hash, receipts);
+            parent_hash = hash;
+        }
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+        let positions = |logs: Vec<Log>| {
+            logs.into_iter()
+                .map(|log| (log.block_number.unwrap(), log.transaction_index.unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        // the logs continue right after the anchor transaction
+        let logs = eth_filter.logs_since_tx(Filter::new(), tx_hashes[1][0]).await.unwrap();
+        assert_eq!(logs[0].transaction_hash, Some(tx_hashes[1][1]));
+        assert_eq!(
+            positions(logs),
+            [(2, 1), (2, 2), (3, 0), (3, 1), (3, 2)]
+                .map(|(block, idx)| (U256::from(block), U256::from(idx)))
+        );
+
+        // the range ends at the filter's `toBlock`
+        let logs = eth_filter.logs_since_tx(Filter::new().to_block(2), tx_hashes[0][2]).await;
+        assert_eq!(
+            positions(logs.unwrap()),
+            [(2, 0), (2, 1), (2, 2)].map(|(block, idx)| (U256::from(block), U256::from(idx)))
+        );
+
+        // nothing follows the last transaction
+        let logs = eth_filter.logs_since_tx(Filter::new(), tx_hashes[2][2]).await.unwrap();
+        assert!(logs.is_empty());
+
+        assert!(matches!(
+            eth_filter.logs_since_tx(Filter::new(), B256::random()).await,
+            Err(FilterError::EthAPIError(EthApiError::TransactionNotFound))
+        ));
+        assert!(matches!(
+            eth_filter
+                .logs_since_tx(Filter::new().at_block_hash(B256::random()), tx_hashes[0][0])
+                .await,
+            Err(FilterError::InvalidFilter(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_combined_logs() {
+        let provider = mock_provider_with_logs(3, 2, 0);
+        let first_block_logs = provider.receipts_by_block(1.into()).unwrap().unwrap();
+        let last_block_logs = provider.receipts_by_block(3.into()).unwrap().unwrap();
+        let first_address = first_block_logs[0].logs[0].address;
+        let last_address = last_block_logs[1].logs[0].address;
+
+        let config = EthFilterConfig::new(3, Duration::from_secs(5 * 60));
+        let eth_filter = build_test_eth_filter(provider, config);
+
+        let filters = vec![
+            Filter::new().address(first_address),
+            Filter::new().address(Address::random()),
+            Filter::new().address(vec![first_address, last_address]),
+        ];
+        let logs = eth_filter.combined_logs(filters, 1, 3).await.unwrap();
+        assert_eq!(logs.len(), 2);
+
+        let (idx, first) = &logs[0];
+        assert_eq!(*idx, 0);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].address, first_address);
+        assert_eq!(first[0].block_number, Some(U256::from(1)));
+
+        let (idx, both) = &logs[1];
+        assert_eq!(*idx, 2);
+        assert_eq!(
+            both.iter().map(|log| log.address).collect::<Vec<_>>(),
+            [first_address, last_address]
+        );
+
+        // the limit applies to each filter on its own
+        let filters = vec![Filter::new().address(first_address), Filter::new()];
+        assert!(matches!(
+            eth_filter.combined_logs(filters, 1, 3).await,
+            Err(FilterError::QueryExceedsMaxResults(3))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_combined_logs_cancellation() {
+        let provider = mock_provider_with_logs(3, 2, 0);
+        let first_block_logs = provider.receipts_by_block(1.into()).unwrap().unwrap();
+        let last_block_logs = provider.receipts_by_block(3.into()).unwrap().unwrap();
+        let first_address = first_block_logs[0].logs[0].address;
+        let last_address = last_block_logs[1].logs[0].address;
+
+        // the retry backoff pauses the scan after it started
+        let config = EthFilterConfig::new(3, Duration::from_secs(5 * 60))
+            .log_scan_retry(LogScanRetryPolicy::new(1, Duration::from_millis(100)));
+        let eth_filter = build_test_eth_filter(provider.clone(), config);
+        provider.fail_headers_range(RethError::Database(DatabaseError::Read(-30778)));
+
+        // the second filter exceeds the limit unless it is dropped
+        let cancels = (0..3).map(|_| CancellationToken::new()).collect::<Vec<_>>();
+        let filters = vec![
+            Filter::new().address(first_address),
+            Filter::new(),
+            Filter::new().address(last_address),
+        ];
+        let filter = eth_filter.clone();
+        let query_filters = filters.iter().cloned().zip(cancels.iter().cloned()).collect();
+        let query = tokio::spawn(async move {
+            filter.combined_logs_with_cancellation(query_filters, 1, 3).await
+        });
+        while !provider.headers_range_errors.lock().is_empty() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        cancels[1].cancel();
+
+        let logs = tokio::time::timeout(Duration::from_secs(5), query).await.unwrap().unwrap();
+        let logs = logs.unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].0, 0);
+        assert_eq!(logs[0].1.iter().map(|log| log.address).collect::<Vec<_>>(), [first_address]);
+        assert_eq!(logs[1].0, 2);
+        assert_eq!(logs[1].1.iter().map(|log| log.address).collect::<Vec<_>>(), [last_address]);
+
+        // nothing is returned once all filters are cancelled
+        let filters = filters.into_iter().zip(cancels).collect::<Vec<_>>();
+        filters[0].1.cancel();
+        filters[2].1.cancel();
+        assert!(eth_filter
+            .combined_logs_with_cancellation(filters, 1, 3)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_logs_of_self_destructed_contract() {
+        // the contract emitted a log and self-destructed in the same block, so it no longer exists
+        // in the state after the block
+        let provider = mock_provider_with_logs(2, 1, 0);
+        let receipts = provider.receipts_by_block(2.into()).unwrap().unwrap();
+        let destroyed = receipts[0].logs[0].address;
+        assert_eq!(provider.basic_account(destroyed).unwrap(), None);
+
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+        let filter = Filter::new().address(destroyed).from_block(1).to_block(2);
+        let logs = eth_filter
+            .inner
+            .logs_for_filter(&LogFilter::new(filter), append_logs, None)
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].address, destroyed);
+        assert_eq!(logs[0].block_number, Some(U256::from(2)));
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_event_logs() {
+        let contract = Address::random();
+        let event = B256::random();
+        let log =
+            |topics| reth_primitives::Log { address: contract, topics, data: Default::default() };
+        let receipts =
+            vec![Receipt { logs: vec![log(vec![]), log(vec![event])], ..Default::default() }];
+        let header = Header {
+            number: 1,
+            logs_bloom: logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs)),
+            ..Default::default()
+        };
+        let provider = MockEthProvider::default();
+        let hash = B256::random();
+        let body = vec![TransactionSigned::default()];
+        provider.add_block(hash, Block { header, body, ..Default::default() });
+        provider.add_receipts(hash, receipts);
+        let eth_filter = &build_test_eth_filter(provider, test_filter_config());
+
+        let topic_counts = |filter: Filter| async move {
+            let filter = LogFilter::new(filter.from_block(1u64).to_block(1u64));
+            let logs = eth_filter.inner.logs_for_filter(&filter, append_logs, None).await;
+            logs.unwrap().into_iter().map(|log| log.topics.len()).collect::<Vec<_>>()
+        };
+
+        // filters without topic constraints match the log without topics
+        assert_eq!(topic_counts(Filter::new()).await, [0, 1]);
+        assert_eq!(topic_counts(Filter::new().address(contract)).await, [0, 1]);
+        // a constrained topic requires the log to have it
+        assert_eq!(topic_counts(Filter::new().event_signature(event)).await, [1]);
+        assert_eq!(topic_counts(Filter::new().topic1(event)).await, Vec::<usize>::new());
+    }
+
+    #[tokio::test]
+    async fn test_exact_topic_count() {
+        let event = B256::random();
+        let log = |num_topics| reth_primitives::Log {
+            address: Address::random(),
+            topics: std::iter::once(event).chain((1..num_topics).map(|_| B256::random())).collect(),
+            data: Default::default(),
+        };
+        let receipts = vec![Receipt {
+            logs: vec![log(1), log(2), log(3), log(2), log(4)],
+            ..Default::default()
+        }];
+        let header = Header {
+            number: 1,
+            logs_bloom: logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs)),
+            ..Default::default()
+        };
+        let provider = MockEthProvider::default();
+        let hash = B256::random();
+        let body = vec![TransactionSigned::default()];
+        provider.add_block(hash, Block { header, body, ..Default::default() });
+        provider.add_receipts(hash, receipts);
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+
+        let filter = Filter::new().from_block(1u64).to_block(1u64).event_signature(event);
+        assert_eq!(eth_filter.logs(filter.clone()).await.unwrap().len(), 5);
+
+        let options = LogFilterOptions::default().exact_topic_count(2);
+        let logs = eth_filter.logs_with_options(filter, options).await.unwrap();
+        assert_eq!(logs.iter().map(|log| log.topics.len()).collect::<Vec<_>>(), [2, 2]);
+        assert_eq!(
+            logs.iter().map(|log| log.log_index).collect::<Vec<_>>(),
+            [Some(U256::from(1)), Some(U256::from(3))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transaction_success() {
+        let log = || reth_primitives::Log {
+            address: Address::random(),
+            topics: vec![B256::random()],
+            data: Default::default(),
+        };
+        // a reverted transaction can't emit logs on mainnet, but the filter doesn't assume it
+        let receipts = vec![
+            Receipt { success: true, logs: vec![log(), log()], ..Default::default() },
+            Receipt { success: false, logs: vec![log()], ..Default::default() },
+            Receipt { success: true, logs: vec![log()], ..Default::default() },
+        ];
+        let header = Header {
+            number: 1,
+            logs_bloom: logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs)),
+            ..Default::default()
+        };
+        let provider = MockEthProvider::default();
+        let hash = B256::random();
+        let body = vec![TransactionSigned::default(); 3];
+        provider.add_block(hash, Block { header, body, ..Default::default() });
+        provider.add_receipts(hash, receipts);
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+
+        let filter = Filter::new().from_block(1u64).to_block(1u64);
+        assert_eq!(eth_filter.logs(filter.clone()).await.unwrap().len(), 4);
+
+        let positions = |logs: Vec<Log>| {
+            logs.into_iter()
+                .map(|log| (log.transaction_index.unwrap(), log.log_index.unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let options = LogFilterOptions::default().transaction_success(true);
+        let logs = eth_filter.logs_with_options(filter.clone(), options).await.unwrap();
+        assert_eq!(
+            positions(logs),
+            [(0, 0), (0, 1), (2, 3)].map(|(tx, log)| (U256::from(tx), U256::from(log)))
+        );
+        let options = LogFilterOptions::default().transaction_success(false);
+        let logs = eth_filter.logs_with_options(filter, options).await.unwrap();
+        assert_eq!(positions(logs), [(U256::from(1), U256::from(2))]);
+    }
+
+    #[tokio::test]
+    async fn test_raw_logs_roundtrip() {
+        let eth_filter =
+            build_test_eth_filter(mock_provider_with_logs(3, 2, 4), test_filter_config());
+        let filter = Filter::new().from_block(1u64).to_block(3u64);
+
+        let encoded = eth_filter.raw_logs(filter.clone()).await.unwrap();
+        let raw_logs = Vec::<RawBlockLog>::decode(&mut encoded.as_ref()).unwrap();
+        assert_eq!(raw_logs.len(), 6);
+        assert_eq!(raw_logs.length(), encoded.len());
+
+        let logs = eth_filter.logs(filter).await.unwrap();
+        for (raw, log) in raw_logs.into_iter().zip(logs) {
+            assert_eq!(raw.log.address, log.address);
+            assert_eq!(raw.log.topics, log.topics);
+            assert_eq!(raw.log.data, log.data);
+            assert_eq!(Some(raw.block_hash), log.block_hash);
+            assert_eq!(Some(U256::from(raw.block_number)), log.block_number);
+            assert_eq!(Some(raw.transaction_hash), log.transaction_hash);
+            assert_eq!(Some(U256::from(raw.transaction_index)), log.transaction_index);
+            assert_eq!(Some(U256::from(raw.log_index)), log.log_index);
+            assert_eq!(raw.removed, log.removed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_scan_retry() {
+        let provider = mock_provider_with_logs(10, 1, 0);
+        let filter = Filter::new().from_block(1u64).to_block(10u64);
+        // MDBX_BUSY
+        let transient = || RethError::Database(DatabaseError::Read(-30778));
+
+        // a single failed read aborts the scan by default
+        provider.fail_headers_range(transient());
+        let eth_filter = build_test_eth_filter(provider.clone(), test_filter_config());
+        assert!(eth_filter.logs(filter.clone()).await.is_err());
+
+        let config = test_filter_config()
+            .log_scan_retry(LogScanRetryPolicy::new(2, Duration::from_millis(1)));
+        let eth_filter = build_test_eth_filter(provider.clone(), config);
+        provider.fail_headers_range(transient());
+        assert_eq!(eth_filter.logs(filter.clone()).await.unwrap().len(), 10);
+
+        // the error is returned once the retries are exhausted
+        for _ in 0..3 {
+            provider.fail_headers_range(transient());
+        }
+        assert!(eth_filter.logs(filter.clone()).await.is_err());
+        assert!(provider.headers_range_errors.lock().is_empty());
+
+        // permanent errors are not retried
+        provider.fail_headers_range(RethError::Custom("corrupted header".to_string()));
+        provider.fail_headers_range(transient());
+        assert!(eth_filter.logs(filter).await.is_err());
+        assert_eq!(provider.headers_range_errors.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_earliest_block_tag() {
+        let earliest_logs = |config: EthFilterConfig| async move {
+            let eth_filter = build_test_eth_filter(mock_provider_with_logs(5, 1, 0), config);
+            let range = eth_filter.inner.filter_block_range(Some(BlockNumberOrTag::Earliest), None);
+            let filter = LogFilter::new(Filter::new().from_block(BlockNumberOrTag::Earliest));
+            let logs = eth_filter.inner.logs_for_filter(&filter, append_logs, None).await.unwrap();
+            let blocks = logs.into_iter().filter_map(|log| log.block_number).collect::<Vec<_>>();
+            (range.unwrap(), blocks)
+        };
+        let blocks = |range: RangeInclusive<u64>| range.map(U256::from).collect::<Vec<_>>();
+
+        // full history
+        assert_eq!(earliest_logs(test_filter_config()).await, ((0, 5), blocks(1..=5)));
+
+        // the scan starts at the lowest block whose receipts are kept
+        let config = test_filter_config().receipts_prune_mode(PruneMode::Before(3));
+        assert_eq!(earliest_logs(config).await, ((3, 5), blocks(3..=5)));
+        let config = test_filter_config().receipts_prune_mode(PruneMode::Distance(1));
+        assert_eq!(earliest_logs(config).await, ((4, 5), blocks(4..=5)));
+
+        // explicit block numbers below the pruned range are clamped as well
+        let config = test_filter_config().receipts_prune_mode(PruneMode::Before(3));
+        let eth_filter = build_test_eth_filter(mock_provider_with_logs(5, 1, 0), config);
+        let range = eth_filter.inner.filter_block_range(Some(1u64.into()), Some(2u64.into()));
+        assert_eq!(range.unwrap(), (3, 2));
+    }
+
+    #[tokio::test]
+    async fn test_log_scan_permits() {
+        let config = test_filter_config().max_concurrent_log_scans(1);
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), config);
+
+        let permit = eth_filter.inner.acquire_log_scan_permit().await.unwrap();
+        assert!(permit.is_some());
+        assert!(matches!(
+            eth_filter.inner.acquire_log_scan_permit().await,
+            Err(FilterError::TooBusy)
+        ));
+        assert!(matches!(
+            eth_filter
+                .inner
+                .get_logs_in_block_range(
+                    &LogFilter::new(Filter::new()),
+                    0,
+                    0,
+                    append_logs,
+                    None,
+                    None
+                )
+                .await,
+            Err(FilterError::TooBusy)
+        ));
+
+        drop(permit);
+        assert!(eth_filter.inner.acquire_log_scan_permit().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_log_scan_permits_zero_limit() {
+        let config = test_filter_config().max_concurrent_log_scans(0);
+        assert_eq!(config.max_concurrent_log_scans, Some(1));
+
+        // a zero limit set on the field directly still lets one scan run
+        let config = EthFilterConfig { max_concurrent_log_scans: Some(0), ..test_filter_config() };
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), config);
+        let permit = tokio::time::timeout(
+            Duration::from_secs(5),
+            eth_filter.inner.acquire_log_scan_permit(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(permit.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_log_scan_permits_queue_timeout() {
+        let config = test_filter_config()
+            .max_concurrent_log_scans(1)
+            .log_scan_queue_timeout(Duration::from_millis(10));
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), config);
+
+        let permit = eth_filter.inner.acquire_log_scan_permit().await.unwrap();
+        assert!(matches!(
+            eth_filter.inner.acquire_log_scan_permit().await,
+            Err(FilterError::TooBusy)
+        ));
+
+        // a waiting scan proceeds once the permit is released
+        let filter = eth_filter.clone();
+        let queued = tokio::spawn(async move {
+            filter.inner.acquire_log_scan_permit().await.map(|permit| permit.is_some())
+        });
+        drop(permit);
+        assert!(queued.await.unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_filter_logs_cancellation() {
+        let config = test_filter_config()
+            .max_concurrent_log_scans(1)
+            .log_scan_queue_timeout(Duration::from_secs(60));
+        let eth_filter = build_test_eth_filter(mock_provider_with_head(0), config);
+        let id = eth_filter.new_filter(Filter::new()).await.unwrap();
+
+        // a query waiting for a scan permit stops once cancelled
+        let permit = eth_filter.inner.acquire_log_scan_permit().await.unwrap();
+        let cancel = CancellationToken::new();
+        let filter = eth_filter.clone();
+        let (query_id, query_cancel) = (id.clone(), cancel.clone());
+        let query = tokio::spawn(async move {
+            filter.filter_logs_with_cancellation(query_id, &query_cancel).await
+        });
+        cancel.cancel();
+        let res = tokio::time::timeout(Duration::from_secs(5), query).await.unwrap().unwrap();
+        assert!(matches!(res, Err(FilterError::Cancelled)));
+
+        drop(permit);
+        let res = eth_filter.filter_logs_with_cancellation(id, &CancellationToken::new()).await;
+        assert!(matches!(res, Ok(FilterChanges::Logs(logs)) if logs.is_empty()));
+    }
+
+    /// Adds a chain of `len` blocks on top of the given parent and returns their hashes.
+    fn extend_chain(provider: &MockEthProvider, parent: BlockNumHash, len: u64) -> Vec<B256> {
+        let mut parent = parent;
+        (1..=len)
+            .map(|_| {
+                let header = Header {
+                    number: parent.number + 1,
+                    parent_hash: parent.hash,
+                    ..Default::default()
+                };
+                let hash = B256::random();
+                provider.add_block(hash, Block { header, ..Default::default() });
+                parent = BlockNumHash::new(parent.number + 1, hash);
+                hash
+            })
+            .collect()
+    }
+
+    /// Removes the blocks from the canonical chain, keeping their headers.
+    fn orphan_blocks(provider: &MockEthProvider, hashes: &[B256]) {
+        let mut blocks = provider.blocks.lock();
+        for hash in hashes {
+            blocks.remove(hash);
+        }
+    }
+
+    #[test]
+    fn test_detect_reorg() {
+        let provider = MockEthProvider::default();
+        let genesis = B256::random();
+        provider.add_block(genesis, Block::default());
+        let chain = extend_chain(&provider, BlockNumHash::new(0, genesis), 5);
+        let tip = BlockNumHash::new(5, chain[4]);
+        assert_eq!(detect_reorg(&provider, tip, MAX_REORG_DEPTH).unwrap(), None);
+
+        // shallow reorg replacing the tip
+        orphan_blocks(&provider, &chain[4..]);
+        let fork = extend_chain(&provider, BlockNumHash::new(4, chain[3]), 2);
+        assert_eq!(
+            detect_reorg(&provider, tip, MAX_REORG_DEPTH).unwrap(),
+            Some(ReorgInfo {
+                common_ancestor: Some(BlockNumHash::new(4, chain[3])),
+                orphaned: vec![chain[4]],
+            })
+        );
+
+        // deep reorg to a shorter chain, orphaning the previous fork as well
+        let fork_tip = BlockNumHash::new(6, fork[1]);
+        orphan_blocks(&provider, &chain[1..]);
+        orphan_blocks(&provider, &fork);
+        extend_chain(&provider, BlockNumHash::new(1, chain[0]), 1);
+        assert_eq!(
+            detect_reorg(&provider, fork_tip, MAX_REORG_DEPTH).unwrap(),
+            Some(ReorgInfo {
+                common_ancestor: Some(BlockNumHash::new(1, chain[0])),
+                orphaned: vec![fork[1], fork[0], chain[3], chain[2], chain[1]],
+            })
+        );
+
+        // the walk gives up on a reorg deeper than the maximum depth
+        assert_eq!(
+            detect_reorg(&provider, fork_tip, 2).unwrap(),
+            Some(ReorgInfo { common_ancestor: None, orphaned: vec![fork[1], fork[0]] })
+        );
+
+        // the ancestry of the orphaned blocks is unknown
+        provider.headers.lock().remove(&chain[2]);
+        assert_eq!(
+            detect_reorg(&provider, tip, MAX_REORG_DEPTH).unwrap(),
+            Some(ReorgInfo { common_ancestor: None, orphaned: vec![chain[4], chain[3], chain[2]] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_filter_changes_after_reorg() {
+        let provider = MockEthProvider::default();
+        let genesis = B256::random();
+        provider.add_block(genesis, Block::default());
+        let chain = extend_chain(&provider, BlockNumHash::new(0, genesis), 3);
+        let eth_filter = build_test_eth_filter(provider.clone(), test_filter_config());
+        let id = eth_filter.new_block_filter().await.unwrap();
+        eth_filter.filter_changes(id.clone()).await.unwrap();
+
+        // the blocks replacing the orphaned ones are reported
+        orphan_blocks(&provider, &chain[1..]);
+        let fork = extend_chain(&provider, BlockNumHash::new(1, chain[0]), 3);
+        assert_eq!(eth_filter.filter_changes(id).await.unwrap(), FilterChanges::Hashes(fork));
+    }
+
+    #[tokio::test]
+    async fn test_reanchor_filter() {
+        let provider = MockEthProvider::default();
+        let genesis = B256::random();
+        provider.add_block(genesis, Block::default());
+        let chain = extend_chain(&provider, BlockNumHash::new(0, genesis), 3);
+        let eth_filter = build_test_eth_filter(provider.clone(), test_filter_config());
+        let block_filter = eth_filter.new_block_filter().await.unwrap();
+        let log_filter = eth_filter.new_filter(Filter::new()).await.unwrap();
+        for id in [&block_filter, &log_filter] {
+            eth_filter.filter_changes(id.clone()).await.unwrap();
+        }
+
+        // the client fell behind by 7 blocks and skips all but the last 3
+        let gap = extend_chain(&provider, BlockNumHash::new(3, chain[2]), 7);
+        for id in [&block_filter, &log_filter] {
+            eth_filter.reanchor_filter(id.clone(), 8).await.unwrap();
+        }
+        assert_eq!(
+            eth_filter.filter_changes(block_filter.clone()).await.unwrap(),
+            FilterChanges::Hashes(gap[4..].to_vec())
+        );
+        eth_filter.filter_changes(log_filter.clone()).await.unwrap();
+        assert_eq!(
+            eth_filter.last_poll_range(&log_filter).await,
+            Some(FilterPollRange { start_block: 8, best_number: 10, from_block: 8, to_block: 10 })
+        );
+
+        // skipping up to the tip or moving backwards
+        assert!(eth_filter.reanchor_filter(block_filter.clone(), 11).await.is_ok());
+        assert_eq!(
+            eth_filter.filter_changes(block_filter.clone()).await.unwrap(),
+            FilterChanges::Empty
+        );
+        assert!(matches!(
+            eth_filter.reanchor_filter(block_filter.clone(), 12).await,
+            Err(FilterError::InvalidFilter(_))
+        ));
+        assert!(matches!(
+            eth_filter.reanchor_filter(log_filter, 5).await,
+            Err(FilterError::InvalidFilter(_))
+        ));
+
+        let pending_filter = eth_filter.new_pending_transaction_filter().await.unwrap();
+        assert!(matches!(
+            eth_filter.reanchor_filter(pending_filter, 11).await,
+            Err(FilterError::InvalidFilter(_))
+        ));
+        assert!(eth_filter.uninstall_filter(block_filter.clone()).await.unwrap());
+        assert!(matches!(
+            eth_filter.reanchor_filter(block_filter, 11).await,
+            Err(FilterError::FilterNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_last_poll_range() {
+        let provider = mock_provider_with_logs(3, 1, 0);
+        let eth_filter = build_test_eth_filter(provider.clone(), test_filter_config());
+        let id = eth_filter.new_filter(Filter::new().from_block(1u64)).await.unwrap();
+        assert_eq!(eth_filter.last_poll_range(&id).await, None);
+
+        eth_filter.filter_changes(id.clone()).await.unwrap();
+        assert_eq!(
+            eth_filter.last_poll_range(&id).await,
+            Some(FilterPollRange { start_block: 3, best_number: 3, from_block: 3, to_block: 3 })
+        );
+
+        // polls without new blocks don't scan
+        eth_filter.filter_changes(id.clone()).await.unwrap();
+        assert_eq!(eth_filter.last_poll_range(&id).await.map(|range| range.start_block), Some(3));
+
+        let parent = provider.block_hash(3).unwrap().unwrap();
+        extend_chain(&provider, BlockNumHash::new(3, parent), 2);
+        eth_filter.filter_changes(id.clone()).await.unwrap();
+        assert_eq!(
+            eth_filter.last_poll_range(&id).await,
+            Some(FilterPollRange { start_block: 4, best_number: 5, from_block: 4, to_block: 5 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filter_changes_only_scans_new_blocks() {
+        let provider = mock_provider_with_logs(3, 1, 0);
+        let eth_filter = build_test_eth_filter(provider.clone(), test_filter_config());
+        let id = eth_filter.new_filter(Filter::new().from_block(1u64)).await.unwrap();
+
+        // the first poll covers the head at installation
+        let logs = match eth_filter.filter_changes(id.clone()).await.unwrap() {
+            FilterChanges::Logs(logs) => logs,
+            changes => panic!("unexpected changes: {changes:?}"),
+        };
+        assert!(logs.iter().all(|log| log.block_number == Some(U256::from(3))));
+        assert!(matches!(eth_filter.filter_changes(id.clone()).await, Ok(FilterChanges::Empty)));
+
+        // a single new block is the only one scanned, not the range from `fromBlock`
+        add_block_with_log(&provider, 4);
+        let logs = match eth_filter.filter_changes(id).await.unwrap() {
+            FilterChanges::Logs(logs) => logs,
+            changes => panic!("unexpected changes: {changes:?}"),
+        };
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].block_number, Some(U256::from(4)));
+    }
+
+    /// Adds a block with a single transaction that emitted a log.
+    fn add_block_with_log(provider: &MockEthProvider, number: u64) {
+        let receipts = vec![Receipt {
+            logs: vec![reth_primitives::Log {
+                address: Address::random(),
+                topics: vec![B256::random()],
+                data: Default::default(),
+            }],
+            ..Default::default()
+        }];
+        let header = Header {
+            number,
+            logs_bloom: logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs)),
+            ..Default::default()
+        };
+        let hash = B256::random();
+        let body = vec![TransactionSigned::default()];
+        provider.add_block(hash, Block { header, body, ..Default::default() });
+        provider.add_receipts(hash, receipts);
+    }
+
+    /// Returns the block numbers of the logs returned by polling the filter until it is empty.
+    async fn poll_log_blocks<Provider, Pool>(
+        eth_filter: &EthFilter<Provider, Pool>,
+        id: &FilterId,
+    ) -> Vec<U256>
+    where
+        Provider: BlockReaderIdExt + EvmEnvProvider + 'static,
+        Pool: TransactionPool + 'static,
+    {
+        let mut blocks = Vec::new();
+        while let FilterChanges::Logs(logs) = eth_filter.filter_changes(id.clone()).await.unwrap() {
+            blocks.extend(logs.into_iter().filter_map(|log| log.block_number));
+        }
+        blocks
+    }
+
+    #[tokio::test]
+    async fn test_logs_best_effort() {
+        let provider = mock_provider_with_logs(5, 1, 0);
+        let failing = provider.block_hash(3).unwrap().unwrap();
+        provider.fail_receipts(failing, RethError::Database(DatabaseError::Read(-30778)));
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+
+        // strict queries fail
+        let filter = Filter::new().from_block(1).to_block(5);
+        assert!(eth_filter.logs(filter.clone()).await.is_err());
+
+        let BestEffortLogs { logs, failed_blocks } =
+            eth_filter.logs_best_effort(filter).await.unwrap();
+        let blocks = logs.into_iter().filter_map(|log| log.block_number).collect::<Vec<_>>();
+        assert_eq!(blocks, [1u64, 2, 4, 5].map(U256::from));
+        assert_eq!(failed_blocks, [3]);
+    }
+
+    #[tokio::test]
+    async fn test_logs_canonical_order() {
+        let provider = MockEthProvider::default();
+        let mut parent_hash = B256::ZERO;
+        for number in 1..=2 {
+            let hash = B256::random();
+            // transactions with a varying number of logs, one of them without any
+            let receipts = [2, 0, 3, 1]
+                .into_iter()
+                .map(|num_logs| {
+                    let logs = (0..num_logs)
+                        .map(|_| reth_primitives::Log {
+                            address: Address::random(),
+                            topics: vec![B256::random()],
+                            data: Default::default(),
+                        })
+                        .collect();
+                    Receipt { logs, ..Default::default() }
+                })
+                .collect::<Vec<_>>();
+            let logs_bloom = logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs));
+            let header = Header { number, parent_hash, logs_bloom, ..Default::default() };
+            let body = vec![TransactionSigned::default(); receipts.len()];
+            provider.add_block(hash, Block { header, body, ..Default::default() });
+            provider.add_receipts(hash, receipts);
+            parent_hash = hash;
+        }
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+
+        let logs = eth_filter.logs(Filter::new().from_block(1).to_block(2)).await.unwrap();
+        let number = |value: Option<U256>| value.unwrap().to::<u64>();
+        let positions = logs
+            .iter()
+            .map(|log| {
+                (number(log.block_number), number(log.transaction_index), number(log.log_index))
+            })
+            .collect::<Vec<_>>();
+        // (transaction index, log index) of the logs of each block
+        let block = [(0, 0), (0, 1), (2, 2), (2, 3), (2, 4), (3, 5)];
+        let expected = [1, 2]
+            .into_iter()
+            .flat_map(|number| block.map(|(tx, log)| (number, tx, log)))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, expected);
+    }
+
+    #[tokio::test]
+    async fn test_logs_from_genesis() {
+        // the hash of a block is derived from the parent hash of the next header in the range, the
+        // genesis block has no parent but is never the last header of a multi-block range
+        let provider = MockEthProvider::default();
+        let mut parent_hash = B256::ZERO;
+        let mut hashes = Vec::new();
+        for number in 0..=3 {
+            let hash = B256::random();
+            let receipts = vec![Receipt {
+                logs: vec![reth_primitives::Log {
+                    address: Address::random(),
+                    topics: vec![B256::random()],
+                    data: Default::default(),
+                }],
+                ..Default::default()
+            }];
+            let logs_bloom = logs_bloom(receipts.iter().flat_map(|receipt| &receipt.logs));
+            let header = Header { number, parent_hash, logs_bloom, ..Default::default() };
+            let body = vec![TransactionSigned::default()];
+            provider.add_block(hash, Block { header, body, ..Default::default() });
+            provider.add_receipts(hash, receipts);
+            hashes.push(hash);
+            parent_hash = hash;
+        }
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+
+        for to_block in 0..=3u64 {
+            let filter = Filter::new().from_block(0).to_block(to_block);
+            let logs = eth_filter.logs(filter).await.unwrap();
+            let blocks = logs
+                .into_iter()
+                .map(|log| (log.block_number.unwrap(), log.block_hash.unwrap()))
+                .collect::<Vec<_>>();
+            let expected = (0..=to_block)
+                .map(|number| (U256::from(number), hashes[number as usize]))
+                .collect::<Vec<_>>();
+            assert_eq!(blocks, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_filter_with_backfill() {
+        for report_install_block in [true, false] {
+            let provider = mock_provider_with_logs(3, 1, 0);
+            let config = test_filter_config().report_install_block(report_install_block);
+            let eth_filter = build_test_eth_filter(provider.clone(), config);
+
+            let filter = Filter::new().from_block(1);
+            let (id, logs) = eth_filter.new_filter_with_backfill(filter).await.unwrap();
+            let blocks = logs.into_iter().filter_map(|log| log.block_number).collect::<Vec<_>>();
+            assert_eq!(blocks, [1u64, 2, 3].map(U256::from));
+
+            // the first poll continues right after the backfill
+            assert_eq!(poll_log_blocks(&eth_filter, &id).await, Vec::<U256>::new());
+            add_block_with_log(&provider, 4);
+            assert_eq!(poll_log_blocks(&eth_filter, &id).await, [U256::from(4)]);
+        }
+
+        // the filter is not installed if the backfill fails
+        let provider = mock_provider_with_logs(3, 1, 0);
+        let config = test_filter_config().max_concurrent_log_scans(0);
+        let eth_filter = build_test_eth_filter(provider, config);
+        assert!(eth_filter.new_filter_with_backfill(Filter::new()).await.is_err());
+        assert!(eth_filter.active_filters().inner.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_candidate_blocks() {
+        let provider = mock_provider_with_logs(5, 2, 0);
+        // a block without logs has an empty bloom, which only matches a filter without criteria
+        provider.add_block(
+            B256::random(),
+            Block { header: Header { number: 6, ..Default::default() }, ..Default::default() },
+        );
+        let eth_filter = build_test_eth_filter(provider, test_filter_config());
+
+        let all_logs = eth_filter.logs(Filter::new().from_block(1).to_block(6)).await.unwrap();
+        let candidates = eth_filter.candidate_blocks(&Filter::new(), 1, 6).await.unwrap();
+        assert_eq!(candidates, [1, 2, 3, 4, 5, 6]);
+
+        // every block with a log of the address is a candidate
+        let address = all_logs[4].address;
+        let filter = Filter::new().address(address);
+        let logs = eth_filter.logs(filter.clone().from_block(1).to_block(6)).await.unwrap();
+        let candidates = eth_filter.candidate_blocks(&filter, 1, 6).await.unwrap();
+        assert!(logs.iter().all(|log| candidates.contains(&log.block_number.unwrap().to::<u64>())));
+        assert!(candidates.contains(&3));
+        assert!(!candidates.contains(&6));
+
+        // the filter's own block range is ignored
+        let candidates = eth_filter.candidate_blocks(&filter.from_block(5), 2, 4).await.unwrap();
+        assert!(candidates.contains(&3));
+    }
+
+    #[tokio::test]
+    async fn test_head_relative_filter() {
+        let provider = mock_provider_with_logs(10, 1, 0);
+        let eth_filter = build_test_eth_filter(provider.clone(), test_filter_config());
+        let window = HeadRelativeRange::new(3, 1);
+        let id = eth_filter.new_head_relative_filter(Filter::new(), window).await.unwrap();
+
+        // the first poll reports the whole window below the head
+        let blocks = [7u64, 8, 9].map(U256::from);
+        assert_eq!(poll_log_blocks(&eth_filter, &id).await, blocks);
+
+        // the window slides forward, only the blocks it newly covers are reported
+        add_block_with_log(&provider, 11);
+        add_block_with_log(&provider, 12);
+        assert_eq!(poll_log_blocks(&eth_filter, &id).await, [U256::from(10), U256::from(11)]);
+        let range = eth_filter.last_poll_range(&id).await.unwrap();
+        assert_eq!((range.from_block, range.to_block), (10, 11));
+
+        // blocks that left the window before the filter was polled are skipped
+        for number in 13..=20 {
+            add_block_with_log(&provider, number);
+        }
+        let blocks = [17u64, 18, 19].map(U256::from);
+        assert_eq!(poll_log_blocks(&eth_filter, &id).await, blocks);
+        let range = eth_filter.last_poll_range(&id).await.unwrap();
+        assert_eq!((range.start_block, range.best_number), (12, 20));
+
+        // a window that ends before it starts is rejected
+        let window = HeadRelativeRange::new(1, 3);
+        assert!(eth_filter.new_head_relative_filter(Filter::new(), window).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_install_block_boundary() {
+        // the block at installation is reported by the first poll by default
+        let provider = mock_provider_with_logs(3, 1, 0);
+        let eth_filter = build_test_eth_filter(provider.clone(), test_filter_config());
+        let id = eth_filter.new_filter(Filter::new()).await.unwrap();
+        add_block_with_log(&provider, 4);
+        assert_eq!(poll_log_blocks(&eth_filter, &id).await, [U256::from(3), U256::from(4)]);
+        add_block_with_log(&provider, 5);
+        assert_eq!(poll_log_blocks(&eth_filter, &id).await, [U256::from(5)]);
+
+        // only blocks after the installation are reported
+        let provider = mock_provider_with_logs(3, 1, 0);
+        let config = test_filter_config().report_install_block(false);
+        let eth_filter = build_test_eth_filter(provider.clone(), config);
+        let id = eth_filter.new_filter(Filter::new()).await.unwrap();
+        assert!(poll_log_blocks(&eth_filter, &id).await.is_empty());
+        add_block_with_log(&provider, 4);
+        assert_eq!(poll_log_blocks(&eth_filter, &id).await, [U256::from(4)]);
+        add_block_with_log(&provider, 5);
+        assert_eq!(poll_log_blocks(&eth_filter, &id).await, [U256::from(5)]);
     }
 }
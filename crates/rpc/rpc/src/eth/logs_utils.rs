@@ -1,6 +1,10 @@
-use reth_primitives::{BlockNumHash, ChainInfo, Receipt, TxHash, U256};
-use reth_rpc_types::{FilteredParams, Log};
+use reth_primitives::{BlockNumHash, ChainInfo, Receipt, TransactionSigned, TxHash, U128, U256};
+use reth_rpc_types::{
+    raw_log::{Log as RawLog, RawBlockLog},
+    FilteredParams, IndexedLog, Log, LogWithGasPrice, LogWithTxContext,
+};
 use reth_rpc_types_compat::log::from_primitive_log;
+use std::ops::ControlFlow;
 
 /// Returns all matching logs of a block's receipts grouped with the hash of their transaction.
 pub(crate) fn matching_block_logs<I>(
@@ -26,26 +30,187 @@ pub(crate) fn append_matching_block_logs<I>(
     removed: bool,
 ) where
     I: IntoIterator<Item = (TxHash, Receipt)>,
+{
+    let tx_and_receipts = tx_and_receipts.into_iter().map(|(hash, receipt)| ((hash, ()), receipt));
+    for_each_matching_block_log(filter, block, tx_and_receipts, removed, |log, _| {
+        all_logs.push(log);
+        ControlFlow::Continue(())
+    });
+}
+
+/// Appends all matching logs of a block's receipts, numbered by their position in `all_logs`.
+pub(crate) fn append_matching_block_indexed_logs<I>(
+    all_logs: &mut Vec<IndexedLog>,
+    filter: &FilteredParams,
+    block: BlockNumHash,
+    tx_and_receipts: I,
+    removed: bool,
+) where
+    I: IntoIterator<Item = (TxHash, Receipt)>,
+{
+    let tx_and_receipts = tx_and_receipts.into_iter().map(|(hash, receipt)| ((hash, ()), receipt));
+    for_each_matching_block_log(filter, block, tx_and_receipts, removed, |log, _| {
+        let result_index = U256::from(all_logs.len());
+        all_logs.push(IndexedLog { log, result_index });
+        ControlFlow::Continue(())
+    });
+}
+
+/// Returns the first matching log of a block's receipts, without looking at the remaining
+/// receipts.
+pub(crate) fn first_matching_block_log<I>(
+    filter: &FilteredParams,
+    block: BlockNumHash,
+    tx_and_receipts: I,
+    removed: bool,
+) -> Option<Log>
+where
+    I: IntoIterator<Item = (TxHash, Receipt)>,
+{
+    let tx_and_receipts = tx_and_receipts.into_iter().map(|(hash, receipt)| ((hash, ()), receipt));
+    let mut first_log = None;
+    for_each_matching_block_log(filter, block, tx_and_receipts, removed, |log, _| {
+        first_log = Some(log);
+        ControlFlow::Break(())
+    });
+    first_log
+}
+
+/// Appends all matching logs of a block's receipts together with whether their transaction was a
+/// contract creation.
+///
+/// The transactions are the block body zipped with the receipts.
+pub(crate) fn append_matching_block_logs_with_tx_context<I>(
+    all_logs: &mut Vec<LogWithTxContext>,
+    filter: &FilteredParams,
+    block: BlockNumHash,
+    txs_and_receipts: I,
+    removed: bool,
+) where
+    I: IntoIterator<Item = (TransactionSigned, Receipt)>,
+{
+    let txs_and_receipts =
+        txs_and_receipts.into_iter().map(|(tx, receipt)| ((tx.hash(), tx.to().is_none()), receipt));
+    for_each_matching_block_log(filter, block, txs_and_receipts, removed, |log, is_create| {
+        all_logs.push(LogWithTxContext { log, is_contract_creation: is_create });
+        ControlFlow::Continue(())
+    });
+}
+
+/// Appends all matching logs of a block's receipts together with the effective gas price of their
+/// transaction, given the base fee of the block.
+///
+/// The transactions are the block body zipped with the receipts.
+pub(crate) fn append_matching_block_logs_with_gas_price<I>(
+    all_logs: &mut Vec<LogWithGasPrice>,
+    filter: &FilteredParams,
+    block: BlockNumHash,
+    txs_and_receipts: I,
+    base_fee: Option<u64>,
+    removed: bool,
+) where
+    I: IntoIterator<Item = (TransactionSigned, Receipt)>,
+{
+    let txs_and_receipts = txs_and_receipts
+        .into_iter()
+        .map(|(tx, receipt)| ((tx.hash(), tx.effective_gas_price(base_fee)), receipt));
+    for_each_matching_block_log(filter, block, txs_and_receipts, removed, |log, gas_price| {
+        all_logs.push(LogWithGasPrice { log, effective_gas_price: U128::from(gas_price) });
+        ControlFlow::Continue(())
+    });
+}
+
+/// Appends all matching logs of a block's receipts in their binary [RawBlockLog] form, without
+/// building the JSON [Log]s.
+pub(crate) fn append_matching_block_raw_logs<I>(
+    all_logs: &mut Vec<RawBlockLog>,
+    filter: &FilteredParams,
+    block: BlockNumHash,
+    tx_and_receipts: I,
+    removed: bool,
+) where
+    I: IntoIterator<Item = (TxHash, Receipt)>,
+{
+    let tx_and_receipts = tx_and_receipts.into_iter().map(|(hash, receipt)| ((hash, ()), receipt));
+    for_each_matching_receipt_log(
+        filter,
+        block,
+        tx_and_receipts,
+        |log, transaction_hash, transaction_idx, log_index, _| {
+            all_logs.push(RawBlockLog {
+                log: RawLog { address: log.address, topics: log.topics, data: log.data },
+                block_hash: block.hash,
+                block_number: block.number,
+                transaction_hash,
+                transaction_index: transaction_idx as u64,
+                log_index: log_index as u64,
+                removed,
+            });
+            ControlFlow::Continue(())
+        },
+    );
+}
+
+/// Calls `f` with every matching log of a block's receipts and the context of its transaction,
+/// until `f` breaks.
+fn for_each_matching_block_log<I, T, F>(
+    filter: &FilteredParams,
+    block: BlockNumHash,
+    tx_and_receipts: I,
+    removed: bool,
+    mut f: F,
+) where
+    I: IntoIterator<Item = ((TxHash, T), Receipt)>,
+    T: Copy,
+    F: FnMut(Log, T) -> ControlFlow<()>,
 {
     let block_number_u256 = U256::from(block.number);
+    for_each_matching_receipt_log(
+        filter,
+        block,
+        tx_and_receipts,
+        |log, transaction_hash, transaction_idx, log_index, tx_context| {
+            let log = Log {
+                address: log.address,
+                topics: log.topics,
+                data: log.data,
+                block_hash: Some(block.hash),
+                block_number: Some(block_number_u256),
+                transaction_hash: Some(transaction_hash),
+                transaction_index: Some(U256::from(transaction_idx)),
+                log_index: Some(U256::from(log_index)),
+                removed,
+            };
+            f(log, tx_context)
+        },
+    );
+}
+
+/// Calls `f` with every matching log of a block's receipts, the hash, index and context of its
+/// transaction and the index of the log in the block, until `f` breaks.
+fn for_each_matching_receipt_log<I, T, F>(
+    filter: &FilteredParams,
+    block: BlockNumHash,
+    tx_and_receipts: I,
+    mut f: F,
+) where
+    I: IntoIterator<Item = ((TxHash, T), Receipt)>,
+    T: Copy,
+    F: FnMut(reth_primitives::Log, TxHash, usize, u32, T) -> ControlFlow<()>,
+{
     // tracks the index of a log in the entire block
     let mut log_index: u32 = 0;
-    for (transaction_idx, (transaction_hash, receipt)) in tx_and_receipts.into_iter().enumerate() {
+    for (transaction_idx, ((transaction_hash, tx_context), receipt)) in
+        tx_and_receipts.into_iter().enumerate()
+    {
         let logs = receipt.logs;
+        let status_matches = filter.filter_transaction_success(receipt.success);
         for log in logs.into_iter() {
-            if log_matches_filter(block, &log, filter) {
-                let log = Log {
-                    address: log.address,
-                    topics: log.topics,
-                    data: log.data,
-                    block_hash: Some(block.hash),
-                    block_number: Some(block_number_u256),
-                    transaction_hash: Some(transaction_hash),
-                    transaction_index: Some(U256::from(transaction_idx)),
-                    log_index: Some(U256::from(log_index)),
-                    removed,
-                };
-                all_logs.push(log);
+            if status_matches &&
+                log_matches_filter(block, &log, filter) &&
+                f(log, transaction_hash, transaction_idx, log_index, tx_context).is_break()
+            {
+                return
             }
             log_index += 1;
         }
@@ -66,7 +231,7 @@ pub(crate) fn log_matches_filter(
     {
         return false
     }
-    true
+    params.filter_topic_count(log.topics.len())
 }
 
 /// Computes the block range based on the filter range and current block numbers
@@ -99,8 +264,41 @@ pub(crate) fn get_filter_block_range(
 mod tests {
     use super::*;
 
+    use reth_primitives::{Address, Signature, Transaction, TransactionKind, TxLegacy, B256};
     use reth_rpc_types::Filter;
 
+    #[test]
+    fn test_logs_with_tx_context() {
+        let block = BlockNumHash::new(1, B256::random());
+        let receipt = Receipt { logs: vec![Default::default()], ..Default::default() };
+        let tx = |to| {
+            TransactionSigned::from_transaction_and_signature(
+                Transaction::Legacy(TxLegacy { to, ..Default::default() }),
+                Signature::default(),
+            )
+        };
+        let txs_and_receipts = vec![
+            (tx(TransactionKind::Create), receipt.clone()),
+            (tx(TransactionKind::Call(Address::random())), receipt),
+        ];
+
+        let mut logs = Vec::new();
+        append_matching_block_logs_with_tx_context(
+            &mut logs,
+            &FilteredParams::default(),
+            block,
+            txs_and_receipts,
+            false,
+        );
+
+        assert_eq!(
+            logs.iter().map(|log| log.is_contract_creation).collect::<Vec<_>>(),
+            [true, false]
+        );
+        assert_eq!(logs[1].log.transaction_index, Some(U256::from(1)));
+        assert_eq!(logs[1].log.log_index, Some(U256::from(1)));
+    }
+
     #[test]
     fn test_log_range_from_and_to() {
         let from = 14000000u64;
@@ -15,6 +15,9 @@ pub(crate) mod utils;
 
 pub use api::{EthApi, EthApiSpec, EthTransactions, TransactionSource, RPC_DEFAULT_GAS_CAP};
 pub use bundle::EthBundle;
-pub use filter::EthFilter;
+pub use filter::{
+    BestEffortLogs, EthFilter, EthFilterConfig, FilterHealth, FilterPollRange, HeadRelativeRange,
+    LogScanRetryPolicy, PendingTransactionCriteria, StaleFilterStats,
+};
 pub use id_provider::EthSubscriptionIdProvider;
 pub use pubsub::EthPubSub;
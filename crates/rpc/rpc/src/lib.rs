@@ -42,7 +42,11 @@ pub use admin::AdminApi;
 pub use blocking_pool::{BlockingTaskGuard, BlockingTaskPool};
 pub use debug::DebugApi;
 pub use engine::{EngineApi, EngineEthApi};
-pub use eth::{EthApi, EthApiSpec, EthFilter, EthPubSub, EthSubscriptionIdProvider};
+pub use eth::{
+    BestEffortLogs, EthApi, EthApiSpec, EthFilter, EthFilterConfig, EthPubSub,
+    EthSubscriptionIdProvider, FilterHealth, FilterPollRange, HeadRelativeRange,
+    LogScanRetryPolicy, PendingTransactionCriteria, StaleFilterStats,
+};
 pub use layers::{AuthLayer, AuthValidator, Claims, JwtAuthValidator, JwtError, JwtSecret};
 pub use net::NetApi;
 pub use otterscan::OtterscanApi;
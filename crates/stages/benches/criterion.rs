@@ -8,7 +8,10 @@ use reth_interfaces::test_utils::TestConsensus;
 use reth_primitives::{stage::StageCheckpoint, MAINNET};
 use reth_provider::ProviderFactory;
 use reth_stages::{
-    stages::{MerkleStage, SenderRecoveryStage, TotalDifficultyStage, TransactionLookupStage},
+    stages::{
+        MerkleStage, SenderRecoveryStage, TotalDifficultyStage, TransactionLookupStage,
+        MERKLE_STAGE_DEFAULT_TRIE_THRESHOLD,
+    },
     test_utils::TestTransaction,
     ExecInput, Stage, UnwindInput,
 };
@@ -95,7 +98,10 @@ fn merkle(c: &mut Criterion) {
     // don't need to run each stage for that many times
     group.sample_size(10);
 
-    let stage = MerkleStage::Both { clean_threshold: u64::MAX };
+    let stage = MerkleStage::Both {
+        clean_threshold: u64::MAX,
+        trie_threshold: MERKLE_STAGE_DEFAULT_TRIE_THRESHOLD,
+    };
     measure_stage(
         &mut group,
         setup::unwind_hashes,
@@ -104,7 +110,10 @@ fn merkle(c: &mut Criterion) {
         "Merkle-incremental".to_string(),
     );
 
-    let stage = MerkleStage::Both { clean_threshold: 0 };
+    let stage = MerkleStage::Both {
+        clean_threshold: 0,
+        trie_threshold: MERKLE_STAGE_DEFAULT_TRIE_THRESHOLD,
+    };
     measure_stage(
         &mut group,
         setup::unwind_hashes,
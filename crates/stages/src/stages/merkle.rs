@@ -9,7 +9,6 @@ use reth_interfaces::consensus;
 use reth_primitives::{
     hex,
     stage::{EntitiesCheckpoint, MerkleCheckpoint, StageCheckpoint, StageId},
-    trie::StoredSubNode,
     BlockNumber, SealedHeader, B256,
 };
 use reth_provider::{
@@ -23,6 +22,10 @@ use tracing::*;
 /// of changes to whole rebuild.
 pub const MERKLE_STAGE_DEFAULT_CLEAN_THRESHOLD: u64 = 50_000;
 
+/// The default threshold (in number of trie updates) after which a rebuild of the trie is
+/// interrupted, so that the updates are written and the progress is saved in the checkpoint.
+pub const MERKLE_STAGE_DEFAULT_TRIE_THRESHOLD: u64 = 100_000;
+
 /// The merkle hashing stage uses input from
 /// [`AccountHashingStage`][crate::stages::AccountHashingStage] and
 /// [`StorageHashingStage`][crate::stages::AccountHashingStage] to calculate intermediate hashes
@@ -58,7 +61,7 @@ pub enum MerkleStage {
     /// Able to execute and unwind. Used for tests
     #[cfg(any(test, feature = "test-utils"))]
     #[allow(missing_docs)]
-    Both { clean_threshold: u64 },
+    Both { clean_threshold: u64, trie_threshold: u64 },
 }
 
 impl MerkleStage {
@@ -77,6 +80,16 @@ impl MerkleStage {
         Self::Execution { clean_threshold }
     }
 
+    /// The number of trie updates after which a rebuild of the trie is interrupted and its
+    /// progress is saved in the checkpoint.
+    fn trie_threshold(&self) -> u64 {
+        #[cfg(any(test, feature = "test-utils"))]
+        if let MerkleStage::Both { trie_threshold, .. } = self {
+            return *trie_threshold
+        }
+        MERKLE_STAGE_DEFAULT_TRIE_THRESHOLD
+    }
+
     /// Check that the computed state root matches the root in the expected header.
     fn validate_state_root(
         &self,
@@ -159,7 +172,7 @@ impl<DB: Database> Stage<DB> for MerkleStage {
             }
             MerkleStage::Execution { clean_threshold } => *clean_threshold,
             #[cfg(any(test, feature = "test-utils"))]
-            MerkleStage::Both { clean_threshold } => *clean_threshold,
+            MerkleStage::Both { clean_threshold, .. } => *clean_threshold,
         };
 
         let range = input.next_block_range();
@@ -213,7 +226,11 @@ impl<DB: Database> Stage<DB> for MerkleStage {
             });
 
             let tx = provider.tx_ref();
+            // the storage trie of a huge contract is interrupted like the account trie, instead of
+            // buffering all of its updates in memory
             let progress = StateRoot::new(tx)
+                .with_threshold(self.trie_threshold())
+                .with_storage_progress(true)
                 .with_intermediate_state(checkpoint.map(IntermediateStateRootState::from))
                 .root_with_progress()
                 .map_err(|e| StageError::Fatal(Box::new(e)))?;
@@ -221,12 +238,7 @@ impl<DB: Database> Stage<DB> for MerkleStage {
                 StateRootProgress::Progress(state, hashed_entries_walked, updates) => {
                     updates.flush(tx)?;
 
-                    let checkpoint = MerkleCheckpoint::new(
-                        to_block,
-                        state.last_account_key,
-                        state.walker_stack.into_iter().map(StoredSubNode::from).collect(),
-                        state.hash_builder.into(),
-                    );
+                    let checkpoint = state.into_checkpoint(to_block);
                     self.save_execution_checkpoint(provider, Some(checkpoint))?;
 
                     entities_checkpoint.processed += hashed_entries_walked as u64;
@@ -343,6 +355,7 @@ mod tests {
         TestTransaction, UnwindStageTestRunner,
     };
     use assert_matches::assert_matches;
+    use rand::Rng;
     use reth_db::{
         cursor::{DbCursorRO, DbCursorRW, DbDupCursorRO},
         tables,
@@ -443,14 +456,71 @@ mod tests {
         assert!(runner.validate_execution(input, result.ok()).is_ok(), "execution validation");
     }
 
+    /// Rebuild the storage trie of a single large contract over several executions
+    #[tokio::test]
+    async fn execute_clean_merkle_interrupted_in_storage_trie() {
+        let mut rng = generators::rng();
+
+        // a low threshold interrupts the rebuild several times inside the storage trie
+        let mut runner = MerkleTestRunner { trie_threshold: 10, ..Default::default() };
+
+        let (address, account) =
+            random_contract_account_range(&mut rng, &mut (0..1)).into_iter().next().unwrap();
+        let storage = (0..1000)
+            .map(|_| StorageEntry {
+                key: B256::random(),
+                value: U256::from(rng.gen_range(1..u64::MAX)),
+            })
+            .collect::<Vec<_>>();
+        runner.tx.insert_accounts_and_storages([(address, (account, storage.clone()))]).unwrap();
+
+        let genesis = random_block(&mut rng, 0, None, Some(0), None);
+        let mut block = random_block(&mut rng, 1, Some(genesis.hash()), Some(0), None).unseal();
+        block.header.state_root = state_root(std::iter::once((
+            address,
+            (account, storage.iter().map(|entry| (entry.key, entry.value))),
+        )));
+        runner.tx.insert_blocks([genesis, block.seal_slow()].iter(), None).unwrap();
+
+        let mut input = ExecInput { target: Some(1), checkpoint: Some(StageCheckpoint::new(0)) };
+        let mut interrupted_in_storage_trie = 0;
+        loop {
+            let output = runner.execute(input).await.unwrap().unwrap();
+            if output.done {
+                assert_eq!(output.checkpoint.block_number, 1);
+                break
+            }
+
+            let checkpoint = runner
+                .tx
+                .inner_rw()
+                .get_stage_checkpoint_progress(StageId::MerkleExecute)
+                .unwrap()
+                .unwrap();
+            let (checkpoint, _) = MerkleCheckpoint::from_compact(&checkpoint, checkpoint.len());
+            if checkpoint.storage_root_checkpoint.is_some() {
+                interrupted_in_storage_trie += 1;
+            }
+            input.checkpoint = Some(output.checkpoint);
+        }
+        // the stage validates the resumed root against the header
+        assert!(interrupted_in_storage_trie > 1);
+        assert!(!runner.tx.table_is_empty::<tables::StoragesTrie>().unwrap());
+    }
+
     struct MerkleTestRunner {
         tx: TestTransaction,
         clean_threshold: u64,
+        trie_threshold: u64,
     }
 
     impl Default for MerkleTestRunner {
         fn default() -> Self {
-            Self { tx: TestTransaction::default(), clean_threshold: 10000 }
+            Self {
+                tx: TestTransaction::default(),
+                clean_threshold: 10000,
+                trie_threshold: MERKLE_STAGE_DEFAULT_TRIE_THRESHOLD,
+            }
         }
     }
 
@@ -462,7 +532,10 @@ mod tests {
         }
 
         fn stage(&self) -> Self::S {
-            Self::S::Both { clean_threshold: self.clean_threshold }
+            Self::S::Both {
+                clean_threshold: self.clean_threshold,
+                trie_threshold: self.trie_threshold,
+            }
         }
     }
 
@@ -1640,7 +1640,7 @@ impl<TX: DbTxMut + DbTx> HashingWriter for DatabaseProvider<TX> {
                 )
                 .with_destroyed_accounts(destroyed_accounts)
                 .root_with_updates()
-                .map_err(Into::<reth_db::DatabaseError>::into)?;
+                .map_err(Into::<ProviderError>::into)?;
             if state_root != expected_state_root {
                 return Err(ProviderError::StateRootMismatch {
                     got: state_root,
@@ -2008,7 +2008,7 @@ impl<TX: DbTxMut + DbTx> BlockExecutionWriter for DatabaseProvider<TX> {
                 )
                 .with_destroyed_accounts(destroyed_accounts)
                 .root_with_updates()
-                .map_err(Into::<reth_db::DatabaseError>::into)?;
+                .map_err(Into::<ProviderError>::into)?;
 
             let parent_number = range.start().saturating_sub(1);
             let parent_state_root = self
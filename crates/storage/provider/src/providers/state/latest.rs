@@ -60,7 +60,7 @@ impl<'b, TX: DbTx> BlockHashReader for LatestStateProviderRef<'b, TX> {
 
 impl<'b, TX: DbTx> StateRootProvider for LatestStateProviderRef<'b, TX> {
     fn state_root(&self, bundle_state: &BundleStateWithReceipts) -> RethResult<B256> {
-        bundle_state.state_root_slow(self.db).map_err(|err| RethError::Database(err.into()))
+        bundle_state.state_root_slow(self.db).map_err(|err| RethError::Provider(err.into()))
     }
 }
 
@@ -8,7 +8,7 @@ use crate::{
 };
 use parking_lot::Mutex;
 use reth_db::models::StoredBlockBodyIndices;
-use reth_interfaces::{provider::ProviderError, RethResult};
+use reth_interfaces::{provider::ProviderError, RethError, RethResult};
 use reth_primitives::{
     keccak256, trie::AccountProof, Account, Address, Block, BlockHash, BlockHashOrNumber, BlockId,
     BlockNumber, BlockWithSenders, Bytecode, Bytes, ChainInfo, ChainSpec, Header, Receipt,
@@ -31,8 +31,16 @@ pub struct MockEthProvider {
     pub headers: Arc<Mutex<HashMap<B256, Header>>>,
     /// Local account store
     pub accounts: Arc<Mutex<HashMap<Address, ExtendedAccount>>>,
+    /// Local receipt store indexed by block hash
+    pub receipts: Arc<Mutex<HashMap<B256, Vec<Receipt>>>>,
+    /// Local store of non-canonical blocks, only returned for the pending [BlockSource]
+    pub side_chain_blocks: Arc<Mutex<HashMap<B256, Block>>>,
     /// Local chain spec
     pub chain_spec: Arc<ChainSpec>,
+    /// Errors returned by the next calls of [HeaderProvider::headers_range], in order
+    pub headers_range_errors: Arc<Mutex<Vec<RethError>>>,
+    /// Errors returned by all calls of [ReceiptProvider::receipts_by_block] for a block hash
+    pub receipts_errors: Arc<Mutex<HashMap<B256, RethError>>>,
 }
 
 impl Default for MockEthProvider {
@@ -41,7 +49,11 @@ impl Default for MockEthProvider {
             blocks: Default::default(),
             headers: Default::default(),
             accounts: Default::default(),
+            receipts: Default::default(),
+            side_chain_blocks: Default::default(),
             chain_spec: Arc::new(reth_primitives::ChainSpecBuilder::mainnet().build()),
+            headers_range_errors: Default::default(),
+            receipts_errors: Default::default(),
         }
     }
 }
@@ -90,6 +102,12 @@ impl MockEthProvider {
         self.blocks.lock().insert(hash, block);
     }
 
+    /// Add a non-canonical block and its receipts to local store
+    pub fn add_side_chain_block(&self, hash: B256, block: Block, receipts: Vec<Receipt>) {
+        self.side_chain_blocks.lock().insert(hash, block);
+        self.add_receipts(hash, receipts);
+    }
+
     /// Add multiple blocks to local block store
     pub fn extend_blocks(&self, iter: impl IntoIterator<Item = (B256, Block)>) {
         for (hash, block) in iter.into_iter() {
@@ -103,6 +121,18 @@ impl MockEthProvider {
         self.headers.lock().insert(hash, header);
     }
 
+    /// Make the next call of [HeaderProvider::headers_range] fail with the given error. Queued
+    /// errors are returned in order, one per call.
+    pub fn fail_headers_range(&self, error: RethError) {
+        self.headers_range_errors.lock().push(error);
+    }
+
+    /// Make all calls of [ReceiptProvider::receipts_by_block] for the block with the given hash
+    /// fail with the given error.
+    pub fn fail_receipts(&self, block_hash: B256, error: RethError) {
+        self.receipts_errors.lock().insert(block_hash, error);
+    }
+
     /// Add multiple headers to local header store
     pub fn extend_headers(&self, iter: impl IntoIterator<Item = (B256, Header)>) {
         for (hash, header) in iter.into_iter() {
@@ -110,6 +140,11 @@ impl MockEthProvider {
         }
     }
 
+    /// Add the receipts of a block to local receipt store
+    pub fn add_receipts(&self, block_hash: B256, receipts: Vec<Receipt>) {
+        self.receipts.lock().insert(block_hash, receipts);
+    }
+
     /// Add account to local account store
     pub fn add_account(&self, address: Address, account: ExtendedAccount) {
         self.accounts.lock().insert(address, account);
@@ -153,6 +188,13 @@ impl HeaderProvider for MockEthProvider {
     }
 
     fn headers_range(&self, range: impl RangeBounds<BlockNumber>) -> RethResult<Vec<Header>> {
+        {
+            let mut errors = self.headers_range_errors.lock();
+            if !errors.is_empty() {
+                return Err(errors.remove(0))
+            }
+        }
+
         let lock = self.headers.lock();
 
         let mut headers: Vec<_> =
@@ -331,8 +373,18 @@ impl ReceiptProvider for MockEthProvider {
         Ok(None)
     }
 
-    fn receipts_by_block(&self, _block: BlockHashOrNumber) -> RethResult<Option<Vec<Receipt>>> {
-        Ok(None)
+    fn receipts_by_block(&self, block: BlockHashOrNumber) -> RethResult<Option<Vec<Receipt>>> {
+        let hash = match block {
+            BlockHashOrNumber::Hash(hash) => hash,
+            BlockHashOrNumber::Number(number) => match self.block_hash(number)? {
+                Some(hash) => hash,
+                None => return Ok(None),
+            },
+        };
+        if let Some(error) = self.receipts_errors.lock().get(&hash) {
+            return Err(error.clone())
+        }
+        Ok(self.receipts.lock().get(&hash).cloned())
     }
 }
 
@@ -409,8 +461,13 @@ impl BlockIdReader for MockEthProvider {
 }
 
 impl BlockReader for MockEthProvider {
-    fn find_block_by_hash(&self, hash: B256, _source: BlockSource) -> RethResult<Option<Block>> {
-        self.block(hash.into())
+    fn find_block_by_hash(&self, hash: B256, source: BlockSource) -> RethResult<Option<Block>> {
+        let side_chain_block = || self.side_chain_blocks.lock().get(&hash).cloned();
+        match source {
+            BlockSource::Any => Ok(self.block(hash.into())?.or_else(side_chain_block)),
+            BlockSource::Pending => Ok(side_chain_block()),
+            BlockSource::Database => self.block(hash.into()),
+        }
     }
 
     fn block(&self, id: BlockHashOrNumber) -> RethResult<Option<Block>> {
@@ -0,0 +1,63 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use reth_db::{tables, test_utils::create_test_rw_db, transaction::DbTxMut};
+use reth_primitives::{Account, B256, MAINNET};
+use reth_provider::ProviderFactory;
+use reth_trie::{
+    updates::{TrieKey, TrieOp, TrieUpdates},
+    StateRoot,
+};
+use std::collections::HashSet;
+
+/// Benchmarks the generation of the storage trie deletes of destroyed accounts, on its own and as
+/// the tail of a state root computation.
+pub fn destroyed_account_deletes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Destroyed accounts");
+    group.sample_size(10);
+
+    for size in [100_000, 1_000_000] {
+        let destroyed = (0..size).map(|_| B256::random()).collect::<HashSet<_>>();
+
+        // the deletes extend the updates of the account trie walk, so the map is not empty
+        let walk_updates =
+            TrieUpdates::from([(TrieKey::StorageTrie(B256::random()), TrieOp::Delete)]);
+        group.bench_function(format!("extend with deletes | accounts: {size}"), |b| {
+            b.iter_batched(
+                || (walk_updates.clone(), destroyed.clone()),
+                |(mut updates, destroyed)| {
+                    updates.extend_with_deletes(destroyed.into_iter().map(TrieKey::StorageTrie));
+                    black_box(updates)
+                },
+                BatchSize::LargeInput,
+            );
+        });
+
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let provider = factory.provider_rw().unwrap();
+        let tx = provider.tx_ref();
+        for nonce in 0..1_000 {
+            let account = Account { nonce, ..Default::default() };
+            tx.put::<tables::HashedAccount>(B256::random(), account).unwrap();
+        }
+
+        group.bench_function(format!("root with updates | accounts: {size}"), |b| {
+            b.iter_batched(
+                || destroyed.clone(),
+                |destroyed| {
+                    StateRoot::new(tx)
+                        .with_destroyed_accounts(destroyed)
+                        .root_with_updates()
+                        .unwrap()
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+}
+
+criterion_group! {
+    name = destroyed_accounts;
+    config = Criterion::default();
+    targets = destroyed_account_deletes
+}
+criterion_main!(destroyed_accounts);
@@ -0,0 +1,161 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use reth_db::{
+    tables,
+    test_utils::create_test_rw_db,
+    transaction::{DbTx, DbTxMut},
+};
+use reth_primitives::{keccak256, Account, StorageEntry, B256, MAINNET, U256};
+use reth_provider::ProviderFactory;
+use reth_trie::{updates::TrieUpdates, StateRoot, StorageRoot};
+use std::collections::HashMap;
+
+/// Benchmarks the state root calculation with the storage roots of the accounts computed
+/// sequentially and in parallel.
+pub fn parallel_storage_roots(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Parallel state root");
+    group.sample_size(10);
+
+    let accounts = 10_000;
+    let slots = 100u64;
+    let group_name = |description: &str| {
+        format!("state root | accounts: {accounts} | slots: {slots} | {description}")
+    };
+
+    let db = create_test_rw_db();
+    let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+    let provider = factory.provider_rw().unwrap();
+    for _ in 0..accounts {
+        insert_account(provider.tx_ref(), B256::random(), slots);
+    }
+    provider.commit().unwrap();
+
+    let provider = factory.provider().unwrap();
+    let tx = provider.tx_ref();
+    let pool = rayon::ThreadPoolBuilder::new().build().unwrap();
+    assert_eq!(
+        StateRoot::new(tx).root_with_updates().unwrap().0,
+        StateRoot::new(tx).parallel_root_with_updates(&pool).unwrap().0
+    );
+
+    group.bench_function(group_name("sequential"), |b| {
+        b.iter(|| StateRoot::new(tx).root_with_updates().unwrap());
+    });
+
+    group.bench_function(group_name("parallel"), |b| {
+        b.iter(|| StateRoot::new(tx).parallel_root_with_updates(&pool).unwrap());
+    });
+}
+
+/// Benchmarks the parallel state root calculation with a few large storage tries next to each
+/// other among many small ones, scheduling the storage roots on the work-stealing queue against
+/// assigning contiguous ranges of accounts to the workers up front.
+pub fn skewed_storage_roots(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Parallel state root");
+    group.sample_size(10);
+
+    let accounts = 2_000;
+    let small_slots = 10u64;
+    let large_accounts = 8;
+    let large_slots = 50_000u64;
+    let group_name = |description: &str| {
+        format!(
+            "skewed state root | accounts: {accounts} | slots: {small_slots} | \
+             large accounts: {large_accounts} | large slots: {large_slots} | {description}"
+        )
+    };
+
+    let db = create_test_rw_db();
+    let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+    let provider = factory.provider_rw().unwrap();
+    let mut hashed_addresses = Vec::with_capacity(accounts);
+    for i in 0..accounts {
+        let mut hashed_address = B256::random();
+        // The large storage tries are adjacent in the walk, so they end up in the same range.
+        let slots = if i < large_accounts {
+            hashed_address[0] = 0;
+            large_slots
+        } else {
+            small_slots
+        };
+        insert_account(provider.tx_ref(), hashed_address, slots);
+        hashed_addresses.push(hashed_address);
+    }
+    hashed_addresses.sort_unstable();
+    provider.commit().unwrap();
+
+    let provider = factory.provider().unwrap();
+    let tx = provider.tx_ref();
+    let pool = rayon::ThreadPoolBuilder::new().build().unwrap();
+    let workers = pool.current_num_threads();
+    assert_eq!(
+        StateRoot::new(tx).parallel_root_with_updates(&pool).unwrap().0,
+        static_assignment_root(tx, &hashed_addresses, workers).0
+    );
+
+    group.bench_function(group_name("static assignment"), |b| {
+        b.iter(|| static_assignment_root(tx, &hashed_addresses, workers));
+    });
+
+    group.bench_function(group_name("work stealing"), |b| {
+        b.iter(|| StateRoot::new(tx).parallel_root_with_updates(&pool).unwrap());
+    });
+}
+
+/// Computes the state root with the storage roots computed in parallel, every worker assigned a
+/// contiguous range of the sorted hashed addresses up front.
+fn static_assignment_root<TX: DbTx>(
+    tx: &TX,
+    hashed_addresses: &[B256],
+    workers: usize,
+) -> (B256, TrieUpdates) {
+    let chunk_size = (hashed_addresses.len() + workers - 1) / workers;
+    let computed = std::thread::scope(|scope| {
+        let handles = hashed_addresses
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|hashed_address| {
+                            let (root, _, updates) = StorageRoot::new_hashed(tx, *hashed_address)
+                                .root_with_updates()
+                                .unwrap();
+                            (*hashed_address, root, updates)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+    });
+
+    let mut storage_roots = HashMap::with_capacity(computed.len());
+    let mut trie_updates = TrieUpdates::default();
+    for (hashed_address, root, updates) in computed {
+        storage_roots.insert(hashed_address, root);
+        trie_updates.extend(updates.into_iter());
+    }
+    let (root, updates) =
+        StateRoot::new(tx).with_storage_root_provider(storage_roots).root_with_updates().unwrap();
+    trie_updates.extend(updates.into_iter());
+    (root, trie_updates)
+}
+
+fn insert_account(tx: &impl DbTxMut, hashed_address: B256, slots: u64) {
+    let account = Account { nonce: 1, ..Default::default() };
+    tx.put::<tables::HashedAccount>(hashed_address, account).unwrap();
+    for i in 0..slots {
+        let entry = StorageEntry {
+            key: keccak256(U256::from(i).to_be_bytes::<32>()),
+            value: U256::from(i + 1),
+        };
+        tx.put::<tables::HashedStorage>(hashed_address, entry).unwrap();
+    }
+}
+
+criterion_group! {
+    name = parallel_state_root;
+    config = Criterion::default();
+    targets = parallel_storage_roots, skewed_storage_roots
+}
+criterion_main!(parallel_state_root);
@@ -0,0 +1,55 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reth_db::{tables, test_utils::create_test_rw_db, transaction::DbTxMut};
+use reth_primitives::{keccak256, StorageEntry, B256, MAINNET, U256};
+use reth_provider::ProviderFactory;
+use reth_trie::StorageRoot;
+
+/// Benchmarks the storage root calculation of an unchanged storage trie, as done for accounts
+/// whose storage didn't change during incremental state root calculation.
+pub fn unchanged_storage_root(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Unchanged storage root");
+
+    for size in [1_000, 10_000] {
+        let group_name =
+            |description: &str| format!("storage root | slots: {size} | {description}");
+
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let provider = factory.provider_rw().unwrap();
+        let tx = provider.tx_ref();
+
+        // the same storage with and without persisted trie nodes
+        let with_trie = B256::with_last_byte(1);
+        let without_trie = B256::with_last_byte(2);
+        for i in 0..size as u64 {
+            let entry = StorageEntry {
+                key: keccak256(U256::from(i).to_be_bytes::<32>()),
+                value: U256::from(i + 1),
+            };
+            tx.put::<tables::HashedStorage>(with_trie, entry).unwrap();
+            tx.put::<tables::HashedStorage>(without_trie, entry).unwrap();
+        }
+        let (_, _, updates) = StorageRoot::new_hashed(tx, with_trie).root_with_updates().unwrap();
+        updates.flush(tx).unwrap();
+
+        assert_eq!(
+            StorageRoot::new_hashed(tx, with_trie).root().unwrap(),
+            StorageRoot::new_hashed(tx, without_trie).root().unwrap()
+        );
+
+        group.bench_function(group_name("persisted trie nodes"), |b| {
+            b.iter(|| StorageRoot::new_hashed(tx, black_box(with_trie)).root().unwrap());
+        });
+
+        group.bench_function(group_name("full walk"), |b| {
+            b.iter(|| StorageRoot::new_hashed(tx, black_box(without_trie)).root().unwrap());
+        });
+    }
+}
+
+criterion_group! {
+    name = storage_root;
+    config = Criterion::default();
+    targets = unchanged_storage_root
+}
+criterion_main!(storage_root);
@@ -0,0 +1,88 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reth_db::{database::Database, tables, test_utils::create_test_rw_db, transaction::DbTxMut};
+use reth_primitives::{keccak256, trie::Nibbles, Account, StorageEntry, B256, U256};
+use reth_trie::{
+    prefix_set::{PrefixSet, PrefixSetMut},
+    trie_cursor::TriePrefetcher,
+    StateRoot,
+};
+use std::collections::HashMap;
+
+/// Benchmarks a state root computation that walks all stored trie nodes, with and without the
+/// trie nodes read ahead by a [TriePrefetcher].
+///
+/// The prefetcher only saves wall-clock time if the walk would otherwise block on disk reads, so
+/// the page cache should be cold, e.g. by dropping it with `echo 3 > /proc/sys/vm/drop_caches`
+/// before each run. With a warm cache both variants do the same reads from memory.
+pub fn full_walk_with_prefetcher(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Trie prefetch");
+    group.sample_size(10);
+
+    for size in [10_000, 100_000] {
+        let db = create_test_rw_db();
+        let (account_prefixes, storage_prefixes) = populate(&db, size);
+
+        let tx = db.tx().unwrap();
+        let prefetcher = TriePrefetcher::spawn(db.clone()).unwrap();
+
+        // all keys are changed, so every stored node is walked and none of their hashes is reused
+        let state_root = || {
+            StateRoot::new(&tx)
+                .with_changed_account_prefixes(account_prefixes.clone())
+                .with_changed_storage_prefixes(storage_prefixes.clone())
+        };
+        assert_eq!(
+            state_root().with_prefetcher(prefetcher.clone()).root().unwrap(),
+            state_root().root().unwrap()
+        );
+
+        let group_name =
+            |description: &str| format!("full walk | accounts: {size} | {description}");
+
+        group.bench_function(group_name("no prefetch"), |b| {
+            b.iter(|| black_box(state_root()).root().unwrap());
+        });
+
+        group.bench_function(group_name("prefetch"), |b| {
+            b.iter(|| black_box(state_root().with_prefetcher(prefetcher.clone())).root().unwrap());
+        });
+    }
+}
+
+/// Writes the given number of accounts, every tenth with storage, and their tries to the database.
+///
+/// Returns the prefix sets of all hashed addresses and of all hashed storage slots.
+fn populate<DB: Database>(db: &DB, size: u64) -> (PrefixSet, HashMap<B256, PrefixSet>) {
+    let hashed_addresses =
+        (0..size).map(|i| keccak256(B256::from(U256::from(i).to_be_bytes()))).collect::<Vec<_>>();
+    let hashed_slots =
+        (0..100u64).map(|i| keccak256(B256::from(U256::from(i).to_be_bytes()))).collect::<Vec<_>>();
+    let account_prefixes = PrefixSetMut::from(hashed_addresses.iter().map(Nibbles::unpack));
+    let storage_slot_prefixes =
+        PrefixSetMut::from(hashed_slots.iter().map(Nibbles::unpack)).freeze();
+    let mut storage_prefixes = HashMap::new();
+    db.update(|tx| {
+        for (i, hashed_address) in hashed_addresses.iter().enumerate() {
+            let account = Account { nonce: i as u64, ..Default::default() };
+            tx.put::<tables::HashedAccount>(*hashed_address, account).unwrap();
+            if i % 10 == 0 {
+                for (slot, key) in hashed_slots.iter().enumerate() {
+                    let entry = StorageEntry { key: *key, value: U256::from(slot + 1) };
+                    tx.put::<tables::HashedStorage>(*hashed_address, entry).unwrap();
+                }
+                storage_prefixes.insert(*hashed_address, storage_slot_prefixes.clone());
+            }
+        }
+        let (_, updates) = StateRoot::new(tx).root_with_updates().unwrap();
+        updates.flush(tx).unwrap();
+    })
+    .unwrap();
+    (account_prefixes.freeze(), storage_prefixes)
+}
+
+criterion_group! {
+    name = trie_prefetch;
+    config = Criterion::default();
+    targets = full_walk_with_prefetcher
+}
+criterion_main!(trie_prefetch);
@@ -0,0 +1,73 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reth_db::{
+    tables,
+    test_utils::create_test_rw_db,
+    transaction::{DbTx, DbTxMut},
+};
+use reth_primitives::{keccak256, trie::Nibbles, Account, B256, MAINNET, U256};
+use reth_provider::ProviderFactory;
+use reth_trie::{
+    prefix_set::PrefixSetMut,
+    trie_cursor::{AccountTrieCursor, UpperTrieCache},
+    StateRoot,
+};
+
+/// Benchmarks the incremental state root calculation after changing a single account, with and
+/// without the upper levels of the account trie cached in memory.
+pub fn incremental_root_with_upper_trie_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Upper trie cache");
+
+    for size in [10_000, 100_000] {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let provider = factory.provider_rw().unwrap();
+        let tx = provider.tx_ref();
+
+        let hashed_addresses = (0..size as u64)
+            .map(|i| keccak256(B256::from(U256::from(i).to_be_bytes())))
+            .collect::<Vec<_>>();
+        for (i, hashed_address) in hashed_addresses.iter().enumerate() {
+            let account = Account { nonce: i as u64, ..Default::default() };
+            tx.put::<tables::HashedAccount>(*hashed_address, account).unwrap();
+        }
+        let (_, updates) = StateRoot::new(tx).root_with_updates().unwrap();
+        updates.flush(tx).unwrap();
+
+        let mut cursor = AccountTrieCursor::new(tx.cursor_read::<tables::AccountsTrie>().unwrap());
+        let cache = UpperTrieCache::load(&mut cursor, 3).unwrap();
+
+        let changed = hashed_addresses[size / 2];
+        tx.put::<tables::HashedAccount>(changed, Account { nonce: 1, ..Default::default() })
+            .unwrap();
+        let changed_prefixes = PrefixSetMut::from([Nibbles::unpack(changed)]).freeze();
+        let state_root =
+            || StateRoot::new(tx).with_changed_account_prefixes(changed_prefixes.clone());
+
+        // the number of trie node reads that a single root computation saves
+        let root = state_root().with_upper_trie_cache(cache.clone()).root().unwrap();
+        assert_eq!(root, state_root().root().unwrap());
+        let reads_saved = cache.hits();
+
+        let group_name = |description: &str| {
+            format!(
+                "incremental root | accounts: {size} | cached nodes: {} | {description}",
+                cache.len()
+            )
+        };
+
+        group.bench_function(group_name("database reads"), |b| {
+            b.iter(|| black_box(state_root()).root().unwrap());
+        });
+
+        group.bench_function(group_name(&format!("cached reads: {reads_saved} per root")), |b| {
+            b.iter(|| black_box(state_root().with_upper_trie_cache(cache.clone())).root().unwrap());
+        });
+    }
+}
+
+criterion_group! {
+    name = upper_trie_cache;
+    config = Criterion::default();
+    targets = incremental_root_with_upper_trie_cache
+}
+criterion_main!(upper_trie_cache);
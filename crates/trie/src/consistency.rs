@@ -0,0 +1,448 @@
+use crate::{
+    trie_cursor::{AccountTrieCursor, StorageTrieCursor, TrieCursor},
+    PersistedTrieError, StateRootError,
+};
+use reth_db::{
+    cursor::{DbCursorRO, DbDupCursorRO},
+    tables,
+    transaction::DbTx,
+    DatabaseError,
+};
+use reth_primitives::{
+    trie::{BranchNodeCompact, Nibbles, TrieMask},
+    B256,
+};
+
+/// A persisted trie node with children that are not backed by any hashed entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedTrieNode {
+    /// The path of the node.
+    pub path: Nibbles,
+    /// The children in the state mask of the node without any hashed entry under their path.
+    pub unsupported_children: TrieMask,
+    /// Whether none of the children of the node are backed by hashed entries.
+    pub orphaned: bool,
+}
+
+/// A hashed entry for which the persisted trie lacks an expected branch node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingTrieNode {
+    /// The path of the child that should lead to the hashed entry.
+    ///
+    /// Either the parent node doesn't have this child in its state mask, or the parent marks the
+    /// child as stored in the trie but no such node leads to the hashed entry.
+    pub path: Nibbles,
+    /// The hashed key of the entry.
+    pub hashed_key: B256,
+}
+
+/// The anomalies between the hashed state and the persisted trie tables found by
+/// [StateRoot::check_trie_consistency](crate::StateRoot::check_trie_consistency).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrieConsistencyReport {
+    /// Account trie nodes with children that have no hashed accounts.
+    pub unsupported_account_nodes: Vec<UnsupportedTrieNode>,
+    /// Hashed accounts whose branch nodes are missing from the account trie.
+    pub missing_account_nodes: Vec<MissingTrieNode>,
+    /// Storage trie nodes with children that have no hashed storage entries, by hashed address.
+    pub unsupported_storage_nodes: Vec<(B256, UnsupportedTrieNode)>,
+    /// Hashed storage entries whose branch nodes are missing from the storage trie, by hashed
+    /// address.
+    pub missing_storage_nodes: Vec<(B256, MissingTrieNode)>,
+}
+
+impl TrieConsistencyReport {
+    /// Returns `true` if no anomalies were found.
+    pub fn is_consistent(&self) -> bool {
+        self.unsupported_account_nodes.is_empty() &&
+            self.missing_account_nodes.is_empty() &&
+            self.unsupported_storage_nodes.is_empty() &&
+            self.missing_storage_nodes.is_empty()
+    }
+}
+
+/// Walks the hashed state and the trie tables and reports the anomalies between them.
+pub(crate) fn check_trie_consistency<TX: DbTx>(
+    tx: &TX,
+) -> Result<TrieConsistencyReport, StateRootError> {
+    let mut report = TrieConsistencyReport::default();
+
+    // Every child of a persisted account trie node must lead to at least one hashed account.
+    let mut hashed_account_cursor = tx.cursor_read::<tables::HashedAccount>()?;
+    for entry in tx.cursor_read::<tables::AccountsTrie>()?.walk(None)? {
+        let (path, node) = entry?;
+        let path = Nibbles::from_hex(path.inner);
+        let unsupported = unsupported_children(&path, &node, |key| {
+            Ok(hashed_account_cursor.seek(key)?.map(|(hashed_address, _)| hashed_address))
+        })?;
+        if let Some(node) = UnsupportedTrieNode::new(path, &node, unsupported) {
+            report.unsupported_account_nodes.push(node);
+        }
+    }
+
+    // Every hashed account must be reachable through the persisted account trie nodes.
+    let mut account_path =
+        TrieNodePath::new(AccountTrieCursor::new(tx.cursor_read::<tables::AccountsTrie>()?));
+    for entry in tx.cursor_read::<tables::HashedAccount>()?.walk(None)? {
+        let (hashed_address, _) = entry?;
+        if let Some(path) = account_path.find_missing(hashed_address)? {
+            report.missing_account_nodes.push(MissingTrieNode { path, hashed_key: hashed_address });
+        }
+    }
+
+    // Every child of a persisted storage trie node must lead to at least one hashed slot.
+    let mut hashed_storage_cursor = tx.cursor_dup_read::<tables::HashedStorage>()?;
+    for entry in tx.cursor_dup_read::<tables::StoragesTrie>()?.walk(None)? {
+        let (hashed_address, entry) = entry?;
+        let path = Nibbles::from_hex(entry.nibbles.inner.clone());
+        let unsupported = unsupported_children(&path, &entry.node, |key| {
+            Ok(hashed_storage_cursor.seek_by_key_subkey(hashed_address, key)?.map(|e| e.key))
+        })?;
+        if let Some(node) = UnsupportedTrieNode::new(path, &entry.node, unsupported) {
+            report.unsupported_storage_nodes.push((hashed_address, node));
+        }
+    }
+
+    // Every hashed slot must be reachable through the persisted storage trie nodes.
+    let mut storage_path = None;
+    for entry in tx.cursor_dup_read::<tables::HashedStorage>()?.walk(None)? {
+        let (hashed_address, entry) = entry?;
+        if storage_path.as_ref().map_or(true, |(address, _)| *address != hashed_address) {
+            let cursor = StorageTrieCursor::new(
+                tx.cursor_dup_read::<tables::StoragesTrie>()?,
+                hashed_address,
+            );
+            storage_path = Some((hashed_address, TrieNodePath::new(cursor)));
+        }
+        if let Some((_, path)) = &mut storage_path {
+            if let Some(path) = path.find_missing(entry.key)? {
+                report
+                    .missing_storage_nodes
+                    .push((hashed_address, MissingTrieNode { path, hashed_key: entry.key }));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// The depth up to which
+/// [StateRoot::verify_persisted_trie](crate::StateRoot::verify_persisted_trie) checks the
+/// structure of the persisted account trie nodes.
+pub const PERSISTED_TRIE_CHECK_DEPTH: usize = 4;
+
+/// Checks the persisted account trie nodes top-down, up to [PERSISTED_TRIE_CHECK_DEPTH] nibbles.
+///
+/// Every visited node must be well-formed and every child in its tree mask must be persisted.
+/// Returns the first inconsistency.
+pub(crate) fn check_persisted_account_nodes<TX: DbTx>(tx: &TX) -> Result<(), PersistedTrieError> {
+    let mut cursor = AccountTrieCursor::new(tx.cursor_read::<tables::AccountsTrie>()?);
+
+    // The root node is never persisted, so the top nodes are the first ones below every nibble.
+    let mut pending = Vec::new();
+    for nibble in (0..16u8).rev() {
+        if let Some(node) = seek_child(&mut cursor, &Nibbles::from_hex(vec![nibble]))? {
+            pending.push(node);
+        }
+    }
+
+    while let Some((path, node)) = pending.pop() {
+        let is_well_formed = node.tree_mask.is_subset_of(&node.state_mask) &&
+            node.hash_mask.is_subset_of(&node.state_mask) &&
+            node.hash_mask.count_ones() as usize == node.hashes.len();
+        if !is_well_formed {
+            return Err(PersistedTrieError::MalformedNode { path })
+        }
+        if path.len() >= PERSISTED_TRIE_CHECK_DEPTH {
+            continue
+        }
+
+        for nibble in (0..16u8).rev() {
+            if !node.tree_mask.is_bit_set(nibble) {
+                continue
+            }
+            let mut child = path.clone();
+            child.extend([nibble]);
+            match seek_child(&mut cursor, &child)? {
+                Some(child) => pending.push(child),
+                None => return Err(PersistedTrieError::MissingChild { path, nibble }),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the first persisted node with the child path as prefix. This is the node of the child
+/// if it is persisted, or the node below it if the child is an extension.
+fn seek_child<C: TrieCursor>(
+    cursor: &mut C,
+    child: &Nibbles,
+) -> Result<Option<(Nibbles, BranchNodeCompact)>, DatabaseError> {
+    Ok(cursor
+        .seek(child.to_vec().into())?
+        .map(|(path, node)| (Nibbles::from_hex(path), node))
+        .filter(|(path, _)| path.has_prefix(child)))
+}
+
+impl UnsupportedTrieNode {
+    /// Returns the anomaly for the node, if any of its children are unsupported.
+    fn new(
+        path: Nibbles,
+        node: &BranchNodeCompact,
+        unsupported_children: TrieMask,
+    ) -> Option<Self> {
+        (!unsupported_children.is_empty()).then(|| Self {
+            path,
+            unsupported_children,
+            orphaned: unsupported_children == node.state_mask,
+        })
+    }
+}
+
+/// Returns the children in the state mask of the node at `path` that don't have any hashed key
+/// with their path as prefix.
+///
+/// `seek_hashed` returns the first hashed key greater or equal to the given key.
+fn unsupported_children(
+    path: &Nibbles,
+    node: &BranchNodeCompact,
+    mut seek_hashed: impl FnMut(B256) -> Result<Option<B256>, DatabaseError>,
+) -> Result<TrieMask, DatabaseError> {
+    let mut unsupported = TrieMask::default();
+    for nibble in 0..16u8 {
+        if !node.state_mask.is_bit_set(nibble) {
+            continue
+        }
+
+        let mut child = path.clone();
+        child.extend([nibble]);
+        let supported = seek_hashed(smallest_key_with_prefix(&child))?
+            .map_or(false, |key| Nibbles::unpack(key).has_prefix(&child));
+        if !supported {
+            unsupported |= TrieMask::from_nibble(nibble);
+        }
+    }
+    Ok(unsupported)
+}
+
+/// Returns the smallest hashed key with the given nibble prefix.
+fn smallest_key_with_prefix(prefix: &Nibbles) -> B256 {
+    let mut nibbles = prefix.to_vec();
+    nibbles.resize(64, 0);
+    B256::from_slice(&Nibbles::from_hex(nibbles).pack())
+}
+
+/// The persisted branch nodes along the path of the last checked hashed key.
+///
+/// Since hashed keys are checked in ascending order, consecutive keys share the nodes of their
+/// common prefix and only the diverging part of the path is read from the trie table.
+struct TrieNodePath<C> {
+    cursor: C,
+    /// The stored nodes from the top of the trie down to the deepest node on the path of the last
+    /// key.
+    stack: Vec<(Nibbles, BranchNodeCompact)>,
+}
+
+impl<C: TrieCursor> TrieNodePath<C> {
+    fn new(cursor: C) -> Self {
+        Self { cursor, stack: Vec::new() }
+    }
+
+    /// Descends the persisted nodes along the path of the hashed key and returns the path of the
+    /// child for which an expected node is missing, if any.
+    fn find_missing(&mut self, hashed_key: B256) -> Result<Option<Nibbles>, DatabaseError> {
+        let key = Nibbles::unpack(hashed_key);
+
+        while self.stack.last().map_or(false, |(path, _)| !key.has_prefix(path)) {
+            self.stack.pop();
+        }
+
+        if self.stack.is_empty() {
+            // The root node is never persisted. The parent of a persisted node is always
+            // persisted, so the first node below the first nibble of the key is the top node of
+            // that subtrie if there is any.
+            let child = key.slice(0, 1);
+            match self.seek_child(&child)? {
+                Some((path, node)) if key.has_prefix(&path) => self.stack.push((path, node)),
+                Some(_) => return Ok(Some(child)),
+                None => return Ok(None),
+            }
+        }
+
+        while let Some((path, node)) = self.stack.last() {
+            let Some(&nibble) = key.get(path.len()) else { return Ok(None) };
+
+            let mut child = path.clone();
+            child.extend([nibble]);
+            if !node.state_mask.is_bit_set(nibble) {
+                return Ok(Some(child))
+            }
+            if !node.tree_mask.is_bit_set(nibble) {
+                // The child is a leaf or a subtrie that is only referenced by its hash.
+                return Ok(None)
+            }
+
+            match self.seek_child(&child)? {
+                Some((path, node)) if key.has_prefix(&path) => self.stack.push((path, node)),
+                _ => return Ok(Some(child)),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// See [seek_child].
+    fn seek_child(
+        &mut self,
+        child: &Nibbles,
+    ) -> Result<Option<(Nibbles, BranchNodeCompact)>, DatabaseError> {
+        seek_child(&mut self.cursor, child)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StateRoot;
+    use reth_db::{cursor::DbCursorRW, test_utils::create_test_rw_db, transaction::DbTxMut};
+    use reth_primitives::{
+        keccak256,
+        trie::{StorageTrieEntry, StoredNibblesSubKey},
+        Account, StorageEntry, MAINNET, U256,
+    };
+    use reth_provider::ProviderFactory;
+
+    fn insert_state(tx: &impl DbTxMut) -> B256 {
+        let account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        for i in 0..256u64 {
+            tx.put::<tables::HashedAccount>(keccak256(B256::with_last_byte(i as u8)), account)
+                .unwrap();
+        }
+
+        let hashed_address = keccak256(B256::ZERO);
+        for i in 0..256u64 {
+            let key = keccak256(U256::from(i).to_be_bytes::<32>());
+            tx.put::<tables::HashedStorage>(
+                hashed_address,
+                StorageEntry { key, value: U256::from(i + 1) },
+            )
+            .unwrap();
+        }
+        hashed_address
+    }
+
+    #[test]
+    fn consistent_trie() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        insert_state(tx.tx_ref());
+        let (_, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        let report = StateRoot::check_trie_consistency(tx.tx_ref()).unwrap();
+        assert!(report.is_consistent(), "{report:?}");
+    }
+
+    #[test]
+    fn verify_persisted_trie() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        insert_state(tx.tx_ref());
+        let (root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+        assert_eq!(StateRoot::verify_persisted_trie(tx.tx_ref(), root), Ok(()));
+
+        let other = B256::random();
+        assert_eq!(
+            StateRoot::verify_persisted_trie(tx.tx_ref(), other),
+            Err(PersistedTrieError::RootMismatch { expected: other, actual: root })
+        );
+
+        // a corrupted hash changes the root
+        let mut cursor = tx.tx_ref().cursor_write::<tables::AccountsTrie>().unwrap();
+        let (path, mut node) = cursor.first().unwrap().unwrap();
+        assert!(!node.hashes.is_empty());
+        node.hashes[0] = B256::random();
+        cursor.upsert(path.clone(), node.clone()).unwrap();
+        assert!(matches!(
+            StateRoot::verify_persisted_trie(tx.tx_ref(), root),
+            Err(PersistedTrieError::RootMismatch { .. })
+        ));
+
+        // a stored child that is not persisted
+        let nibble = (0..16u8).find(|nibble| !node.tree_mask.is_bit_set(*nibble)).unwrap();
+        node.state_mask |= TrieMask::from_nibble(nibble);
+        node.tree_mask |= TrieMask::from_nibble(nibble);
+        cursor.upsert(path.clone(), node.clone()).unwrap();
+        assert_eq!(
+            StateRoot::verify_persisted_trie(tx.tx_ref(), root),
+            Err(PersistedTrieError::MissingChild {
+                path: Nibbles::from_hex(path.inner.to_vec()),
+                nibble
+            })
+        );
+
+        // a stored child outside of the state mask
+        node.state_mask = TrieMask::new(*node.state_mask & !*TrieMask::from_nibble(nibble));
+        cursor.upsert(path.clone(), node).unwrap();
+        assert_eq!(
+            StateRoot::verify_persisted_trie(tx.tx_ref(), root),
+            Err(PersistedTrieError::MalformedNode { path: Nibbles::from_hex(path.inner.to_vec()) })
+        );
+    }
+
+    #[test]
+    fn inconsistent_trie() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_address = insert_state(tx.tx_ref());
+        let (_, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        // remove a persisted account node
+        let (removed, _) =
+            tx.tx_ref().cursor_read::<tables::AccountsTrie>().unwrap().first().unwrap().unwrap();
+        tx.tx_ref().delete::<tables::AccountsTrie>(removed.clone(), None).unwrap();
+        let removed = Nibbles::from_hex(removed.inner);
+
+        // add a storage node without any hashed slots below it
+        let orphan = Nibbles::from_hex(vec![0xf; 5]);
+        let node = BranchNodeCompact::new(0b1, 0, 0, Vec::new(), None);
+        tx.tx_ref()
+            .put::<tables::StoragesTrie>(
+                hashed_address,
+                StorageTrieEntry {
+                    nibbles: StoredNibblesSubKey::from(orphan.to_vec()),
+                    node: node.clone(),
+                },
+            )
+            .unwrap();
+
+        let report = StateRoot::check_trie_consistency(tx.tx_ref()).unwrap();
+        assert!(report.unsupported_account_nodes.is_empty());
+        assert!(!report.missing_account_nodes.is_empty());
+        for missing in &report.missing_account_nodes {
+            assert!(removed.has_prefix(&missing.path));
+            assert!(Nibbles::unpack(missing.hashed_key).has_prefix(&removed));
+        }
+        assert_eq!(
+            report.unsupported_storage_nodes,
+            Vec::from([(
+                hashed_address,
+                UnsupportedTrieNode {
+                    path: orphan,
+                    unsupported_children: TrieMask::new(0b1),
+                    orphaned: true
+                }
+            )])
+        );
+        assert!(report.missing_storage_nodes.is_empty());
+    }
+}
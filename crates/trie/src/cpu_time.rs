@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+/// Measures the CPU time consumed by the current thread since it was started.
+///
+/// Unlike the elapsed wall-clock time, the CPU time excludes the time the thread spends blocked,
+/// e.g. waiting for database pages to be read from disk. On platforms without a per-thread CPU
+/// clock, the elapsed wall-clock time is measured instead.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ThreadCpuTimer {
+    started_at: Duration,
+}
+
+impl ThreadCpuTimer {
+    /// Starts measuring the CPU time of the current thread.
+    pub(crate) fn start() -> Self {
+        Self { started_at: thread_cpu_time() }
+    }
+
+    /// Returns the CPU time consumed by the current thread since the timer was started.
+    ///
+    /// The timer must be read on the thread that started it.
+    pub(crate) fn elapsed(&self) -> Duration {
+        thread_cpu_time().saturating_sub(self.started_at)
+    }
+}
+
+#[cfg(unix)]
+fn thread_cpu_time() -> Duration {
+    let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: the timespec is valid for writes for the duration of the call.
+    let result = unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) };
+    debug_assert_eq!(result, 0, "thread CPU clock unavailable");
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+#[cfg(not(unix))]
+fn thread_cpu_time() -> Duration {
+    use std::{sync::OnceLock, time::Instant};
+
+    static STARTED_AT: OnceLock<Instant> = OnceLock::new();
+    STARTED_AT.get_or_init(Instant::now).elapsed()
+}
@@ -1,3 +1,8 @@
+use reth_interfaces::provider::ProviderError;
+use reth_primitives::{
+    trie::{BranchNodeCompact, Nibbles},
+    B256,
+};
 use thiserror::Error;
 
 /// State root error.
@@ -9,21 +14,145 @@ pub enum StateRootError {
     /// Storage root error.
     #[error(transparent)]
     StorageRootError(#[from] StorageRootError),
+    /// Storage root error of an account encountered during state root computation.
+    #[error("failed to compute storage root of account {hashed_address}: {source}")]
+    StorageRoot {
+        /// The hashed address of the account.
+        hashed_address: B256,
+        /// The storage root error.
+        source: StorageRootError,
+    },
+    /// The computation of the storage root of an account panicked on a worker of
+    /// [StateRoot::parallel_root_with_updates](crate::StateRoot::parallel_root_with_updates).
+    #[error("storage root computation of account {hashed_address} panicked")]
+    StorageRootPanicked {
+        /// The hashed address of the account.
+        hashed_address: B256,
+    },
+    /// The persisted trie does not match the expected root before the changes were applied.
+    #[error("pre-state root mismatch: expected {expected}, got {actual}")]
+    PreRootMismatch {
+        /// The expected root before the changes.
+        expected: B256,
+        /// The root represented by the persisted trie.
+        actual: B256,
+    },
+    /// The computation was aborted by its cancellation token, see
+    /// [StateRoot::with_cancellation](crate::StateRoot::with_cancellation).
+    #[error("state root computation cancelled")]
+    Cancelled,
+    /// The computation exceeded its CPU budget without being able to return the intermediate
+    /// progress, see [StateRoot::with_cpu_budget](crate::StateRoot::with_cpu_budget).
+    #[error("state root computation exceeded its CPU budget")]
+    CpuBudgetExceeded,
+    /// The computed root does not match the expected one, see
+    /// [StateRoot::root_expecting](crate::StateRoot::root_expecting).
+    #[error("state root mismatch: expected {expected}, computed {computed}")]
+    RootMismatch {
+        /// The expected root.
+        expected: B256,
+        /// The computed root.
+        computed: B256,
+    },
+    /// A computed account trie branch node differs from the expected one, see
+    /// [StateRoot::root_with_divergence_check](crate::StateRoot::root_with_divergence_check).
+    #[error("account trie diverges at {path:?}: expected {expected:?}, computed {computed:?}")]
+    DivergenceAt {
+        /// The path of the first diverging branch node.
+        path: Nibbles,
+        /// The expected branch node, if any.
+        expected: Option<Box<BranchNodeCompact>>,
+        /// The computed branch node, if any.
+        computed: Option<Box<BranchNodeCompact>>,
+    },
 }
 
-impl From<StateRootError> for reth_db::DatabaseError {
+impl From<StateRootError> for ProviderError {
     fn from(err: StateRootError) -> Self {
         match err {
-            StateRootError::DB(err) => err,
-            StateRootError::StorageRootError(StorageRootError::DB(err)) => err,
+            StateRootError::DB(err) => Self::Database(err),
+            StateRootError::StorageRootError(StorageRootError::DB(err)) => Self::Database(err),
+            StateRootError::StorageRoot { source: StorageRootError::DB(err), .. } => {
+                Self::Database(err)
+            }
+            err => Self::StateRootComputation(err.to_string()),
         }
     }
 }
 
+/// Error returned by [StateRoot::verify_persisted_trie](crate::StateRoot::verify_persisted_trie)
+/// at the first inconsistency of the persisted account trie.
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum PersistedTrieError {
+    /// Internal error while reading the trie or computing the root.
+    #[error(transparent)]
+    StateRoot(#[from] StateRootError),
+    /// A persisted node whose masks contradict each other or its hashes.
+    #[error("malformed account trie node at {path:?}")]
+    MalformedNode {
+        /// The path of the node.
+        path: Nibbles,
+    },
+    /// A child that is marked as stored in the tree mask of a node, but not persisted.
+    #[error("account trie node at {path:?} is missing its stored child {nibble:x}")]
+    MissingChild {
+        /// The path of the node.
+        path: Nibbles,
+        /// The nibble of the missing child.
+        nibble: u8,
+    },
+    /// The persisted trie doesn't produce the expected root.
+    #[error("persisted trie root mismatch: expected {expected}, got {actual}")]
+    RootMismatch {
+        /// The expected root.
+        expected: B256,
+        /// The root produced by the persisted trie.
+        actual: B256,
+    },
+}
+
+impl From<reth_db::DatabaseError> for PersistedTrieError {
+    fn from(err: reth_db::DatabaseError) -> Self {
+        Self::StateRoot(err.into())
+    }
+}
+
+/// Error returned by the [Proof](crate::proof::Proof) generator.
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum ProofError {
+    /// Error while walking the account trie.
+    #[error(transparent)]
+    StateRoot(#[from] StateRootError),
+    /// Error while walking a storage trie.
+    #[error(transparent)]
+    StorageRoot(#[from] StorageRootError),
+    /// A trie node is deeper than the configured maximum proof depth.
+    #[error("trie node at {path:?} exceeds the maximum proof depth of {max_depth}")]
+    DepthExceeded {
+        /// The path of the node.
+        path: Nibbles,
+        /// The maximum proof depth.
+        max_depth: usize,
+    },
+}
+
+impl From<reth_db::DatabaseError> for ProofError {
+    fn from(err: reth_db::DatabaseError) -> Self {
+        Self::StateRoot(err.into())
+    }
+}
+
 /// Storage root error.
 #[derive(Error, PartialEq, Eq, Clone, Debug)]
 pub enum StorageRootError {
     /// Internal database error.
     #[error(transparent)]
     DB(#[from] reth_db::DatabaseError),
+    /// The plain address of the account is needed to read its history and changesets, but the
+    /// calculator was created from the hashed address.
+    #[error("plain address of account {hashed_address} is unknown")]
+    MissingAddress {
+        /// The hashed address of the account.
+        hashed_address: B256,
+    },
 }
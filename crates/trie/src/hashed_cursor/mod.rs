@@ -7,6 +7,10 @@ mod default;
 mod post_state;
 pub use post_state::*;
 
+/// Iterator over the hashed accounts under a key prefix.
+mod prefix;
+pub use prefix::HashedAccountPrefixIter;
+
 /// The factory trait for creating cursors over the hashed state.
 pub trait HashedCursorFactory {
     /// The hashed account cursor type.
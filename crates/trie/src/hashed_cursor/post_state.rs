@@ -2,11 +2,18 @@ use super::{HashedAccountCursor, HashedCursorFactory, HashedStorageCursor};
 use crate::prefix_set::{PrefixSet, PrefixSetMut};
 use reth_db::{
     cursor::{DbCursorRO, DbDupCursorRO},
+    models::{AccountBeforeTx, BlockNumberAddress},
     tables,
     transaction::{DbTx, DbTxGAT},
+    DatabaseError,
+};
+use reth_primitives::{
+    keccak256, trie::Nibbles, Account, Address, BlockNumber, StorageEntry, B256, U256,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
 };
-use reth_primitives::{trie::Nibbles, Account, StorageEntry, B256, U256};
-use std::collections::{HashMap, HashSet};
 
 /// The post state account storage with hashed slots.
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -78,6 +85,59 @@ impl Default for HashedPostState {
 }
 
 impl HashedPostState {
+    /// Construct the hashed state as it was before the given block range from the account and
+    /// storage changesets of the range.
+    ///
+    /// When layered over the hashed tables, the result represents the state at the parent of the
+    /// first block in the range.
+    pub fn from_changesets<TX: DbTx>(
+        tx: &TX,
+        range: RangeInclusive<BlockNumber>,
+    ) -> Result<Self, DatabaseError> {
+        // The first changeset entry of each account and slot holds its value before the range.
+        let mut accounts = HashMap::<Address, Option<Account>>::default();
+        let mut account_changeset_cursor = tx.cursor_read::<tables::AccountChangeSet>()?;
+        for account_entry in account_changeset_cursor.walk_range(range.clone())? {
+            let (_, AccountBeforeTx { address, info }) = account_entry?;
+            accounts.entry(address).or_insert(info);
+        }
+
+        let mut storages = HashMap::<Address, HashMap<B256, U256>>::default();
+        let mut storage_changeset_cursor = tx.cursor_dup_read::<tables::StorageChangeSet>()?;
+        for storage_entry in
+            storage_changeset_cursor.walk_range(BlockNumberAddress::range(range))?
+        {
+            let (BlockNumberAddress((_, address)), StorageEntry { key, value }) = storage_entry?;
+            storages.entry(address).or_default().entry(key).or_insert(value);
+        }
+
+        let mut this = Self::default();
+        for (address, info) in &accounts {
+            let hashed_address = keccak256(address);
+            match info {
+                Some(account) => this.insert_account(hashed_address, *account),
+                None => this.insert_cleared_account(hashed_address),
+            }
+        }
+
+        for (address, storage) in storages {
+            // Accounts that did not exist before the range had no storage either.
+            let wiped = matches!(accounts.get(&address), Some(None));
+            let mut hashed_storage = HashedStorage::new(wiped);
+            for (slot, value) in storage {
+                let hashed_slot = keccak256(slot);
+                if value == U256::ZERO {
+                    hashed_storage.insert_zero_valued_slot(hashed_slot);
+                } else {
+                    hashed_storage.insert_non_zero_valued_storage(hashed_slot, value);
+                }
+            }
+            this.insert_hashed_storage(keccak256(address), hashed_storage);
+        }
+
+        Ok(this.sorted())
+    }
+
     /// Sort and return self.
     pub fn sorted(mut self) -> Self {
         self.sort();
@@ -107,6 +167,11 @@ impl HashedPostState {
         self.cleared_accounts.insert(hashed_address);
     }
 
+    /// Returns the cleared hashed account keys.
+    pub fn cleared_accounts(&self) -> &HashSet<B256> {
+        &self.cleared_accounts
+    }
+
     /// Insert hashed storage entry.
     pub fn insert_hashed_storage(&mut self, hashed_address: B256, hashed_storage: HashedStorage) {
         self.sorted &= hashed_storage.sorted;
@@ -0,0 +1,113 @@
+use super::HashedAccountCursor;
+use reth_primitives::{trie::Nibbles, Account, B256};
+
+/// An iterator over the hashed account leaves whose keys start with the given nibble prefix.
+///
+/// The cursor is positioned at the first key under the prefix and the iteration stops at the
+/// first key that no longer matches it.
+#[derive(Debug)]
+pub struct HashedAccountPrefixIter<C> {
+    /// The cursor for the hashed account entries.
+    cursor: C,
+    /// The prefix of the hashed account keys.
+    prefix: Nibbles,
+    /// Whether the cursor has been positioned at the start of the prefix.
+    seeked: bool,
+    /// Whether the iteration has finished.
+    done: bool,
+}
+
+impl<C> HashedAccountPrefixIter<C> {
+    /// Create new iterator over hashed accounts under the given prefix.
+    ///
+    /// # Panics
+    ///
+    /// If the prefix is longer than a hashed key.
+    pub fn new(cursor: C, prefix: Nibbles) -> Self {
+        assert!(prefix.len() <= 64, "prefix is longer than a hashed key");
+        Self { cursor, prefix, seeked: false, done: false }
+    }
+}
+
+impl<C: HashedAccountCursor> HashedAccountPrefixIter<C> {
+    fn next_entry(&mut self) -> Result<Option<(B256, Account)>, reth_db::DatabaseError> {
+        let entry = if self.seeked {
+            self.cursor.next()?
+        } else {
+            self.seeked = true;
+            // The prefix padded with zero nibbles is the smallest key under it.
+            let packed = self.prefix.pack();
+            let mut start = B256::ZERO;
+            start[..packed.len()].copy_from_slice(&packed);
+            self.cursor.seek(start)?
+        };
+        Ok(entry.filter(|(key, _)| Nibbles::unpack(key).has_prefix(&self.prefix)))
+    }
+}
+
+impl<C: HashedAccountCursor> Iterator for HashedAccountPrefixIter<C> {
+    type Item = Result<(B256, Account), reth_db::DatabaseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+
+        let entry = self.next_entry().transpose();
+        self.done = !matches!(entry, Some(Ok(_)));
+        entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashed_cursor::HashedCursorFactory;
+    use reth_db::{
+        database::Database, tables, test_utils::create_test_rw_db, transaction::DbTxMut,
+    };
+    use reth_primitives::{hex_literal::hex, keccak256, Address, U256};
+
+    #[test]
+    fn accounts_under_prefix() {
+        // The hashed account keys of the `account_and_storage_trie` regression test.
+        let key1 =
+            B256::new(hex!("b000000000000000000000000000000000000000000000000000000000000000"));
+        let key2 = keccak256(Address::new(hex!("7db3e81b72d2695e19764583f6d219dbee0f35ca")));
+        let key3 = keccak256(Address::new(hex!("16b07afd1c635f77172e842a000ead9a2a222459")));
+        let key4a =
+            B256::new(hex!("B1A0000000000000000000000000000000000000000000000000000000000000"));
+        let key5 =
+            B256::new(hex!("B310000000000000000000000000000000000000000000000000000000000000"));
+        let key6 =
+            B256::new(hex!("B340000000000000000000000000000000000000000000000000000000000000"));
+        let keys = [key1, key2, key3, key4a, key5, key6];
+
+        let db = create_test_rw_db();
+        db.update(|tx| {
+            for (nonce, key) in keys.iter().enumerate() {
+                let account =
+                    Account { nonce: nonce as u64, balance: U256::ZERO, bytecode_hash: None };
+                tx.put::<tables::HashedAccount>(*key, account).unwrap();
+            }
+        })
+        .unwrap();
+
+        let tx = db.tx().unwrap();
+        let accounts_under = |prefix: &[u8]| {
+            let cursor = (&tx).hashed_account_cursor().unwrap();
+            HashedAccountPrefixIter::new(cursor, Nibbles::from_hex(prefix.to_vec()))
+                .map(|entry| entry.unwrap().0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(accounts_under(&[0xB, 0x0]), vec![key1, key2, key3]);
+        assert_eq!(accounts_under(&[0xB, 0x0, 0x4]), vec![key2, key3]);
+        assert_eq!(accounts_under(&[0xB, 0x0, 0x4, 0x1]), vec![key3]);
+        assert_eq!(accounts_under(&[0xB, 0x3]), vec![key5, key6]);
+        assert_eq!(accounts_under(&[0xB]), keys.to_vec());
+        assert!(accounts_under(&[0xB, 0x2]).is_empty());
+        assert!(accounts_under(&[0xA]).is_empty());
+        assert_eq!(accounts_under(&[]), keys.to_vec());
+    }
+}
@@ -42,14 +42,38 @@ pub mod proof;
 
 /// The implementation of the Merkle Patricia Trie.
 mod trie;
-pub use trie::{StateRoot, StorageRoot};
+pub use trie::{
+    InlineStorageRoots, StateRoot, StorageProofChunks, StorageRoot, StorageRootProvider,
+    StorageSlotChange,
+};
+
+/// The hooks recording the metrics of the state root computation.
+mod metrics;
+pub use metrics::{NoopTrieMetrics, TrieMetrics, TrieStats};
+
+/// The CPU time measurement of the state root computation.
+mod cpu_time;
+
+/// The cache of computed storage roots.
+mod storage_root_cache;
+pub use storage_root_cache::StorageRootCache;
+
+/// State root computation from a sorted stream of hashed entries.
+mod sorted;
+pub use sorted::compute_root_from_sorted;
 
 /// Buffer for trie updates.
 pub mod updates;
 
+/// Consistency checks between the hashed state and the trie tables.
+pub mod consistency;
+
 /// Utilities for state root checkpoint progress.
 mod progress;
-pub use progress::{IntermediateStateRootState, StateRootProgress};
+pub use progress::{
+    IntermediateStateRootState, IntermediateStorageRootState, StateRootProgress,
+    StorageRootProgress,
+};
 
 /// Collection of trie-related test utilities.
 #[cfg(any(test, feature = "test-utils"))]
@@ -0,0 +1,100 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// The hooks recording the metrics of the state root computation, see
+/// [StateRoot::with_metrics](crate::StateRoot::with_metrics).
+///
+/// All hooks do nothing by default, so a recorder only implements the ones it is interested in.
+/// The hooks are called on the thread of the computation and should be cheap, e.g. increment a
+/// counter.
+pub trait TrieMetrics: Debug + Send + Sync {
+    /// Records the given number of walked account leaves.
+    fn record_account_leaves(&self, _n: usize) {}
+
+    /// Records the given number of walked storage leaves.
+    fn record_storage_leaves(&self, _n: usize) {}
+
+    /// Records the given number of branch nodes of the account trie added to the hash builder.
+    fn record_branch_nodes(&self, _n: usize) {}
+
+    /// Records the duration of a computation. A computation that returns the intermediate
+    /// progress records the duration of every resumed part.
+    fn record_duration(&self, _duration: Duration) {}
+}
+
+/// The [TrieMetrics] recorder that records nothing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopTrieMetrics;
+
+impl TrieMetrics for NoopTrieMetrics {}
+
+/// The counts of the nodes walked by a state root computation, see
+/// [StateRoot::root_with_stats](crate::StateRoot::root_with_stats).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TrieStats {
+    /// The number of walked account leaves.
+    pub accounts_walked: usize,
+    /// The number of walked storage leaves.
+    pub storage_slots_walked: usize,
+    /// The number of stored branch nodes of the account trie added to the hash builder, i.e. the
+    /// unchanged subtries that were not walked.
+    pub branch_nodes: usize,
+}
+
+/// The [TrieMetrics] recorder counting the [TrieStats] before forwarding the hooks to the recorder
+/// of the computation.
+#[derive(Debug)]
+pub(crate) struct TrieStatsRecorder {
+    inner: Arc<dyn TrieMetrics>,
+    accounts_walked: AtomicUsize,
+    storage_slots_walked: AtomicUsize,
+    branch_nodes: AtomicUsize,
+}
+
+impl TrieStatsRecorder {
+    /// Creates a new recorder forwarding the hooks to the given one.
+    pub(crate) fn new(inner: Arc<dyn TrieMetrics>) -> Self {
+        Self {
+            inner,
+            accounts_walked: AtomicUsize::new(0),
+            storage_slots_walked: AtomicUsize::new(0),
+            branch_nodes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the counted stats.
+    pub(crate) fn stats(&self) -> TrieStats {
+        TrieStats {
+            accounts_walked: self.accounts_walked.load(Ordering::Relaxed),
+            storage_slots_walked: self.storage_slots_walked.load(Ordering::Relaxed),
+            branch_nodes: self.branch_nodes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl TrieMetrics for TrieStatsRecorder {
+    fn record_account_leaves(&self, n: usize) {
+        self.accounts_walked.fetch_add(n, Ordering::Relaxed);
+        self.inner.record_account_leaves(n);
+    }
+
+    fn record_storage_leaves(&self, n: usize) {
+        self.storage_slots_walked.fetch_add(n, Ordering::Relaxed);
+        self.inner.record_storage_leaves(n);
+    }
+
+    fn record_branch_nodes(&self, n: usize) {
+        self.branch_nodes.fetch_add(n, Ordering::Relaxed);
+        self.inner.record_branch_nodes(n);
+    }
+
+    fn record_duration(&self, duration: Duration) {
+        self.inner.record_duration(duration);
+    }
+}
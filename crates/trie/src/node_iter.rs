@@ -134,6 +134,9 @@ pub(crate) struct StorageNodeIter<C, H> {
     pub(crate) hashed_storage_cursor: H,
     /// The hashed address this storage trie belongs to.
     hashed_address: B256,
+    /// The previous slot key. If the iteration was previously interrupted, this value can be
+    /// used to resume iterating from the last returned leaf node.
+    previous_slot_key: Option<B256>,
 
     /// Current hashed storage entry.
     current_hashed_entry: Option<StorageEntry>,
@@ -151,10 +154,16 @@ impl<C, H> StorageNodeIter<C, H> {
             walker,
             hashed_storage_cursor,
             hashed_address,
+            previous_slot_key: None,
             current_walker_key_checked: false,
             current_hashed_entry: None,
         }
     }
+
+    pub(crate) fn with_last_slot_key(mut self, previous_slot_key: B256) -> Self {
+        self.previous_slot_key = Some(previous_slot_key);
+        self
+    }
 }
 
 impl<C, H> StorageNodeIter<C, H>
@@ -171,10 +180,12 @@ where
     /// 3. Reposition the hashed storage cursor on the next unprocessed key.
     /// 4. Return every hashed storage entry up to the key of the current intermediate branch node.
     /// 5. Repeat.
+    ///
+    /// NOTE: The iteration will start from the key of the previous hashed entry if it was supplied.
     pub(crate) fn try_next(&mut self) -> Result<Option<StorageNode>, StorageRootError> {
         loop {
             if let Some(key) = self.walker.key() {
-                if !self.current_walker_key_checked {
+                if !self.current_walker_key_checked && self.previous_slot_key.is_none() {
                     self.current_walker_key_checked = true;
                     if self.walker.can_skip_current_node {
                         return Ok(Some(StorageNode::Branch(TrieBranchNode::new(
@@ -197,10 +208,18 @@ where
                 return Ok(Some(StorageNode::Leaf(hashed_key, value)))
             }
 
-            let Some(seek_key) = self.walker.next_unprocessed_key() else { break };
-            self.current_hashed_entry =
-                self.hashed_storage_cursor.seek(self.hashed_address, seek_key)?;
-            self.walker.advance()?;
+            match self.previous_slot_key.take() {
+                Some(slot_key) => {
+                    self.hashed_storage_cursor.seek(self.hashed_address, slot_key)?;
+                    self.current_hashed_entry = self.hashed_storage_cursor.next()?;
+                }
+                None => {
+                    let Some(seek_key) = self.walker.next_unprocessed_key() else { break };
+                    self.current_hashed_entry =
+                        self.hashed_storage_cursor.seek(self.hashed_address, seek_key)?;
+                    self.walker.advance()?;
+                }
+            }
         }
 
         Ok(None)
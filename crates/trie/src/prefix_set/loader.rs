@@ -24,6 +24,71 @@ pub struct LoadedPrefixSets {
     pub destroyed_accounts: HashSet<B256>,
 }
 
+impl LoadedPrefixSets {
+    /// Derives the prefix sets and the destroyed accounts from an ordered log of state changes in
+    /// a single pass.
+    ///
+    /// An account is destroyed if its last account change in the log destroys it, a later update
+    /// recreates it. The storage of a recreated account is computed from its changed slots, so the
+    /// log must also clear the slots that were wiped when the account was destroyed.
+    pub fn from_change_log(changes: impl IntoIterator<Item = StateChange>) -> Self {
+        let mut loaded_prefix_sets = Self::default();
+        for change in changes {
+            match change {
+                StateChange::AccountUpdated(hashed_address) => {
+                    loaded_prefix_sets.account_prefix_set.insert(Nibbles::unpack(hashed_address));
+                    loaded_prefix_sets.destroyed_accounts.remove(&hashed_address);
+                }
+                StateChange::AccountDestroyed(hashed_address) => {
+                    loaded_prefix_sets.account_prefix_set.insert(Nibbles::unpack(hashed_address));
+                    loaded_prefix_sets.destroyed_accounts.insert(hashed_address);
+                }
+                StateChange::StorageUpdated { hashed_address, hashed_slot } |
+                StateChange::StorageCleared { hashed_address, hashed_slot } => {
+                    loaded_prefix_sets.account_prefix_set.insert(Nibbles::unpack(hashed_address));
+                    loaded_prefix_sets
+                        .storage_prefix_sets
+                        .entry(hashed_address)
+                        .or_default()
+                        .insert(Nibbles::unpack(hashed_slot));
+                }
+            }
+        }
+        loaded_prefix_sets
+    }
+
+    /// Returns `true` if no account or storage changed.
+    pub fn is_empty(&self) -> bool {
+        self.account_prefix_set.is_empty() &&
+            self.storage_prefix_sets.is_empty() &&
+            self.destroyed_accounts.is_empty()
+    }
+}
+
+/// A change of the hashed state, as recorded in an ordered log of the changes between two root
+/// computations, see [LoadedPrefixSets::from_change_log].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateChange {
+    /// The account with the given hashed address was created or updated.
+    AccountUpdated(B256),
+    /// The account with the given hashed address was destroyed, together with its storage.
+    AccountDestroyed(B256),
+    /// A storage slot was set to a non-zero value.
+    StorageUpdated {
+        /// The hashed address of the account.
+        hashed_address: B256,
+        /// The hashed slot.
+        hashed_slot: B256,
+    },
+    /// A storage slot was set to zero.
+    StorageCleared {
+        /// The hashed address of the account.
+        hashed_address: B256,
+        /// The hashed slot.
+        hashed_slot: B256,
+    },
+}
+
 /// A wrapper around a database transaction that loads prefix sets within a given block range.
 #[derive(Debug, Deref)]
 pub struct PrefixSetLoader<'a, TX>(&'a TX);
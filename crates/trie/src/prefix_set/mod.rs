@@ -1,8 +1,8 @@
 use reth_primitives::trie::Nibbles;
-use std::rc::Rc;
+use std::sync::Arc;
 
 mod loader;
-pub use loader::{LoadedPrefixSets, PrefixSetLoader};
+pub use loader::{LoadedPrefixSets, PrefixSetLoader, StateChange};
 
 /// A container for efficiently storing and checking for the presence of key prefixes.
 ///
@@ -102,7 +102,7 @@ impl PrefixSetMut {
             self.keys.dedup();
         }
 
-        PrefixSet { keys: Rc::new(self.keys), index: self.index }
+        PrefixSet { keys: Arc::new(self.keys), index: self.index }
     }
 }
 
@@ -111,7 +111,7 @@ impl PrefixSetMut {
 /// See also [PrefixSetMut::freeze].
 #[derive(Debug, Default, Clone)]
 pub struct PrefixSet {
-    keys: Rc<Vec<Nibbles>>,
+    keys: Arc<Vec<Nibbles>>,
     index: usize,
 }
 
@@ -141,6 +141,11 @@ impl PrefixSet {
         false
     }
 
+    /// Returns an iterator over the keys in the set.
+    pub fn iter(&self) -> std::slice::Iter<'_, Nibbles> {
+        self.keys.iter()
+    }
+
     /// Returns the number of elements in the set.
     pub fn len(&self) -> usize {
         self.keys.len()
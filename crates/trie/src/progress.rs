@@ -1,5 +1,9 @@
 use crate::{trie_cursor::CursorSubNode, updates::TrieUpdates};
-use reth_primitives::{stage::MerkleCheckpoint, trie::hash_builder::HashBuilder, B256};
+use reth_primitives::{
+    stage::{MerkleCheckpoint, StorageRootMerkleCheckpoint},
+    trie::{hash_builder::HashBuilder, StoredSubNode},
+    Account, BlockNumber, B256,
+};
 
 /// The progress of the state root computation.
 #[derive(Debug)]
@@ -20,6 +24,32 @@ pub struct IntermediateStateRootState {
     pub walker_stack: Vec<CursorSubNode>,
     /// The last hashed account key processed.
     pub last_account_key: B256,
+    /// The account at [Self::last_account_key] and its interrupted storage root computation, if
+    /// the progress was returned in the middle of its storage trie. The leaf of the account is not
+    /// in the hash builder yet.
+    pub storage_root_state: Option<(Account, IntermediateStorageRootState)>,
+}
+
+impl IntermediateStateRootState {
+    /// Converts the intermediate state into a checkpoint of the computation of the state root at
+    /// the given target block, including the interrupted storage root computation, if any.
+    pub fn into_checkpoint(self, target_block: BlockNumber) -> MerkleCheckpoint {
+        let storage_root_checkpoint = self.storage_root_state.map(|(account, storage_state)| {
+            StorageRootMerkleCheckpoint::new(
+                account,
+                storage_state.last_slot_key,
+                storage_state.walker_stack.into_iter().map(StoredSubNode::from).collect(),
+                storage_state.hash_builder.into(),
+            )
+        });
+        MerkleCheckpoint::new(
+            target_block,
+            self.last_account_key,
+            self.walker_stack.into_iter().map(StoredSubNode::from).collect(),
+            self.hash_builder.into(),
+        )
+        .with_storage_root_checkpoint(storage_root_checkpoint)
+    }
 }
 
 impl From<MerkleCheckpoint> for IntermediateStateRootState {
@@ -28,6 +58,39 @@ impl From<MerkleCheckpoint> for IntermediateStateRootState {
             hash_builder: HashBuilder::from(value.state),
             walker_stack: value.walker_stack.into_iter().map(CursorSubNode::from).collect(),
             last_account_key: value.last_account_key,
+            storage_root_state: value.storage_root_checkpoint.map(|checkpoint| {
+                let storage_state = IntermediateStorageRootState {
+                    hash_builder: HashBuilder::from(checkpoint.state),
+                    walker_stack: checkpoint
+                        .walker_stack
+                        .into_iter()
+                        .map(CursorSubNode::from)
+                        .collect(),
+                    last_slot_key: checkpoint.last_slot_key,
+                };
+                (checkpoint.account, storage_state)
+            }),
         }
     }
 }
+
+/// The progress of the storage root computation.
+#[derive(Debug)]
+pub enum StorageRootProgress {
+    /// The complete storage root computation with updates and computed root.
+    Complete(B256, usize, TrieUpdates),
+    /// The intermediate progress of storage root computation.
+    /// Contains the walker stack, the hash builder and the trie updates.
+    Progress(Box<IntermediateStorageRootState>, usize, TrieUpdates),
+}
+
+/// The intermediate state of the storage root computation.
+#[derive(Debug)]
+pub struct IntermediateStorageRootState {
+    /// Previously constructed hash builder.
+    pub hash_builder: HashBuilder,
+    /// Previously recorded walker stack.
+    pub walker_stack: Vec<CursorSubNode>,
+    /// The last hashed storage slot processed.
+    pub last_slot_key: B256,
+}
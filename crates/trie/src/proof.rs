@@ -5,7 +5,7 @@ use crate::{
     prefix_set::PrefixSetMut,
     trie_cursor::{AccountTrieCursor, StorageTrieCursor},
     walker::TrieWalker,
-    StateRootError, StorageRootError,
+    ProofError,
 };
 use alloy_rlp::{BufMut, Encodable};
 use reth_db::{tables, transaction::DbTx};
@@ -16,6 +16,12 @@ use reth_primitives::{
     Address, B256,
 };
 
+/// The default maximum depth of the trie nodes walked by the [Proof] generator.
+///
+/// The keys of the state and storage tries are 32 byte hashes, so no valid node is deeper than 64
+/// nibbles.
+pub const DEFAULT_MAX_PROOF_DEPTH: usize = 64;
+
 /// A struct for generating merkle proofs.
 ///
 /// Proof generator adds the target address and slots to the prefix set, enables the proof retainer
@@ -27,13 +33,38 @@ pub struct Proof<'a, TX, H> {
     tx: &'a TX,
     /// The factory for hashed cursors.
     hashed_cursor_factory: H,
+    /// The maximum depth of the walked trie nodes.
+    max_proof_depth: usize,
 }
 
 impl<'a, TX> Proof<'a, TX, &'a TX> {
     /// Create a new [Proof] instance.
     pub fn new(tx: &'a TX) -> Self {
-        Self { tx, hashed_cursor_factory: tx }
+        Self { tx, hashed_cursor_factory: tx, max_proof_depth: DEFAULT_MAX_PROOF_DEPTH }
+    }
+}
+
+impl<'a, TX, H> Proof<'a, TX, H> {
+    /// Set the maximum depth of the walked trie nodes. Proof generation fails with
+    /// [ProofError::DepthExceeded] on a deeper node, which only a corrupt trie can contain with
+    /// the [default](DEFAULT_MAX_PROOF_DEPTH) depth.
+    pub fn with_max_proof_depth(mut self, max_proof_depth: usize) -> Self {
+        self.max_proof_depth = max_proof_depth;
+        self
+    }
+
+    /// Return an error if the node at the given path is deeper than the maximum proof depth.
+    fn check_depth(&self, path: &Nibbles) -> Result<(), ProofError> {
+        check_proof_depth(path, self.max_proof_depth)
+    }
+}
+
+/// Return an error if the node at the given path is deeper than the given maximum proof depth.
+pub(crate) fn check_proof_depth(path: &Nibbles, max_depth: usize) -> Result<(), ProofError> {
+    if path.len() > max_depth {
+        return Err(ProofError::DepthExceeded { path: path.clone(), max_depth })
     }
+    Ok(())
 }
 
 impl<'a, TX, H> Proof<'a, TX, H>
@@ -46,7 +77,7 @@ where
         &self,
         address: Address,
         slots: &[B256],
-    ) -> Result<AccountProof, StateRootError> {
+    ) -> Result<AccountProof, ProofError> {
         let target_hashed_address = keccak256(address);
         let target_nibbles = Nibbles::unpack(target_hashed_address);
         let mut account_proof = AccountProof::new(address);
@@ -68,6 +99,7 @@ where
         while let Some(account_node) = account_node_iter.try_next()? {
             match account_node {
                 AccountNode::Branch(node) => {
+                    self.check_depth(&node.key)?;
                     hash_builder.add_branch(node.key, node.value, node.children_are_in_trie);
                 }
                 AccountNode::Leaf(hashed_address, account) => {
@@ -92,13 +124,16 @@ where
         let _ = hash_builder.root();
 
         let proofs = hash_builder.take_proofs();
+        for path in proofs.keys() {
+            self.check_depth(path)?;
+        }
         account_proof.set_proof(proofs.values().cloned().collect());
 
         Ok(account_proof)
     }
 
     /// Compute storage root.
-    pub fn storage_root(&self, hashed_address: B256) -> Result<B256, StorageRootError> {
+    pub fn storage_root(&self, hashed_address: B256) -> Result<B256, ProofError> {
         let (storage_root, _) = self.storage_root_with_proofs(hashed_address, &[])?;
         Ok(storage_root)
     }
@@ -108,7 +143,7 @@ where
         &self,
         hashed_address: B256,
         slots: &[B256],
-    ) -> Result<(B256, Vec<StorageProof>), StorageRootError> {
+    ) -> Result<(B256, Vec<StorageProof>), ProofError> {
         let mut hashed_storage_cursor = self.hashed_cursor_factory.hashed_storage_cursor()?;
 
         let mut proofs = slots.iter().copied().map(StorageProof::new).collect::<Vec<_>>();
@@ -132,6 +167,7 @@ where
         while let Some(node) = storage_node_iter.try_next()? {
             match node {
                 StorageNode::Branch(node) => {
+                    self.check_depth(&node.key)?;
                     hash_builder.add_branch(node.key, node.value, node.children_are_in_trie);
                 }
                 StorageNode::Leaf(hashed_slot, value) => {
@@ -147,6 +183,9 @@ where
         let root = hash_builder.root();
 
         let all_proof_nodes = hash_builder.take_proofs();
+        for path in all_proof_nodes.keys() {
+            self.check_depth(path)?;
+        }
         for proof in proofs.iter_mut() {
             // Iterate over all proof nodes and find the matching ones.
             // The filtered results are guaranteed to be in order.
@@ -164,11 +203,14 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::StateRoot;
+    use crate::{test_utils::verify_witness, StateRoot, StorageRoot};
     use once_cell::sync::Lazy;
-    use reth_db::{database::Database, test_utils::create_test_rw_db};
-    use reth_interfaces::RethResult;
-    use reth_primitives::{Account, Bytes, Chain, ChainSpec, StorageEntry, HOLESKY, MAINNET, U256};
+    use reth_db::{database::Database, test_utils::create_test_rw_db, transaction::DbTxMut};
+    use reth_interfaces::{provider::ProviderError, RethResult};
+    use reth_primitives::{
+        trie::{BranchNodeCompact, StoredNibbles},
+        Account, Bytes, Chain, ChainSpec, StorageEntry, HOLESKY, MAINNET, U256,
+    };
     use reth_provider::{HashingWriter, ProviderFactory};
     use std::{str::FromStr, sync::Arc};
 
@@ -222,7 +264,7 @@ mod tests {
 
         let (_, updates) = StateRoot::new(provider.tx_ref())
             .root_with_updates()
-            .map_err(Into::<reth_db::DatabaseError>::into)?;
+            .map_err(Into::<ProviderError>::into)?;
         updates.flush(provider.tx_mut())?;
 
         provider.commit()?;
@@ -288,6 +330,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn testspec_proof_max_depth() {
+        // Create test database and insert genesis accounts.
+        let db = create_test_rw_db();
+        insert_genesis(db.clone(), TEST_SPEC.clone()).unwrap();
+
+        let tx = db.tx().unwrap();
+
+        // The proof nodes of the target are at most 6 nibbles deep.
+        let target = Address::from_str("0x1ed9b1dd266b607ee278726d324b855a093394a6").unwrap();
+        let expected = Proof::new(&tx).account_proof(target, &[]).unwrap();
+        assert_eq!(
+            Proof::new(&tx).with_max_proof_depth(6).account_proof(target, &[]).unwrap(),
+            expected
+        );
+        assert!(matches!(
+            Proof::new(&tx).with_max_proof_depth(2).account_proof(target, &[]),
+            Err(ProofError::DepthExceeded { path, max_depth: 2 }) if path.len() > 2
+        ));
+    }
+
+    #[test]
+    fn corrupt_trie_proof_depth_exceeded() {
+        let db = create_test_rw_db();
+
+        // A node deeper than any valid key.
+        let corrupt_path = vec![0; DEFAULT_MAX_PROOF_DEPTH + 2];
+        let node =
+            BranchNodeCompact::new(0b11, 0, 0b11, vec![B256::random(), B256::random()], None);
+        let tx = db.tx_mut().unwrap();
+        tx.put::<tables::AccountsTrie>(StoredNibbles::from(corrupt_path), node).unwrap();
+        tx.commit().unwrap();
+
+        let tx = db.tx().unwrap();
+        assert!(matches!(
+            Proof::new(&tx).account_proof(Address::random(), &[]),
+            Err(ProofError::DepthExceeded { path, max_depth: DEFAULT_MAX_PROOF_DEPTH })
+                if path.len() > DEFAULT_MAX_PROOF_DEPTH
+        ));
+    }
+
     #[test]
     fn testspec_empty_storage_proof() {
         // Create test database and insert genesis accounts.
@@ -438,4 +521,81 @@ mod tests {
         let account_proof = Proof::new(&tx).account_proof(target, &slots).unwrap();
         pretty_assertions::assert_eq!(account_proof, expected);
     }
+
+    #[test]
+    fn account_proofs_verify_against_state_root() {
+        let db = create_test_rw_db();
+        let tx = db.tx_mut().unwrap();
+
+        let accounts = (1..=50u64)
+            .map(|nonce| (Address::random(), Account { nonce, ..Default::default() }))
+            .collect::<Vec<_>>();
+        for (idx, (address, account)) in accounts.iter().enumerate() {
+            let hashed_address = keccak256(address);
+            tx.put::<tables::HashedAccount>(hashed_address, *account).unwrap();
+            for slot in 0..idx as u64 % 5 {
+                let entry = StorageEntry { key: B256::random(), value: U256::from(slot + 1) };
+                tx.put::<tables::HashedStorage>(hashed_address, entry).unwrap();
+            }
+        }
+        let root = StateRoot::new(&tx).root().unwrap();
+
+        // Inclusion proofs resolve to the encoded account.
+        for (address, account) in &accounts {
+            let account_proof = Proof::new(&tx).account_proof(*address, &[]).unwrap();
+            assert_eq!(account_proof.info, Some(*account));
+
+            let mut expected = Vec::new();
+            EthAccount::from(*account)
+                .with_storage_root(account_proof.storage_root)
+                .encode(&mut expected as &mut dyn BufMut);
+            assert_eq!(
+                verify_witness(&account_proof.proof, root, keccak256(address)),
+                Some(expected)
+            );
+        }
+
+        // Exclusion proof of a missing account.
+        let missing = Address::random();
+        let account_proof = Proof::new(&tx).account_proof(missing, &[]).unwrap();
+        assert_eq!(account_proof.info, None);
+        assert_eq!(verify_witness(&account_proof.proof, root, keccak256(missing)), None);
+    }
+
+    #[test]
+    fn storage_proofs_verify_against_storage_root() {
+        let db = create_test_rw_db();
+        let tx = db.tx_mut().unwrap();
+
+        let hashed_address = B256::random();
+        let slots = (0..20u64).map(|slot| B256::from(U256::from(slot))).collect::<Vec<_>>();
+        for (idx, slot) in slots.iter().enumerate() {
+            let entry = StorageEntry { key: keccak256(slot), value: U256::from(idx + 1) };
+            tx.put::<tables::HashedStorage>(hashed_address, entry).unwrap();
+        }
+        let missing = B256::random();
+        let requested = slots.iter().copied().chain([missing]).collect::<Vec<_>>();
+
+        let expected_root = StorageRoot::new_hashed(&tx, hashed_address).root().unwrap();
+        let (root, proofs) =
+            Proof::new(&tx).storage_root_with_proofs(hashed_address, &requested).unwrap();
+        assert_eq!(root, expected_root);
+        assert_eq!(proofs.len(), requested.len());
+        for (idx, proof) in proofs.iter().enumerate() {
+            let value = verify_witness(&proof.proof, root, keccak256(proof.key));
+            if proof.key == missing {
+                assert_eq!(proof.value, U256::ZERO);
+                assert_eq!(value, None);
+            } else {
+                assert_eq!(proof.value, U256::from(idx + 1));
+                assert_eq!(value, Some(alloy_rlp::encode_fixed_size(&proof.value).to_vec()));
+            }
+        }
+
+        // The proofs of an empty storage are empty.
+        let (root, proofs) =
+            Proof::new(&tx).storage_root_with_proofs(B256::random(), &requested).unwrap();
+        assert_eq!(root, EMPTY_ROOT_HASH);
+        assert!(proofs.iter().all(|proof| proof.proof.is_empty() && proof.value == U256::ZERO));
+    }
 }
@@ -0,0 +1,106 @@
+use crate::account::EthAccount;
+use alloy_rlp::{BufMut, Encodable};
+use reth_primitives::{
+    trie::{HashBuilder, Nibbles},
+    B256, U256,
+};
+
+/// Computes the state root from the complete hashed state, without a database.
+///
+/// The accounts must be sorted by hashed address and the storage entries, given as
+/// `(hashed_address, hashed_slot, value)`, by hashed address and then by hashed slot. Since there
+/// are no existing trie nodes to reuse, the leaves are fed straight into a [HashBuilder].
+///
+/// The storage root of every account is computed from its storage entries, replacing the one it
+/// carries. Storage entries of addresses without an account are skipped.
+pub fn compute_root_from_sorted<A, S>(accounts: A, storage: S) -> B256
+where
+    A: IntoIterator<Item = (B256, EthAccount)>,
+    S: IntoIterator<Item = (B256, B256, U256)>,
+{
+    let mut storage = storage.into_iter().peekable();
+    let mut hash_builder = HashBuilder::default();
+    let mut last_hashed_address = None;
+    let mut account_rlp = Vec::with_capacity(128);
+
+    for (hashed_address, account) in accounts {
+        // The hash builder requires the leaves in strictly ascending order.
+        if let Some(last) = last_hashed_address {
+            debug_assert!(
+                last < hashed_address,
+                "hashed accounts out of order: {hashed_address} after {last}"
+            );
+        }
+        last_hashed_address = Some(hashed_address);
+
+        let mut storage_hash_builder = HashBuilder::default();
+        let mut last_hashed_slot = None;
+        while let Some((address, hashed_slot, value)) =
+            storage.next_if(|(address, _, _)| *address <= hashed_address)
+        {
+            if address < hashed_address {
+                continue
+            }
+
+            if let Some(last) = last_hashed_slot {
+                debug_assert!(
+                    last < hashed_slot,
+                    "hashed storage slots out of order: {hashed_slot} after {last}"
+                );
+            }
+            last_hashed_slot = Some(hashed_slot);
+            storage_hash_builder.add_leaf(
+                Nibbles::unpack(hashed_slot),
+                alloy_rlp::encode_fixed_size(&value).as_ref(),
+            );
+        }
+
+        let account = account.with_storage_root(storage_hash_builder.root());
+
+        account_rlp.clear();
+        account.encode(&mut account_rlp as &mut dyn BufMut);
+
+        hash_builder.add_leaf(Nibbles::unpack(hashed_address), &account_rlp);
+    }
+
+    hash_builder.root()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::state_root_prehashed;
+    use proptest::{prelude::ProptestConfig, proptest};
+    use reth_primitives::Account;
+    use std::collections::BTreeMap;
+
+    type State = BTreeMap<B256, (Account, BTreeMap<B256, U256>)>;
+
+    #[test]
+    fn arbitrary_root_from_sorted() {
+        proptest!(ProptestConfig::with_cases(10), |(state: State)| {
+            let accounts = state
+                .iter()
+                .map(|(hashed_address, (account, _))| (*hashed_address, EthAccount::from(*account)));
+            let storage = state.iter().flat_map(|(hashed_address, (_, storage))| {
+                storage.iter().map(|(hashed_slot, value)| (*hashed_address, *hashed_slot, *value))
+            });
+            let got = compute_root_from_sorted(accounts, storage);
+
+            assert_eq!(got, state_root_prehashed(state.into_iter()));
+        });
+    }
+
+    #[test]
+    fn skips_storage_without_account() {
+        let account = EthAccount::from(Account { nonce: 1, ..Default::default() });
+        let orphan = (B256::with_last_byte(1), B256::with_last_byte(1), U256::from(1));
+        let root = compute_root_from_sorted([(B256::with_last_byte(2), account)], [orphan]);
+
+        let expected = state_root_prehashed(
+            [(B256::with_last_byte(2), (Account { nonce: 1, ..Default::default() }, []))]
+                .into_iter(),
+        );
+        assert_eq!(root, expected);
+    }
+}
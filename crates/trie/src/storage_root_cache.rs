@@ -0,0 +1,126 @@
+use crate::{prefix_set::PrefixSet, updates::TrieUpdates};
+use parking_lot::Mutex;
+use reth_primitives::{keccak256, B256};
+use schnellru::{ByLength, LruMap};
+use std::{fmt, sync::Arc};
+
+/// A least recently used cache of computed storage roots that is shared between storage root
+/// calculations.
+///
+/// See [StorageRoot::with_storage_root_cache](crate::StorageRoot::with_storage_root_cache).
+///
+/// The entries are keyed by the hashed address of the account and a fingerprint of its changed
+/// storage prefixes, so a calculation with different changes never hits an entry of another one.
+/// The hashed storage itself is not part of the key: invalidating the entries once the hashed
+/// storage changes in a way that is not reflected by the changed prefixes, e.g. by clearing the
+/// cache after the storage tries are flushed, is the responsibility of the caller.
+///
+/// Clones share the same entries.
+#[derive(Clone)]
+pub struct StorageRootCache {
+    entries: Arc<Mutex<LruMap<StorageRootCacheKey, CachedStorageRoot>>>,
+}
+
+impl StorageRootCache {
+    /// Creates a new cache holding at most `max_entries` storage roots.
+    pub fn new(max_entries: u32) -> Self {
+        Self { entries: Arc::new(Mutex::new(LruMap::new(ByLength::new(max_entries)))) }
+    }
+
+    /// Returns the number of cached storage roots.
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    /// Returns `true` if no storage roots are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all cached storage roots.
+    pub fn clear(&self) {
+        self.entries.lock().clear();
+    }
+
+    /// Returns the cached storage root and, if `retain_updates` is set, the cached trie updates.
+    ///
+    /// An entry that was computed without retaining the updates is a miss if they are requested.
+    pub(crate) fn get(
+        &self,
+        key: &StorageRootCacheKey,
+        retain_updates: bool,
+    ) -> Option<(B256, TrieUpdates)> {
+        let mut entries = self.entries.lock();
+        let entry = entries.get(key)?;
+        match &entry.updates {
+            Some(updates) if retain_updates => Some((entry.root, updates.clone())),
+            None if retain_updates => None,
+            _ => Some((entry.root, TrieUpdates::default())),
+        }
+    }
+
+    /// Caches the computed storage root and the trie updates, if they were retained.
+    pub(crate) fn insert(
+        &self,
+        key: StorageRootCacheKey,
+        root: B256,
+        updates: Option<TrieUpdates>,
+    ) {
+        self.entries.lock().insert(key, CachedStorageRoot { root, updates });
+    }
+}
+
+impl fmt::Debug for StorageRootCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StorageRootCache").field("len", &self.len()).finish()
+    }
+}
+
+/// The key of a [StorageRootCache] entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct StorageRootCacheKey {
+    /// The hashed address of the account.
+    hashed_address: B256,
+    /// The hash of the changed storage prefixes and the options affecting the root and the trie
+    /// updates.
+    fingerprint: B256,
+}
+
+impl StorageRootCacheKey {
+    /// Creates the key of the storage root calculation with the given inputs.
+    ///
+    /// The prefixes of a frozen [PrefixSet] are sorted and unique, so equal sets produce equal
+    /// fingerprints. Every prefix is length-prefixed to keep the encoding unambiguous.
+    pub(crate) fn new(
+        hashed_address: B256,
+        changed_prefixes: &PrefixSet,
+        emit_storage_deletes: bool,
+        retained_depth: Option<usize>,
+        empty_root: B256,
+    ) -> Self {
+        let mut encoded = Vec::with_capacity(42 + changed_prefixes.len() * 65);
+        encoded.extend_from_slice(empty_root.as_slice());
+        encoded.push(emit_storage_deletes as u8);
+        match retained_depth {
+            Some(depth) => {
+                encoded.push(1);
+                encoded.extend_from_slice(&(depth as u64).to_be_bytes());
+            }
+            None => encoded.push(0),
+        }
+        for prefix in changed_prefixes.iter() {
+            encoded.push(prefix.hex_data.len() as u8);
+            encoded.extend_from_slice(&prefix.hex_data);
+        }
+        Self { hashed_address, fingerprint: keccak256(encoded) }
+    }
+}
+
+/// A cached storage root.
+#[derive(Debug)]
+struct CachedStorageRoot {
+    /// The storage root.
+    root: B256,
+    /// The trie updates, if they were retained.
+    updates: Option<TrieUpdates>,
+}
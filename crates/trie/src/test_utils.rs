@@ -1,6 +1,13 @@
-use crate::account::EthAccount;
+use crate::{account::EthAccount, IntermediateStateRootState};
 use alloy_rlp::{encode_fixed_size, Encodable};
-use reth_primitives::{proofs::triehash::KeccakHasher, Account, Address, B256, U256};
+use reth_primitives::{
+    constants::EMPTY_ROOT_HASH,
+    keccak256,
+    proofs::triehash::KeccakHasher,
+    trie::{HashBuilder, Nibbles},
+    Account, Address, Bytes, B256, U256,
+};
+use std::collections::HashMap;
 
 /// Re-export of [triehash].
 pub use triehash;
@@ -49,3 +56,105 @@ pub fn storage_root_prehashed<I: Iterator<Item = (B256, U256)>>(storage: I) -> B
     let encoded_storage = storage.map(|(k, v)| (k, encode_fixed_size(&v).to_vec()));
     triehash::trie_root::<KeccakHasher, _, _, _>(encoded_storage)
 }
+
+/// Constructs the intermediate state of a state root computation over empty trie tables, as if it
+/// was interrupted right after the last of the given prehashed accounts.
+///
+/// The accounts must be sorted by key. Since there are no trie nodes to walk, the walker stack is
+/// empty and all progress is in the hash builder.
+///
+/// # Panics
+///
+/// If no accounts are given.
+pub fn intermediate_state_prehashed<I, S>(accounts: I) -> IntermediateStateRootState
+where
+    I: Iterator<Item = (B256, (Account, S))>,
+    S: IntoIterator<Item = (B256, U256)>,
+{
+    let mut hash_builder = HashBuilder::default();
+    let mut last_account_key = None;
+    for (hashed_address, (account, storage)) in accounts {
+        let storage_root = storage_root_prehashed(storage.into_iter());
+        let mut out = Vec::new();
+        EthAccount::from(account).with_storage_root(storage_root).encode(&mut out);
+        hash_builder.add_leaf(Nibbles::unpack(hashed_address), &out);
+        last_account_key = Some(hashed_address);
+    }
+
+    IntermediateStateRootState {
+        hash_builder,
+        walker_stack: Vec::new(),
+        last_account_key: last_account_key.expect("no accounts"),
+        storage_root_state: None,
+    }
+}
+
+/// Looks up the value of the key by walking the witness nodes from the root, returns `None`
+/// if the witness proves that the key is missing.
+///
+/// # Panics
+///
+/// If a node on the path is not part of the witness.
+pub fn verify_witness(witness: &[Bytes], root: B256, key: B256) -> Option<Vec<u8>> {
+    fn list_items(node: &[u8]) -> Vec<&[u8]> {
+        let mut buf = node;
+        let header = alloy_rlp::Header::decode(&mut buf).unwrap();
+        assert!(header.list, "trie node is not a list");
+        let mut payload = &buf[..header.payload_length];
+        let mut items = Vec::new();
+        while !payload.is_empty() {
+            let item = payload;
+            let header = alloy_rlp::Header::decode(&mut payload).unwrap();
+            payload = &payload[header.payload_length..];
+            items.push(&item[..item.len() - payload.len()]);
+        }
+        items
+    }
+    fn string_payload(item: &[u8]) -> &[u8] {
+        let mut buf = item;
+        let header = alloy_rlp::Header::decode(&mut buf).unwrap();
+        &buf[..header.payload_length]
+    }
+    // children are either embedded, referenced by hash or empty
+    fn resolve<'a>(nodes: &HashMap<B256, &'a [u8]>, item: &'a [u8]) -> Option<&'a [u8]> {
+        if item[0] >= alloy_rlp::EMPTY_LIST_CODE {
+            return Some(item)
+        }
+        let hash = string_payload(item);
+        (!hash.is_empty()).then(|| nodes[&B256::from_slice(hash)])
+    }
+
+    let nodes = witness.iter().map(|node| (keccak256(node), &node[..])).collect::<HashMap<_, _>>();
+
+    if root == EMPTY_ROOT_HASH {
+        return None
+    }
+    let key = Nibbles::unpack(key);
+    let mut path = &key.hex_data[..];
+    let mut node = nodes[&root];
+    loop {
+        let items = list_items(node);
+        if items.len() == 17 {
+            let Some((nibble, rest)) = path.split_first() else {
+                return Some(string_payload(items[16]).to_vec())
+            };
+            path = rest;
+            node = resolve(&nodes, items[*nibble as usize])?;
+            continue
+        }
+
+        let encoded_path = string_payload(items[0]);
+        let mut node_path = Vec::new();
+        if encoded_path[0] & 0x10 != 0 {
+            node_path.push(encoded_path[0] & 0x0f);
+        }
+        for byte in &encoded_path[1..] {
+            node_path.extend([byte >> 4, byte & 0x0f]);
+        }
+        if encoded_path[0] & 0x20 != 0 {
+            return (path == node_path).then(|| string_payload(items[1]).to_vec())
+        }
+        path = path.strip_prefix(node_path.as_slice())?;
+        node = resolve(&nodes, items[1])?;
+    }
+}
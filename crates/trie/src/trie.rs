@@ -1,34 +1,111 @@
 use crate::{
     account::EthAccount,
-    hashed_cursor::{HashedCursorFactory, HashedStorageCursor},
+    consistency::{self, TrieConsistencyReport},
+    cpu_time::ThreadCpuTimer,
+    hashed_cursor::{
+        HashedCursorFactory, HashedPostState, HashedPostStateCursorFactory, HashedStorage,
+        HashedStorageCursor,
+    },
+    metrics::{NoopTrieMetrics, TrieMetrics, TrieStats, TrieStatsRecorder},
     node_iter::{AccountNode, AccountNodeIter, StorageNode, StorageNodeIter},
-    prefix_set::{PrefixSet, PrefixSetLoader, PrefixSetMut},
-    progress::{IntermediateStateRootState, StateRootProgress},
-    trie_cursor::{AccountTrieCursor, StorageTrieCursor},
+    prefix_set::{LoadedPrefixSets, PrefixSet, PrefixSetLoader, PrefixSetMut, StateChange},
+    progress::{
+        IntermediateStateRootState, IntermediateStorageRootState, StateRootProgress,
+        StorageRootProgress,
+    },
+    proof::{check_proof_depth, DEFAULT_MAX_PROOF_DEPTH},
+    storage_root_cache::{StorageRootCache, StorageRootCacheKey},
+    trie_cursor::{
+        AccountTrieCursor, PrefetchTrieCursor, StorageTrieCursor, TriePrefetcher, UpperTrieCache,
+        UpperTrieCacheCursor,
+    },
     updates::{TrieKey, TrieOp, TrieUpdates},
     walker::TrieWalker,
-    StateRootError, StorageRootError,
+    PersistedTrieError, ProofError, StateRootError, StorageRootError,
 };
 use alloy_rlp::{BufMut, Encodable};
-use reth_db::{tables, transaction::DbTx};
+use crossbeam_deque::{Injector, Steal};
+use parking_lot::{Condvar, Mutex};
+use reth_db::{
+    cursor::{DbCursorRO, DbDupCursorRO},
+    models::{storage_sharded_key::StorageShardedKey, BlockNumberAddress},
+    tables,
+    transaction::{DbTx, DbTxMut},
+};
 use reth_primitives::{
     constants::EMPTY_ROOT_HASH,
     keccak256,
-    trie::{HashBuilder, Nibbles},
-    Address, BlockNumber, B256,
+    trie::{BranchNodeCompact, HashBuilder, Nibbles, StorageProof},
+    Account, Address, BlockNumber, Bytes, StorageEntry, B256, U256,
 };
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     ops::RangeInclusive,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
+use tokio_util::sync::CancellationToken;
+
+/// Provides precomputed storage roots of accounts to the [StateRoot] computation.
+pub trait StorageRootProvider {
+    /// Returns the storage root of the account with the given hashed address, if it is known.
+    ///
+    /// If `None` is returned, the storage root is computed from the hashed storage.
+    fn storage_root(&self, hashed_address: B256) -> Option<B256>;
+}
+
+impl<T: StorageRootProvider> StorageRootProvider for &T {
+    fn storage_root(&self, hashed_address: B256) -> Option<B256> {
+        (**self).storage_root(hashed_address)
+    }
+}
+
+impl StorageRootProvider for HashMap<B256, B256> {
+    fn storage_root(&self, hashed_address: B256) -> Option<B256> {
+        self.get(&hashed_address).copied()
+    }
+}
+
+/// The default [StorageRootProvider] that doesn't know any storage roots, so all of them are
+/// computed from the hashed storage.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InlineStorageRoots;
+
+impl StorageRootProvider for InlineStorageRoots {
+    fn storage_root(&self, _hashed_address: B256) -> Option<B256> {
+        None
+    }
+}
+
+/// Encodes the account with the given storage root as a leaf of the account trie.
+fn encode_account(account: &Account, storage_root: B256) -> Bytes {
+    let account = EthAccount::from(*account).with_storage_root(storage_root);
+    let mut account_rlp = Vec::with_capacity(128);
+    account.encode(&mut account_rlp as &mut dyn BufMut);
+    account_rlp.into()
+}
+
+/// Includes every account of the hashed state in the account trie.
+///
+/// Empty accounts are not filtered, since [EIP-161](https://eips.ethereum.org/EIPS/eip-161)
+/// removes them from the state during execution, before they reach the hashed state.
+fn include_account(_account: &Account) -> bool {
+    true
+}
 
 /// StateRoot is used to compute the root node of a state trie.
 #[derive(Debug)]
-pub struct StateRoot<'a, TX, H> {
+pub struct StateRoot<'a, TX, H, S = InlineStorageRoots> {
     /// A reference to the database transaction.
     pub tx: &'a TX,
     /// The factory for hashed cursors.
     pub hashed_cursor_factory: H,
+    /// The provider of known storage roots.
+    pub storage_root_provider: S,
     /// A set of account prefixes that have changed.
     pub changed_account_prefixes: PrefixSet,
     /// A map containing storage changes with the hashed address as key and a set of storage key
@@ -38,11 +115,114 @@ pub struct StateRoot<'a, TX, H> {
     pub destroyed_accounts: HashSet<B256>,
     /// Previous intermediate state.
     previous_state: Option<IntermediateStateRootState>,
+    /// The options of the computation.
+    config: StateRootConfig,
+}
+
+/// The options of a [StateRoot] computation, moved as a whole when the calculator is re-targeted
+/// at another transaction, hashed cursor factory or storage root provider.
+#[derive(Debug, Clone)]
+struct StateRootConfig {
+    /// Whether to emit the deletes of empty and destroyed storage tries.
+    emit_storage_deletes: bool,
+    /// The maximum path length of the branch nodes to store, if limited.
+    retained_depth: Option<usize>,
+    /// The function encoding an account and its storage root into a leaf of the account trie.
+    account_encoder: fn(&Account, B256) -> Bytes,
+    /// The predicate deciding whether an account is included in the account trie.
+    account_inclusion_predicate: fn(&Account) -> bool,
+    /// The prefixes of the hashed addresses of the accounts to include, if not all of them.
+    account_prefix_bounds: Option<Vec<Nibbles>>,
+    /// The hashed addresses of the accounts to include, if not all of them.
+    account_allowlist: Option<HashSet<B256>>,
+    /// The cache of computed storage roots, if any.
+    storage_root_cache: Option<StorageRootCache>,
+    /// The cache of the upper account trie nodes, if any.
+    upper_trie_cache: Option<UpperTrieCache>,
+    /// The prefetcher reading the trie nodes ahead of the walks, if any.
+    prefetcher: Option<TriePrefetcher>,
     /// The number of updates after which the intermediate progress should be returned.
     threshold: u64,
+    /// Whether the intermediate progress may be returned in the middle of a storage trie.
+    storage_progress: bool,
+    /// The CPU time after which the intermediate progress should be returned, if limited.
+    cpu_budget: Option<Duration>,
+    /// The token aborting the computation once cancelled, if any.
+    cancellation: Option<CancellationToken>,
+    /// The recorder of the computation metrics.
+    metrics: Arc<dyn TrieMetrics>,
+    /// The root of an empty trie.
+    empty_root: B256,
+    /// The maximum depth of the trie nodes walked by the witness computation.
+    max_proof_depth: usize,
+}
+
+impl Default for StateRootConfig {
+    fn default() -> Self {
+        Self {
+            emit_storage_deletes: true,
+            retained_depth: None,
+            account_encoder: encode_account,
+            account_inclusion_predicate: include_account,
+            account_prefix_bounds: None,
+            account_allowlist: None,
+            storage_root_cache: None,
+            upper_trie_cache: None,
+            prefetcher: None,
+            threshold: 100_000,
+            storage_progress: false,
+            cpu_budget: None,
+            cancellation: None,
+            metrics: Arc::new(NoopTrieMetrics),
+            empty_root: EMPTY_ROOT_HASH,
+            max_proof_depth: DEFAULT_MAX_PROOF_DEPTH,
+        }
+    }
+}
+
+impl StateRootConfig {
+    /// Returns the changed account prefixes extended with the paths to the bounded prefixes and
+    /// the allowlisted accounts, if the computation is restricted to them.
+    ///
+    /// The walker has to descend along these paths, the nodes outside of them are skipped by the
+    /// computation. The updates would describe the partial trie, so none are kept.
+    fn restricted_account_prefixes(
+        &self,
+        changed_account_prefixes: &PrefixSet,
+    ) -> Option<PrefixSet> {
+        if self.account_prefix_bounds.is_none() && self.account_allowlist.is_none() {
+            return None
+        }
+        let prefixes = changed_account_prefixes
+            .iter()
+            .chain(self.account_prefix_bounds.iter().flatten())
+            .cloned()
+            .chain(self.account_allowlist.iter().flatten().map(Nibbles::unpack));
+        Some(PrefixSetMut::from(prefixes).freeze())
+    }
+
+    /// Returns `true` if the path is within the account prefix bounds, if any.
+    fn is_in_bounds(&self, key: &Nibbles) -> bool {
+        self.account_prefix_bounds
+            .as_ref()
+            .map_or(true, |bounds| bounds.iter().any(|prefix| key.has_prefix(prefix)))
+    }
+
+    /// Returns `true` if the account is in the account allowlist, if any.
+    fn is_allowlisted(&self, hashed_address: &B256) -> bool {
+        self.account_allowlist.as_ref().map_or(true, |allowlist| allowlist.contains(hashed_address))
+    }
+
+    /// Returns `true` if the leaf of the account is fed into the hash builder, so that its storage
+    /// root is computed.
+    fn includes_leaf(&self, hashed_address: &B256, account: &Account) -> bool {
+        self.is_in_bounds(&Nibbles::unpack(hashed_address)) &&
+            self.is_allowlisted(hashed_address) &&
+            (self.account_inclusion_predicate)(account)
+    }
 }
 
-impl<'a, TX, H> StateRoot<'a, TX, H> {
+impl<'a, TX, H, S> StateRoot<'a, TX, H, S> {
     /// Set the changed account prefixes.
     pub fn with_changed_account_prefixes(mut self, prefixes: PrefixSet) -> Self {
         self.changed_account_prefixes = prefixes;
@@ -61,15 +241,191 @@ impl<'a, TX, H> StateRoot<'a, TX, H> {
         self
     }
 
+    /// Set whether the updates should delete the storage tries of accounts without storage.
+    ///
+    /// Enabled by default. If disabled, neither the [TrieKey::StorageTrie] deletes of accounts
+    /// whose storage is found to be empty nor the ones of the destroyed accounts are emitted, so
+    /// cleaning up these storage tries is left to the caller. All other updates, including the
+    /// storage node updates of accounts with storage, are unaffected.
+    pub fn with_emit_storage_deletes(mut self, emit_storage_deletes: bool) -> Self {
+        self.config.emit_storage_deletes = emit_storage_deletes;
+        self
+    }
+
+    /// Set the maximum path length in nibbles of the branch nodes that are stored.
+    ///
+    /// Deeper branch nodes of the account trie and the storage tries are still hashed, but they are
+    /// not emitted as updates. This keeps the trie tables small at the cost of recomputing the
+    /// lower parts of the tries from the hashed state on every incremental update. If `None`, the
+    /// default, all branch nodes are stored.
+    pub fn with_retained_depth(mut self, depth: Option<usize>) -> Self {
+        self.config.retained_depth = depth;
+        self
+    }
+
+    /// Set the function that encodes an account and its storage root into an account trie leaf.
+    ///
+    /// Defaults to the RLP encoding of [EthAccount]. An alternative encoding allows computing the
+    /// root under the rules of a fork that changes the account encoding before it is activated.
+    pub fn with_account_encoder(mut self, encoder: fn(&Account, B256) -> Bytes) -> Self {
+        self.config.account_encoder = encoder;
+        self
+    }
+
+    /// Set the predicate deciding whether an account of the hashed state is included in the account
+    /// trie, the excluded accounts are skipped as if they didn't exist.
+    ///
+    /// Defaults to including every account. The branch nodes stored in the trie tables reflect the
+    /// predicate they were computed with, so a different predicate only applies to the walked
+    /// accounts, for example in a computation over empty trie tables or with all account prefixes
+    /// marked as changed.
+    pub fn with_account_inclusion_predicate(mut self, predicate: fn(&Account) -> bool) -> Self {
+        self.config.account_inclusion_predicate = predicate;
+        self
+    }
+
+    /// Restrict the computation to the accounts whose hashed addresses start with one of the
+    /// given nibble prefixes, for example the top-level nibbles `0x0..=0x3` of a shard.
+    ///
+    /// The computed root is **not** the state root, but the root of a partial trie that only
+    /// contains the accounts under the prefixes. Within the prefixes, the subtrees of the partial
+    /// trie are the same as the ones of the full trie, so their stored hashes are still reused.
+    /// Since the trie tables hold the nodes of the full trie, no updates are collected and no
+    /// intermediate progress is returned for a bounded computation.
+    pub fn with_account_prefix_bounds(mut self, prefixes: Vec<Nibbles>) -> Self {
+        self.config.account_prefix_bounds = Some(prefixes);
+        self
+    }
+
+    /// Restrict the computation to the accounts with the given hashed addresses, for example the
+    /// contracts of a single protocol.
+    ///
+    /// The computed root is **not** the state root, but the root of a partial trie that only
+    /// contains the allowlisted accounts. The walk descends along the paths to the allowlisted
+    /// accounts and skips the rest of the trie, so the stored nodes can't be reused and every
+    /// branch node of the partial trie is recomputed from its leaves. As with
+    /// [Self::with_account_prefix_bounds], no updates are collected and no intermediate progress is
+    /// returned. If both are set, only the allowlisted accounts under the prefixes are included.
+    pub fn with_account_allowlist(mut self, allowlist: HashSet<B256>) -> Self {
+        self.config.account_allowlist = Some(allowlist);
+        self
+    }
+
+    /// Set the cache of computed storage roots that is shared by the storage root calculations,
+    /// see [StorageRoot::with_storage_root_cache].
+    ///
+    /// Storage roots supplied by the storage root provider are not cached.
+    pub fn with_storage_root_cache(mut self, cache: StorageRootCache) -> Self {
+        self.config.storage_root_cache = Some(cache);
+        self
+    }
+
+    /// Set the cache of the upper account trie nodes, which are then read from memory instead of
+    /// the `AccountsTrie` table.
+    ///
+    /// The updates returned by the computation are not applied to the cache, since they may never
+    /// be flushed. The caller has to apply them with [UpperTrieCache::apply_updates] once they are,
+    /// except for [Self::compute_and_flush], which keeps the cache in sync itself.
+    pub fn with_upper_trie_cache(mut self, cache: UpperTrieCache) -> Self {
+        self.config.upper_trie_cache = Some(cache);
+        self
+    }
+
+    /// Set the prefetcher that reads the trie nodes ahead of the account and storage trie walks.
+    ///
+    /// The walks hint the prefetcher with the stored children of every branch node they descend
+    /// into, see [TriePrefetcher]. This only changes the I/O pattern of the computation, not its
+    /// results.
+    pub fn with_prefetcher(mut self, prefetcher: TriePrefetcher) -> Self {
+        self.config.prefetcher = Some(prefetcher);
+        self
+    }
+
     /// Set the threshold.
     pub fn with_threshold(mut self, threshold: u64) -> Self {
-        self.threshold = threshold;
+        self.config.threshold = threshold;
         self
     }
 
-    /// Set the threshold to maximum value so that intermediate progress is not returned.
+    /// Set the threshold to maximum value so that intermediate progress is not returned once
+    /// updates accumulate.
+    ///
+    /// The CPU budget, if any, is kept.
     pub fn with_no_threshold(mut self) -> Self {
-        self.threshold = u64::MAX;
+        self.config.threshold = u64::MAX;
+        self
+    }
+
+    /// Set whether the intermediate progress may be returned in the middle of the storage trie of
+    /// an account.
+    ///
+    /// By default, the storage root of every account is computed in one go and the threshold is
+    /// only checked after the account leaf, so a contract with a huge storage trie buffers all of
+    /// its updates in memory. With the storage progress enabled, the storage root computation
+    /// returns as soon as the threshold is reached and the intermediate state records it in
+    /// [IntermediateStateRootState::storage_root_state].
+    ///
+    /// When the intermediate state is persisted, it has to be converted with
+    /// [IntermediateStateRootState::into_checkpoint] so that the storage state is kept in the
+    /// [MerkleCheckpoint](reth_primitives::stage::MerkleCheckpoint).
+    pub fn with_storage_progress(mut self, storage_progress: bool) -> Self {
+        self.config.storage_progress = storage_progress;
+        self
+    }
+
+    /// Set the CPU budget of a single computation.
+    ///
+    /// The CPU time consumed by the computing thread is checked after every account leaf, so the
+    /// time the thread spends blocked, e.g. waiting for the database to read pages from disk, is
+    /// not counted. The check is coarse: a single account with a large storage trie may overrun
+    /// the budget.
+    ///
+    /// Once the budget is exceeded, [Self::root_with_progress] returns the intermediate progress
+    /// the same way as when the threshold is reached, see [Self::with_intermediate_state] for
+    /// resuming, and [Self::compute_and_flush] flushes the updates before resuming. The methods
+    /// that compute the root in one go, like [Self::root] and [Self::root_with_updates], fail with
+    /// [StateRootError::CpuBudgetExceeded] instead.
+    pub fn with_cpu_budget(mut self, budget: Duration) -> Self {
+        self.config.cpu_budget = Some(budget);
+        self
+    }
+
+    /// Set the token that aborts the computation with [StateRootError::Cancelled] once it is
+    /// cancelled, e.g. on shutdown.
+    ///
+    /// The token is checked before every account leaf, so a single account with a large storage
+    /// trie delays the abort until its storage root is computed. Nothing is written by the
+    /// computation itself, so the collected updates and the intermediate state are just dropped.
+    /// A computation that returned [StateRootProgress::Progress] before can be resumed from that
+    /// progress.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.config.cancellation = Some(token);
+        self
+    }
+
+    /// Set the recorder of the computation metrics, see [TrieMetrics].
+    ///
+    /// The walked account leaves, storage leaves and branch nodes are recorded as they are added
+    /// to the hash builders and the duration once the computation returns. Defaults to
+    /// [NoopTrieMetrics].
+    pub fn with_metrics(mut self, recorder: Arc<dyn TrieMetrics>) -> Self {
+        self.config.metrics = recorder;
+        self
+    }
+
+    /// Set the root of an empty trie, [EMPTY_ROOT_HASH] by default.
+    ///
+    /// The root is returned for an empty account trie and used as the storage root of the accounts
+    /// without storage, see [StorageRoot::with_empty_root].
+    pub fn with_empty_root(mut self, empty_root: B256) -> Self {
+        self.config.empty_root = empty_root;
+        self
+    }
+
+    /// Set the maximum depth of the trie nodes walked by [Self::root_with_block_witness], see
+    /// [Proof::with_max_proof_depth](crate::proof::Proof::with_max_proof_depth).
+    pub fn with_max_proof_depth(mut self, max_proof_depth: usize) -> Self {
+        self.config.max_proof_depth = max_proof_depth;
         self
     }
 
@@ -83,15 +439,68 @@ impl<'a, TX, H> StateRoot<'a, TX, H> {
     pub fn with_hashed_cursor_factory<HF>(
         self,
         hashed_cursor_factory: HF,
-    ) -> StateRoot<'a, TX, HF> {
+    ) -> StateRoot<'a, TX, HF, S> {
         StateRoot {
             tx: self.tx,
+            hashed_cursor_factory,
+            storage_root_provider: self.storage_root_provider,
             changed_account_prefixes: self.changed_account_prefixes,
             changed_storage_prefixes: self.changed_storage_prefixes,
             destroyed_accounts: self.destroyed_accounts,
-            threshold: self.threshold,
             previous_state: self.previous_state,
-            hashed_cursor_factory,
+            config: self.config,
+        }
+    }
+
+    /// Set the provider of known storage roots.
+    ///
+    /// The storage roots of accounts that the provider knows are not computed. The storage trie
+    /// updates of these accounts are not collected either, keeping their storage tries in sync
+    /// is up to the owner of the provider.
+    pub fn with_storage_root_provider<SP>(
+        self,
+        storage_root_provider: SP,
+    ) -> StateRoot<'a, TX, H, SP> {
+        StateRoot {
+            tx: self.tx,
+            hashed_cursor_factory: self.hashed_cursor_factory,
+            storage_root_provider,
+            changed_account_prefixes: self.changed_account_prefixes,
+            changed_storage_prefixes: self.changed_storage_prefixes,
+            destroyed_accounts: self.destroyed_accounts,
+            previous_state: self.previous_state,
+            config: self.config,
+        }
+    }
+}
+
+impl<'a, TX: DbTx, S> StateRoot<'a, TX, &'a TX, S> {
+    /// Re-target the computation at the given consistent snapshot of the database, a read
+    /// transaction that can be dropped and replaced by a newer one between checkpoints.
+    ///
+    /// Both the trie tables and the hashed state are read from the snapshot, so the computation
+    /// observes a single consistent view regardless of which transaction the calculator was
+    /// created with. The changed prefixes, destroyed accounts, options and intermediate state are
+    /// retained. Only a calculator reading the hashed state from its transaction can be
+    /// re-targeted, a custom hashed cursor factory, e.g. a [HashedPostStateCursorFactory], would
+    /// keep reading from the transaction it was created with.
+    ///
+    /// A read transaction pins the database pages it observes for as long as it is open. Long
+    /// running builds should therefore be split into checkpoints using [Self::with_threshold]:
+    /// on each [StateRootProgress::Progress], the caller can drop the old snapshot, open a new one
+    /// and resume with [Self::with_intermediate_state] against the new snapshot. The resumed
+    /// computation is only correct if the hashed state and the trie tables were not modified
+    /// between the two snapshots.
+    pub fn with_snapshot<'b, SN: DbTx>(self, snapshot: &'b SN) -> StateRoot<'b, SN, &'b SN, S> {
+        StateRoot {
+            tx: snapshot,
+            hashed_cursor_factory: snapshot,
+            storage_root_provider: self.storage_root_provider,
+            changed_account_prefixes: self.changed_account_prefixes,
+            changed_storage_prefixes: self.changed_storage_prefixes,
+            destroyed_accounts: self.destroyed_accounts,
+            previous_state: self.previous_state,
+            config: self.config,
         }
     }
 }
@@ -101,13 +510,43 @@ impl<'a, TX: DbTx> StateRoot<'a, TX, &'a TX> {
     pub fn new(tx: &'a TX) -> Self {
         Self {
             tx,
+            hashed_cursor_factory: tx,
+            storage_root_provider: InlineStorageRoots,
             changed_account_prefixes: PrefixSetMut::default().freeze(),
             changed_storage_prefixes: HashMap::default(),
             destroyed_accounts: HashSet::default(),
             previous_state: None,
-            threshold: 100_000,
-            hashed_cursor_factory: tx,
+            config: StateRootConfig::default(),
+        }
+    }
+
+    /// Walks the hashed state and the persisted trie tables in parallel and reports the anomalies
+    /// between them, like trie nodes that are left over from an interrupted flush.
+    ///
+    /// The trie tables are not modified.
+    pub fn check_trie_consistency(tx: &'a TX) -> Result<TrieConsistencyReport, StateRootError> {
+        consistency::check_trie_consistency(tx)
+    }
+
+    /// Verifies that the persisted trie tables produce the expected state root, e.g. after the
+    /// updates of an incremental build are flushed and before the next build trusts them.
+    ///
+    /// The upper levels of the account trie are first checked top-down, see
+    /// [PERSISTED_TRIE_CHECK_DEPTH](consistency::PERSISTED_TRIE_CHECK_DEPTH). The root is then
+    /// computed without any changed prefixes, so only the persisted nodes and the hashed entries
+    /// that are not covered by persisted hashes are read.
+    ///
+    /// Returns an error at the first inconsistency.
+    pub fn verify_persisted_trie(
+        tx: &'a TX,
+        expected_root: B256,
+    ) -> Result<(), PersistedTrieError> {
+        consistency::check_persisted_account_nodes(tx)?;
+        let actual = Self::new(tx).root()?;
+        if actual != expected_root {
+            return Err(PersistedTrieError::RootMismatch { expected: expected_root, actual })
         }
+        Ok(())
     }
 
     /// Given a block number range, identifies all the accounts and storage keys that
@@ -121,8 +560,20 @@ impl<'a, TX: DbTx> StateRoot<'a, TX, &'a TX> {
         range: RangeInclusive<BlockNumber>,
     ) -> Result<Self, StateRootError> {
         let loaded_prefix_sets = PrefixSetLoader::new(tx).load(range)?;
-        Ok(Self::new(tx)
-            .with_changed_account_prefixes(loaded_prefix_sets.account_prefix_set.freeze())
+        Ok(Self::new(tx).with_loaded_prefix_sets(loaded_prefix_sets))
+    }
+
+    /// Creates a state root calculator for an ordered log of the changes of the hashed state since
+    /// the persisted trie was computed, see [LoadedPrefixSets::from_change_log].
+    ///
+    /// The changes must already be applied to the hashed state tables.
+    pub fn from_change_log(tx: &'a TX, changes: impl IntoIterator<Item = StateChange>) -> Self {
+        Self::new(tx).with_loaded_prefix_sets(LoadedPrefixSets::from_change_log(changes))
+    }
+
+    /// Set the changed account and storage prefixes and the destroyed accounts.
+    fn with_loaded_prefix_sets(self, loaded_prefix_sets: LoadedPrefixSets) -> Self {
+        self.with_changed_account_prefixes(loaded_prefix_sets.account_prefix_set.freeze())
             .with_changed_storage_prefixes(
                 loaded_prefix_sets
                     .storage_prefix_sets
@@ -130,7 +581,7 @@ impl<'a, TX: DbTx> StateRoot<'a, TX, &'a TX> {
                     .map(|(k, v)| (k, v.freeze()))
                     .collect(),
             )
-            .with_destroyed_accounts(loaded_prefix_sets.destroyed_accounts))
+            .with_destroyed_accounts(loaded_prefix_sets.destroyed_accounts)
     }
 
     /// Computes the state root of the trie with the changed account and storage prefixes and
@@ -147,11 +598,56 @@ impl<'a, TX: DbTx> StateRoot<'a, TX, &'a TX> {
         Self::incremental_root_calculator(tx, range)?.root()
     }
 
+    /// Computes the state root represented by the persisted trie tables before the changes of the
+    /// given block range are applied to them.
+    ///
+    /// The hashed tables are expected to already contain the changes of the range. The values
+    /// before the range are read from the changesets and layered over the hashed state.
+    ///
+    /// This is not a lookup: the root is computed by walking the trie along the paths changed by
+    /// the range, which costs about as much as [Self::incremental_root] over the same range. The
+    /// state root in the header of the block before the range is the root the trie should
+    /// represent, not the one it does, so it can't replace this computation.
+    pub fn persisted_root(
+        tx: &'a TX,
+        range: RangeInclusive<BlockNumber>,
+    ) -> Result<B256, StateRootError> {
+        let pre_state = HashedPostState::from_changesets(tx, range)?;
+        let (account_prefixes, storage_prefixes) = pre_state.construct_prefix_sets();
+        StateRoot::new(tx)
+            .with_hashed_cursor_factory(HashedPostStateCursorFactory::new(tx, &pre_state))
+            .with_changed_account_prefixes(account_prefixes)
+            .with_changed_storage_prefixes(storage_prefixes)
+            .root()
+    }
+
+    /// Verifies that the persisted trie matches `expected_pre_root` and computes the state root
+    /// after the changes of the given block range.
+    ///
+    /// Returns [StateRootError::PreRootMismatch] without computing the new root if the persisted
+    /// trie belongs to a different state. The check is a full [Self::persisted_root] computation,
+    /// so a successful call walks the changed paths twice and costs about twice as much as
+    /// [Self::incremental_root].
+    pub fn verify_and_compute(
+        tx: &'a TX,
+        expected_pre_root: B256,
+        range: RangeInclusive<BlockNumber>,
+    ) -> Result<B256, StateRootError> {
+        let actual = Self::persisted_root(tx, range.clone())?;
+        if actual != expected_pre_root {
+            return Err(StateRootError::PreRootMismatch { expected: expected_pre_root, actual })
+        }
+        Self::incremental_root(tx, range)
+    }
+
     /// Computes the state root of the trie with the changed account and storage prefixes and
     /// existing trie nodes collecting updates in the process.
     ///
     /// Ignores the threshold.
     ///
+    /// If the range has no changes, for example because it is empty, the trie tables are already up
+    /// to date. The root is then computed from them without collecting any updates.
+    ///
     /// # Returns
     ///
     /// The updated state root and the trie updates.
@@ -160,7 +656,62 @@ impl<'a, TX: DbTx> StateRoot<'a, TX, &'a TX> {
         range: RangeInclusive<BlockNumber>,
     ) -> Result<(B256, TrieUpdates), StateRootError> {
         tracing::debug!(target: "loader", "incremental state root");
-        Self::incremental_root_calculator(tx, range)?.root_with_updates()
+        let loaded_prefix_sets = PrefixSetLoader::new(tx).load(range)?;
+        if loaded_prefix_sets.is_empty() {
+            return Ok((Self::new(tx).root()?, TrieUpdates::default()))
+        }
+        Self::new(tx).with_loaded_prefix_sets(loaded_prefix_sets).root_with_updates()
+    }
+
+    /// Computes the state root after the blocks of the given range were reverted, for example
+    /// because a reorg orphaned them, collecting the trie updates in the process.
+    ///
+    /// Unlike [Self::incremental_root_with_updates], the hashed tables are not trusted to hold the
+    /// target state. The values of the accounts and storage slots touched by the reverted blocks
+    /// before the range are read from the changesets of the range and layered over the hashed
+    /// state, so the trie updates can be computed before or after the hashed tables are unwound,
+    /// as long as the changesets of the range are still present. The storage tries of the accounts
+    /// created by the reverted blocks are deleted.
+    ///
+    /// Ignores the threshold.
+    ///
+    /// # Returns
+    ///
+    /// The state root before the range and the trie updates that revert the trie tables to it.
+    pub fn incremental_root_for_reorg(
+        tx: &'a TX,
+        revert_range: RangeInclusive<BlockNumber>,
+    ) -> Result<(B256, TrieUpdates), StateRootError> {
+        tracing::debug!(target: "loader", ?revert_range, "incremental state root for reorg");
+        let pre_state = HashedPostState::from_changesets(tx, revert_range)?;
+        let (account_prefixes, storage_prefixes) = pre_state.construct_prefix_sets();
+        StateRoot::new(tx)
+            .with_hashed_cursor_factory(HashedPostStateCursorFactory::new(tx, &pre_state))
+            .with_changed_account_prefixes(account_prefixes)
+            .with_changed_storage_prefixes(storage_prefixes)
+            .with_destroyed_accounts(pre_state.cleared_accounts().clone())
+            .root_with_updates()
+    }
+
+    /// Computes the state root before and after the changes of the given block range, collecting
+    /// the trie updates in the process.
+    ///
+    /// The prior root is read with [Self::persisted_root] before the trie is walked, so both roots
+    /// are computed from the same view of the trie tables.
+    ///
+    /// Ignores the threshold.
+    ///
+    /// # Returns
+    ///
+    /// The prior state root, the updated state root and the trie updates.
+    pub fn incremental_root_transition(
+        tx: &'a TX,
+        range: RangeInclusive<BlockNumber>,
+    ) -> Result<(B256, B256, TrieUpdates), StateRootError> {
+        tracing::debug!(target: "loader", "incremental state root transition");
+        let prior_root = Self::persisted_root(tx, range.clone())?;
+        let (root, updates) = Self::incremental_root_with_updates(tx, range)?;
+        Ok((prior_root, root, updates))
     }
 
     /// Computes the state root of the trie with the changed account and storage prefixes and
@@ -178,10 +729,11 @@ impl<'a, TX: DbTx> StateRoot<'a, TX, &'a TX> {
     }
 }
 
-impl<'a, TX, H> StateRoot<'a, TX, H>
+impl<'a, TX, H, S> StateRoot<'a, TX, H, S>
 where
     TX: DbTx,
     H: HashedCursorFactory + Clone,
+    S: StorageRootProvider,
 {
     /// Walks the intermediate nodes of existing state trie (if any) and hashed entries. Feeds the
     /// nodes into the hash builder. Collects the updates in the process.
@@ -192,9 +744,38 @@ where
     ///
     /// The intermediate progress of state root computation and the trie updates.
     pub fn root_with_updates(self) -> Result<(B256, TrieUpdates), StateRootError> {
-        match self.with_no_threshold().calculate(true)? {
+        match self.with_no_threshold().calculate(
+            true,
+            TrieUpdates::default(),
+            CalculateHooks::default(),
+        )? {
             StateRootProgress::Complete(root, _, updates) => Ok((root, updates)),
-            StateRootProgress::Progress(..) => unreachable!(), // unreachable threshold
+            // the CPU budget is the only source of progress
+            StateRootProgress::Progress(..) => Err(StateRootError::CpuBudgetExceeded),
+        }
+    }
+
+    /// Same as [Self::root_with_updates], but extends the given buffer with the trie updates
+    /// instead of allocating new ones, so that a buffer can be reused across computations by
+    /// clearing it in between.
+    ///
+    /// Ignores the threshold. If the computation fails, the buffer is left empty.
+    ///
+    /// # Returns
+    ///
+    /// The state root and the number of walked hashed entries.
+    pub fn root_with_updates_into(
+        self,
+        updates: &mut TrieUpdates,
+    ) -> Result<(B256, usize), StateRootError> {
+        let buffer = std::mem::take(updates);
+        match self.with_no_threshold().calculate(true, buffer, CalculateHooks::default())? {
+            StateRootProgress::Complete(root, walked, buffer) => {
+                *updates = buffer;
+                Ok((root, walked))
+            }
+            // the CPU budget is the only source of progress
+            StateRootProgress::Progress(..) => Err(StateRootError::CpuBudgetExceeded),
         }
     }
 
@@ -205,135 +786,1047 @@ where
     ///
     /// The state root hash.
     pub fn root(self) -> Result<B256, StateRootError> {
-        match self.calculate(false)? {
+        match self.calculate(false, TrieUpdates::default(), CalculateHooks::default())? {
             StateRootProgress::Complete(root, _, _) => Ok(root),
-            StateRootProgress::Progress(..) => unreachable!(), // update retenion is disabled
+            // the CPU budget is the only source of progress
+            StateRootProgress::Progress(..) => Err(StateRootError::CpuBudgetExceeded),
         }
     }
 
     /// Walks the intermediate nodes of existing state trie (if any) and hashed entries. Feeds the
-    /// nodes into the hash builder. Collects the updates in the process.
+    /// nodes into the hash builder and counts them instead of collecting the updates, e.g. to
+    /// estimate the cost of a computation with [Self::root_with_updates] without holding its
+    /// updates in memory.
+    ///
+    /// The hooks of the recorder set with [Self::with_metrics] are still called.
     ///
     /// # Returns
     ///
-    /// The intermediate progress of state root computation.
-    pub fn root_with_progress(self) -> Result<StateRootProgress, StateRootError> {
-        self.calculate(true)
+    /// The state root hash and the counts of the walked nodes.
+    pub fn root_with_stats(mut self) -> Result<(B256, TrieStats), StateRootError> {
+        let recorder = Arc::new(TrieStatsRecorder::new(self.config.metrics));
+        self.config.metrics = recorder.clone();
+        match self.calculate(false, TrieUpdates::default(), CalculateHooks::default())? {
+            StateRootProgress::Complete(root, _, _) => Ok((root, recorder.stats())),
+            // the CPU budget is the only source of progress
+            StateRootProgress::Progress(..) => Err(StateRootError::CpuBudgetExceeded),
+        }
     }
 
-    fn calculate(self, retain_updates: bool) -> Result<StateRootProgress, StateRootError> {
-        tracing::debug!(target: "loader", "calculating state root");
-        let mut trie_updates = TrieUpdates::default();
+    /// Checks whether the hashed state and the existing trie nodes produce the claimed state root.
+    ///
+    /// The root is computed without collecting any updates.
+    pub fn verify_root(self, claimed: B256) -> Result<bool, StateRootError> {
+        Ok(self.root()? == claimed)
+    }
 
-        let hashed_account_cursor = self.hashed_cursor_factory.hashed_account_cursor()?;
-        let trie_cursor = AccountTrieCursor::new(self.tx.cursor_read::<tables::AccountsTrie>()?);
+    /// Same as [Self::root_with_updates], but fails if the computed root differs from the expected
+    /// one, e.g. the state root of the block header being validated.
+    ///
+    /// The roots are compared once the computation is complete, so the updates are only returned
+    /// if they lead to the expected root and can be written right away. On a mismatch,
+    /// [Self::root_with_divergence_check] can locate the node where the computation diverges.
+    ///
+    /// Returns [StateRootError::RootMismatch] with both roots if they differ.
+    pub fn root_expecting(self, expected: B256) -> Result<TrieUpdates, StateRootError> {
+        let (computed, updates) = self.root_with_updates()?;
+        if computed != expected {
+            return Err(StateRootError::RootMismatch { expected, computed })
+        }
+        Ok(updates)
+    }
 
-        let (mut hash_builder, mut account_node_iter) = match self.previous_state {
-            Some(state) => {
-                let walker = TrieWalker::from_stack(
-                    trie_cursor,
-                    state.walker_stack,
-                    self.changed_account_prefixes,
-                );
-                (
-                    state.hash_builder,
-                    AccountNodeIter::new(walker, hashed_account_cursor)
-                        .with_last_account_key(state.last_account_key),
-                )
-            }
-            None => {
-                let walker = TrieWalker::new(trie_cursor, self.changed_account_prefixes);
-                (HashBuilder::default(), AccountNodeIter::new(walker, hashed_account_cursor))
-            }
-        };
+    /// Same as [Self::root_with_updates], but compares the computed account trie branch nodes
+    /// against the expected ones, e.g. the persisted nodes of a node that computed the expected
+    /// root, to locate where a root mismatch originates.
+    ///
+    /// The nodes are compared during the walk as the hash builder completes them, and the
+    /// computation stops at the first one that differs. Only the branch nodes emitted by the walk
+    /// are compared, so the whole trie is only checked if it is computed from the hashed state
+    /// alone, without persisted trie nodes. The root node is never compared, since it is not
+    /// persisted. Nothing is compared if the updates are not retained, i.e. with account prefix
+    /// bounds or an account allowlist.
+    ///
+    /// Returns [StateRootError::DivergenceAt] with the first path in the walk whose computed node
+    /// differs from the expected one. A persisted node that is removed by the walk is reported
+    /// once the walk is complete if it is expected.
+    pub fn root_with_divergence_check(
+        self,
+        expected: &BTreeMap<Nibbles, BranchNodeCompact>,
+    ) -> Result<(B256, TrieUpdates), StateRootError> {
+        match self.with_no_threshold().calculate(
+            true,
+            TrieUpdates::default(),
+            CalculateHooks { expected_nodes: Some(expected), ..Default::default() },
+        )? {
+            StateRootProgress::Complete(root, _, updates) => Ok((root, updates)),
+            // the CPU budget is the only source of progress
+            StateRootProgress::Progress(..) => Err(StateRootError::CpuBudgetExceeded),
+        }
+    }
 
-        account_node_iter.walker.set_updates(retain_updates);
-        hash_builder.set_updates(retain_updates);
+    /// Computes the state root together with the witness of the given accesses: the deduplicated
+    /// RLP encoded account and storage trie nodes that prove every accessed account and storage
+    /// slot against the root.
+    ///
+    /// The storage slots are keyed by the raw address and slot. The accounts with accessed storage
+    /// are proven as well, so they don't need to be listed in `accessed_accounts`. Accesses of
+    /// missing accounts and slots are covered by exclusion proofs.
+    ///
+    /// Ignores the threshold and doesn't collect any updates. Fails with
+    /// [ProofError::DepthExceeded] on a trie node deeper than the maximum proof depth, see
+    /// [Self::with_max_proof_depth].
+    ///
+    /// # Returns
+    ///
+    /// The state root and the witness nodes, the account trie nodes sorted by path first.
+    pub fn root_with_block_witness(
+        self,
+        accessed_accounts: &[Address],
+        accessed_storage: &HashMap<Address, Vec<B256>>,
+    ) -> Result<(B256, Vec<Bytes>), ProofError> {
+        let accessed_storage = accessed_storage
+            .iter()
+            .map(|(address, slots)| (keccak256(address), slots.as_slice()))
+            .collect::<HashMap<_, _>>();
+        let targets = accessed_accounts
+            .iter()
+            .map(keccak256)
+            .chain(accessed_storage.keys().copied())
+            .map(Nibbles::unpack)
+            .collect::<Vec<_>>();
 
-        let mut account_rlp = Vec::with_capacity(128);
-        let mut hashed_entries_walked = 0;
+        // The walker needs to descend into the changed prefixes to compute the correct root and
+        // into the target paths to reveal the proof nodes.
+        let prefix_set = PrefixSetMut::from(
+            self.changed_account_prefixes.iter().cloned().chain(targets.iter().cloned()),
+        )
+        .freeze();
+        let hashed_account_cursor = self.hashed_cursor_factory.hashed_account_cursor()?;
+        let trie_cursor = UpperTrieCacheCursor::new(
+            AccountTrieCursor::new(self.tx.cursor_read::<tables::AccountsTrie>()?),
+            self.config.upper_trie_cache.clone(),
+        );
+        let walker = TrieWalker::new(trie_cursor, prefix_set);
+
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(targets);
+        let mut storage_witness = Vec::new();
+        let mut account_node_iter = AccountNodeIter::new(walker, hashed_account_cursor);
         while let Some(node) = account_node_iter.try_next()? {
             match node {
                 AccountNode::Branch(node) => {
+                    check_proof_depth(&node.key, self.config.max_proof_depth)?;
                     hash_builder.add_branch(node.key, node.value, node.children_are_in_trie);
                 }
+                AccountNode::Leaf(_, account)
+                    if !(self.config.account_inclusion_predicate)(&account) => {}
                 AccountNode::Leaf(hashed_address, account) => {
-                    hashed_entries_walked += 1;
-
-                    // We assume we can always calculate a storage root without
-                    // OOMing. This opens us up to a potential DOS vector if
-                    // a contract had too many storage entries and they were
-                    // all buffered w/o us returning and committing our intermediate
-                    // progress.
-                    // TODO: We can consider introducing the TrieProgress::Progress/Complete
-                    // abstraction inside StorageRoot, but let's give it a try as-is for now.
-                    let storage_root_calculator = StorageRoot::new_hashed(self.tx, hashed_address)
-                        .with_hashed_cursor_factory(self.hashed_cursor_factory.clone())
-                        .with_changed_prefixes(
-                            self.changed_storage_prefixes
-                                .get(&hashed_address)
-                                .cloned()
-                                .unwrap_or_default(),
-                        );
-
-                    let storage_root = if retain_updates {
-                        let (root, storage_slots_walked, updates) =
-                            storage_root_calculator.root_with_updates()?;
-                        hashed_entries_walked += storage_slots_walked;
-                        trie_updates.extend(updates.into_iter());
-                        root
-                    } else {
-                        storage_root_calculator.root()?
+                    let slots = accessed_storage.get(&hashed_address);
+                    let known_root = match slots {
+                        Some(_) => None,
+                        None => self.storage_root_provider.storage_root(hashed_address),
+                    };
+                    let storage_root = match known_root {
+                        Some(root) => root,
+                        None => {
+                            let mut storage_root_calculator =
+                                StorageRoot::new_hashed(self.tx, hashed_address)
+                                    .with_hashed_cursor_factory(self.hashed_cursor_factory.clone())
+                                    .with_changed_prefixes(
+                                        self.changed_storage_prefixes
+                                            .get(&hashed_address)
+                                            .cloned()
+                                            .unwrap_or_default(),
+                                    )
+                                    .with_empty_root(self.config.empty_root)
+                                    .with_max_proof_depth(self.config.max_proof_depth);
+                            storage_root_calculator.cache = self.config.storage_root_cache.clone();
+
+                            let with_address =
+                                |source| StateRootError::StorageRoot { hashed_address, source };
+                            match slots {
+                                Some(slots) => {
+                                    let (root, proofs) = storage_root_calculator
+                                        .root_with_proof_for_slots(slots)
+                                        .map_err(|err| match err {
+                                            ProofError::StorageRoot(source) => {
+                                                with_address(source).into()
+                                            }
+                                            err => err,
+                                        })?;
+                                    storage_witness
+                                        .extend(proofs.into_values().flat_map(|proof| proof.proof));
+                                    root
+                                }
+                                None => storage_root_calculator.root().map_err(with_address)?,
+                            }
+                        }
                     };
 
-                    let account = EthAccount::from(account).with_storage_root(storage_root);
-
-                    account_rlp.clear();
-                    account.encode(&mut account_rlp as &mut dyn BufMut);
-
+                    let account_rlp = (self.config.account_encoder)(&account, storage_root);
                     hash_builder.add_leaf(Nibbles::unpack(hashed_address), &account_rlp);
+                }
+            }
+        }
 
-                    // Decide if we need to return intermediate progress.
-                    let total_updates_len = trie_updates.len() +
-                        account_node_iter.walker.updates_len() +
-                        hash_builder.updates_len();
-                    if retain_updates && total_updates_len as u64 >= self.threshold {
-                        let (walker_stack, walker_updates) = account_node_iter.walker.split();
-                        let (hash_builder, hash_builder_updates) = hash_builder.split();
-
-                        let state = IntermediateStateRootState {
-                            hash_builder,
-                            walker_stack,
-                            last_account_key: hashed_address,
-                        };
+        // The hash builder returns the default empty root if no nodes were added.
+        let root = hash_builder.root();
+        let root = if root == EMPTY_ROOT_HASH { self.config.empty_root } else { root };
+
+        // The proofs of different keys share the nodes along their common paths, identical
+        // storage tries share all of them.
+        let mut seen = HashSet::new();
+        let witness = hash_builder
+            .take_proofs()
+            .into_values()
+            .chain(storage_witness)
+            .filter(|node| seen.insert(node.clone()))
+            .collect();
+
+        Ok((root, witness))
+    }
 
-                        trie_updates.extend(walker_updates.into_iter());
-                        trie_updates.extend_with_account_updates(hash_builder_updates);
+    /// Returns the keys of the trie nodes that [Self::root_with_updates] would update or delete,
+    /// without computing the new root.
+    ///
+    /// The walk is the same, but only the storage tries of the accounts with changed storage
+    /// prefixes are walked and the account leaves are not encoded, since the layout of the account
+    /// trie only depends on the keys of its leaves. Storage trie deletes of accounts that have no
+    /// storage and no changed storage prefixes aren't reported, they don't change the tables.
+    ///
+    /// Ignores the threshold, the account prefix bounds and the account allowlist.
+    pub fn changed_node_keys(self) -> Result<HashSet<TrieKey>, StateRootError> {
+        let hashed_account_cursor = self.hashed_cursor_factory.hashed_account_cursor()?;
+        let trie_cursor = UpperTrieCacheCursor::new(
+            AccountTrieCursor::new(self.tx.cursor_read::<tables::AccountsTrie>()?),
+            self.config.upper_trie_cache.clone(),
+        );
+        let walker = TrieWalker::new(trie_cursor, self.changed_account_prefixes).with_updates(true);
 
-                        return Ok(StateRootProgress::Progress(
-                            Box::new(state),
-                            hashed_entries_walked,
-                            trie_updates,
-                        ))
+        let mut hash_builder = HashBuilder::default()
+            .with_updates(true)
+            .with_retained_depth(self.config.retained_depth);
+        let mut trie_updates = TrieUpdates::default();
+        let mut account_node_iter = AccountNodeIter::new(walker, hashed_account_cursor);
+        while let Some(node) = account_node_iter.try_next()? {
+            match node {
+                AccountNode::Branch(node) => {
+                    hash_builder.add_branch(node.key, node.value, node.children_are_in_trie);
+                }
+                AccountNode::Leaf(_, account)
+                    if !(self.config.account_inclusion_predicate)(&account) => {}
+                AccountNode::Leaf(hashed_address, _) => {
+                    let changed_prefixes =
+                        self.changed_storage_prefixes.get(&hashed_address).filter(|_| {
+                            self.storage_root_provider.storage_root(hashed_address).is_none()
+                        });
+                    if let Some(changed_prefixes) = changed_prefixes {
+                        let (_, _, updates) = StorageRoot::new_hashed(self.tx, hashed_address)
+                            .with_hashed_cursor_factory(self.hashed_cursor_factory.clone())
+                            .with_changed_prefixes(changed_prefixes.clone())
+                            .with_emit_storage_deletes(self.config.emit_storage_deletes)
+                            .with_retained_depth(self.config.retained_depth)
+                            .with_empty_root(self.config.empty_root)
+                            .root_with_updates()
+                            .map_err(|source| StateRootError::StorageRoot {
+                                hashed_address,
+                                source,
+                            })?;
+                        trie_updates.extend(updates.into_iter());
+                    }
+
+                    hash_builder.add_leaf(Nibbles::unpack(hashed_address), B256::ZERO.as_slice());
+                }
+            }
+        }
+
+        hash_builder.root();
+
+        let (_, walker_updates) = account_node_iter.walker.split();
+        let (_, hash_builder_updates) = hash_builder.split();
+        trie_updates.extend(walker_updates.into_iter());
+        trie_updates.extend_with_account_updates(hash_builder_updates);
+        if self.config.emit_storage_deletes {
+            trie_updates
+                .extend_with_deletes(self.destroyed_accounts.into_iter().map(TrieKey::StorageTrie));
+        }
+
+        Ok(trie_updates.into_iter().map(|(key, _)| key).collect())
+    }
+
+    /// Walks the intermediate nodes of existing state trie (if any) and hashed entries. Feeds the
+    /// nodes into the hash builder. Collects the updates in the process.
+    ///
+    /// # Returns
+    ///
+    /// The intermediate progress of state root computation.
+    pub fn root_with_progress(self) -> Result<StateRootProgress, StateRootError> {
+        self.calculate(true, TrieUpdates::default(), CalculateHooks::default())
+    }
+
+    /// Computes the state root and writes the trie updates to the database as they are produced.
+    ///
+    /// Instead of holding all updates until the end, the updates collected so far are flushed
+    /// every time the threshold is reached and the computation resumes from the intermediate
+    /// state, re-reading the trie tables it just wrote to. The updates are written in the same
+    /// order as a single [TrieUpdates::flush] of all of them would: updates of a node that are
+    /// produced later win, and the storage trie deletes of the destroyed accounts are written
+    /// last.
+    ///
+    /// # Returns
+    ///
+    /// The state root.
+    pub fn compute_and_flush(mut self) -> Result<B256, StateRootError>
+    where
+        TX: DbTxMut,
+    {
+        let mut intermediate_state = self.previous_state.take();
+        loop {
+            let calculator = StateRoot {
+                tx: self.tx,
+                hashed_cursor_factory: self.hashed_cursor_factory.clone(),
+                storage_root_provider: &self.storage_root_provider,
+                changed_account_prefixes: self.changed_account_prefixes.clone(),
+                changed_storage_prefixes: self.changed_storage_prefixes.clone(),
+                destroyed_accounts: self.destroyed_accounts.clone(),
+                previous_state: intermediate_state.take(),
+                config: self.config.clone(),
+            };
+            match calculator.calculate(true, TrieUpdates::default(), CalculateHooks::default())? {
+                StateRootProgress::Progress(state, _, updates) => {
+                    self.flush_updates(updates)?;
+                    intermediate_state = Some(*state);
+                }
+                StateRootProgress::Complete(root, _, updates) => {
+                    self.flush_updates(updates)?;
+                    return Ok(root)
+                }
+            }
+        }
+    }
+
+    /// Writes the trie updates to the database and applies them to the upper trie cache, if any.
+    fn flush_updates(&self, updates: TrieUpdates) -> Result<(), StateRootError>
+    where
+        TX: DbTxMut,
+    {
+        if let Some(cache) = &self.config.upper_trie_cache {
+            cache.apply_updates(&updates);
+        }
+        updates.flush(self.tx)?;
+        Ok(())
+    }
+
+    /// Computes the root, extending the given trie updates.
+    fn calculate(
+        self,
+        retain_updates: bool,
+        mut trie_updates: TrieUpdates,
+        mut hooks: CalculateHooks<'_>,
+    ) -> Result<StateRootProgress, StateRootError> {
+        tracing::debug!(target: "loader", "calculating state root");
+        let started_at = Instant::now();
+        let cpu_timer = ThreadCpuTimer::start();
+
+        // The nodes outside of the bounded prefixes and the allowlisted accounts are skipped
+        // below.
+        let (changed_account_prefixes, retain_updates) =
+            match self.config.restricted_account_prefixes(&self.changed_account_prefixes) {
+                Some(prefixes) => (prefixes, false),
+                None => (self.changed_account_prefixes, retain_updates),
+            };
+
+        let hashed_account_cursor = self.hashed_cursor_factory.hashed_account_cursor()?;
+        let trie_cursor = PrefetchTrieCursor::account(
+            UpperTrieCacheCursor::new(
+                AccountTrieCursor::new(self.tx.cursor_read::<tables::AccountsTrie>()?),
+                self.config.upper_trie_cache.clone(),
+            ),
+            self.config.prefetcher.clone(),
+        );
+        let mut last_hashed_address =
+            self.previous_state.as_ref().map(|state| state.last_account_key);
+
+        // The account whose storage root computation was interrupted is finished first.
+        let mut resumed_account = None;
+        let (mut hash_builder, mut account_node_iter) = match self.previous_state {
+            Some(state) => {
+                resumed_account = state.storage_root_state.map(|(account, storage_state)| {
+                    (state.last_account_key, account, storage_state)
+                });
+                let walker = TrieWalker::from_stack(
+                    trie_cursor,
+                    state.walker_stack,
+                    changed_account_prefixes,
+                );
+                (
+                    state.hash_builder,
+                    AccountNodeIter::new(walker, hashed_account_cursor)
+                        .with_last_account_key(state.last_account_key),
+                )
+            }
+            None => {
+                let walker = TrieWalker::new(trie_cursor, changed_account_prefixes);
+                (HashBuilder::default(), AccountNodeIter::new(walker, hashed_account_cursor))
+            }
+        };
+
+        account_node_iter.walker.set_updates(retain_updates);
+        account_node_iter.walker.set_prefetch(self.config.prefetcher.is_some());
+        hash_builder.set_updates(retain_updates);
+        hash_builder.set_retained_depth(self.config.retained_depth);
+
+        // The branch nodes can only be compared if the hash builder emits them.
+        let expected_nodes = hooks.expected_nodes.filter(|_| retain_updates);
+        let mut emitted_nodes = HashMap::default();
+
+        let mut hashed_entries_walked = 0;
+        loop {
+            if let Some(expected_nodes) = expected_nodes {
+                check_emitted_nodes(&mut hash_builder, expected_nodes, &mut emitted_nodes)?;
+            }
+
+            let (hashed_address, account, previous_storage_state) = match resumed_account.take() {
+                Some((hashed_address, account, storage_state)) => {
+                    (hashed_address, account, Some(storage_state))
+                }
+                None => match account_node_iter.try_next()? {
+                    None => break,
+                    Some(AccountNode::Branch(node)) => {
+                        // The paths to the allowlisted accounts are marked as changed, so the
+                        // subtree of a stored branch node never contains any of them.
+                        if self.config.account_allowlist.is_none() &&
+                            self.config.is_in_bounds(&node.key)
+                        {
+                            hash_builder.add_branch(
+                                node.key,
+                                node.value,
+                                node.children_are_in_trie,
+                            );
+                            self.config.metrics.record_branch_nodes(1);
+                        }
+                        continue
+                    }
+                    Some(AccountNode::Leaf(hashed_address, account))
+                        if !self.config.includes_leaf(&hashed_address, &account) =>
+                    {
+                        continue
+                    }
+                    Some(AccountNode::Leaf(hashed_address, account)) => {
+                        hashed_entries_walked += 1;
+                        self.config.metrics.record_account_leaves(1);
+
+                        // The hash builder requires the leaves in strictly ascending order.
+                        if let Some(last) = last_hashed_address {
+                            debug_assert!(
+                                last < hashed_address,
+                                "hashed accounts out of order: {hashed_address} after {last}"
+                            );
+                        }
+                        last_hashed_address = Some(hashed_address);
+                        (hashed_address, account, None)
+                    }
+                },
+            };
+
+            if self.config.cancellation.as_ref().map_or(false, CancellationToken::is_cancelled) {
+                return Err(StateRootError::Cancelled)
+            }
+
+            // An interrupted storage root computation is always resumed here.
+            let storage_root = match self.storage_root_provider.storage_root(hashed_address) {
+                Some(root) => Some((root, 0, TrieUpdates::default())),
+                None => match hooks.storage_roots.as_mut() {
+                    Some(storage_roots) if previous_storage_state.is_none() => {
+                        storage_roots(hashed_address)?
+                    }
+                    _ => None,
+                },
+            };
+            let storage_root_progress = match storage_root {
+                Some((root, storage_slots_walked, updates)) => {
+                    StorageRootProgress::Complete(root, storage_slots_walked, updates)
+                }
+                None => {
+                    let mut storage_root_calculator =
+                        StorageRoot::new_hashed(self.tx, hashed_address)
+                            .with_hashed_cursor_factory(self.hashed_cursor_factory.clone())
+                            .with_changed_prefixes(
+                                self.changed_storage_prefixes
+                                    .get(&hashed_address)
+                                    .cloned()
+                                    .unwrap_or_default(),
+                            )
+                            .with_emit_storage_deletes(self.config.emit_storage_deletes)
+                            .with_retained_depth(self.config.retained_depth)
+                            .with_empty_root(self.config.empty_root);
+                    storage_root_calculator.cache = self.config.storage_root_cache.clone();
+                    storage_root_calculator.prefetcher = self.config.prefetcher.clone();
+
+                    let with_address =
+                        |source| StateRootError::StorageRoot { hashed_address, source };
+                    if !retain_updates {
+                        let (root, storage_slots_walked, _) =
+                            storage_root_calculator.calculate(false).map_err(with_address)?;
+                        StorageRootProgress::Complete(
+                            root,
+                            storage_slots_walked,
+                            TrieUpdates::default(),
+                        )
+                    } else if self.config.storage_progress {
+                        // The storage trie gets the updates left until the threshold.
+                        let total_updates_len = trie_updates.len() +
+                            account_node_iter.walker.updates_len() +
+                            hash_builder.updates_len();
+                        storage_root_calculator
+                            .with_threshold(
+                                self.config.threshold.saturating_sub(total_updates_len as u64),
+                            )
+                            .with_intermediate_state(previous_storage_state)
+                            .root_with_progress()
+                            .map_err(with_address)?
+                    } else {
+                        let (root, storage_slots_walked, updates) =
+                            storage_root_calculator.root_with_updates().map_err(with_address)?;
+                        StorageRootProgress::Complete(root, storage_slots_walked, updates)
                     }
                 }
+            };
+
+            let storage_root_state = match storage_root_progress {
+                StorageRootProgress::Complete(storage_root, storage_slots_walked, updates) => {
+                    hashed_entries_walked += storage_slots_walked;
+                    self.config.metrics.record_storage_leaves(storage_slots_walked);
+                    trie_updates.extend(updates.into_iter());
+
+                    let account_rlp = (self.config.account_encoder)(&account, storage_root);
+                    hash_builder.add_leaf(Nibbles::unpack(hashed_address), &account_rlp);
+                    None
+                }
+                StorageRootProgress::Progress(storage_state, storage_slots_walked, updates) => {
+                    hashed_entries_walked += storage_slots_walked;
+                    self.config.metrics.record_storage_leaves(storage_slots_walked);
+                    trie_updates.extend(updates.into_iter());
+                    Some((account, *storage_state))
+                }
+            };
+
+            // Decide if we need to return intermediate progress.
+            let total_updates_len = trie_updates.len() +
+                account_node_iter.walker.updates_len() +
+                hash_builder.updates_len();
+            let budget_exceeded =
+                self.config.cpu_budget.map_or(false, |budget| cpu_timer.elapsed() >= budget);
+            if budget_exceeded ||
+                (retain_updates &&
+                    (storage_root_state.is_some() ||
+                        total_updates_len as u64 >= self.config.threshold))
+            {
+                let (walker_stack, walker_updates) = account_node_iter.walker.split();
+                let (hash_builder, hash_builder_updates) = hash_builder.split();
+
+                let state = IntermediateStateRootState {
+                    hash_builder,
+                    walker_stack,
+                    last_account_key: hashed_address,
+                    storage_root_state,
+                };
+
+                trie_updates.extend(walker_updates.into_iter());
+                trie_updates.extend_with_account_updates(emitted_nodes);
+                trie_updates.extend_with_account_updates(hash_builder_updates);
+
+                self.config.metrics.record_duration(started_at.elapsed());
+                return Ok(StateRootProgress::Progress(
+                    Box::new(state),
+                    hashed_entries_walked,
+                    trie_updates,
+                ))
             }
         }
 
+        // The hash builder returns the default empty root if no nodes were added.
         let root = hash_builder.root();
+        let root = if root == EMPTY_ROOT_HASH { self.config.empty_root } else { root };
 
         let (_, walker_updates) = account_node_iter.walker.split();
+        if let Some(expected_nodes) = expected_nodes {
+            check_emitted_nodes(&mut hash_builder, expected_nodes, &mut emitted_nodes)?;
+
+            // The persisted nodes removed by the walk and not emitted again no longer exist.
+            let mut removed = walker_updates
+                .iter()
+                .filter_map(|(key, _)| match key {
+                    TrieKey::AccountNode(nibbles) => Some(Nibbles::from_hex(nibbles.inner.clone())),
+                    _ => None,
+                })
+                .filter(|path| !emitted_nodes.contains_key(path))
+                .collect::<Vec<_>>();
+            removed.sort_unstable();
+            if let Some(path) = removed.into_iter().find(|path| expected_nodes.contains_key(path)) {
+                return Err(StateRootError::DivergenceAt {
+                    expected: expected_nodes.get(&path).cloned().map(Box::new),
+                    computed: None,
+                    path,
+                })
+            }
+        }
         let (_, hash_builder_updates) = hash_builder.split();
 
         trie_updates.extend(walker_updates.into_iter());
+        trie_updates.extend_with_account_updates(emitted_nodes);
         trie_updates.extend_with_account_updates(hash_builder_updates);
-        trie_updates
-            .extend_with_deletes(self.destroyed_accounts.into_iter().map(TrieKey::StorageTrie));
+        if self.config.emit_storage_deletes && retain_updates {
+            trie_updates
+                .extend_with_deletes(self.destroyed_accounts.into_iter().map(TrieKey::StorageTrie));
+        }
 
+        self.config.metrics.record_duration(started_at.elapsed());
         Ok(StateRootProgress::Complete(root, hashed_entries_walked, trie_updates))
     }
 }
 
+/// Moves the account trie branch nodes completed by the hash builder since the last call into
+/// `emitted`.
+///
+/// Returns [StateRootError::DivergenceAt] for the first of them that differs from the expected node
+/// at its path. The root node is skipped, since it is not persisted.
+fn check_emitted_nodes(
+    hash_builder: &mut HashBuilder,
+    expected: &BTreeMap<Nibbles, BranchNodeCompact>,
+    emitted: &mut HashMap<Nibbles, BranchNodeCompact>,
+) -> Result<(), StateRootError> {
+    let (builder, nodes) = std::mem::take(hash_builder).split();
+    *hash_builder = builder;
+    hash_builder.set_updates(true);
+
+    // The nodes completed by a single step of the walk lie on one path and are completed from the
+    // bottom up.
+    let mut nodes = nodes.into_iter().collect::<Vec<_>>();
+    nodes.sort_unstable_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+    for (path, node) in nodes {
+        if !path.is_empty() && expected.get(&path) != Some(&node) {
+            return Err(StateRootError::DivergenceAt {
+                expected: expected.get(&path).cloned().map(Box::new),
+                computed: Some(Box::new(node)),
+                path,
+            })
+        }
+        emitted.insert(path, node);
+    }
+    Ok(())
+}
+
+/// A storage root computed outside of the walk of [StateRoot::calculate]: the root, the number of
+/// walked storage slots and the storage trie updates.
+type ComputedStorageRoot = (B256, usize, TrieUpdates);
+
+/// The optional hooks of [StateRoot::calculate].
+#[derive(Default)]
+struct CalculateHooks<'h> {
+    /// The branch nodes the emitted account trie nodes are compared against, see
+    /// [StateRoot::root_with_divergence_check].
+    expected_nodes: Option<&'h BTreeMap<Nibbles, BranchNodeCompact>>,
+    /// Returns the storage root of an account leaf if it was computed outside of the walk, see
+    /// [StateRoot::parallel_root_with_updates]. Called in the order of the walk for the account
+    /// leaves whose storage roots are not known by the storage root provider.
+    #[allow(clippy::type_complexity)]
+    storage_roots:
+        Option<&'h mut dyn FnMut(B256) -> Result<Option<ComputedStorageRoot>, StateRootError>>,
+}
+
+/// The maximum number of account leaves whose storage roots [StateRoot::parallel_root_with_updates]
+/// queues or computes ahead of the walk.
+const PARALLEL_STORAGE_ROOTS_LOOKAHEAD: usize = 1024;
+
+impl<'a, TX, S> StateRoot<'a, TX, &'a TX, S>
+where
+    TX: DbTx,
+    S: StorageRootProvider,
+{
+    /// Same as [Self::root_with_updates], but computes the storage roots of the accounts in
+    /// parallel on the threads of the given pool, one worker per thread.
+    ///
+    /// The account leaves are looked up ahead of the walk and their storage roots are queued on a
+    /// shared work-stealing queue. An idle worker steals the next queued account, so a worker
+    /// grinding through a large storage trie doesn't hold up the others. The computed storage
+    /// roots go through a reorder buffer, so the walk feeds them into the hash builder in ascending
+    /// order of the hashed addresses.
+    ///
+    /// The workers read the hashed storage and the storage tries through the transaction of the
+    /// calculator, each with cursors of its own, so they see the same view as the walk, including
+    /// the uncommitted writes of a read-write transaction.
+    ///
+    /// All options apply the same way as with [Self::root_with_updates]: the CPU time of the
+    /// workers counts against the CPU budget and the workers check the cancellation token before
+    /// every storage root. The workers don't use the prefetcher.
+    ///
+    /// While the walk waits for a storage root, it computes the queued ones itself, so the
+    /// computation completes even if the threads of the pool are busy, or if it's called from one
+    /// of them.
+    pub fn parallel_root_with_updates(
+        self,
+        pool: &rayon::ThreadPool,
+    ) -> Result<(B256, TrieUpdates), StateRootError> {
+        tracing::debug!(target: "loader", "calculating state root in parallel");
+        let StateRoot {
+            tx,
+            hashed_cursor_factory,
+            storage_root_provider,
+            changed_account_prefixes,
+            changed_storage_prefixes,
+            destroyed_accounts,
+            previous_state,
+            config,
+        } = self;
+
+        let scheduler = StorageRootScheduler::new(tx, &config, &changed_account_prefixes);
+        let mut storage_roots = ParallelStorageRoots::new(
+            tx,
+            &storage_root_provider,
+            &changed_account_prefixes,
+            &changed_storage_prefixes,
+            previous_state.as_ref().map(|state| state.last_account_key),
+            &config,
+            &scheduler,
+        )?;
+
+        let calculator = StateRoot {
+            tx,
+            hashed_cursor_factory,
+            storage_root_provider: &storage_root_provider,
+            changed_account_prefixes: changed_account_prefixes.clone(),
+            changed_storage_prefixes: changed_storage_prefixes.clone(),
+            destroyed_accounts,
+            previous_state,
+            config: config.clone(),
+        };
+        let progress = pool.in_place_scope(|scope| {
+            for _ in 0..pool.current_num_threads() {
+                scope.spawn(|_| scheduler.run_worker());
+            }
+
+            let mut next_storage_root = |hashed_address| storage_roots.next(hashed_address);
+            let hooks = CalculateHooks {
+                storage_roots: Some(&mut next_storage_root),
+                ..Default::default()
+            };
+            let progress =
+                calculator.with_no_threshold().calculate(true, TrieUpdates::default(), hooks);
+            // The workers are stopped once the lookahead is dropped, also on a panic.
+            drop(storage_roots);
+            progress
+        })?;
+        match progress {
+            StateRootProgress::Complete(root, _, updates) => Ok((root, updates)),
+            // the CPU budget is the only source of progress
+            StateRootProgress::Progress(..) => Err(StateRootError::CpuBudgetExceeded),
+        }
+    }
+}
+
+/// Looks up the account leaves ahead of the walk of [StateRoot::parallel_root_with_updates],
+/// queues their storage roots on the [StorageRootScheduler] and hands the computed ones to the walk
+/// in its order.
+struct ParallelStorageRoots<'p, TX> {
+    /// The account leaves whose storage roots are computed with their changed storage prefixes,
+    /// in the order of the walk.
+    accounts: Box<dyn Iterator<Item = Result<(B256, PrefixSet), StateRootError>> + 'p>,
+    /// The hashed addresses of the queued accounts, in the order of the walk.
+    queued: VecDeque<B256>,
+    /// The sequence number of the first queued account.
+    next_sequence: u64,
+    /// The scheduler computing the storage roots.
+    scheduler: &'p StorageRootScheduler<'p, TX>,
+}
+
+impl<'p, TX: DbTx> ParallelStorageRoots<'p, TX> {
+    /// Looks up the account leaves ahead of the walk the same way [StateRoot::calculate] walks
+    /// them.
+    ///
+    /// Accounts up to the last account key of the intermediate state, if any, are skipped, since
+    /// the walk resumes after them.
+    fn new<S: StorageRootProvider>(
+        tx: &'p TX,
+        storage_root_provider: &'p S,
+        changed_account_prefixes: &'p PrefixSet,
+        changed_storage_prefixes: &'p HashMap<B256, PrefixSet>,
+        resumed_after: Option<B256>,
+        config: &'p StateRootConfig,
+        scheduler: &'p StorageRootScheduler<'p, TX>,
+    ) -> Result<Self, StateRootError> {
+        let changed_account_prefixes = config
+            .restricted_account_prefixes(changed_account_prefixes)
+            .unwrap_or_else(|| changed_account_prefixes.clone());
+        let walker = TrieWalker::new(
+            AccountTrieCursor::new(tx.cursor_read::<tables::AccountsTrie>()?),
+            changed_account_prefixes,
+        );
+        let mut account_node_iter = AccountNodeIter::new(walker, tx.hashed_account_cursor()?);
+
+        let accounts = std::iter::from_fn(move || loop {
+            let (hashed_address, account) = match account_node_iter.try_next() {
+                Ok(Some(AccountNode::Leaf(hashed_address, account))) => (hashed_address, account),
+                Ok(Some(AccountNode::Branch(_))) => continue,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err.into())),
+            };
+            if resumed_after.map_or(true, |last| hashed_address > last) &&
+                config.includes_leaf(&hashed_address, &account) &&
+                storage_root_provider.storage_root(hashed_address).is_none()
+            {
+                let prefixes =
+                    changed_storage_prefixes.get(&hashed_address).cloned().unwrap_or_default();
+                return Some(Ok((hashed_address, prefixes)))
+            }
+        });
+
+        Ok(Self {
+            accounts: Box::new(accounts.fuse()),
+            queued: VecDeque::with_capacity(PARALLEL_STORAGE_ROOTS_LOOKAHEAD),
+            next_sequence: 0,
+            scheduler,
+        })
+    }
+
+    /// Returns the storage root of the given account leaf, waiting for the workers if needed.
+    ///
+    /// Returns `None` if the account was not looked up ahead.
+    fn next(
+        &mut self,
+        hashed_address: B256,
+    ) -> Result<Option<ComputedStorageRoot>, StateRootError> {
+        // Keep the workers busy while the walk waits for the next storage root.
+        while self.queued.len() < PARALLEL_STORAGE_ROOTS_LOOKAHEAD {
+            let Some(account) = self.accounts.next() else { break };
+            let (queued_address, prefixes) = account?;
+            let sequence = self.next_sequence + self.queued.len() as u64;
+            self.scheduler.push(StorageRootTask {
+                sequence,
+                hashed_address: queued_address,
+                prefixes,
+            });
+            self.queued.push_back(queued_address);
+        }
+
+        while let Some(&queued_address) = self.queued.front() {
+            if queued_address > hashed_address {
+                break
+            }
+            self.queued.pop_front();
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+            let storage_root = self.scheduler.wait_for(sequence)?;
+            // The walk never skips a leaf that was looked up ahead.
+            if queued_address == hashed_address {
+                self.scheduler.check_cpu_budget()?;
+                return Ok(Some(storage_root))
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'p, TX> Drop for ParallelStorageRoots<'p, TX> {
+    fn drop(&mut self) {
+        self.scheduler.close();
+    }
+}
+
+/// The storage root computation of an account queued on the [StorageRootScheduler].
+struct StorageRootTask {
+    /// The position of the account in the walk, ordering the computed storage roots.
+    sequence: u64,
+    /// The hashed address of the account.
+    hashed_address: B256,
+    /// The changed storage prefixes of the account.
+    prefixes: PrefixSet,
+}
+
+/// Schedules the storage root computations of [StateRoot::parallel_root_with_updates] on the
+/// worker threads with the options of the state root computation.
+///
+/// The tasks are pushed on a work-stealing queue idle workers steal from, and their results are
+/// collected in a reorder buffer keyed by the position of the account in the walk.
+struct StorageRootScheduler<'p, TX> {
+    /// The transaction of the calculator the workers read through.
+    tx: &'p TX,
+    /// The queued tasks.
+    queue: Injector<StorageRootTask>,
+    /// The reorder buffer of the computed storage roots by the sequence numbers of their tasks.
+    results: Mutex<HashMap<u64, Result<ComputedStorageRoot, StateRootError>>>,
+    /// Notifies the idle workers of a queued task or of the scheduler being closed.
+    task_queued: Condvar,
+    /// Notifies the walk of a computed storage root.
+    result_ready: Condvar,
+    /// Whether the workers should stop.
+    closed: AtomicBool,
+    /// Whether the storage trie updates are retained.
+    retain_updates: bool,
+    /// Whether to emit the deletes of empty storage tries.
+    emit_storage_deletes: bool,
+    /// The maximum path length of the branch nodes to store, if limited.
+    retained_depth: Option<usize>,
+    /// The root of an empty storage trie.
+    empty_root: B256,
+    /// The cache of computed storage roots, if any.
+    storage_root_cache: Option<StorageRootCache>,
+    /// The token aborting the computation once cancelled, if any.
+    cancellation: Option<CancellationToken>,
+    /// The CPU budget shared by the calling thread and the workers, if limited.
+    cpu_budget: Option<Duration>,
+    /// The CPU time of the calling thread.
+    cpu_timer: ThreadCpuTimer,
+    /// The CPU time spent by the workers, in nanoseconds.
+    workers_cpu_time: AtomicU64,
+}
+
+impl<'p, TX> StorageRootScheduler<'p, TX> {
+    /// Stops the workers, dropping the queued tasks.
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        // Taking the lock, a worker can't miss the notification between checking the flag and
+        // waiting.
+        drop(self.results.lock());
+        self.task_queued.notify_all();
+    }
+}
+
+impl<'p, TX: DbTx> StorageRootScheduler<'p, TX> {
+    /// Creates the scheduler with the options of the state root computation.
+    ///
+    /// Like the walk, the workers don't retain any updates if the computation is restricted to
+    /// the bounded prefixes or the allowlisted accounts.
+    fn new(tx: &'p TX, config: &StateRootConfig, changed_account_prefixes: &PrefixSet) -> Self {
+        Self {
+            tx,
+            queue: Injector::new(),
+            results: Mutex::new(HashMap::new()),
+            task_queued: Condvar::new(),
+            result_ready: Condvar::new(),
+            closed: AtomicBool::new(false),
+            retain_updates: config.restricted_account_prefixes(changed_account_prefixes).is_none(),
+            emit_storage_deletes: config.emit_storage_deletes,
+            retained_depth: config.retained_depth,
+            empty_root: config.empty_root,
+            storage_root_cache: config.storage_root_cache.clone(),
+            cancellation: config.cancellation.clone(),
+            cpu_budget: config.cpu_budget,
+            cpu_timer: ThreadCpuTimer::start(),
+            workers_cpu_time: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues a storage root computation.
+    fn push(&self, task: StorageRootTask) {
+        self.queue.push(task);
+        // Taking the lock, a worker can't miss the notification between finding the queue empty
+        // and waiting.
+        drop(self.results.lock());
+        self.task_queued.notify_one();
+    }
+
+    /// Waits for the storage root computed by the task with the given sequence number.
+    ///
+    /// The queued tasks are computed on the calling thread while waiting, so the result arrives
+    /// even if no worker is running.
+    fn wait_for(&self, sequence: u64) -> Result<ComputedStorageRoot, StateRootError> {
+        loop {
+            {
+                let mut results = self.results.lock();
+                if let Some(result) = results.remove(&sequence) {
+                    return result
+                }
+                // the task is taken by a worker, its result is on the way
+                if self.queue.is_empty() {
+                    self.result_ready.wait(&mut results);
+                    continue
+                }
+            }
+
+            // The CPU time of the calling thread is already counted against the budget.
+            if let Some(task) = self.steal() {
+                let sequence = task.sequence;
+                let result = self.run_task(task);
+                self.results.lock().insert(sequence, result);
+            }
+        }
+    }
+
+    /// Steals the next queued task, if any.
+    fn steal(&self) -> Option<StorageRootTask> {
+        std::iter::repeat_with(|| self.queue.steal())
+            .find(|steal| !steal.is_retry())
+            .and_then(Steal::success)
+    }
+
+    /// Computes the queued storage roots until the scheduler is closed.
+    fn run_worker(&self) {
+        loop {
+            if self.closed.load(Ordering::Acquire) {
+                return
+            }
+            let Some(task) = self.steal() else {
+                let mut results = self.results.lock();
+                if self.queue.is_empty() && !self.closed.load(Ordering::Acquire) {
+                    self.task_queued.wait(&mut results);
+                }
+                continue
+            };
+
+            let cpu_timer = ThreadCpuTimer::start();
+            let sequence = task.sequence;
+            let result = self.run_task(task);
+            let spent = cpu_timer.elapsed().as_nanos() as u64;
+            self.workers_cpu_time.fetch_add(spent, Ordering::Relaxed);
+            self.results.lock().insert(sequence, result);
+            self.result_ready.notify_one();
+        }
+    }
+
+    /// Computes the storage root of a task, turning a panic into an error, so that the walk waiting
+    /// for the result fails instead of waiting forever.
+    fn run_task(&self, task: StorageRootTask) -> Result<ComputedStorageRoot, StateRootError> {
+        let StorageRootTask { hashed_address, prefixes, .. } = task;
+        panic::catch_unwind(AssertUnwindSafe(|| self.compute(hashed_address, prefixes)))
+            .unwrap_or_else(|_| Err(StateRootError::StorageRootPanicked { hashed_address }))
+    }
+
+    /// Computes the storage root of an account.
+    fn compute(
+        &self,
+        hashed_address: B256,
+        prefixes: PrefixSet,
+    ) -> Result<ComputedStorageRoot, StateRootError> {
+        if self.cancellation.as_ref().map_or(false, CancellationToken::is_cancelled) {
+            return Err(StateRootError::Cancelled)
+        }
+
+        let mut storage_root_calculator = StorageRoot::new_hashed(self.tx, hashed_address)
+            .with_changed_prefixes(prefixes)
+            .with_emit_storage_deletes(self.emit_storage_deletes)
+            .with_retained_depth(self.retained_depth)
+            .with_empty_root(self.empty_root);
+        storage_root_calculator.cache = self.storage_root_cache.clone();
+        storage_root_calculator
+            .calculate(self.retain_updates)
+            .map_err(|source| StateRootError::StorageRoot { hashed_address, source })
+    }
+
+    /// Fails with [StateRootError::CpuBudgetExceeded] if the calling thread and the workers
+    /// together exceeded the CPU budget.
+    fn check_cpu_budget(&self) -> Result<(), StateRootError> {
+        let workers_cpu_time = Duration::from_nanos(self.workers_cpu_time.load(Ordering::Relaxed));
+        if self
+            .cpu_budget
+            .map_or(false, |budget| self.cpu_timer.elapsed() + workers_cpu_time >= budget)
+        {
+            return Err(StateRootError::CpuBudgetExceeded)
+        }
+        Ok(())
+    }
+}
+
 /// StorageRoot is used to compute the root node of an account storage trie.
 #[derive(Debug)]
 pub struct StorageRoot<'a, TX, H> {
@@ -341,64 +1834,286 @@ pub struct StorageRoot<'a, TX, H> {
     pub tx: &'a TX,
     /// The factory for hashed cursors.
     pub hashed_cursor_factory: H,
+    /// The plain address of the account, if the calculator was created from it. Needed to read
+    /// the history and the changesets of the account, which are keyed by it.
+    address: Option<Address>,
     /// The hashed address of an account.
     pub hashed_address: B256,
     /// The set of storage slot prefixes that have changed.
     pub changed_prefixes: PrefixSet,
+    /// Whether to emit the delete of the storage trie if the storage is empty.
+    emit_storage_deletes: bool,
+    /// The maximum path length of the branch nodes to store, if limited.
+    retained_depth: Option<usize>,
+    /// The cache of computed storage roots, if any.
+    cache: Option<StorageRootCache>,
+    /// The prefetcher reading the storage trie nodes ahead of the walk, if any.
+    prefetcher: Option<TriePrefetcher>,
+    /// Previous intermediate state.
+    previous_state: Option<IntermediateStorageRootState>,
+    /// The number of updates after which the intermediate progress should be returned.
+    threshold: u64,
+    /// The root of an empty storage trie.
+    empty_root: B256,
+    /// The maximum depth of the trie nodes walked by the proof computations.
+    max_proof_depth: usize,
 }
 
 impl<'a, TX: DbTx> StorageRoot<'a, TX, &'a TX> {
     /// Creates a new storage root calculator given an raw address.
     pub fn new(tx: &'a TX, address: Address) -> Self {
-        Self::new_hashed(tx, keccak256(address))
+        Self { address: Some(address), ..Self::new_hashed(tx, keccak256(address)) }
     }
 
     /// Creates a new storage root calculator given a hashed address.
     pub fn new_hashed(tx: &'a TX, hashed_address: B256) -> Self {
         Self {
             tx,
+            address: None,
             hashed_address,
             changed_prefixes: PrefixSetMut::default().freeze(),
+            emit_storage_deletes: true,
+            retained_depth: None,
+            cache: None,
+            prefetcher: None,
+            previous_state: None,
+            threshold: 100_000,
+            empty_root: EMPTY_ROOT_HASH,
+            max_proof_depth: DEFAULT_MAX_PROOF_DEPTH,
             hashed_cursor_factory: tx,
         }
     }
-}
-
-impl<'a, TX, H> StorageRoot<'a, TX, H> {
-    /// Creates a new storage root calculator given an raw address.
-    pub fn new_with_factory(tx: &'a TX, hashed_cursor_factory: H, address: Address) -> Self {
-        Self::new_hashed_with_factory(tx, hashed_cursor_factory, keccak256(address))
-    }
 
-    /// Creates a new storage root calculator given a hashed address.
-    pub fn new_hashed_with_factory(
+    /// Computes the storage root of the account with the given address as it was at the end of
+    /// the given block, see [Self::root_at_block].
+    pub fn historical(
         tx: &'a TX,
-        hashed_cursor_factory: H,
-        hashed_address: B256,
-    ) -> Self {
-        Self {
-            tx,
-            hashed_address,
-            changed_prefixes: PrefixSetMut::default().freeze(),
-            hashed_cursor_factory,
-        }
+        address: Address,
+        at_block: BlockNumber,
+    ) -> Result<B256, StorageRootError> {
+        Self::new(tx, address).root_at_block(at_block)
     }
 
-    /// Set the changed prefixes.
-    pub fn with_changed_prefixes(mut self, prefixes: PrefixSet) -> Self {
-        self.changed_prefixes = prefixes;
-        self
+    /// Returns the plain address of the account, if the calculator was created from it.
+    fn plain_address(&self) -> Result<Address, StorageRootError> {
+        self.address.ok_or(StorageRootError::MissingAddress { hashed_address: self.hashed_address })
     }
 
-    /// Set the hashed cursor factory.
-    pub fn with_hashed_cursor_factory<HF>(
-        self,
-        hashed_cursor_factory: HF,
+    /// Computes the storage root of the account as it was at the end of the given block, with the
+    /// options of this calculator.
+    ///
+    /// The slots of the account that changed after `at_block` are looked up in the
+    /// `StorageHistory` index and rolled back with their first storage changeset after the block.
+    /// The root is then computed over the persisted trie. The cost is proportional to the number
+    /// of slots of the account with a history, not to the size of the storage or the length of the
+    /// chain. The history index and the changesets must cover the blocks after `at_block`.
+    ///
+    /// Since the history and the changesets are keyed by the plain address, this fails with
+    /// [StorageRootError::MissingAddress] if the calculator was created from the hashed address.
+    pub fn root_at_block(&self, at_block: BlockNumber) -> Result<B256, StorageRootError> {
+        let address = self.plain_address()?;
+        let mut changes = HashMap::<B256, U256>::default();
+        if let Some(first_block) = at_block.checked_add(1) {
+            let mut history_cursor = self.tx.cursor_read::<tables::StorageHistory>()?;
+            let mut storage_changeset_cursor =
+                self.tx.cursor_dup_read::<tables::StorageChangeSet>()?;
+
+            let mut entry =
+                history_cursor.seek(StorageShardedKey::new(address, B256::ZERO, first_block))?;
+            while let Some((key, blocks)) = entry.filter(|(key, _)| key.address == address) {
+                let slot = key.sharded_key.key;
+                if key.sharded_key.highest_block_number < first_block {
+                    // The shard only holds changes up to the block, skip to the first shard of the
+                    // slot that ends after it.
+                    entry =
+                        history_cursor.seek(StorageShardedKey::new(address, slot, first_block))?;
+                    continue
+                }
+
+                // The first change of the slot after the block holds its value at the block.
+                let blocks = blocks.0.enable_rank();
+                let rank = blocks.rank(first_block as usize);
+                if rank < blocks.len() {
+                    let block = blocks.select(rank) as BlockNumber;
+                    let changeset_entry = storage_changeset_cursor
+                        .seek_by_key_subkey(BlockNumberAddress((block, address)), slot)?
+                        .filter(|entry| entry.key == slot);
+                    if let Some(StorageEntry { value, .. }) = changeset_entry {
+                        changes.insert(keccak256(slot), value);
+                    }
+                }
+
+                // The following shards of the slot only hold later changes.
+                entry = match history_cursor.seek(StorageShardedKey::last(address, slot))? {
+                    Some((key, _)) if key.address == address && key.sharded_key.key == slot => {
+                        history_cursor.next()?
+                    }
+                    entry => entry,
+                };
+            }
+        }
+
+        let (root, _, _) = self.root_with_overlay(&changes)?;
+        Ok(root)
+    }
+
+    /// Calculates the storage root with the given slot changes layered over the persisted hashed
+    /// storage, without writing them to the hashed storage table first.
+    ///
+    /// The changes are keyed by the hashed slot, a zero value deletes the slot. The changed slots
+    /// are added to the changed prefixes and the other options of the calculator apply. The
+    /// storage root cache is not used, since its keys don't capture the values of the changes.
+    ///
+    /// # Returns
+    ///
+    /// The storage root and storage trie updates for a given address.
+    pub fn root_with_overlay(
+        &self,
+        changes: &HashMap<B256, U256>,
+    ) -> Result<(B256, usize, TrieUpdates), StorageRootError> {
+        let mut hashed_storage = HashedStorage::new(false);
+        let mut changed_prefixes = PrefixSetMut::from(self.changed_prefixes.iter().cloned());
+        for (hashed_slot, value) in changes {
+            if *value == U256::ZERO {
+                hashed_storage.insert_zero_valued_slot(*hashed_slot);
+            } else {
+                hashed_storage.insert_non_zero_valued_storage(*hashed_slot, *value);
+            }
+            changed_prefixes.insert(Nibbles::unpack(hashed_slot));
+        }
+        hashed_storage.sort_storage();
+
+        let mut post_state = HashedPostState::default();
+        post_state.insert_hashed_storage(self.hashed_address, hashed_storage);
+
+        let mut storage_root_calculator = StorageRoot::new_hashed_with_factory(
+            self.tx,
+            HashedPostStateCursorFactory::new(self.tx, &post_state),
+            self.hashed_address,
+        )
+        .with_changed_prefixes(changed_prefixes.freeze())
+        .with_emit_storage_deletes(self.emit_storage_deletes)
+        .with_retained_depth(self.retained_depth)
+        .with_empty_root(self.empty_root);
+        storage_root_calculator.prefetcher = self.prefetcher.clone();
+        storage_root_calculator.root_with_updates()
+    }
+}
+
+impl<'a, TX, H> StorageRoot<'a, TX, H> {
+    /// Creates a new storage root calculator given an raw address.
+    pub fn new_with_factory(tx: &'a TX, hashed_cursor_factory: H, address: Address) -> Self {
+        Self {
+            address: Some(address),
+            ..Self::new_hashed_with_factory(tx, hashed_cursor_factory, keccak256(address))
+        }
+    }
+
+    /// Creates a new storage root calculator given a hashed address.
+    pub fn new_hashed_with_factory(
+        tx: &'a TX,
+        hashed_cursor_factory: H,
+        hashed_address: B256,
+    ) -> Self {
+        Self {
+            tx,
+            address: None,
+            hashed_address,
+            changed_prefixes: PrefixSetMut::default().freeze(),
+            emit_storage_deletes: true,
+            retained_depth: None,
+            cache: None,
+            prefetcher: None,
+            previous_state: None,
+            threshold: 100_000,
+            empty_root: EMPTY_ROOT_HASH,
+            max_proof_depth: DEFAULT_MAX_PROOF_DEPTH,
+            hashed_cursor_factory,
+        }
+    }
+
+    /// Set the changed prefixes.
+    pub fn with_changed_prefixes(mut self, prefixes: PrefixSet) -> Self {
+        self.changed_prefixes = prefixes;
+        self
+    }
+
+    /// Set whether the updates should delete the storage trie if the storage is empty.
+    ///
+    /// Enabled by default. If disabled, the [TrieKey::StorageTrie] delete is not emitted and
+    /// removing the stale storage trie is left to the caller.
+    pub fn with_emit_storage_deletes(mut self, emit_storage_deletes: bool) -> Self {
+        self.emit_storage_deletes = emit_storage_deletes;
+        self
+    }
+
+    /// Set the maximum path length in nibbles of the branch nodes that are stored, see
+    /// [StateRoot::with_retained_depth].
+    pub fn with_retained_depth(mut self, depth: Option<usize>) -> Self {
+        self.retained_depth = depth;
+        self
+    }
+
+    /// Set the cache of computed storage roots.
+    ///
+    /// A calculation with the same hashed address, changed prefixes and update options as a cached
+    /// one returns the cached root and trie updates without walking the storage, see
+    /// [StorageRootCache] for the invalidation caveats.
+    pub fn with_storage_root_cache(mut self, cache: StorageRootCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Set the prefetcher that reads the storage trie nodes ahead of the walk, see
+    /// [StateRoot::with_prefetcher].
+    pub fn with_prefetcher(mut self, prefetcher: TriePrefetcher) -> Self {
+        self.prefetcher = Some(prefetcher);
+        self
+    }
+
+    /// Set the root of an empty storage trie, [EMPTY_ROOT_HASH] by default.
+    pub fn with_empty_root(mut self, empty_root: B256) -> Self {
+        self.empty_root = empty_root;
+        self
+    }
+
+    /// Set the maximum depth of the trie nodes walked by the proof computations, see
+    /// [Proof::with_max_proof_depth](crate::proof::Proof::with_max_proof_depth).
+    pub fn with_max_proof_depth(mut self, max_proof_depth: usize) -> Self {
+        self.max_proof_depth = max_proof_depth;
+        self
+    }
+
+    /// Set the threshold of [Self::root_with_progress].
+    pub fn with_threshold(mut self, threshold: u64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the previously recorded intermediate state of [Self::root_with_progress].
+    pub fn with_intermediate_state(mut self, state: Option<IntermediateStorageRootState>) -> Self {
+        self.previous_state = state;
+        self
+    }
+
+    /// Set the hashed cursor factory.
+    pub fn with_hashed_cursor_factory<HF>(
+        self,
+        hashed_cursor_factory: HF,
     ) -> StorageRoot<'a, TX, HF> {
         StorageRoot {
             tx: self.tx,
+            address: self.address,
             hashed_address: self.hashed_address,
             changed_prefixes: self.changed_prefixes,
+            emit_storage_deletes: self.emit_storage_deletes,
+            retained_depth: self.retained_depth,
+            cache: self.cache,
+            prefetcher: self.prefetcher,
+            previous_state: self.previous_state,
+            threshold: self.threshold,
+            empty_root: self.empty_root,
+            max_proof_depth: self.max_proof_depth,
             hashed_cursor_factory,
         }
     }
@@ -418,6 +2133,93 @@ where
         self.calculate(true)
     }
 
+    /// Walks the hashed storage table entries for a given address and calculates the storage root,
+    /// returning the intermediate progress once the number of collected updates reaches the
+    /// threshold.
+    ///
+    /// This bounds the memory held by the updates of a huge storage trie: the caller can write
+    /// the updates of every [StorageRootProgress::Progress] and resume with
+    /// [Self::with_intermediate_state]. The storage root cache is not used.
+    pub fn root_with_progress(mut self) -> Result<StorageRootProgress, StorageRootError> {
+        let previous_state = self.previous_state.take();
+        self.calculate_with_progress(true, previous_state, self.threshold, |_, _| {})
+    }
+
+    /// Returns `true` if the given hashed slot of the account is set.
+    ///
+    /// This is a single seek of the hashed storage cursor, without walking the storage, e.g. to
+    /// decide between generating an inclusion or an exclusion proof for the slot.
+    pub fn slot_exists(&self, hashed_slot: B256) -> Result<bool, StorageRootError> {
+        let mut hashed_storage_cursor = self.hashed_cursor_factory.hashed_storage_cursor()?;
+        let entry = hashed_storage_cursor.seek(self.hashed_address, hashed_slot)?;
+        Ok(entry.map_or(false, |entry| entry.key == hashed_slot))
+    }
+
+    /// Same as [Self::root_with_updates], but also classifies how the leaf of every changed slot
+    /// changed over the given block range, keyed by the hashed slot.
+    ///
+    /// The changed slots are the full-length changed prefixes, shorter prefixes are ignored. A
+    /// slot is classified by comparing its leaf in the walk against its value before the range,
+    /// taken from the first storage changeset of the account in the range. This matches the
+    /// regular incremental flow, where the hashed storage already holds the storage at the end of
+    /// the range and the changed prefixes are loaded from the same changesets. Slots without a
+    /// changeset in the range and slots whose value didn't change are omitted.
+    ///
+    /// The storage root cache is not used, since the storage has to be walked. Like
+    /// [StorageRoot::root_at_block], this fails with [StorageRootError::MissingAddress] if the
+    /// calculator was created from the hashed address.
+    pub fn root_with_slot_changes(
+        &self,
+        range: RangeInclusive<BlockNumber>,
+    ) -> Result<(B256, usize, TrieUpdates, BTreeMap<B256, StorageSlotChange>), StorageRootError>
+    {
+        let address = self.plain_address()?;
+
+        let changed_slots = self
+            .changed_prefixes
+            .iter()
+            .filter(|prefix| prefix.len() == 64)
+            .map(|prefix| B256::from_slice(&prefix.pack()))
+            .collect::<HashSet<_>>();
+
+        // The first changeset entry of a slot in the range holds its value before the range. A
+        // zero value means the slot didn't exist.
+        let mut previous_values = HashMap::<B256, U256>::default();
+        let mut storage_changeset_cursor = self.tx.cursor_read::<tables::StorageChangeSet>()?;
+        let (start, end) = range.into_inner();
+        let changesets = storage_changeset_cursor.walk_range(
+            BlockNumberAddress((start, address))..=BlockNumberAddress((end, address)),
+        )?;
+        for entry in changesets {
+            let (BlockNumberAddress((_, changed_address)), StorageEntry { key, value }) = entry?;
+            if changed_address == address {
+                previous_values.entry(keccak256(key)).or_insert(value);
+            }
+        }
+
+        let mut values = HashMap::with_capacity(changed_slots.len());
+        let (root, walked, updates) =
+            self.calculate_uncached_with(true, |hashed_slot, value| {
+                if changed_slots.contains(&hashed_slot) {
+                    values.insert(hashed_slot, value);
+                }
+            })?;
+
+        let mut slot_changes = BTreeMap::new();
+        for hashed_slot in changed_slots {
+            let Some(previous) = previous_values.get(&hashed_slot) else { continue };
+            let change = match (*previous, values.get(&hashed_slot)) {
+                (U256::ZERO, Some(_)) => StorageSlotChange::Added,
+                (previous, Some(value)) if previous != *value => StorageSlotChange::Updated,
+                (previous, None) if previous != U256::ZERO => StorageSlotChange::Deleted,
+                _ => continue,
+            };
+            slot_changes.insert(hashed_slot, change);
+        }
+
+        Ok((root, walked, updates, slot_changes))
+    }
+
     /// Walks the hashed storage table entries for a given address and calculates the storage root.
     ///
     /// # Returns
@@ -428,331 +2230,3116 @@ where
         Ok(root)
     }
 
-    fn calculate(
+    /// Checks whether the hashed storage produces the claimed storage root.
+    ///
+    /// The root is computed without collecting any updates.
+    pub fn verify_root(&self, claimed: B256) -> Result<bool, StorageRootError> {
+        Ok(self.root()? == claimed)
+    }
+
+    /// Estimates how many existing storage slots are affected by the changed prefixes without
+    /// calculating the root.
+    ///
+    /// Every changed prefix is probed with a single seek of the hashed storage cursor and counts
+    /// at most one existing slot. The result is an estimate of the work needed to recompute the
+    /// root, not the exact number of changed leaves.
+    pub fn changed_slot_count(&self) -> Result<usize, StorageRootError> {
+        let mut hashed_storage_cursor = self.hashed_cursor_factory.hashed_storage_cursor()?;
+        if hashed_storage_cursor.is_storage_empty(self.hashed_address)? {
+            return Ok(0)
+        }
+
+        let mut count = 0;
+        for prefix in self.changed_prefixes.iter() {
+            // The prefix padded with zero nibbles is the smallest slot under it.
+            let packed = prefix.pack();
+            let len = packed.len().min(32);
+            let mut seek_key = B256::ZERO;
+            seek_key[..len].copy_from_slice(&packed[..len]);
+
+            if hashed_storage_cursor
+                .seek(self.hashed_address, seek_key)?
+                .map_or(false, |entry| Nibbles::unpack(entry.key).has_prefix(prefix))
+            {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Walks the hashed storage table entries for a given address and calculates the storage root
+    /// while retaining the merkle proofs for the given storage slots in the same pass.
+    ///
+    /// Slots that are not present in the storage get an exclusion proof with a zero value. Fails
+    /// with [ProofError::DepthExceeded] on a trie node deeper than the maximum proof depth, see
+    /// [Self::with_max_proof_depth].
+    ///
+    /// # Returns
+    ///
+    /// The storage root and the storage proofs keyed by the raw storage slot.
+    pub fn root_with_proof_for_slots(
         &self,
-        retain_updates: bool,
-    ) -> Result<(B256, usize, TrieUpdates), StorageRootError> {
-        tracing::debug!(target: "trie::storage_root", hashed_address = ?self.hashed_address, "calculating storage root");
+        slots: &[B256],
+    ) -> Result<(B256, HashMap<B256, StorageProof>), ProofError> {
+        let mut proofs =
+            slots.iter().map(|slot| (*slot, StorageProof::new(*slot))).collect::<HashMap<_, _>>();
+
+        // Lookup from the target nibbles to the requested slot.
+        let targets = proofs
+            .values()
+            .map(|proof| (proof.nibbles.clone(), proof.key))
+            .collect::<HashMap<_, _>>();
+
+        let Some((root, mut hash_builder)) = self.walk_with_proof_retainer(
+            targets.keys().cloned().collect(),
+            |_, nibbles, value| {
+                let Some(value) = value else { return };
+                if let Some(proof) = targets.get(nibbles).and_then(|slot| proofs.get_mut(slot)) {
+                    proof.set_value(value);
+                }
+            },
+        )?
+        else {
+            return Ok((self.empty_root, proofs))
+        };
+        let all_proof_nodes = hash_builder.take_proofs();
+
+        for proof in proofs.values_mut() {
+            // The proof nodes are sorted by path, so the matching ones are in order from the root.
+            let matching_proof_nodes = all_proof_nodes
+                .iter()
+                .filter(|(path, _)| proof.nibbles.starts_with(path))
+                .map(|(_, node)| node.clone());
+            proof.set_proof(matching_proof_nodes.collect());
+        }
+
+        Ok((root, proofs))
+    }
+
+    /// Computes the storage root and passes the merkle proof of every given storage slot to
+    /// `on_proof` as soon as it is complete, so the caller can serialize it right away.
+    ///
+    /// A proof node is only complete once the walk has left its subtree, so the nodes a proof
+    /// shares with the following slots would only be complete after their proofs. These shared
+    /// nodes are computed by a first walk that only descends along the shared paths, which is
+    /// cheap if the storage trie is persisted. The second walk then emits the proofs in the order
+    /// of the hashed slots, each as soon as the walk has left the subtree below the nodes it shares
+    /// with the next slot, and the shared nodes are released after the last proof containing them.
+    /// Besides the shared nodes, at most one per requested slot, the memory is bounded by about a
+    /// single proof.
+    ///
+    /// Slots that are not present in the storage get an exclusion proof with a zero value.
+    /// Duplicate slots are proven once. Like [Self::root_with_proof_for_slots], this fails with
+    /// [ProofError::DepthExceeded] on a trie node deeper than the maximum proof depth.
+    pub fn stream_proofs_for_slots(
+        &self,
+        slots: &[B256],
+        mut on_proof: impl FnMut(StorageProof),
+    ) -> Result<B256, ProofError> {
+        // The requested slots in the order of the walk.
+        let targets = slots
+            .iter()
+            .map(|slot| (Nibbles::unpack(keccak256(slot)), *slot))
+            .collect::<BTreeMap<_, _>>();
+        let mut pending = VecDeque::with_capacity(targets.len());
+        let mut targets = targets.into_iter().peekable();
+        while let Some((nibbles, slot)) = targets.next() {
+            let shared_len = targets.peek().map(|(next, _)| nibbles.common_prefix_length(next));
+            pending.push_back(PendingStorageProof { nibbles, slot, shared_len, value: None });
+        }
+
+        let shared_paths = pending
+            .iter()
+            .filter_map(|target| Some(target.nibbles.slice(0, target.shared_len?)))
+            .collect::<Vec<_>>();
+        let mut shared_nodes = if shared_paths.is_empty() {
+            BTreeMap::new()
+        } else {
+            self.walk_with_proof_retainer(shared_paths, |_, _, _| {})?
+                .map(|(_, mut hash_builder)| hash_builder.take_proofs())
+                .unwrap_or_default()
+        };
+
+        let walked = self.walk_with_proof_retainer(
+            pending.iter().map(|target| target.nibbles.clone()).collect(),
+            |hash_builder, key, value| {
+                if let Some(value) = value {
+                    if let Some(target) = pending.iter_mut().find(|target| target.nibbles >= *key) {
+                        if target.nibbles == *key {
+                            target.value = Some(value);
+                        }
+                    }
+                }
+                while pending.front().map_or(false, |target| target.is_complete_before(key)) {
+                    let target = pending.pop_front().expect("front exists");
+                    on_proof(target.into_proof(hash_builder, &mut shared_nodes, pending.front()));
+                }
+            },
+        )?;
+
+        let Some((root, mut hash_builder)) = walked else {
+            for target in pending {
+                on_proof(StorageProof::new_with_nibbles(target.slot, target.nibbles));
+            }
+            return Ok(self.empty_root)
+        };
+        // All nodes are complete once the root is computed.
+        while let Some(target) = pending.pop_front() {
+            on_proof(target.into_proof(&mut hash_builder, &mut shared_nodes, pending.front()));
+        }
+
+        Ok(root)
+    }
+
+    /// Walks the storage and computes the root while retaining the proof nodes of the target
+    /// paths. `on_walked` is called with the hash builder after every walked node was added to
+    /// it, with the key of the node and the value of a walked slot.
+    ///
+    /// Returns `None` if the storage is empty, otherwise the root and the hash builder holding
+    /// the retained proof nodes. Fails on a branch node deeper than the maximum proof depth.
+    fn walk_with_proof_retainer(
+        &self,
+        targets: Vec<Nibbles>,
+        mut on_walked: impl FnMut(&mut HashBuilder, &Nibbles, Option<U256>),
+    ) -> Result<Option<(B256, HashBuilder)>, ProofError> {
         let mut hashed_storage_cursor = self.hashed_cursor_factory.hashed_storage_cursor()?;
 
         // short circuit on empty storage
         if hashed_storage_cursor.is_storage_empty(self.hashed_address)? {
-            return Ok((
-                EMPTY_ROOT_HASH,
-                0,
-                TrieUpdates::from([(TrieKey::StorageTrie(self.hashed_address), TrieOp::Delete)]),
-            ))
+            return Ok(None)
         }
 
+        // The walker needs to descend into the changed prefixes to compute the correct root and
+        // into the target paths to reveal the proof nodes.
+        let prefix_set = PrefixSetMut::from(
+            self.changed_prefixes.iter().cloned().chain(targets.iter().cloned()),
+        )
+        .freeze();
         let trie_cursor = StorageTrieCursor::new(
             self.tx.cursor_dup_read::<tables::StoragesTrie>()?,
             self.hashed_address,
         );
-        let walker = TrieWalker::new(trie_cursor, self.changed_prefixes.clone())
-            .with_updates(retain_updates);
-
-        let mut hash_builder = HashBuilder::default().with_updates(retain_updates);
+        let walker = TrieWalker::new(trie_cursor, prefix_set);
 
-        let mut storage_slots_walked = 0;
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(targets);
         let mut storage_node_iter =
             StorageNodeIter::new(walker, hashed_storage_cursor, self.hashed_address);
         while let Some(node) = storage_node_iter.try_next()? {
             match node {
                 StorageNode::Branch(node) => {
-                    hash_builder.add_branch(node.key, node.value, node.children_are_in_trie);
+                    check_proof_depth(&node.key, self.max_proof_depth)?;
+                    hash_builder.add_branch(
+                        node.key.clone(),
+                        node.value,
+                        node.children_are_in_trie,
+                    );
+                    on_walked(&mut hash_builder, &node.key, None);
                 }
                 StorageNode::Leaf(hashed_slot, value) => {
-                    storage_slots_walked += 1;
-                    hash_builder.add_leaf(
-                        Nibbles::unpack(hashed_slot),
-                        alloy_rlp::encode_fixed_size(&value).as_ref(),
-                    );
+                    let nibbles = Nibbles::unpack(hashed_slot);
+                    hash_builder
+                        .add_leaf(nibbles.clone(), alloy_rlp::encode_fixed_size(&value).as_ref());
+                    on_walked(&mut hash_builder, &nibbles, Some(value));
                 }
             }
         }
 
+        // The hash builder returns the default empty root if no leaves were added.
         let root = hash_builder.root();
+        let root = if root == EMPTY_ROOT_HASH { self.empty_root } else { root };
+        Ok(Some((root, hash_builder)))
+    }
 
-        let (_, hash_builder_updates) = hash_builder.split();
-        let (_, walker_updates) = storage_node_iter.walker.split();
+    /// Computes the merkle proofs for the given storage slots in chunks of at most `chunk_size`
+    /// slots.
+    ///
+    /// Every chunk is a separate walk of the storage, see [Self::root_with_proof_for_slots], that
+    /// only retains the proof nodes of the slots in the chunk. Compared to proving all slots in a
+    /// single pass, this bounds the memory held by the retained proof nodes by the chunk size at
+    /// the cost of walking the storage once per chunk. The chunks are computed lazily as the
+    /// iterator is advanced.
+    ///
+    /// # Panics
+    ///
+    /// If `chunk_size` is zero.
+    pub fn proofs_for_slots_chunked<'b>(
+        &'b self,
+        slots: &'b [B256],
+        chunk_size: usize,
+    ) -> StorageProofChunks<'b, 'a, TX, H> {
+        StorageProofChunks { storage_root: self, chunks: slots.chunks(chunk_size) }
+    }
 
-        let mut trie_updates = TrieUpdates::default();
-        trie_updates.extend(walker_updates.into_iter());
-        trie_updates.extend_with_storage_updates(self.hashed_address, hash_builder_updates);
+    /// Calculates the storage root, unless it is cached. No storage slots are walked on a hit.
+    fn calculate(
+        &self,
+        retain_updates: bool,
+    ) -> Result<(B256, usize, TrieUpdates), StorageRootError> {
+        let Some(cache) = &self.cache else { return self.calculate_uncached(retain_updates) };
 
-        tracing::debug!(target: "trie::storage_root", ?root, hashed_address = ?self.hashed_address, "calculated storage root");
+        let key = StorageRootCacheKey::new(
+            self.hashed_address,
+            &self.changed_prefixes,
+            self.emit_storage_deletes,
+            self.retained_depth,
+            self.empty_root,
+        );
+        if let Some((root, trie_updates)) = cache.get(&key, retain_updates) {
+            tracing::trace!(target: "trie::storage_root", ?root, hashed_address = ?self.hashed_address, "storage root cache hit");
+            return Ok((root, 0, trie_updates))
+        }
+
+        let (root, storage_slots_walked, trie_updates) = self.calculate_uncached(retain_updates)?;
+        cache.insert(key, root, retain_updates.then(|| trie_updates.clone()));
         Ok((root, storage_slots_walked, trie_updates))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::test_utils::{
-        state_root, state_root_prehashed, storage_root, storage_root_prehashed,
-    };
-    use proptest::{prelude::ProptestConfig, proptest};
-    use reth_db::{
-        cursor::{DbCursorRO, DbCursorRW, DbDupCursorRO},
-        tables,
-        test_utils::create_test_rw_db,
-        transaction::DbTxMut,
-        DatabaseEnv,
-    };
-    use reth_primitives::{
-        hex_literal::hex,
-        keccak256,
-        proofs::triehash::KeccakHasher,
-        trie::{BranchNodeCompact, TrieMask},
-        Account, Address, StorageEntry, B256, MAINNET, U256,
-    };
-    use reth_provider::{DatabaseProviderRW, ProviderFactory};
-    use std::{collections::BTreeMap, ops::Mul, str::FromStr};
+    fn calculate_uncached(
+        &self,
+        retain_updates: bool,
+    ) -> Result<(B256, usize, TrieUpdates), StorageRootError> {
+        self.calculate_uncached_with(retain_updates, |_, _| {})
+    }
 
-    fn insert_account(
-        tx: &impl DbTxMut,
-        address: Address,
-        account: Account,
-        storage: &BTreeMap<B256, U256>,
-    ) {
-        let hashed_address = keccak256(address);
-        tx.put::<tables::HashedAccount>(hashed_address, account).unwrap();
-        insert_storage(tx, hashed_address, storage);
+    /// Same as [Self::calculate_uncached], but calls `on_leaf` with every walked storage slot.
+    fn calculate_uncached_with(
+        &self,
+        retain_updates: bool,
+        on_leaf: impl FnMut(B256, U256),
+    ) -> Result<(B256, usize, TrieUpdates), StorageRootError> {
+        match self.calculate_with_progress(retain_updates, None, u64::MAX, on_leaf)? {
+            StorageRootProgress::Complete(root, walked, updates) => Ok((root, walked, updates)),
+            StorageRootProgress::Progress(..) => unreachable!(), // unreachable threshold
+        }
     }
 
-    fn insert_storage(tx: &impl DbTxMut, hashed_address: B256, storage: &BTreeMap<B256, U256>) {
-        for (k, v) in storage {
-            tx.put::<tables::HashedStorage>(
-                hashed_address,
-                StorageEntry { key: keccak256(k), value: *v },
-            )
-            .unwrap();
-        }
+    /// Computes the storage root, resuming from the given intermediate state, if any.
+    ///
+    /// Returns the intermediate progress once `threshold` updates are collected.
+    fn calculate_with_progress(
+        &self,
+        retain_updates: bool,
+        previous_state: Option<IntermediateStorageRootState>,
+        threshold: u64,
+        mut on_leaf: impl FnMut(B256, U256),
+    ) -> Result<StorageRootProgress, StorageRootError> {
+        tracing::debug!(target: "trie::storage_root", hashed_address = ?self.hashed_address, "calculating storage root");
+        let mut hashed_storage_cursor = self.hashed_cursor_factory.hashed_storage_cursor()?;
+
+        // short circuit on empty storage
+        if hashed_storage_cursor.is_storage_empty(self.hashed_address)? {
+            let trie_updates = if self.emit_storage_deletes {
+                TrieUpdates::from([(TrieKey::StorageTrie(self.hashed_address), TrieOp::Delete)])
+            } else {
+                TrieUpdates::default()
+            };
+            return Ok(StorageRootProgress::Complete(self.empty_root, 0, trie_updates))
+        }
+
+        let trie_cursor = PrefetchTrieCursor::storage(
+            StorageTrieCursor::new(
+                self.tx.cursor_dup_read::<tables::StoragesTrie>()?,
+                self.hashed_address,
+            ),
+            self.hashed_address,
+            self.prefetcher.clone(),
+        );
+        let mut last_hashed_slot = previous_state.as_ref().map(|state| state.last_slot_key);
+        let (mut hash_builder, mut storage_node_iter) = match previous_state {
+            Some(state) => {
+                let walker = TrieWalker::from_stack(
+                    trie_cursor,
+                    state.walker_stack,
+                    self.changed_prefixes.clone(),
+                );
+                (
+                    state.hash_builder,
+                    StorageNodeIter::new(walker, hashed_storage_cursor, self.hashed_address)
+                        .with_last_slot_key(state.last_slot_key),
+                )
+            }
+            None => {
+                let walker = TrieWalker::new(trie_cursor, self.changed_prefixes.clone());
+                (
+                    HashBuilder::default(),
+                    StorageNodeIter::new(walker, hashed_storage_cursor, self.hashed_address),
+                )
+            }
+        };
+
+        storage_node_iter.walker.set_updates(retain_updates);
+        storage_node_iter.walker.set_prefetch(self.prefetcher.is_some());
+        hash_builder.set_updates(retain_updates);
+        hash_builder.set_retained_depth(self.retained_depth);
+
+        let mut storage_slots_walked = 0;
+        while let Some(node) = storage_node_iter.try_next()? {
+            match node {
+                StorageNode::Branch(node) => {
+                    hash_builder.add_branch(node.key, node.value, node.children_are_in_trie);
+                }
+                StorageNode::Leaf(hashed_slot, value) => {
+                    storage_slots_walked += 1;
+
+                    // The hash builder requires the leaves in strictly ascending order.
+                    if let Some(last) = last_hashed_slot {
+                        debug_assert!(
+                            last < hashed_slot,
+                            "hashed storage slots out of order: {hashed_slot} after {last}"
+                        );
+                    }
+                    last_hashed_slot = Some(hashed_slot);
+                    on_leaf(hashed_slot, value);
+                    hash_builder.add_leaf(
+                        Nibbles::unpack(hashed_slot),
+                        alloy_rlp::encode_fixed_size(&value).as_ref(),
+                    );
+
+                    // Decide if we need to return intermediate progress.
+                    let total_updates_len =
+                        storage_node_iter.walker.updates_len() + hash_builder.updates_len();
+                    if retain_updates && total_updates_len as u64 >= threshold {
+                        let (walker_stack, walker_updates) = storage_node_iter.walker.split();
+                        let (hash_builder, hash_builder_updates) = hash_builder.split();
+
+                        let state = IntermediateStorageRootState {
+                            hash_builder,
+                            walker_stack,
+                            last_slot_key: hashed_slot,
+                        };
+
+                        let mut trie_updates = TrieUpdates::default();
+                        trie_updates.extend(walker_updates.into_iter());
+                        trie_updates
+                            .extend_with_storage_updates(self.hashed_address, hash_builder_updates);
+
+                        return Ok(StorageRootProgress::Progress(
+                            Box::new(state),
+                            storage_slots_walked,
+                            trie_updates,
+                        ))
+                    }
+                }
+            }
+        }
+
+        // The hash builder returns the default empty root if no leaves were added.
+        let root = hash_builder.root();
+        let root = if root == EMPTY_ROOT_HASH { self.empty_root } else { root };
+
+        let (_, hash_builder_updates) = hash_builder.split();
+        let (_, walker_updates) = storage_node_iter.walker.split();
+
+        let mut trie_updates = TrieUpdates::default();
+        trie_updates.extend(walker_updates.into_iter());
+        trie_updates.extend_with_storage_updates(self.hashed_address, hash_builder_updates);
+
+        tracing::debug!(target: "trie::storage_root", ?root, hashed_address = ?self.hashed_address, "calculated storage root");
+        Ok(StorageRootProgress::Complete(root, storage_slots_walked, trie_updates))
+    }
+}
+
+/// How the leaf of a changed storage slot changed, see [StorageRoot::root_with_slot_changes].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageSlotChange {
+    /// The slot was added to the storage.
+    Added,
+    /// The value of the slot changed.
+    Updated,
+    /// The slot was removed from the storage.
+    Deleted,
+}
+
+/// A storage proof of [StorageRoot::stream_proofs_for_slots] waiting for its nodes to be
+/// complete.
+struct PendingStorageProof {
+    /// The nibbles of the hashed slot.
+    nibbles: Nibbles,
+    /// The requested slot.
+    slot: B256,
+    /// The length of the path shared with the next requested slot, if any.
+    shared_len: Option<usize>,
+    /// The value of the slot, if it is present in the storage.
+    value: Option<U256>,
+}
+
+impl PendingStorageProof {
+    /// The path below which the nodes of the proof are not shared with the next slot.
+    fn private_path(&self) -> Nibbles {
+        self.nibbles.slice(0, self.shared_len.map_or(0, |len| len + 1))
+    }
+
+    /// Returns `true` if all nodes of the proof below the shared ones are complete once the walk
+    /// reached the given key.
+    ///
+    /// The nodes of the last slot are only complete once the root is computed.
+    fn is_complete_before(&self, key: &Nibbles) -> bool {
+        if self.shared_len.is_none() {
+            return false
+        }
+        let private_path = self.private_path();
+        *key > private_path && !key.has_prefix(&private_path)
+    }
+
+    /// Assembles the proof from the shared nodes of the first walk and the private nodes retained
+    /// by the hash builder.
+    ///
+    /// The shared nodes also shared with the slot after the next one are kept, the others are
+    /// released.
+    fn into_proof(
+        self,
+        hash_builder: &mut HashBuilder,
+        shared_nodes: &mut BTreeMap<Nibbles, Bytes>,
+        next: Option<&PendingStorageProof>,
+    ) -> StorageProof {
+        let private_path = self.private_path();
+        let mut proof = Vec::new();
+        for len in 0..private_path.len() {
+            let path = self.nibbles.slice(0, len);
+            let node = if next.and_then(|next| next.shared_len).map_or(false, |next| len <= next) {
+                shared_nodes.get(&path).cloned()
+            } else {
+                shared_nodes.remove(&path)
+            };
+            proof.extend(node);
+        }
+        // The retained nodes are sorted by path, so the matching ones are in order from the root.
+        proof.extend(
+            hash_builder
+                .take_proofs_with_prefix(&private_path)
+                .into_iter()
+                .filter(|(path, _)| self.nibbles.has_prefix(path))
+                .map(|(_, node)| node),
+        );
+
+        let mut storage_proof = StorageProof::new_with_nibbles(self.slot, self.nibbles);
+        storage_proof.set_value(self.value.unwrap_or_default());
+        storage_proof.set_proof(proof);
+        storage_proof
+    }
+}
+
+/// An iterator over the merkle proofs of storage slots, computed in chunks.
+///
+/// See [StorageRoot::proofs_for_slots_chunked].
+#[derive(Debug)]
+pub struct StorageProofChunks<'b, 'a, TX, H> {
+    /// The storage root calculator of the account.
+    storage_root: &'b StorageRoot<'a, TX, H>,
+    /// The remaining chunks of slots to prove.
+    chunks: std::slice::Chunks<'b, B256>,
+}
+
+impl<'b, 'a, TX, H> Iterator for StorageProofChunks<'b, 'a, TX, H>
+where
+    TX: DbTx,
+    H: HashedCursorFactory,
+{
+    type Item = Result<HashMap<B256, StorageProof>, ProofError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.chunks.next()?;
+        Some(self.storage_root.root_with_proof_for_slots(chunk).map(|(_, proofs)| proofs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        intermediate_state_prehashed, state_root, state_root_prehashed, storage_root,
+        storage_root_prehashed, verify_witness,
+    };
+    use proptest::{prelude::ProptestConfig, proptest};
+    use reth_db::{
+        cursor::{DbCursorRO, DbCursorRW, DbDupCursorRO},
+        database::Database,
+        models::{AccountBeforeTx, BlockNumberAddress},
+        tables,
+        test_utils::create_test_rw_db,
+        transaction::DbTxMut,
+        BlockNumberList, DatabaseEnv,
+    };
+    use reth_interfaces::provider::ProviderError;
+    use reth_primitives::{
+        hex_literal::hex,
+        keccak256,
+        proofs::triehash::KeccakHasher,
+        trie::{BranchNodeCompact, StoredNibbles, TrieMask},
+        Account, Address, StorageEntry, B256, MAINNET, U256,
+    };
+    use reth_provider::{DatabaseProviderRW, ProviderFactory};
+    use std::{cell::RefCell, collections::BTreeMap, ops::Mul, rc::Rc, str::FromStr};
+
+    fn insert_account(
+        tx: &impl DbTxMut,
+        address: Address,
+        account: Account,
+        storage: &BTreeMap<B256, U256>,
+    ) {
+        let hashed_address = keccak256(address);
+        tx.put::<tables::HashedAccount>(hashed_address, account).unwrap();
+        insert_storage(tx, hashed_address, storage);
+    }
+
+    fn insert_storage(tx: &impl DbTxMut, hashed_address: B256, storage: &BTreeMap<B256, U256>) {
+        for (k, v) in storage {
+            tx.put::<tables::HashedStorage>(
+                hashed_address,
+                StorageEntry { key: keccak256(k), value: *v },
+            )
+            .unwrap();
+        }
+    }
+
+    fn incremental_vs_full_root(inputs: &[&str], modified: &str) {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+        let hashed_address = B256::with_last_byte(1);
+
+        let mut hashed_storage_cursor =
+            tx.tx_ref().cursor_dup_write::<tables::HashedStorage>().unwrap();
+        let data = inputs.iter().map(|x| B256::from_str(x).unwrap());
+        let value = U256::from(0);
+        for key in data {
+            hashed_storage_cursor.upsert(hashed_address, StorageEntry { key, value }).unwrap();
+        }
+
+        // Generate the intermediate nodes on the receiving end of the channel
+        let (_, _, trie_updates) =
+            StorageRoot::new_hashed(tx.tx_ref(), hashed_address).root_with_updates().unwrap();
+
+        // 1. Some state transition happens, update the hashed storage to the new value
+        let modified_key = B256::from_str(modified).unwrap();
+        let value = U256::from(1);
+        if hashed_storage_cursor.seek_by_key_subkey(hashed_address, modified_key).unwrap().is_some()
+        {
+            hashed_storage_cursor.delete_current().unwrap();
+        }
+        hashed_storage_cursor
+            .upsert(hashed_address, StorageEntry { key: modified_key, value })
+            .unwrap();
+
+        // 2. Calculate full merkle root
+        let loader = StorageRoot::new_hashed(tx.tx_ref(), hashed_address);
+        let modified_root = loader.root().unwrap();
+
+        // Update the intermediate roots table so that we can run the incremental verification
+        trie_updates.flush(tx.tx_ref()).unwrap();
+
+        // 3. Calculate the incremental root
+        let mut storage_changes = PrefixSetMut::default();
+        storage_changes.insert(Nibbles::unpack(modified_key));
+        let loader = StorageRoot::new_hashed(tx.tx_ref(), hashed_address)
+            .with_changed_prefixes(storage_changes.freeze());
+        let incremental_root = loader.root().unwrap();
+
+        assert_eq!(modified_root, incremental_root);
+    }
+
+    #[test]
+    fn branch_node_child_changes() {
+        incremental_vs_full_root(
+            &[
+                "1000000000000000000000000000000000000000000000000000000000000000",
+                "1100000000000000000000000000000000000000000000000000000000000000",
+                "1110000000000000000000000000000000000000000000000000000000000000",
+                "1200000000000000000000000000000000000000000000000000000000000000",
+                "1220000000000000000000000000000000000000000000000000000000000000",
+                "1320000000000000000000000000000000000000000000000000000000000000",
+            ],
+            "1200000000000000000000000000000000000000000000000000000000000000",
+        );
+    }
+
+    #[test]
+    fn arbitrary_storage_root() {
+        proptest!(ProptestConfig::with_cases(10), |(item: (Address, std::collections::BTreeMap<B256, U256>))| {
+            let (address, storage) = item;
+
+            let hashed_address = keccak256(address);
+            let db = create_test_rw_db();
+            let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+            let tx = factory.provider_rw().unwrap();
+            for (key, value) in &storage {
+                tx.tx_ref().put::<tables::HashedStorage>(
+                    hashed_address,
+                    StorageEntry { key: keccak256(key), value: *value },
+                )
+                .unwrap();
+            }
+            tx.commit().unwrap();
+
+            let tx =  factory.provider_rw().unwrap();
+            let got = StorageRoot::new(tx.tx_ref(), address).root().unwrap();
+            let expected = storage_root(storage.into_iter());
+            assert_eq!(expected, got);
+        });
+    }
+
+    #[test]
+    // This ensures we dont add empty accounts to the trie
+    fn test_empty_account() {
+        let state: State = BTreeMap::from([
+            (
+                Address::random(),
+                (
+                    Account { nonce: 0, balance: U256::from(0), bytecode_hash: None },
+                    BTreeMap::from([(B256::with_last_byte(0x4), U256::from(12))]),
+                ),
+            ),
+            (
+                Address::random(),
+                (
+                    Account { nonce: 0, balance: U256::from(0), bytecode_hash: None },
+                    BTreeMap::default(),
+                ),
+            ),
+            (
+                Address::random(),
+                (
+                    Account {
+                        nonce: 155,
+                        balance: U256::from(414241124u32),
+                        bytecode_hash: Some(keccak256("test")),
+                    },
+                    BTreeMap::from([
+                        (B256::ZERO, U256::from(3)),
+                        (B256::with_last_byte(2), U256::from(1)),
+                    ]),
+                ),
+            ),
+        ]);
+        test_state_root_with_state(state);
+    }
+
+    #[test]
+    // This ensures we return an empty root when there are no storage entries
+    fn test_empty_storage_root() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let address = Address::random();
+        let code = "el buen fla";
+        let account = Account {
+            nonce: 155,
+            balance: U256::from(414241124u32),
+            bytecode_hash: Some(keccak256(code)),
+        };
+        insert_account(tx.tx_ref(), address, account, &Default::default());
+        tx.commit().unwrap();
+
+        let tx = factory.provider_rw().unwrap();
+        let got = StorageRoot::new(tx.tx_ref(), address).root().unwrap();
+        assert_eq!(got, EMPTY_ROOT_HASH);
+    }
+
+    #[test]
+    // This ensures that the walker goes over all the storage slots
+    fn test_storage_root() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let address = Address::random();
+        let storage =
+            BTreeMap::from([(B256::ZERO, U256::from(3)), (B256::with_last_byte(2), U256::from(1))]);
+
+        let code = "el buen fla";
+        let account = Account {
+            nonce: 155,
+            balance: U256::from(414241124u32),
+            bytecode_hash: Some(keccak256(code)),
+        };
+
+        insert_account(tx.tx_ref(), address, account, &storage);
+        tx.commit().unwrap();
+
+        let tx = factory.provider_rw().unwrap();
+        let got = StorageRoot::new(tx.tx_ref(), address).root().unwrap();
+
+        assert_eq!(storage_root(storage.into_iter()), got);
+    }
+
+    #[test]
+    fn compute_and_flush_matches_separate_flush() {
+        let state = (0..256u64)
+            .map(|nonce| {
+                let account = Account { nonce, balance: U256::from(1), bytecode_hash: None };
+                let storage = (0..nonce % 4)
+                    .map(|slot| (B256::with_last_byte(slot as u8), U256::from(slot + 1)))
+                    .collect::<BTreeMap<_, _>>();
+                (Address::random(), (account, storage))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let expected = state_root(state.clone().into_iter());
+
+        let trie_tables = |fused: bool| {
+            let db = create_test_rw_db();
+            let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+            let tx = factory.provider_rw().unwrap();
+            for (address, (account, storage)) in &state {
+                insert_account(tx.tx_ref(), *address, *account, storage);
+            }
+
+            let root = if fused {
+                // flush in several chunks
+                StateRoot::new(tx.tx_ref()).with_threshold(10).compute_and_flush().unwrap()
+            } else {
+                let (root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+                updates.flush(tx.tx_ref()).unwrap();
+                root
+            };
+            assert_eq!(root, expected);
+
+            let accounts_trie = tx
+                .tx_ref()
+                .cursor_read::<tables::AccountsTrie>()
+                .unwrap()
+                .walk(None)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let storages_trie = tx
+                .tx_ref()
+                .cursor_read::<tables::StoragesTrie>()
+                .unwrap()
+                .walk(None)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            (accounts_trie, storages_trie)
+        };
+
+        let (accounts_trie, storages_trie) = trie_tables(true);
+        assert!(!accounts_trie.is_empty());
+        assert_eq!((accounts_trie, storages_trie), trie_tables(false));
+    }
+
+    #[test]
+    fn resume_from_constructed_state() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let state = (0..64u64)
+            .map(|nonce| {
+                let account = Account { nonce, balance: U256::from(1), bytecode_hash: None };
+                let storage = (0..nonce % 3)
+                    .map(|slot| (keccak256(B256::with_last_byte(slot as u8)), U256::from(slot + 1)))
+                    .collect::<BTreeMap<_, _>>();
+                (keccak256(B256::from(U256::from(nonce).to_be_bytes())), (account, storage))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (hashed_address, (account, storage)) in &state {
+            tx.tx_ref().put::<tables::HashedAccount>(*hashed_address, *account).unwrap();
+            for (hashed_slot, value) in storage {
+                tx.tx_ref()
+                    .put::<tables::HashedStorage>(
+                        *hashed_address,
+                        StorageEntry { key: *hashed_slot, value: *value },
+                    )
+                    .unwrap();
+            }
+        }
+        let expected = state_root_prehashed(state.clone().into_iter());
+        assert_eq!(StateRoot::new(tx.tx_ref()).root(), Ok(expected));
+
+        // interrupted after the first account, after the last one and at a branch boundary, right
+        // before the first account with a different first nibble
+        let first_nibble = |key: &B256| key[0] >> 4;
+        let branch_boundary = state
+            .keys()
+            .position(|key| first_nibble(key) != first_nibble(state.keys().next().unwrap()))
+            .unwrap();
+        for processed in [1, state.len(), branch_boundary] {
+            let intermediate_state =
+                intermediate_state_prehashed(state.clone().into_iter().take(processed));
+            let root = StateRoot::new(tx.tx_ref())
+                .with_intermediate_state(Some(intermediate_state))
+                .root();
+            assert_eq!(root, Ok(expected), "resumed after {processed} accounts");
+        }
+    }
+
+    #[test]
+    fn retained_depth() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let mut state = (0..1000u64)
+            .map(|nonce| {
+                let account = Account { nonce, balance: U256::from(1), bytecode_hash: None };
+                (B256::random(), (account, BTreeMap::<B256, U256>::new()))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (hashed_address, (account, _)) in &state {
+            tx.tx_ref().put::<tables::HashedAccount>(*hashed_address, *account).unwrap();
+        }
+
+        let (root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        let (limited_root, limited_updates) =
+            StateRoot::new(tx.tx_ref()).with_retained_depth(Some(1)).root_with_updates().unwrap();
+        assert_eq!(limited_root, root);
+        assert!(limited_updates.len() < updates.len());
+        assert!(limited_updates.keys().all(|key| match key {
+            TrieKey::AccountNode(nibbles) => nibbles.inner.len() <= 1,
+            _ => true,
+        }));
+
+        // the lower parts of the trie are recomputed on incremental updates
+        limited_updates.flush(tx.tx_ref()).unwrap();
+        let (hashed_address, (account, _)) = state.iter_mut().next().unwrap();
+        account.nonce += 1;
+        tx.tx_ref().put::<tables::HashedAccount>(*hashed_address, *account).unwrap();
+        let changed = PrefixSetMut::from([Nibbles::unpack(hashed_address)]).freeze();
+        let root = StateRoot::new(tx.tx_ref())
+            .with_changed_account_prefixes(changed)
+            .with_retained_depth(Some(1))
+            .root()
+            .unwrap();
+        assert_eq!(root, state_root_prehashed(state.into_iter()));
+    }
+
+    #[test]
+    fn incremental_root_bytecode_hash_change() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let mut state = (0..1000u64)
+            .map(|nonce| {
+                let account = Account { nonce, balance: U256::from(1), bytecode_hash: None };
+                (B256::random(), (account, BTreeMap::<B256, U256>::new()))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (hashed_address, (account, _)) in &state {
+            tx.tx_ref().put::<tables::HashedAccount>(*hashed_address, *account).unwrap();
+        }
+        let (mut root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        // only the code hash changes, first from empty to a contract and then to another one
+        let hashed_address = *state.keys().nth(500).unwrap();
+        for bytecode_hash in [B256::random(), B256::random()] {
+            let (account, _) = state.get_mut(&hashed_address).unwrap();
+            account.bytecode_hash = Some(bytecode_hash);
+            tx.tx_ref().put::<tables::HashedAccount>(hashed_address, *account).unwrap();
+
+            let changed = PrefixSetMut::from([Nibbles::unpack(hashed_address)]).freeze();
+            let (new_root, updates) = StateRoot::new(tx.tx_ref())
+                .with_changed_account_prefixes(changed)
+                .root_with_updates()
+                .unwrap();
+            assert_ne!(new_root, root);
+            assert_eq!(new_root, state_root_prehashed(state.clone().into_iter()));
+
+            updates.flush(tx.tx_ref()).unwrap();
+            root = new_root;
+        }
+    }
+
+    #[test]
+    fn from_change_log() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_slot = |slot| keccak256(B256::with_last_byte(slot));
+        let mut state = (0..100u64)
+            .map(|nonce| {
+                let account = Account { nonce, balance: U256::from(1), bytecode_hash: None };
+                let storage = (0..3).map(|slot| (hashed_slot(slot), U256::from(1)));
+                (B256::random(), (account, storage.collect::<BTreeMap<_, _>>()))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (hashed_address, (account, storage)) in &state {
+            tx.tx_ref().put::<tables::HashedAccount>(*hashed_address, *account).unwrap();
+            for (key, value) in storage {
+                let entry = StorageEntry { key: *key, value: *value };
+                tx.tx_ref().put::<tables::HashedStorage>(*hashed_address, entry).unwrap();
+            }
+        }
+        let (_, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        let addresses = state.keys().copied().take(4).collect::<Vec<_>>();
+        let mut changes = Vec::new();
+
+        // an updated account
+        let (account, _) = state.get_mut(&addresses[0]).unwrap();
+        account.nonce += 1;
+        tx.tx_ref().put::<tables::HashedAccount>(addresses[0], *account).unwrap();
+        changes.push(StateChange::AccountUpdated(addresses[0]));
+
+        // a destroyed account
+        state.remove(&addresses[1]);
+        tx.tx_ref().delete::<tables::HashedAccount>(addresses[1], None).unwrap();
+        tx.tx_ref().delete::<tables::HashedStorage>(addresses[1], None).unwrap();
+        changes.push(StateChange::AccountDestroyed(addresses[1]));
+
+        // a set and a cleared slot
+        let (_, storage) = state.get_mut(&addresses[2]).unwrap();
+        let (set, cleared) = (hashed_slot(10), hashed_slot(0));
+        storage.insert(set, U256::from(2));
+        let entry = StorageEntry { key: set, value: U256::from(2) };
+        tx.tx_ref().put::<tables::HashedStorage>(addresses[2], entry).unwrap();
+        let entry = StorageEntry { key: cleared, value: storage.remove(&cleared).unwrap() };
+        tx.tx_ref().delete::<tables::HashedStorage>(addresses[2], Some(entry)).unwrap();
+        changes
+            .push(StateChange::StorageUpdated { hashed_address: addresses[2], hashed_slot: set });
+        changes.push(StateChange::StorageCleared {
+            hashed_address: addresses[2],
+            hashed_slot: cleared,
+        });
+
+        // an account that is destroyed and recreated within the log
+        changes.push(StateChange::AccountDestroyed(addresses[3]));
+        changes.push(StateChange::AccountUpdated(addresses[3]));
+
+        let loaded = LoadedPrefixSets::from_change_log(changes.clone());
+        assert_eq!(loaded.destroyed_accounts, HashSet::from([addresses[1]]));
+
+        let expected = StateRoot::new(tx.tx_ref())
+            .with_changed_account_prefixes(
+                PrefixSetMut::from(addresses.iter().map(Nibbles::unpack)).freeze(),
+            )
+            .with_changed_storage_prefixes(HashMap::from([(
+                addresses[2],
+                PrefixSetMut::from([Nibbles::unpack(set), Nibbles::unpack(cleared)]).freeze(),
+            )]))
+            .with_destroyed_accounts(HashSet::from([addresses[1]]))
+            .root()
+            .unwrap();
+        let root = StateRoot::from_change_log(tx.tx_ref(), changes).root().unwrap();
+        assert_eq!(root, expected);
+        assert_eq!(root, state_root_prehashed(state.into_iter()));
+    }
+
+    #[test]
+    fn changed_node_keys() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        let slots = |range: std::ops::Range<u8>| {
+            range
+                .map(|slot| (B256::with_last_byte(slot), U256::from(1)))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let hashed_addresses = (0..1000).map(|_| B256::random()).collect::<Vec<_>>();
+        for (idx, hashed_address) in hashed_addresses.iter().enumerate() {
+            tx.tx_ref().put::<tables::HashedAccount>(*hashed_address, account).unwrap();
+            // the first account has a storage trie with persisted nodes
+            let storage = if idx == 0 { slots(0..100) } else { slots(0..1) };
+            insert_storage(tx.tx_ref(), *hashed_address, &storage);
+        }
+        let (_, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        let mut changed_accounts = PrefixSetMut::default();
+        let mut changed_storages = HashMap::new();
+        let mut insert_changes = |hashed_address: B256, storage: &BTreeMap<B256, U256>| {
+            changed_accounts.insert(Nibbles::unpack(hashed_address));
+            let prefixes = storage.keys().map(|slot| Nibbles::unpack(keccak256(slot)));
+            changed_storages.insert(hashed_address, PrefixSetMut::from(prefixes).freeze());
+        };
+
+        // new slots of the account with the persisted storage trie
+        let storage = slots(100..110);
+        insert_storage(tx.tx_ref(), hashed_addresses[0], &storage);
+        insert_changes(hashed_addresses[0], &storage);
+
+        // new accounts
+        for _ in 0..10 {
+            let hashed_address = B256::random();
+            let storage = slots(0..3);
+            tx.tx_ref().put::<tables::HashedAccount>(hashed_address, account).unwrap();
+            insert_storage(tx.tx_ref(), hashed_address, &storage);
+            insert_changes(hashed_address, &storage);
+        }
+
+        // a destroyed account
+        let destroyed = hashed_addresses[1];
+        tx.tx_ref().delete::<tables::HashedAccount>(destroyed, None).unwrap();
+        tx.tx_ref().delete::<tables::HashedStorage>(destroyed, None).unwrap();
+        changed_accounts.insert(Nibbles::unpack(destroyed));
+
+        let changed_accounts = changed_accounts.freeze();
+        let state_root = || {
+            StateRoot::new(tx.tx_ref())
+                .with_changed_account_prefixes(changed_accounts.clone())
+                .with_changed_storage_prefixes(changed_storages.clone())
+                .with_destroyed_accounts(HashSet::from([destroyed]))
+        };
+        let keys = state_root().changed_node_keys().unwrap();
+        let (_, updates) = state_root().root_with_updates().unwrap();
+
+        let expected = updates.into_iter().map(|(key, _)| key).collect::<HashSet<_>>();
+        assert!(expected.contains(&TrieKey::StorageTrie(destroyed)));
+        assert!(expected.iter().any(
+            |key| matches!(key, TrieKey::StorageNode(address, _) if *address == hashed_addresses[0])
+        ));
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn verify_claimed_roots() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let address = Address::random();
+        let account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        let storage = BTreeMap::from([(B256::with_last_byte(1), U256::from(1))]);
+        insert_account(tx.tx_ref(), address, account, &storage);
+
+        let expected_storage_root = storage_root(storage.clone().into_iter());
+        let storage_root = StorageRoot::new(tx.tx_ref(), address);
+        assert_eq!(storage_root.verify_root(expected_storage_root), Ok(true));
+        assert_eq!(storage_root.verify_root(EMPTY_ROOT_HASH), Ok(false));
+
+        let expected_state_root = state_root([(address, (account, storage))].into_iter());
+        assert_eq!(StateRoot::new(tx.tx_ref()).verify_root(expected_state_root), Ok(true));
+        assert_eq!(StateRoot::new(tx.tx_ref()).verify_root(expected_storage_root), Ok(false));
+    }
+
+    #[test]
+    fn alternative_account_encoder() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let address = Address::random();
+        let account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        let storage = BTreeMap::from([(B256::with_last_byte(1), U256::from(1))]);
+        insert_account(tx.tx_ref(), address, account, &storage);
+
+        // encodes only the nonce, dropping the rest of the account
+        fn nonce_only(account: &Account, _storage_root: B256) -> Bytes {
+            let mut out = Vec::new();
+            account.nonce.encode(&mut out);
+            out.into()
+        }
+
+        let mut hash_builder = HashBuilder::default();
+        hash_builder
+            .add_leaf(Nibbles::unpack(keccak256(address)), &nonce_only(&account, EMPTY_ROOT_HASH));
+        let expected = hash_builder.root();
+
+        let root = StateRoot::new(tx.tx_ref()).with_account_encoder(nonce_only).root().unwrap();
+        assert_eq!(root, expected);
+        assert_ne!(root, StateRoot::new(tx.tx_ref()).root().unwrap());
+    }
+
+    #[test]
+    fn account_inclusion_predicate() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let storage = BTreeMap::from([(B256::with_last_byte(1), U256::from(1))]);
+        let state: State = BTreeMap::from([
+            (Address::random(), (Account::default(), storage.clone())),
+            (Address::random(), (Account { nonce: 1, ..Default::default() }, BTreeMap::default())),
+            (
+                Address::random(),
+                (Account { balance: U256::from(1), ..Default::default() }, storage),
+            ),
+        ]);
+        for (address, (account, storage)) in &state {
+            insert_account(tx.tx_ref(), *address, *account, storage);
+        }
+
+        // every account is included by default
+        let root = StateRoot::new(tx.tx_ref()).root().unwrap();
+        assert_eq!(root, state_root(state.clone().into_iter()));
+
+        let non_empty_root = StateRoot::new(tx.tx_ref())
+            .with_account_inclusion_predicate(|account| !account.is_empty())
+            .root()
+            .unwrap();
+        let non_empty = state.clone().into_iter().filter(|(_, (account, _))| !account.is_empty());
+        assert_eq!(non_empty_root, state_root(non_empty));
+        assert_ne!(non_empty_root, root);
+
+        let funded_root = StateRoot::new(tx.tx_ref())
+            .with_account_inclusion_predicate(|account| account.balance > U256::ZERO)
+            .root()
+            .unwrap();
+        let funded = state.into_iter().filter(|(_, (account, _))| account.balance > U256::ZERO);
+        assert_eq!(funded_root, state_root(funded));
+        assert_ne!(funded_root, non_empty_root);
+
+        let excluded_root =
+            StateRoot::new(tx.tx_ref()).with_account_inclusion_predicate(|_| false).root().unwrap();
+        assert_eq!(excluded_root, EMPTY_ROOT_HASH);
+    }
+
+    #[test]
+    fn account_prefix_bounds() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let state = (0..256u64)
+            .map(|i| {
+                let account = Account { nonce: i, balance: U256::from(i), bytecode_hash: None };
+                let storage = BTreeMap::from([(B256::with_last_byte(i as u8), U256::from(i + 1))]);
+                (Address::random(), (account, storage))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (address, (account, storage)) in &state {
+            insert_account(tx.tx_ref(), *address, *account, storage);
+        }
+
+        let bounds = (0..4).map(|nibble| Nibbles::from_hex(vec![nibble])).collect::<Vec<_>>();
+        let expected = state_root(
+            state.clone().into_iter().filter(|(address, _)| keccak256(address)[0] >> 4 < 4),
+        );
+
+        // without stored nodes
+        let (root, updates) = StateRoot::new(tx.tx_ref())
+            .with_account_prefix_bounds(bounds.clone())
+            .root_with_updates()
+            .unwrap();
+        assert_eq!(root, expected);
+        assert!(updates.is_empty());
+
+        // the stored nodes of the full trie under the prefixes are reused
+        let (full_root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        assert_eq!(full_root, state_root(state.into_iter()));
+        updates.flush(tx.tx_ref()).unwrap();
+        let root = StateRoot::new(tx.tx_ref()).with_account_prefix_bounds(bounds).root().unwrap();
+        assert_eq!(root, expected);
+    }
+
+    #[test]
+    fn account_allowlist() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let state = (0..256u64)
+            .map(|i| {
+                let account = Account { nonce: i, balance: U256::from(i), bytecode_hash: None };
+                let storage = BTreeMap::from([(B256::with_last_byte(i as u8), U256::from(i + 1))]);
+                (Address::random(), (account, storage))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (address, (account, storage)) in &state {
+            insert_account(tx.tx_ref(), *address, *account, storage);
+        }
+
+        let allowed = state.keys().step_by(10).copied().collect::<HashSet<_>>();
+        let allowlist = allowed.iter().map(keccak256).collect::<HashSet<_>>();
+        let expected =
+            state_root(state.clone().into_iter().filter(|(address, _)| allowed.contains(address)));
+
+        // without stored nodes
+        let (root, updates) = StateRoot::new(tx.tx_ref())
+            .with_account_allowlist(allowlist.clone())
+            .root_with_updates()
+            .unwrap();
+        assert_eq!(root, expected);
+        assert!(updates.is_empty());
+
+        // the stored nodes of the full trie are skipped
+        let (full_root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        assert_eq!(full_root, state_root(state.clone().into_iter()));
+        assert_ne!(full_root, expected);
+        updates.flush(tx.tx_ref()).unwrap();
+        let root =
+            StateRoot::new(tx.tx_ref()).with_account_allowlist(allowlist.clone()).root().unwrap();
+        assert_eq!(root, expected);
+
+        // a single account is a trie of a single leaf
+        let (address, (account, storage)) = state.iter().next().unwrap();
+        let root = StateRoot::new(tx.tx_ref())
+            .with_account_allowlist(HashSet::from([keccak256(address)]))
+            .root()
+            .unwrap();
+        assert_eq!(root, state_root(std::iter::once((*address, (*account, storage.clone())))));
+
+        // combined with the prefix bounds, only the allowlisted accounts under them are included
+        let bounds = (0..4).map(|nibble| Nibbles::from_hex(vec![nibble])).collect::<Vec<_>>();
+        let root = StateRoot::new(tx.tx_ref())
+            .with_account_allowlist(allowlist)
+            .with_account_prefix_bounds(bounds)
+            .root()
+            .unwrap();
+        let expected =
+            state_root(state.into_iter().filter(|(address, _)| {
+                allowed.contains(address) && keccak256(address)[0] >> 4 < 4
+            }));
+        assert_eq!(root, expected);
+    }
+
+    #[test]
+    fn root_with_updates_into_buffer() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let state = (0..64u64)
+            .map(|i| {
+                let account = Account { nonce: i, balance: U256::from(i), bytecode_hash: None };
+                let storage = BTreeMap::from([(B256::with_last_byte(i as u8), U256::from(i + 1))]);
+                (Address::random(), (account, storage))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (address, (account, storage)) in &state {
+            insert_account(tx.tx_ref(), *address, *account, storage);
+        }
+        let (expected_root, expected_updates) =
+            StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+
+        let mut buffer = TrieUpdates::default();
+        for _ in 0..2 {
+            buffer.clear();
+            let (root, walked) =
+                StateRoot::new(tx.tx_ref()).root_with_updates_into(&mut buffer).unwrap();
+            assert_eq!(root, expected_root);
+            assert_eq!(walked, state.len() * 2);
+            assert_eq!(*buffer, *expected_updates);
+        }
+
+        // existing updates in the buffer are kept
+        let existing = TrieKey::StorageTrie(B256::random());
+        buffer.clear();
+        buffer.schedule_delete(existing.clone());
+        StateRoot::new(tx.tx_ref()).root_with_updates_into(&mut buffer).unwrap();
+        assert_eq!(buffer.get(&existing), Some(&TrieOp::Delete));
+        assert_eq!(buffer.len(), expected_updates.len() + 1);
+    }
+
+    #[test]
+    fn storage_changed_slot_count() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_address = B256::random();
+        let storage = (0..8u64)
+            .map(|i| (B256::from(U256::from(i).to_be_bytes()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        insert_storage(tx.tx_ref(), hashed_address, &storage);
+        let hashed_slots = storage.keys().map(keccak256).collect::<Vec<_>>();
+
+        // two existing slots, a slot that is not in storage and a prefix of an existing slot
+        let mut changed_prefixes = PrefixSetMut::default();
+        changed_prefixes.insert(Nibbles::unpack(hashed_slots[0]));
+        changed_prefixes.insert(Nibbles::unpack(hashed_slots[1]));
+        changed_prefixes.insert(Nibbles::unpack(keccak256(B256::with_last_byte(0xff))));
+        changed_prefixes.insert(Nibbles::unpack(hashed_slots[2]).slice(0, 3));
+        let changed_prefixes = changed_prefixes.freeze();
+
+        let storage_root = StorageRoot::new_hashed(tx.tx_ref(), hashed_address)
+            .with_changed_prefixes(changed_prefixes.clone());
+        assert_eq!(storage_root.changed_slot_count(), Ok(3));
+
+        // nothing changed
+        let storage_root = StorageRoot::new_hashed(tx.tx_ref(), hashed_address);
+        assert_eq!(storage_root.changed_slot_count(), Ok(0));
+
+        // no storage
+        let storage_root = StorageRoot::new_hashed(tx.tx_ref(), B256::random())
+            .with_changed_prefixes(changed_prefixes);
+        assert_eq!(storage_root.changed_slot_count(), Ok(0));
+    }
+
+    #[test]
+    fn storage_root_with_overlay() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_address = B256::random();
+        let storage = (0..32u64)
+            .map(|i| (keccak256(U256::from(i).to_be_bytes::<32>()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        for (hashed_slot, value) in &storage {
+            let entry = StorageEntry { key: *hashed_slot, value: *value };
+            tx.tx_ref().put::<tables::HashedStorage>(hashed_address, entry).unwrap();
+        }
+        let (original_root, _, updates) =
+            StorageRoot::new_hashed(tx.tx_ref(), hashed_address).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        // update, delete and insert slots
+        let slots = storage.keys().copied().collect::<Vec<_>>();
+        let changes = HashMap::from([
+            (slots[0], U256::from(100)),
+            (slots[1], U256::ZERO),
+            (slots[2], U256::ZERO),
+            (B256::random(), U256::from(200)),
+        ]);
+        let (root, _, overlay_updates) = StorageRoot::new_hashed(tx.tx_ref(), hashed_address)
+            .root_with_overlay(&changes)
+            .unwrap();
+
+        // the overlay doesn't touch the hashed storage
+        assert_ne!(root, original_root);
+        assert_eq!(
+            StorageRoot::new_hashed(tx.tx_ref(), hashed_address).root().unwrap(),
+            original_root
+        );
+
+        // write the changes, then compute the root
+        let mut changed_prefixes = PrefixSetMut::default();
+        for (hashed_slot, value) in &changes {
+            if let Some(old) = storage.get(hashed_slot) {
+                let entry = StorageEntry { key: *hashed_slot, value: *old };
+                tx.tx_ref().delete::<tables::HashedStorage>(hashed_address, Some(entry)).unwrap();
+            }
+            if *value != U256::ZERO {
+                let entry = StorageEntry { key: *hashed_slot, value: *value };
+                tx.tx_ref().put::<tables::HashedStorage>(hashed_address, entry).unwrap();
+            }
+            changed_prefixes.insert(Nibbles::unpack(hashed_slot));
+        }
+        let (expected_root, _, expected_updates) =
+            StorageRoot::new_hashed(tx.tx_ref(), hashed_address)
+                .with_changed_prefixes(changed_prefixes.freeze())
+                .root_with_updates()
+                .unwrap();
+        assert_eq!(root, expected_root);
+        assert_eq!(*overlay_updates, *expected_updates);
+        assert_eq!(root, StorageRoot::new_hashed(tx.tx_ref(), hashed_address).root().unwrap());
+    }
+
+    #[test]
+    fn storage_root_with_slot_changes() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let address = Address::random();
+        let slot = |i: u64| B256::from(U256::from(i));
+        let mut storage =
+            (0..32u64).map(|i| (slot(i), U256::from(i + 1))).collect::<BTreeMap<_, _>>();
+        insert_storage(tx.tx_ref(), keccak256(address), &storage);
+        let (_, _, updates) = StorageRoot::new(tx.tx_ref(), address).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        // update, delete, rewrite with the same value and insert slots, then change some of them
+        // again in the next block
+        let changes: [(BlockNumber, u64, u64); 8] = [
+            (1, 0, 100),
+            (1, 1, 0),
+            (1, 2, 3),
+            (1, 100, 200),
+            (1, 101, 300),
+            // deleting a missing slot is not a change
+            (1, 102, 0),
+            (2, 0, 101),
+            // a slot created and deleted within the range is not a change
+            (2, 101, 0),
+        ];
+        let mut hashed_storage = tx.tx_ref().cursor_dup_write::<tables::HashedStorage>().unwrap();
+        for (block, key, value) in changes {
+            let previous = storage.get(&slot(key)).copied().unwrap_or_default();
+            tx.tx_ref()
+                .put::<tables::StorageChangeSet>(
+                    BlockNumberAddress((block, address)),
+                    StorageEntry { key: slot(key), value: previous },
+                )
+                .unwrap();
+
+            let hashed_slot = keccak256(slot(key));
+            if hashed_storage
+                .seek_by_key_subkey(keccak256(address), hashed_slot)
+                .unwrap()
+                .filter(|entry| entry.key == hashed_slot)
+                .is_some()
+            {
+                hashed_storage.delete_current().unwrap();
+            }
+            if value == 0 {
+                storage.remove(&slot(key));
+            } else {
+                storage.insert(slot(key), U256::from(value));
+                hashed_storage
+                    .upsert(
+                        keccak256(address),
+                        StorageEntry { key: hashed_slot, value: U256::from(value) },
+                    )
+                    .unwrap();
+            }
+        }
+
+        // the changesets of the accounts around it in the range are skipped
+        for other in [Address::ZERO, Address::repeat_byte(0xff)] {
+            tx.tx_ref()
+                .put::<tables::StorageChangeSet>(
+                    BlockNumberAddress((1, other)),
+                    StorageEntry { key: slot(2), value: U256::ZERO },
+                )
+                .unwrap();
+        }
+
+        let changed_prefixes = PrefixSetMut::from(
+            changes.iter().map(|(_, key, _)| Nibbles::unpack(keccak256(slot(*key)))),
+        )
+        .freeze();
+        let (root, _, _, slot_changes) = StorageRoot::new(tx.tx_ref(), address)
+            .with_changed_prefixes(changed_prefixes)
+            .root_with_slot_changes(1..=2)
+            .unwrap();
+        assert_eq!(root, storage_root(storage.into_iter()));
+        assert_eq!(
+            slot_changes,
+            BTreeMap::from([
+                (keccak256(slot(0)), StorageSlotChange::Updated),
+                (keccak256(slot(1)), StorageSlotChange::Deleted),
+                (keccak256(slot(100)), StorageSlotChange::Added),
+            ])
+        );
+    }
+
+    #[test]
+    fn storage_root_cache_hit() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_address = B256::random();
+        let storage = (0..8u64)
+            .map(|i| (B256::from(U256::from(i).to_be_bytes()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        insert_storage(tx.tx_ref(), hashed_address, &storage);
+
+        let cache = StorageRootCache::new(8);
+        let storage_root = || {
+            StorageRoot::new_hashed(tx.tx_ref(), hashed_address)
+                .with_storage_root_cache(cache.clone())
+        };
+        let (root, storage_slots_walked, updates) = storage_root().root_with_updates().unwrap();
+        assert_eq!(storage_slots_walked, storage.len());
+        assert_eq!(cache.len(), 1);
+
+        // change the storage without marking the slot as changed, a walk would pick it up
+        let new_slot = B256::with_last_byte(0xff);
+        insert_storage(tx.tx_ref(), hashed_address, &BTreeMap::from([(new_slot, U256::from(1))]));
+
+        let (cached_root, storage_slots_walked, cached_updates) =
+            storage_root().root_with_updates().unwrap();
+        assert_eq!((cached_root, storage_slots_walked), (root, 0));
+        assert_eq!(*cached_updates, *updates);
+        assert_eq!(storage_root().root(), Ok(root));
+
+        // the changed prefixes are part of the key
+        let changed_prefixes = PrefixSetMut::from([Nibbles::unpack(keccak256(new_slot))]).freeze();
+        let (new_root, storage_slots_walked, _) =
+            storage_root().with_changed_prefixes(changed_prefixes).root_with_updates().unwrap();
+        assert_eq!(storage_slots_walked, storage.len() + 1);
+        assert_ne!(new_root, root);
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        assert_eq!(storage_root().root(), Ok(new_root));
+    }
+
+    #[test]
+    fn upper_trie_cache() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_addresses = (0..1_000u64)
+            .map(|i| keccak256(B256::from(U256::from(i).to_be_bytes())))
+            .collect::<Vec<_>>();
+        for (i, hashed_address) in hashed_addresses.iter().enumerate() {
+            let account = Account { nonce: i as u64, ..Default::default() };
+            tx.tx_ref().put::<tables::HashedAccount>(*hashed_address, account).unwrap();
+        }
+        let (_, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        let load_cache = || {
+            let mut cursor =
+                AccountTrieCursor::new(tx.tx_ref().cursor_read::<tables::AccountsTrie>().unwrap());
+            UpperTrieCache::load(&mut cursor, 2).unwrap()
+        };
+        let cache = load_cache();
+        assert!(!cache.is_empty());
+
+        let changed_account = Account { nonce: 1_000, ..Default::default() };
+        tx.tx_ref().put::<tables::HashedAccount>(hashed_addresses[0], changed_account).unwrap();
+        let changed_prefixes = PrefixSetMut::from([Nibbles::unpack(hashed_addresses[0])]).freeze();
+        let state_root =
+            || StateRoot::new(tx.tx_ref()).with_changed_account_prefixes(changed_prefixes.clone());
+
+        let expected = state_root().root().unwrap();
+        assert_eq!(state_root().with_upper_trie_cache(cache.clone()).root().unwrap(), expected);
+        assert!(cache.hits() > 0);
+
+        // flushing the updates keeps the cache in sync with the table
+        let root = state_root().with_upper_trie_cache(cache.clone()).compute_and_flush().unwrap();
+        assert_eq!(root, expected);
+        assert_eq!(cache.len(), load_cache().len());
+        assert_eq!(StateRoot::new(tx.tx_ref()).with_upper_trie_cache(cache).root().unwrap(), root);
+    }
+
+    #[test]
+    fn corrupt_trie_block_witness_depth_exceeded() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        // A node deeper than any valid key.
+        let corrupt_path = vec![0; DEFAULT_MAX_PROOF_DEPTH + 2];
+        let node =
+            BranchNodeCompact::new(0b11, 0, 0b11, vec![B256::random(), B256::random()], None);
+        tx.tx_ref().put::<tables::AccountsTrie>(StoredNibbles::from(corrupt_path), node).unwrap();
+
+        assert!(matches!(
+            StateRoot::new(tx.tx_ref())
+                .root_with_block_witness(&[Address::random()], &HashMap::new()),
+            Err(ProofError::DepthExceeded { path, max_depth: DEFAULT_MAX_PROOF_DEPTH })
+                if path.len() > DEFAULT_MAX_PROOF_DEPTH
+        ));
+    }
+
+    #[test]
+    fn root_with_block_witness() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let storage = (0..32u64)
+            .map(|i| (B256::from(U256::from(i).to_be_bytes()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        let mut accounts = BTreeMap::new();
+        for i in 0..64u8 {
+            let address = Address::with_last_byte(i);
+            let account = Account { nonce: i as u64, ..Default::default() };
+            // every other account has the same storage
+            let account_storage = if i % 2 == 0 { storage.clone() } else { BTreeMap::new() };
+            insert_account(tx.tx_ref(), address, account, &account_storage);
+            accounts.insert(address, (account, account_storage));
+        }
+
+        let missing_address = Address::with_last_byte(100);
+        let accessed_accounts =
+            [Address::with_last_byte(1), Address::with_last_byte(3), missing_address];
+        let slots =
+            storage.keys().take(4).copied().chain([B256::with_last_byte(100)]).collect::<Vec<_>>();
+        let accessed_storage = HashMap::from([
+            (Address::with_last_byte(0), slots.clone()),
+            (Address::with_last_byte(2), slots.clone()),
+            (Address::with_last_byte(3), slots.clone()),
+        ]);
+
+        let (root, witness) = StateRoot::new(tx.tx_ref())
+            .root_with_block_witness(&accessed_accounts, &accessed_storage)
+            .unwrap();
+        assert_eq!(root, StateRoot::new(tx.tx_ref()).root().unwrap());
+        assert_eq!(witness.iter().collect::<HashSet<_>>().len(), witness.len());
+
+        for address in accessed_accounts.iter().chain(accessed_storage.keys()) {
+            let value = verify_witness(&witness, root, keccak256(address));
+            let Some((account, account_storage)) = accounts.get(address) else {
+                assert_eq!(value, None);
+                continue
+            };
+            let storage_root =
+                storage_root(account_storage.iter().map(|(slot, value)| (*slot, *value)));
+            assert_eq!(value, Some(encode_account(*account, Some(storage_root))));
+
+            for slot in accessed_storage.get(address).into_iter().flatten() {
+                let value = verify_witness(&witness, storage_root, keccak256(slot));
+                let expected = account_storage
+                    .get(slot)
+                    .map(|value| alloy_rlp::encode_fixed_size(value).to_vec());
+                assert_eq!(value, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn state_root_with_prefetcher() {
+        let db = create_test_rw_db();
+        let storage = (0..64u64)
+            .map(|i| (B256::from(U256::from(i).to_be_bytes()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        let hashed_addresses = (0..1_000u64)
+            .map(|i| keccak256(B256::from(U256::from(i).to_be_bytes())))
+            .collect::<Vec<_>>();
+        db.update(|tx| {
+            for (i, hashed_address) in hashed_addresses.iter().enumerate() {
+                let account = Account { nonce: i as u64, ..Default::default() };
+                tx.put::<tables::HashedAccount>(*hashed_address, account).unwrap();
+                if i % 100 == 0 {
+                    insert_storage(tx, *hashed_address, &storage);
+                }
+            }
+            let (_, updates) = StateRoot::new(tx).root_with_updates().unwrap();
+            updates.flush(tx).unwrap();
+        })
+        .unwrap();
+
+        // the walk descends into the stored nodes along the changed keys
+        let tx = db.tx().unwrap();
+        let changed_account_prefixes =
+            PrefixSetMut::from(hashed_addresses.iter().step_by(7).map(Nibbles::unpack)).freeze();
+        let changed_storage_prefixes = HashMap::from([(
+            hashed_addresses[0],
+            PrefixSetMut::from(storage.keys().step_by(3).map(keccak256).map(Nibbles::unpack))
+                .freeze(),
+        )]);
+        let state_root = || {
+            StateRoot::new(&tx)
+                .with_changed_account_prefixes(changed_account_prefixes.clone())
+                .with_changed_storage_prefixes(changed_storage_prefixes.clone())
+        };
+
+        let expected = state_root().root().unwrap();
+        let prefetcher = TriePrefetcher::spawn(db.clone()).unwrap();
+        assert_eq!(state_root().with_prefetcher(prefetcher).root().unwrap(), expected);
+    }
+
+    #[test]
+    fn storage_root_with_proof_for_slots() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_address = B256::random();
+        let storage = (0..64u64)
+            .map(|i| (B256::from(U256::from(i * 2).to_be_bytes()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        insert_storage(tx.tx_ref(), hashed_address, &storage);
+
+        let (_, _, updates) =
+            StorageRoot::new_hashed(tx.tx_ref(), hashed_address).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+        tx.commit().unwrap();
+
+        // existing slots spread over the keyspace and slots that are not in storage
+        let slots =
+            (0..16u64).map(|i| B256::from(U256::from(i * 7).to_be_bytes())).collect::<Vec<_>>();
+
+        let tx = factory.provider_rw().unwrap();
+        let (root, proofs) = StorageRoot::new_hashed(tx.tx_ref(), hashed_address)
+            .root_with_proof_for_slots(&slots)
+            .unwrap();
+        assert_eq!(root, storage_root(storage.clone().into_iter()));
+
+        let (expected_root, expected_proofs) = crate::proof::Proof::new(tx.tx_ref())
+            .storage_root_with_proofs(hashed_address, &slots)
+            .unwrap();
+        assert_eq!(root, expected_root);
+        assert_eq!(proofs.len(), slots.len());
+        for expected in expected_proofs {
+            assert_eq!(expected.value, storage.get(&expected.key).copied().unwrap_or_default());
+            assert_eq!(proofs.get(&expected.key), Some(&expected));
+        }
+    }
+
+    #[test]
+    fn storage_proofs_for_slots_max_depth() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_address = B256::random();
+        let storage = (0..64u64)
+            .map(|i| (B256::from(U256::from(i).to_be_bytes()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        insert_storage(tx.tx_ref(), hashed_address, &storage);
+        let (_, _, updates) =
+            StorageRoot::new_hashed(tx.tx_ref(), hashed_address).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        // the persisted branch nodes below the root are deeper than the maximum depth
+        let slots = [B256::ZERO];
+        let storage_root =
+            StorageRoot::new_hashed(tx.tx_ref(), hashed_address).with_max_proof_depth(0);
+        assert!(matches!(
+            storage_root.root_with_proof_for_slots(&slots),
+            Err(ProofError::DepthExceeded { max_depth: 0, .. })
+        ));
+        assert!(matches!(
+            storage_root.stream_proofs_for_slots(&slots, |_| {}),
+            Err(ProofError::DepthExceeded { max_depth: 0, .. })
+        ));
+        assert!(matches!(
+            storage_root.proofs_for_slots_chunked(&slots, 1).next(),
+            Some(Err(ProofError::DepthExceeded { max_depth: 0, .. }))
+        ));
+    }
+
+    #[test]
+    fn storage_proofs_for_slots_chunked() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_address = B256::random();
+        let storage = (0..64u64)
+            .map(|i| (B256::from(U256::from(i * 2).to_be_bytes()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        insert_storage(tx.tx_ref(), hashed_address, &storage);
+
+        let slots =
+            (0..16u64).map(|i| B256::from(U256::from(i * 7).to_be_bytes())).collect::<Vec<_>>();
+        let storage_root = StorageRoot::new_hashed(tx.tx_ref(), hashed_address);
+        let (_, expected) = storage_root.root_with_proof_for_slots(&slots).unwrap();
+
+        let chunks = storage_root
+            .proofs_for_slots_chunked(&slots, 5)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(chunks.iter().map(HashMap::len).collect::<Vec<_>>(), [5, 5, 5, 1]);
+        assert_eq!(chunks.into_iter().flatten().collect::<HashMap<_, _>>(), expected);
+    }
+
+    /// Hashed cursors recording the keys of the walked storage slots.
+    #[derive(Debug, Clone)]
+    struct RecordingStorageCursorFactory<'a, TX> {
+        tx: &'a TX,
+        walked: Rc<RefCell<Vec<B256>>>,
+    }
+
+    #[derive(Debug)]
+    struct RecordingStorageCursor<C> {
+        cursor: C,
+        walked: Rc<RefCell<Vec<B256>>>,
+    }
+
+    impl<'a, TX: DbTx> HashedCursorFactory for RecordingStorageCursorFactory<'a, TX> {
+        type AccountCursor = <&'a TX as HashedCursorFactory>::AccountCursor;
+        type StorageCursor = RecordingStorageCursor<<&'a TX as HashedCursorFactory>::StorageCursor>;
+
+        fn hashed_account_cursor(&self) -> Result<Self::AccountCursor, reth_db::DatabaseError> {
+            self.tx.hashed_account_cursor()
+        }
+
+        fn hashed_storage_cursor(&self) -> Result<Self::StorageCursor, reth_db::DatabaseError> {
+            Ok(RecordingStorageCursor {
+                cursor: self.tx.hashed_storage_cursor()?,
+                walked: self.walked.clone(),
+            })
+        }
+    }
+
+    impl<C: HashedStorageCursor> HashedStorageCursor for RecordingStorageCursor<C> {
+        fn is_storage_empty(&mut self, key: B256) -> Result<bool, reth_db::DatabaseError> {
+            self.cursor.is_storage_empty(key)
+        }
+
+        fn seek(
+            &mut self,
+            key: B256,
+            subkey: B256,
+        ) -> Result<Option<StorageEntry>, reth_db::DatabaseError> {
+            let entry = self.cursor.seek(key, subkey)?;
+            self.walked.borrow_mut().extend(entry.map(|entry| entry.key));
+            Ok(entry)
+        }
+
+        fn next(&mut self) -> Result<Option<StorageEntry>, reth_db::DatabaseError> {
+            let entry = self.cursor.next()?;
+            self.walked.borrow_mut().extend(entry.map(|entry| entry.key));
+            Ok(entry)
+        }
+    }
+
+    #[test]
+    fn stream_storage_proofs_for_slots() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_address = B256::random();
+        let storage = (0..1_000u64)
+            .map(|i| (B256::from(U256::from(i * 2).to_be_bytes()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        insert_storage(tx.tx_ref(), hashed_address, &storage);
+
+        // existing and missing slots, with a duplicate
+        let mut slots =
+            (0..500u64).map(|i| B256::from(U256::from(i * 3).to_be_bytes())).collect::<Vec<_>>();
+        slots.push(slots[0]);
+
+        for persisted_trie in [false, true] {
+            if persisted_trie {
+                let (_, _, updates) = StorageRoot::new_hashed(tx.tx_ref(), hashed_address)
+                    .root_with_updates()
+                    .unwrap();
+                updates.flush(tx.tx_ref()).unwrap();
+            }
+
+            let storage_root = StorageRoot::new_hashed(tx.tx_ref(), hashed_address);
+            let (expected_root, expected) = storage_root.root_with_proof_for_slots(&slots).unwrap();
+
+            let walked = Rc::new(RefCell::new(Vec::new()));
+            let storage_root =
+                storage_root.with_hashed_cursor_factory(RecordingStorageCursorFactory {
+                    tx: tx.tx_ref(),
+                    walked: walked.clone(),
+                });
+            let mut streamed = Vec::new();
+            let root = storage_root.stream_proofs_for_slots(&slots, |proof| {
+                streamed.push((proof, walked.borrow().last().copied()))
+            });
+            assert_eq!(root, Ok(expected_root));
+            assert_eq!(streamed.len(), expected.len());
+
+            // emitted in the order of the walk, while walking past the slots
+            assert!(streamed.windows(2).all(|proofs| proofs[0].0.nibbles < proofs[1].0.nibbles));
+            let last_walked = walked.borrow().last().copied();
+            let emitted_during_walk = streamed
+                .iter()
+                .filter(|(proof, walked)| {
+                    walked.map_or(false, |walked| walked > keccak256(proof.key)) &&
+                        *walked != last_walked
+                })
+                .count();
+            assert!(emitted_during_walk > streamed.len() / 2);
+
+            for (proof, _) in streamed {
+                // every proof verifies on its own
+                let value = verify_witness(&proof.proof, expected_root, keccak256(proof.key));
+                let expected_value = storage
+                    .get(&proof.key)
+                    .map(|value| alloy_rlp::encode_fixed_size(value).to_vec());
+                assert_eq!(value, expected_value);
+                assert_eq!(proof.value, storage.get(&proof.key).copied().unwrap_or_default());
+                assert_eq!(expected.get(&proof.key), Some(&proof));
+            }
+        }
+
+        // empty storage
+        let mut streamed = Vec::new();
+        let root = StorageRoot::new_hashed(tx.tx_ref(), B256::random())
+            .stream_proofs_for_slots(&slots[..2], |proof| streamed.push(proof));
+        assert_eq!(root, Ok(EMPTY_ROOT_HASH));
+        assert_eq!(streamed, [StorageProof::new(slots[0]), StorageProof::new(slots[1])]);
+    }
+
+    type State = BTreeMap<Address, (Account, BTreeMap<B256, U256>)>;
+
+    #[test]
+    fn arbitrary_state_root() {
+        proptest!(
+            ProptestConfig::with_cases(10), | (state: State) | {
+                test_state_root_with_state(state);
+            }
+        );
+    }
+
+    #[test]
+    fn arbitrary_state_root_with_progress() {
+        proptest!(
+            ProptestConfig::with_cases(10), | (state: State) | {
+                let hashed_entries_total = state.len() +
+                    state.values().map(|(_, slots)| slots.len()).sum::<usize>();
+
+                let db = create_test_rw_db();
+                let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+                let tx = factory.provider_rw().unwrap();
+
+                for (address, (account, storage)) in &state {
+                    insert_account(tx.tx_ref(), *address, *account, storage)
+                }
+                tx.commit().unwrap();
+                let tx =  factory.provider_rw().unwrap();
+
+                let expected = state_root(state.into_iter());
+
+                let threshold = 10;
+                let mut got = None;
+                let mut hashed_entries_walked = 0;
+
+                let mut intermediate_state: Option<Box<IntermediateStateRootState>> = None;
+                while got.is_none() {
+                    let calculator = StateRoot::new(tx.tx_ref())
+                        .with_threshold(threshold)
+                        .with_intermediate_state(intermediate_state.take().map(|state| *state));
+                    match calculator.root_with_progress().unwrap() {
+                        StateRootProgress::Progress(state, walked, _) => {
+                            intermediate_state = Some(state);
+                            hashed_entries_walked += walked;
+                        },
+                        StateRootProgress::Complete(root, walked, _) => {
+                            got = Some(root);
+                            hashed_entries_walked += walked;
+                        },
+                    };
+                }
+                assert_eq!(expected, got.unwrap());
+                assert_eq!(hashed_entries_total, hashed_entries_walked)
+            }
+        );
+    }
+
+    #[test]
+    fn cpu_budget_yields_progress() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let state = (1..=10u64)
+            .map(|i| {
+                let account = Account { nonce: i, balance: U256::from(i), bytecode_hash: None };
+                let storage = BTreeMap::from([(B256::with_last_byte(i as u8), U256::from(i))]);
+                (Address::with_last_byte(i as u8), (account, storage))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for (address, (account, storage)) in &state {
+            insert_account(tx.tx_ref(), *address, *account, storage);
+        }
+        let state_len = state.len();
+        let expected = state_root(state.into_iter());
+
+        let mut progress_returned = 0;
+        let mut intermediate_state: Option<Box<IntermediateStateRootState>> = None;
+        let got = loop {
+            let calculator = StateRoot::new(tx.tx_ref())
+                .with_cpu_budget(Duration::ZERO)
+                .with_intermediate_state(intermediate_state.take().map(|state| *state));
+            match calculator.root_with_progress().unwrap() {
+                StateRootProgress::Progress(state, _, _) => {
+                    progress_returned += 1;
+                    intermediate_state = Some(state);
+                }
+                StateRootProgress::Complete(root, _, _) => break root,
+            }
+        };
+        // the budget is exhausted after every account
+        assert_eq!(progress_returned, state_len);
+        assert_eq!(got, expected);
+
+        // the computations that can't return the progress fail instead
+        let calculator = || StateRoot::new(tx.tx_ref()).with_cpu_budget(Duration::ZERO);
+        assert_eq!(calculator().root(), Err(StateRootError::CpuBudgetExceeded));
+        assert_eq!(
+            calculator().root_with_updates().unwrap_err(),
+            StateRootError::CpuBudgetExceeded
+        );
+
+        // a budget that isn't exceeded doesn't interrupt the computation
+        let calculator = StateRoot::new(tx.tx_ref()).with_cpu_budget(Duration::from_secs(3600));
+        assert!(matches!(
+            calculator.root_with_progress(),
+            Ok(StateRootProgress::Complete(root, _, _)) if root == expected
+        ));
+    }
+
+    #[test]
+    fn cancellation() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        for _ in 0..100 {
+            tx.tx_ref().put::<tables::HashedAccount>(B256::random(), account).unwrap();
+        }
+
+        // an uncancelled token doesn't affect the computation
+        let token = CancellationToken::new();
+        let (root, _) = StateRoot::new(tx.tx_ref())
+            .with_cancellation(token.clone())
+            .root_with_updates()
+            .unwrap();
+
+        token.cancel();
+        assert_eq!(
+            StateRoot::new(tx.tx_ref()).with_cancellation(token.clone()).root(),
+            Err(StateRootError::Cancelled)
+        );
+        assert!(matches!(
+            StateRoot::new(tx.tx_ref()).with_cancellation(token).root_with_progress(),
+            Err(StateRootError::Cancelled)
+        ));
+
+        // the aborted computations leave nothing behind
+        assert_eq!(StateRoot::new(tx.tx_ref()).root().unwrap(), root);
+    }
+
+    #[test]
+    // This ensures that unchanged storage tries are not walked again in incremental runs
+    fn incremental_root_skips_unchanged_storage() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let address = Address::random();
+        let mut account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        let storage = (0..256u64)
+            .map(|i| (B256::from(U256::from(i).to_be_bytes()), U256::from(i + 1)))
+            .collect::<BTreeMap<_, _>>();
+        insert_account(tx.tx_ref(), address, account, &storage);
+
+        let (_, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        // only the balance changes
+        account.balance = U256::from(2);
+        let hashed_address = keccak256(address);
+        tx.tx_ref().put::<tables::HashedAccount>(hashed_address, account).unwrap();
+
+        let mut changed_accounts = PrefixSetMut::default();
+        changed_accounts.insert(Nibbles::unpack(hashed_address));
+        let progress = StateRoot::new(tx.tx_ref())
+            .with_changed_account_prefixes(changed_accounts.freeze())
+            .root_with_progress()
+            .unwrap();
+        let StateRootProgress::Complete(root, hashed_entries_walked, _) = progress else {
+            panic!("unexpected intermediate progress")
+        };
+
+        assert_eq!(root, state_root([(address, (account, storage))].into_iter()));
+        // the persisted storage trie nodes cover all slots
+        assert_eq!(hashed_entries_walked, 1);
+    }
+
+    /// Persists the trie of an initial state and applies the changes of block 1 to the hashed
+    /// state and changesets.
+    ///
+    /// Returns the state roots before and after block 1.
+    fn apply_block_changes(tx: &DatabaseProviderRW<'_, &DatabaseEnv>) -> (B256, B256) {
+        let changed = Address::random();
+        let created = Address::random();
+        let slot = B256::with_last_byte(1);
+        let old_account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        let old_storage = BTreeMap::from([(slot, U256::from(1))]);
+        insert_account(tx.tx_ref(), changed, old_account, &old_storage);
+
+        let (pre_root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        // block 1 changes the account and its slot, and creates a new account with storage
+        let new_account = Account { nonce: 2, ..old_account };
+        let new_storage = BTreeMap::from([(slot, U256::from(2))]);
+        let created_account = Account { nonce: 1, balance: U256::from(3), bytecode_hash: None };
+        tx.tx_ref().delete::<tables::HashedStorage>(keccak256(changed), None).unwrap();
+        insert_account(tx.tx_ref(), changed, new_account, &new_storage);
+        insert_account(tx.tx_ref(), created, created_account, &new_storage);
+        for (address, account, info) in
+            [(changed, new_account, Some(old_account)), (created, created_account, None)]
+        {
+            tx.tx_ref().put::<tables::PlainAccountState>(address, account).unwrap();
+            tx.tx_ref()
+                .put::<tables::AccountChangeSet>(1, AccountBeforeTx { address, info })
+                .unwrap();
+        }
+        tx.tx_ref()
+            .put::<tables::StorageChangeSet>(
+                (1, changed).into(),
+                StorageEntry { key: slot, value: U256::from(1) },
+            )
+            .unwrap();
+        tx.tx_ref()
+            .put::<tables::StorageChangeSet>(
+                (1, created).into(),
+                StorageEntry { key: slot, value: U256::ZERO },
+            )
+            .unwrap();
+
+        let post_root = state_root(
+            [
+                (changed, (new_account, new_storage.clone())),
+                (created, (created_account, new_storage)),
+            ]
+            .into_iter(),
+        );
+        (pre_root, post_root)
+    }
+
+    #[test]
+    fn root_expecting() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        for _ in 0..100 {
+            tx.tx_ref().put::<tables::HashedAccount>(B256::random(), account).unwrap();
+        }
+        let (root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+
+        let got = StateRoot::new(tx.tx_ref()).root_expecting(root).unwrap();
+        assert_eq!(*got, *updates);
+
+        let wrong = B256::random();
+        assert!(matches!(
+            StateRoot::new(tx.tx_ref()).root_expecting(wrong),
+            Err(StateRootError::RootMismatch { expected, computed })
+                if expected == wrong && computed == root
+        ));
+    }
+
+    #[test]
+    fn root_with_divergence_check() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        for _ in 0..1000 {
+            tx.tx_ref().put::<tables::HashedAccount>(B256::random(), account).unwrap();
+        }
+        let (root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        let expected = updates
+            .iter()
+            .filter_map(|(key, op)| match (key, op) {
+                (TrieKey::AccountNode(nibbles), TrieOp::Update(node))
+                    if !nibbles.inner.is_empty() =>
+                {
+                    Some((Nibbles::from_hex(nibbles.inner.clone()), node.clone()))
+                }
+                _ => None,
+            })
+            .collect::<BTreeMap<_, _>>();
+        assert!(expected.len() > 10);
+
+        let (checked_root, _) =
+            StateRoot::new(tx.tx_ref()).root_with_divergence_check(&expected).unwrap();
+        assert_eq!(checked_root, root);
+
+        let divergence = |diverged: &BTreeMap<_, _>| {
+            StateRoot::new(tx.tx_ref()).root_with_divergence_check(diverged).unwrap_err()
+        };
+        let paths = expected.keys().cloned().collect::<Vec<_>>();
+
+        // a node that differs
+        let mut diverged = expected.clone();
+        let node = diverged.get_mut(&paths[3]).unwrap();
+        node.root_hash = Some(B256::random());
+        let tampered = node.clone();
+        assert_eq!(
+            divergence(&diverged),
+            StateRootError::DivergenceAt {
+                path: paths[3].clone(),
+                expected: Some(Box::new(tampered)),
+                computed: Some(Box::new(expected[&paths[3]].clone())),
+            }
+        );
+
+        // a missing node
+        let mut diverged = expected.clone();
+        let missing = diverged.remove(&paths[10]).unwrap();
+        assert_eq!(
+            divergence(&diverged),
+            StateRootError::DivergenceAt {
+                path: paths[10].clone(),
+                expected: None,
+                computed: Some(Box::new(missing)),
+            }
+        );
+
+        // of two diverging subtries, the walk stops in the first one
+        let mut diverged = expected.clone();
+        diverged.remove(&paths[0]);
+        diverged.remove(paths.last().unwrap());
+        assert!(matches!(
+            divergence(&diverged),
+            StateRootError::DivergenceAt { path, .. } if path == paths[0]
+        ));
+    }
+
+    #[test]
+    fn slot_exists() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let hashed_address = B256::with_last_byte(1);
+        let slot = B256::with_last_byte;
+        let storage = BTreeMap::from([(slot(1), U256::from(1)), (slot(3), U256::from(3))]);
+        insert_storage(tx.tx_ref(), hashed_address, &storage);
+        insert_storage(
+            tx.tx_ref(),
+            B256::with_last_byte(2),
+            &BTreeMap::from([(slot(2), U256::from(2))]),
+        );
+
+        let storage_root = StorageRoot::new_hashed(tx.tx_ref(), hashed_address);
+        assert!(storage_root.slot_exists(slot(1)).unwrap());
+        assert!(storage_root.slot_exists(slot(3)).unwrap());
+        // absent slots between, before and after the account's slots, the slot of another account
+        // doesn't leak in
+        for absent in [0, 2, 4] {
+            assert!(!storage_root.slot_exists(slot(absent)).unwrap());
+        }
+
+        // a slot zeroed by the post state is not set anymore
+        let mut hashed_storage = HashedStorage::new(false);
+        hashed_storage.insert_zero_valued_slot(slot(1));
+        hashed_storage.sort_storage();
+        let mut post_state = HashedPostState::default();
+        post_state.insert_hashed_storage(hashed_address, hashed_storage);
+        let storage_root = StorageRoot::new_hashed_with_factory(
+            tx.tx_ref(),
+            HashedPostStateCursorFactory::new(tx.tx_ref(), &post_state),
+            hashed_address,
+        );
+        assert!(!storage_root.slot_exists(slot(1)).unwrap());
+        assert!(storage_root.slot_exists(slot(3)).unwrap());
+    }
+
+    #[test]
+    fn historical_storage_root() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let address = Address::random();
+        let other = Address::random();
+        let slot = B256::with_last_byte;
+        let storage = |slots: &[(u8, u64)]| {
+            slots
+                .iter()
+                .map(|(key, value)| (slot(*key), U256::from(*value)))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let changeset: [(BlockNumber, Address, [(u8, u64); 2]); 3] = [
+            // block 1 updates slot 1 and creates slot 3
+            (1, address, [(1, 1), (3, 0)]),
+            // block 2 deletes slot 2 and updates slot 1 again, another account changes as well
+            (2, address, [(2, 2), (1, 5)]),
+            (2, other, [(1, 9), (2, 0)]),
+        ];
+        for (block, changed_address, slots) in changeset {
+            for (key, value) in slots {
+                tx.tx_ref()
+                    .put::<tables::StorageChangeSet>(
+                        BlockNumberAddress((block, changed_address)),
+                        StorageEntry { key: slot(key), value: U256::from(value) },
+                    )
+                    .unwrap();
+            }
+        }
+        let history: [(Address, u8, BlockNumber, &[usize]); 6] = [
+            // the history of slot 1 is split into two shards
+            (address, 1, 1, &[1]),
+            (address, 1, u64::MAX, &[2]),
+            (address, 2, u64::MAX, &[2]),
+            (address, 3, u64::MAX, &[1]),
+            (other, 1, u64::MAX, &[2]),
+            (other, 2, u64::MAX, &[2]),
+        ];
+        for (history_address, key, highest_block_number, blocks) in history {
+            tx.tx_ref()
+                .put::<tables::StorageHistory>(
+                    StorageShardedKey::new(history_address, slot(key), highest_block_number),
+                    BlockNumberList::new_pre_sorted(blocks),
+                )
+                .unwrap();
+        }
+
+        let latest = storage(&[(1, 7), (3, 3)]);
+        insert_storage(tx.tx_ref(), keccak256(address), &latest);
+        insert_storage(tx.tx_ref(), keccak256(other), &storage(&[(2, 1)]));
+        let (root, _, updates) =
+            StorageRoot::new(tx.tx_ref(), address).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+        assert_eq!(root, storage_root(latest.clone().into_iter()));
+
+        let expected = [
+            storage(&[(1, 1), (2, 2)]),
+            storage(&[(1, 5), (2, 2), (3, 3)]),
+            storage(&[(1, 7), (3, 3)]),
+        ];
+        for (at_block, storage) in expected.into_iter().enumerate() {
+            assert_eq!(
+                StorageRoot::historical(tx.tx_ref(), address, at_block as BlockNumber),
+                Ok(storage_root(storage.into_iter())),
+                "storage root at block {at_block}"
+            );
+        }
+        assert_eq!(
+            StorageRoot::historical(tx.tx_ref(), address, BlockNumber::MAX),
+            Ok(storage_root(latest.into_iter()))
+        );
+    }
+
+    #[test]
+    fn verify_and_compute_checks_pre_root() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+        let (pre_root, post_root) = apply_block_changes(&tx);
+
+        assert_eq!(StateRoot::persisted_root(tx.tx_ref(), 1..=1), Ok(pre_root));
+        assert_eq!(StateRoot::verify_and_compute(tx.tx_ref(), pre_root, 1..=1), Ok(post_root));
+
+        let wrong_root = B256::random();
+        let err = StateRoot::verify_and_compute(tx.tx_ref(), wrong_root, 1..=1).unwrap_err();
+        assert_eq!(err, StateRootError::PreRootMismatch { expected: wrong_root, actual: pre_root });
+
+        // The mismatch is surfaced to providers as an error instead of a panic.
+        assert!(matches!(ProviderError::from(err), ProviderError::StateRootComputation(_)));
+    }
+
+    #[test]
+    fn incremental_root_transition() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+        let (pre_root, post_root) = apply_block_changes(&tx);
+
+        let (prior_root, root, updates) =
+            StateRoot::incremental_root_transition(tx.tx_ref(), 1..=1).unwrap();
+        assert_eq!((prior_root, root), (pre_root, post_root));
+
+        updates.flush(tx.tx_ref()).unwrap();
+        assert_eq!(StateRoot::new(tx.tx_ref()).root(), Ok(post_root));
+    }
+
+    /// Rolls the hashed and plain state back to the values recorded in the changesets of the
+    /// block.
+    fn revert_block_changes(tx: &DatabaseProviderRW<'_, &DatabaseEnv>, block: BlockNumber) {
+        let account_changes = tx
+            .tx_ref()
+            .cursor_read::<tables::AccountChangeSet>()
+            .unwrap()
+            .walk_range(block..=block)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        for (_, AccountBeforeTx { address, info }) in account_changes {
+            match info {
+                Some(account) => {
+                    tx.tx_ref().put::<tables::HashedAccount>(keccak256(address), account).unwrap();
+                    tx.tx_ref().put::<tables::PlainAccountState>(address, account).unwrap();
+                }
+                None => {
+                    tx.tx_ref().delete::<tables::HashedAccount>(keccak256(address), None).unwrap();
+                    tx.tx_ref().delete::<tables::PlainAccountState>(address, None).unwrap();
+                }
+            }
+        }
+
+        let storage_changes = tx
+            .tx_ref()
+            .cursor_dup_read::<tables::StorageChangeSet>()
+            .unwrap()
+            .walk_range(BlockNumberAddress::range(block..=block))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let mut hashed_storage_cursor =
+            tx.tx_ref().cursor_dup_write::<tables::HashedStorage>().unwrap();
+        for (BlockNumberAddress((_, address)), StorageEntry { key, value }) in storage_changes {
+            let (hashed_address, hashed_key) = (keccak256(address), keccak256(key));
+            if hashed_storage_cursor
+                .seek_by_key_subkey(hashed_address, hashed_key)
+                .unwrap()
+                .filter(|entry| entry.key == hashed_key)
+                .is_some()
+            {
+                hashed_storage_cursor.delete_current().unwrap();
+            }
+            if value != U256::ZERO {
+                hashed_storage_cursor
+                    .upsert(hashed_address, StorageEntry { key: hashed_key, value })
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_root_for_reorg() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+        let (pre_root, post_root) = apply_block_changes(&tx);
+
+        let (root, updates) = StateRoot::incremental_root_with_updates(tx.tx_ref(), 1..=1).unwrap();
+        assert_eq!(root, post_root);
+        updates.flush(tx.tx_ref()).unwrap();
+
+        // block 1 is orphaned, the trie updates don't depend on whether the hashed state is
+        // already unwound
+        let (root, reorg_updates) =
+            StateRoot::incremental_root_for_reorg(tx.tx_ref(), 1..=1).unwrap();
+        assert_eq!(root, pre_root);
+        revert_block_changes(&tx, 1);
+        let (root, updates) = StateRoot::incremental_root_for_reorg(tx.tx_ref(), 1..=1).unwrap();
+        assert_eq!(root, pre_root);
+        assert_eq!(*updates, *reorg_updates);
+
+        updates.flush(tx.tx_ref()).unwrap();
+        assert_eq!(StateRoot::new(tx.tx_ref()).root(), Ok(pre_root));
+    }
+
+    #[test]
+    fn incremental_root_without_changes() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+        let (_, post_root) = apply_block_changes(&tx);
+        let (_, updates) = StateRoot::incremental_root_with_updates(tx.tx_ref(), 1..=1).unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
+
+        // neither the block after the changes nor an empty range has any changesets
+        for range in [2..=2, 2..=1] {
+            let loaded = PrefixSetLoader::new(tx.tx_ref()).load(range.clone()).unwrap();
+            assert!(loaded.is_empty());
+
+            let (root, updates) =
+                StateRoot::incremental_root_with_updates(tx.tx_ref(), range.clone()).unwrap();
+            assert_eq!(root, post_root);
+            assert_eq!(StateRoot::persisted_root(tx.tx_ref(), range.clone()), Ok(root));
+            assert!(updates.is_empty());
+            assert_eq!(StateRoot::incremental_root(tx.tx_ref(), range), Ok(root));
+        }
+    }
+
+    #[test]
+    fn storage_deletes_can_be_suppressed() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        // an account without storage and a destroyed account
+        let hashed_address = B256::random();
+        let destroyed = B256::random();
+        let account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        tx.tx_ref().put::<tables::HashedAccount>(hashed_address, account).unwrap();
+
+        let storage_deletes = |emit_storage_deletes| {
+            let (_, updates) = StateRoot::new(tx.tx_ref())
+                .with_destroyed_accounts(HashSet::from([destroyed]))
+                .with_emit_storage_deletes(emit_storage_deletes)
+                .root_with_updates()
+                .unwrap();
+            let mut deleted = updates
+                .iter()
+                .filter_map(|(key, op)| match (key, op) {
+                    (TrieKey::StorageTrie(address), TrieOp::Delete) => Some(*address),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            deleted.sort();
+            deleted
+        };
+        let mut expected = vec![hashed_address, destroyed];
+        expected.sort();
+        assert_eq!(storage_deletes(true), expected);
+        assert!(storage_deletes(false).is_empty());
+
+        let (root, _, updates) = StorageRoot::new_hashed(tx.tx_ref(), hashed_address)
+            .with_emit_storage_deletes(false)
+            .root_with_updates()
+            .unwrap();
+        assert_eq!(root, EMPTY_ROOT_HASH);
+        assert!(updates.is_empty());
+    }
+
+    #[test]
+    fn state_root_with_storage_root_provider() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        // only the account is in the hashed state, its storage root is provided
+        let address = Address::random();
+        let hashed_address = keccak256(address);
+        let account = Account { nonce: 1, balance: U256::from(1), bytecode_hash: None };
+        let storage = BTreeMap::from([(B256::with_last_byte(1), U256::from(1))]);
+        tx.tx_ref().put::<tables::HashedAccount>(hashed_address, account).unwrap();
+
+        let storage_roots =
+            HashMap::from([(hashed_address, storage_root(storage.clone().into_iter()))]);
+        let (root, updates) = StateRoot::new(tx.tx_ref())
+            .with_storage_root_provider(&storage_roots)
+            .root_with_updates()
+            .unwrap();
+        assert_eq!(root, state_root([(address, (account, storage))].into_iter()));
+        assert!(!updates
+            .keys()
+            .any(|key| matches!(key, TrieKey::StorageNode(..) | TrieKey::StorageTrie(_))));
+
+        // unknown storage roots are computed from the hashed storage
+        let (root, updates) = StateRoot::new(tx.tx_ref())
+            .with_storage_root_provider(HashMap::<B256, B256>::new())
+            .root_with_updates()
+            .unwrap();
+        assert_eq!(root, state_root([(address, (account, BTreeMap::new()))].into_iter()));
+        assert_eq!(updates.get(&TrieKey::StorageTrie(hashed_address)), Some(&TrieOp::Delete));
+    }
+
+    /// Hashed cursors that yield their entries in the given order, regardless of the seek key.
+    #[derive(Debug, Clone, Default)]
+    struct UnorderedCursorFactory {
+        accounts: Vec<(B256, Account)>,
+        storage: Vec<StorageEntry>,
+    }
+
+    #[derive(Debug)]
+    struct UnorderedCursor<T> {
+        entries: Vec<T>,
+        position: usize,
+    }
+
+    impl<T: Clone> UnorderedCursor<T> {
+        fn new(entries: Vec<T>) -> Self {
+            Self { entries, position: 0 }
+        }
+
+        fn first(&mut self) -> Option<T> {
+            self.position = 0;
+            self.next_entry()
+        }
+
+        fn next_entry(&mut self) -> Option<T> {
+            let entry = self.entries.get(self.position).cloned();
+            self.position += 1;
+            entry
+        }
+    }
+
+    impl HashedCursorFactory for UnorderedCursorFactory {
+        type AccountCursor = UnorderedCursor<(B256, Account)>;
+        type StorageCursor = UnorderedCursor<StorageEntry>;
+
+        fn hashed_account_cursor(&self) -> Result<Self::AccountCursor, reth_db::DatabaseError> {
+            Ok(UnorderedCursor::new(self.accounts.clone()))
+        }
+
+        fn hashed_storage_cursor(&self) -> Result<Self::StorageCursor, reth_db::DatabaseError> {
+            Ok(UnorderedCursor::new(self.storage.clone()))
+        }
+    }
+
+    impl crate::hashed_cursor::HashedAccountCursor for UnorderedCursor<(B256, Account)> {
+        fn seek(&mut self, _key: B256) -> Result<Option<(B256, Account)>, reth_db::DatabaseError> {
+            Ok(self.first())
+        }
+
+        fn next(&mut self) -> Result<Option<(B256, Account)>, reth_db::DatabaseError> {
+            Ok(self.next_entry())
+        }
+    }
+
+    impl HashedStorageCursor for UnorderedCursor<StorageEntry> {
+        fn is_storage_empty(&mut self, _key: B256) -> Result<bool, reth_db::DatabaseError> {
+            Ok(self.entries.is_empty())
+        }
+
+        fn seek(
+            &mut self,
+            _key: B256,
+            _subkey: B256,
+        ) -> Result<Option<StorageEntry>, reth_db::DatabaseError> {
+            Ok(self.first())
+        }
+
+        fn next(&mut self) -> Result<Option<StorageEntry>, reth_db::DatabaseError> {
+            Ok(self.next_entry())
+        }
+    }
+
+    /// Hashed cursors whose storage cursor always fails.
+    #[derive(Debug, Clone)]
+    struct FailingStorageCursorFactory(UnorderedCursorFactory);
+
+    #[derive(Debug)]
+    struct FailingStorageCursor;
+
+    impl HashedCursorFactory for FailingStorageCursorFactory {
+        type AccountCursor = UnorderedCursor<(B256, Account)>;
+        type StorageCursor = FailingStorageCursor;
+
+        fn hashed_account_cursor(&self) -> Result<Self::AccountCursor, reth_db::DatabaseError> {
+            self.0.hashed_account_cursor()
+        }
+
+        fn hashed_storage_cursor(&self) -> Result<Self::StorageCursor, reth_db::DatabaseError> {
+            Ok(FailingStorageCursor)
+        }
+    }
+
+    impl HashedStorageCursor for FailingStorageCursor {
+        fn is_storage_empty(&mut self, _key: B256) -> Result<bool, reth_db::DatabaseError> {
+            Err(reth_db::DatabaseError::Read(-1))
+        }
+
+        fn seek(
+            &mut self,
+            _key: B256,
+            _subkey: B256,
+        ) -> Result<Option<StorageEntry>, reth_db::DatabaseError> {
+            Err(reth_db::DatabaseError::Read(-1))
+        }
+
+        fn next(&mut self) -> Result<Option<StorageEntry>, reth_db::DatabaseError> {
+            Err(reth_db::DatabaseError::Read(-1))
+        }
+    }
+
+    #[test]
+    fn storage_root_error_has_account() {
+        let db = create_test_rw_db();
+        let tx = db.tx().unwrap();
+
+        let hashed_address = B256::random();
+        let factory = FailingStorageCursorFactory(UnorderedCursorFactory {
+            accounts: vec![(hashed_address, Account::default())],
+            ..Default::default()
+        });
+
+        for retain_updates in [false, true] {
+            let calculator = StateRoot::new(&tx).with_hashed_cursor_factory(factory.clone());
+            let err = if retain_updates {
+                calculator.root_with_updates().unwrap_err()
+            } else {
+                calculator.root().unwrap_err()
+            };
+            assert_eq!(
+                err,
+                StateRootError::StorageRoot {
+                    hashed_address,
+                    source: StorageRootError::DB(reth_db::DatabaseError::Read(-1)),
+                }
+            );
+            assert!(err.to_string().contains(&hashed_address.to_string()));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "hashed accounts out of order")]
+    fn unordered_hashed_accounts() {
+        let db = create_test_rw_db();
+        let tx = db.tx().unwrap();
+
+        let account = Account::default();
+        let factory = UnorderedCursorFactory {
+            accounts: vec![(B256::with_last_byte(2), account), (B256::with_last_byte(1), account)],
+            ..Default::default()
+        };
+        let _ = StateRoot::new(&tx).with_hashed_cursor_factory(factory).root();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "hashed storage slots out of order")]
+    fn unordered_hashed_storage_slots() {
+        let db = create_test_rw_db();
+        let tx = db.tx().unwrap();
+
+        let factory = UnorderedCursorFactory {
+            storage: vec![
+                StorageEntry { key: B256::with_last_byte(2), value: U256::from(1) },
+                StorageEntry { key: B256::with_last_byte(1), value: U256::from(1) },
+            ],
+            ..Default::default()
+        };
+        let _ =
+            StorageRoot::new_hashed(&tx, B256::random()).with_hashed_cursor_factory(factory).root();
+    }
+
+    #[test]
+    fn arbitrary_state_root_with_renewed_snapshots() {
+        proptest!(
+            ProptestConfig::with_cases(10), | (state: State) | {
+                let db = create_test_rw_db();
+                let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+                let tx = factory.provider_rw().unwrap();
+
+                for (address, (account, storage)) in &state {
+                    insert_account(tx.tx_ref(), *address, *account, storage)
+                }
+                tx.commit().unwrap();
+
+                let expected = state_root(state.into_iter());
+
+                let mut got = None;
+                let mut intermediate_state: Option<Box<IntermediateStateRootState>> = None;
+                let mut snapshot = db.tx().unwrap();
+                while got.is_none() {
+                    // The transaction the calculator is created with is closed before the
+                    // computation reads from the snapshot.
+                    let original = db.tx().unwrap();
+                    let calculator = StateRoot::new(&original)
+                        .with_threshold(10)
+                        .with_intermediate_state(intermediate_state.take().map(|state| *state))
+                        .with_snapshot(&snapshot);
+                    drop(original);
+                    match calculator.root_with_progress().unwrap() {
+                        StateRootProgress::Progress(state, _, _) => intermediate_state = Some(state),
+                        StateRootProgress::Complete(root, _, _) => got = Some(root),
+                    };
+
+                    // Renew the snapshot at every checkpoint, closing the old one first.
+                    drop(snapshot);
+                    snapshot = db.tx().unwrap();
+                }
+                assert_eq!(expected, got.unwrap());
+            }
+        );
     }
 
-    fn incremental_vs_full_root(inputs: &[&str], modified: &str) {
+    #[test]
+    fn custom_empty_root() {
         let db = create_test_rw_db();
         let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
         let tx = factory.provider_rw().unwrap();
-        let hashed_address = B256::with_last_byte(1);
+        let empty_root = B256::random();
 
-        let mut hashed_storage_cursor =
-            tx.tx_ref().cursor_dup_write::<tables::HashedStorage>().unwrap();
-        let data = inputs.iter().map(|x| B256::from_str(x).unwrap());
-        let value = U256::from(0);
-        for key in data {
-            hashed_storage_cursor.upsert(hashed_address, StorageEntry { key, value }).unwrap();
-        }
+        assert_eq!(StateRoot::new(tx.tx_ref()).with_empty_root(empty_root).root(), Ok(empty_root));
 
-        // Generate the intermediate nodes on the receiving end of the channel
-        let (_, _, trie_updates) =
-            StorageRoot::new_hashed(tx.tx_ref(), hashed_address).root_with_updates().unwrap();
+        let address = Address::random();
+        let account = Account { nonce: 1, ..Default::default() };
+        insert_account(tx.tx_ref(), address, account, &BTreeMap::new());
+        assert_eq!(
+            StorageRoot::new(tx.tx_ref(), address).with_empty_root(empty_root).root(),
+            Ok(empty_root)
+        );
 
-        // 1. Some state transition happens, update the hashed storage to the new value
-        let modified_key = B256::from_str(modified).unwrap();
-        let value = U256::from(1);
-        if hashed_storage_cursor.seek_by_key_subkey(hashed_address, modified_key).unwrap().is_some()
-        {
-            hashed_storage_cursor.delete_current().unwrap();
-        }
-        hashed_storage_cursor
-            .upsert(hashed_address, StorageEntry { key: modified_key, value })
+        let mut hash_builder = HashBuilder::default();
+        hash_builder.add_leaf(
+            Nibbles::unpack(keccak256(address)),
+            &encode_account(account, Some(empty_root)),
+        );
+        let expected = hash_builder.root();
+        assert_eq!(StateRoot::new(tx.tx_ref()).with_empty_root(empty_root).root(), Ok(expected));
+        assert_ne!(StateRoot::new(tx.tx_ref()).root(), Ok(expected));
+
+        // An overlay deleting every slot of a storage yields the configured empty root.
+        let address = Address::random();
+        let slot = B256::with_last_byte(1);
+        insert_storage(tx.tx_ref(), keccak256(address), &BTreeMap::from([(slot, U256::from(1))]));
+        let storage_root = StorageRoot::new(tx.tx_ref(), address).with_empty_root(empty_root);
+        let (root, _, _) = storage_root
+            .root_with_overlay(&HashMap::from([(keccak256(slot), U256::ZERO)]))
             .unwrap();
+        assert_eq!(root, empty_root);
 
-        // 2. Calculate full merkle root
-        let loader = StorageRoot::new_hashed(tx.tx_ref(), hashed_address);
-        let modified_root = loader.root().unwrap();
+        // So does the storage before the block that created it.
+        tx.tx_ref()
+            .put::<tables::StorageChangeSet>(
+                BlockNumberAddress((1, address)),
+                StorageEntry { key: slot, value: U256::ZERO },
+            )
+            .unwrap();
+        assert_eq!(storage_root.root_at_block(0), Ok(empty_root));
+        assert_eq!(StorageRoot::historical(tx.tx_ref(), address, 0), Ok(EMPTY_ROOT_HASH));
+
+        // The history is keyed by the plain address, which a calculator created from the hashed
+        // one doesn't know.
+        assert_eq!(
+            StorageRoot::new_hashed(tx.tx_ref(), keccak256(address)).root_at_block(0),
+            Err(StorageRootError::MissingAddress { hashed_address: keccak256(address) })
+        );
+    }
 
-        // Update the intermediate roots table so that we can run the incremental verification
-        trie_updates.flush(tx.tx_ref()).unwrap();
+    #[test]
+    fn state_root_metrics() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, Default)]
+        struct CountingMetrics {
+            account_leaves: AtomicUsize,
+            storage_leaves: AtomicUsize,
+            durations: AtomicUsize,
+        }
 
-        // 3. Calculate the incremental root
-        let mut storage_changes = PrefixSetMut::default();
-        storage_changes.insert(Nibbles::unpack(modified_key));
-        let loader = StorageRoot::new_hashed(tx.tx_ref(), hashed_address)
-            .with_changed_prefixes(storage_changes.freeze());
-        let incremental_root = loader.root().unwrap();
+        impl TrieMetrics for CountingMetrics {
+            fn record_account_leaves(&self, n: usize) {
+                self.account_leaves.fetch_add(n, Ordering::Relaxed);
+            }
 
-        assert_eq!(modified_root, incremental_root);
+            fn record_storage_leaves(&self, n: usize) {
+                self.storage_leaves.fetch_add(n, Ordering::Relaxed);
+            }
+
+            fn record_duration(&self, _duration: Duration) {
+                self.durations.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let storage = (1..=3u64)
+            .map(|slot| (B256::with_last_byte(slot as u8), U256::from(slot)))
+            .collect::<BTreeMap<_, _>>();
+        for nonce in 1..=5 {
+            let account = Account { nonce, ..Default::default() };
+            insert_account(tx.tx_ref(), Address::random(), account, &storage);
+        }
+
+        let metrics = Arc::new(CountingMetrics::default());
+        StateRoot::new(tx.tx_ref()).with_metrics(metrics.clone()).root_with_updates().unwrap();
+        assert_eq!(metrics.account_leaves.load(Ordering::Relaxed), 5);
+        assert_eq!(metrics.storage_leaves.load(Ordering::Relaxed), 15);
+        assert_eq!(metrics.durations.load(Ordering::Relaxed), 1);
     }
 
     #[test]
-    fn branch_node_child_changes() {
-        incremental_vs_full_root(
-            &[
-                "1000000000000000000000000000000000000000000000000000000000000000",
-                "1100000000000000000000000000000000000000000000000000000000000000",
-                "1110000000000000000000000000000000000000000000000000000000000000",
-                "1200000000000000000000000000000000000000000000000000000000000000",
-                "1220000000000000000000000000000000000000000000000000000000000000",
-                "1320000000000000000000000000000000000000000000000000000000000000",
-            ],
-            "1200000000000000000000000000000000000000000000000000000000000000",
+    fn state_root_stats() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+        let tx = factory.provider_rw().unwrap();
+
+        let storage = (1..=3u64)
+            .map(|slot| (B256::with_last_byte(slot as u8), U256::from(slot)))
+            .collect::<BTreeMap<_, _>>();
+        for nonce in 1..=20 {
+            let account = Account { nonce, ..Default::default() };
+            insert_account(tx.tx_ref(), Address::random(), account, &storage);
+        }
+
+        // Without trie nodes, all leaves are walked.
+        let (root, stats) = StateRoot::new(tx.tx_ref()).root_with_stats().unwrap();
+        assert_eq!(
+            stats,
+            TrieStats { accounts_walked: 20, storage_slots_walked: 60, branch_nodes: 0 }
+        );
+
+        // With the persisted trie nodes and no changes, only the stored root node is added.
+        let (expected, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        assert_eq!(root, expected);
+        updates.flush(tx.tx_ref()).unwrap();
+        let (root, stats) = StateRoot::new(tx.tx_ref()).root_with_stats().unwrap();
+        assert_eq!(root, expected);
+        assert_eq!(
+            stats,
+            TrieStats { accounts_walked: 0, storage_slots_walked: 0, branch_nodes: 1 }
         );
     }
 
     #[test]
-    fn arbitrary_storage_root() {
-        proptest!(ProptestConfig::with_cases(10), |(item: (Address, std::collections::BTreeMap<B256, U256>))| {
-            let (address, storage) = item;
+    fn arbitrary_state_root_parallel() {
+        proptest!(
+            ProptestConfig::with_cases(10), | (state: State) | {
+                let db = create_test_rw_db();
+                let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+                let tx = factory.provider_rw().unwrap();
 
-            let hashed_address = keccak256(address);
-            let db = create_test_rw_db();
-            let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
-            let tx = factory.provider_rw().unwrap();
-            for (key, value) in &storage {
-                tx.tx_ref().put::<tables::HashedStorage>(
-                    hashed_address,
-                    StorageEntry { key: keccak256(key), value: *value },
-                )
-                .unwrap();
-            }
-            tx.commit().unwrap();
+                for (address, (account, storage)) in &state {
+                    insert_account(tx.tx_ref(), *address, *account, storage)
+                }
+                tx.commit().unwrap();
 
-            let tx =  factory.provider_rw().unwrap();
-            let got = StorageRoot::new(tx.tx_ref(), address).root().unwrap();
-            let expected = storage_root(storage.into_iter());
-            assert_eq!(expected, got);
-        });
-    }
+                let expected = state_root(state.into_iter());
 
-    #[test]
-    // This ensures we dont add empty accounts to the trie
-    fn test_empty_account() {
-        let state: State = BTreeMap::from([
-            (
-                Address::random(),
-                (
-                    Account { nonce: 0, balance: U256::from(0), bytecode_hash: None },
-                    BTreeMap::from([(B256::with_last_byte(0x4), U256::from(12))]),
-                ),
-            ),
-            (
-                Address::random(),
-                (
-                    Account { nonce: 0, balance: U256::from(0), bytecode_hash: None },
-                    BTreeMap::default(),
-                ),
-            ),
-            (
-                Address::random(),
-                (
-                    Account {
-                        nonce: 155,
-                        balance: U256::from(414241124u32),
-                        bytecode_hash: Some(keccak256("test")),
-                    },
-                    BTreeMap::from([
-                        (B256::ZERO, U256::from(3)),
-                        (B256::with_last_byte(2), U256::from(1)),
-                    ]),
-                ),
-            ),
-        ]);
-        test_state_root_with_state(state);
+                let tx = factory.provider().unwrap();
+                let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+                let (root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+                let (parallel_root, parallel_updates) = StateRoot::new(tx.tx_ref())
+                    .parallel_root_with_updates(&pool)
+                    .unwrap();
+                assert_eq!(expected, root);
+                assert_eq!(root, parallel_root);
+                assert_eq!(*updates, *parallel_updates);
+            }
+        );
     }
 
     #[test]
-    // This ensures we return an empty root when there are no storage entries
-    fn test_empty_storage_root() {
+    fn parallel_state_root_uncommitted_writes() {
         let db = create_test_rw_db();
         let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
-        let tx = factory.provider_rw().unwrap();
 
-        let address = Address::random();
-        let code = "el buen fla";
-        let account = Account {
-            nonce: 155,
-            balance: U256::from(414241124u32),
-            bytecode_hash: Some(keccak256(code)),
-        };
-        insert_account(tx.tx_ref(), address, account, &Default::default());
+        let tx = factory.provider_rw().unwrap();
+        for i in 0..20u8 {
+            let account = Account { nonce: i as u64, ..Default::default() };
+            let storage = (0..i)
+                .map(|slot| (B256::with_last_byte(slot), U256::from(slot as u64 + 1)))
+                .collect::<BTreeMap<_, _>>();
+            insert_account(tx.tx_ref(), Address::with_last_byte(i), account, &storage);
+        }
         tx.commit().unwrap();
 
+        // the workers read the writes the transaction of the calculator didn't commit yet
         let tx = factory.provider_rw().unwrap();
-        let got = StorageRoot::new(tx.tx_ref(), address).root().unwrap();
-        assert_eq!(got, EMPTY_ROOT_HASH);
+        let hashed_address = keccak256(Address::with_last_byte(7));
+        let entry = StorageEntry { key: keccak256(B256::with_last_byte(42)), value: U256::from(1) };
+        tx.tx_ref().put::<tables::HashedStorage>(hashed_address, entry).unwrap();
+
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let (root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        let (parallel_root, parallel_updates) =
+            StateRoot::new(tx.tx_ref()).parallel_root_with_updates(&pool).unwrap();
+        assert_eq!(root, parallel_root);
+        assert_eq!(*updates, *parallel_updates);
     }
 
     #[test]
-    // This ensures that the walker goes over all the storage slots
-    fn test_storage_root() {
+    fn parallel_state_root_on_busy_pool() {
         let db = create_test_rw_db();
         let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
+
         let tx = factory.provider_rw().unwrap();
+        for i in 0..50u8 {
+            let account = Account { nonce: i as u64, ..Default::default() };
+            let storage = (0..i % 5 * 10)
+                .map(|slot| (B256::with_last_byte(slot), U256::from(slot as u64 + 1)))
+                .collect::<BTreeMap<_, _>>();
+            insert_account(tx.tx_ref(), Address::with_last_byte(i), account, &storage);
+        }
+        tx.commit().unwrap();
 
-        let address = Address::random();
-        let storage =
-            BTreeMap::from([(B256::ZERO, U256::from(3)), (B256::with_last_byte(2), U256::from(1))]);
+        let tx = factory.provider().unwrap();
+        let (root, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
 
-        let code = "el buen fla";
-        let account = Account {
-            nonce: 155,
-            balance: U256::from(414241124u32),
-            bytecode_hash: Some(keccak256(code)),
-        };
+        // Called from the only thread of the pool, the workers can't start before the walk is
+        // done, so the walk computes all the storage roots itself.
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let (parallel_root, parallel_updates) =
+            pool.install(|| StateRoot::new(tx.tx_ref()).parallel_root_with_updates(&pool)).unwrap();
+        assert_eq!(root, parallel_root);
+        assert_eq!(*updates, *parallel_updates);
+    }
 
-        insert_account(tx.tx_ref(), address, account, &storage);
-        tx.commit().unwrap();
+    #[test]
+    fn parallel_state_root_with_options() {
+        let db = create_test_rw_db();
+        let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
 
         let tx = factory.provider_rw().unwrap();
-        let got = StorageRoot::new(tx.tx_ref(), address).root().unwrap();
-
-        assert_eq!(storage_root(storage.into_iter()), got);
-    }
+        let mut hashed_addresses = Vec::new();
+        for i in 0..50u8 {
+            let account = Account { nonce: i as u64, ..Default::default() };
+            let storage = (0..i % 5 * 10)
+                .map(|slot| (B256::with_last_byte(slot), U256::from(slot as u64 + 1)))
+                .collect::<BTreeMap<_, _>>();
+            insert_account(tx.tx_ref(), Address::with_last_byte(i), account, &storage);
+            hashed_addresses.push(keccak256(Address::with_last_byte(i)));
+        }
+        let (_, updates) = StateRoot::new(tx.tx_ref()).root_with_updates().unwrap();
+        updates.flush(tx.tx_ref()).unwrap();
 
-    type State = BTreeMap<Address, (Account, BTreeMap<B256, U256>)>;
+        // change a slot of every other account with storage
+        let mut account_prefixes = PrefixSetMut::default();
+        let mut storage_prefixes = HashMap::new();
+        for hashed_address in hashed_addresses.iter().step_by(2) {
+            let hashed_slot = keccak256(B256::with_last_byte(3));
+            let entry = StorageEntry { key: hashed_slot, value: U256::from(100) };
+            tx.tx_ref().put::<tables::HashedStorage>(*hashed_address, entry).unwrap();
+            account_prefixes.insert(Nibbles::unpack(hashed_address));
+            storage_prefixes.insert(
+                *hashed_address,
+                PrefixSetMut::from([Nibbles::unpack(hashed_slot)]).freeze(),
+            );
+        }
+        let account_prefixes = account_prefixes.freeze();
+        tx.commit().unwrap();
 
-    #[test]
-    fn arbitrary_state_root() {
-        proptest!(
-            ProptestConfig::with_cases(10), | (state: State) | {
-                test_state_root_with_state(state);
-            }
+        let tx = factory.provider().unwrap();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let calculator = || {
+            StateRoot::new(tx.tx_ref())
+                .with_changed_account_prefixes(account_prefixes.clone())
+                .with_changed_storage_prefixes(storage_prefixes.clone())
+                .with_emit_storage_deletes(false)
+                .with_retained_depth(Some(1))
+                .with_storage_root_cache(StorageRootCache::new(16))
+                .with_threshold(1)
+        };
+        let (root, updates) = calculator().root_with_updates().unwrap();
+        assert!(!updates.is_empty());
+        let (parallel_root, parallel_updates) =
+            calculator().parallel_root_with_updates(&pool).unwrap();
+        assert_eq!(root, parallel_root);
+        assert_eq!(*updates, *parallel_updates);
+
+        // the restricted computations don't retain any updates
+        let bounds = vec![Nibbles::from_hex(vec![0x3])];
+        let (root, updates) =
+            calculator().with_account_prefix_bounds(bounds.clone()).root_with_updates().unwrap();
+        let (parallel_root, parallel_updates) = calculator()
+            .with_account_prefix_bounds(bounds)
+            .parallel_root_with_updates(&pool)
+            .unwrap();
+        assert_eq!(root, parallel_root);
+        assert!(updates.is_empty() && parallel_updates.is_empty());
+
+        let allowlist = HashSet::from([hashed_addresses[4], hashed_addresses[7]]);
+        let root = calculator().with_account_allowlist(allowlist.clone()).root().unwrap();
+        let (parallel_root, parallel_updates) = calculator()
+            .with_account_allowlist(allowlist)
+            .parallel_root_with_updates(&pool)
+            .unwrap();
+        assert_eq!(root, parallel_root);
+        assert!(parallel_updates.is_empty());
+
+        // the CPU budget applies to the parallel computation as well
+        assert_eq!(
+            calculator()
+                .with_cpu_budget(Duration::ZERO)
+                .parallel_root_with_updates(&pool)
+                .unwrap_err(),
+            StateRootError::CpuBudgetExceeded
         );
     }
 
     #[test]
-    fn arbitrary_state_root_with_progress() {
+    fn huge_storage_trie_with_progress() {
         proptest!(
-            ProptestConfig::with_cases(10), | (state: State) | {
-                let hashed_entries_total = state.len() +
-                    state.values().map(|(_, slots)| slots.len()).sum::<usize>();
-
+            ProptestConfig::with_cases(5), | (
+                address: Address,
+                storage in proptest::collection::btree_map(
+                    proptest::arbitrary::any::<B256>(),
+                    proptest::arbitrary::any::<U256>(),
+                    1000..3000,
+                ),
+            ) | {
                 let db = create_test_rw_db();
                 let factory = ProviderFactory::new(db.as_ref(), MAINNET.clone());
                 let tx = factory.provider_rw().unwrap();
 
-                for (address, (account, storage)) in &state {
-                    insert_account(tx.tx_ref(), *address, *account, storage)
-                }
+                let account = Account { nonce: 1, ..Default::default() };
+                insert_account(tx.tx_ref(), address, account, &storage);
                 tx.commit().unwrap();
-                let tx =  factory.provider_rw().unwrap();
 
-                let expected = state_root(state.into_iter());
+                let expected_storage_root = storage_root(storage.clone().into_iter());
+                let expected = state_root(std::iter::once((address, (account, storage))));
 
-                let threshold = 10;
+                let tx = factory.provider_rw().unwrap();
                 let mut got = None;
-                let mut hashed_entries_walked = 0;
+                let mut progress = 0;
+                let mut intermediate_state: Option<Box<IntermediateStorageRootState>> = None;
+                while got.is_none() {
+                    let calculator = StorageRoot::new(tx.tx_ref(), address)
+                        .with_threshold(10)
+                        .with_intermediate_state(intermediate_state.take().map(|state| *state));
+                    match calculator.root_with_progress().unwrap() {
+                        StorageRootProgress::Progress(state, _, _) => {
+                            progress += 1;
+                            intermediate_state = Some(state);
+                        }
+                        StorageRootProgress::Complete(root, _, _) => got = Some(root),
+                    };
+                }
+                assert!(progress > 0);
+                assert_eq!(expected_storage_root, got.unwrap());
 
+                let mut got = None;
+                let mut progress = 0;
                 let mut intermediate_state: Option<Box<IntermediateStateRootState>> = None;
                 while got.is_none() {
                     let calculator = StateRoot::new(tx.tx_ref())
-                        .with_threshold(threshold)
+                        .with_storage_progress(true)
+                        .with_threshold(10)
                         .with_intermediate_state(intermediate_state.take().map(|state| *state));
                     match calculator.root_with_progress().unwrap() {
-                        StateRootProgress::Progress(state, walked, _) => {
-                            intermediate_state = Some(state);
-                            hashed_entries_walked += walked;
-                        },
-                        StateRootProgress::Complete(root, walked, _) => {
-                            got = Some(root);
-                            hashed_entries_walked += walked;
-                        },
+                        StateRootProgress::Progress(state, _, _) => {
+                            progress += 1;
+                            // resume from the checkpoint the state would be persisted as
+                            let checkpoint = state.into_checkpoint(1);
+                            intermediate_state =
+                                Some(Box::new(IntermediateStateRootState::from(checkpoint)));
+                        }
+                        StateRootProgress::Complete(root, _, _) => got = Some(root),
                     };
                 }
+                assert!(progress > 1);
                 assert_eq!(expected, got.unwrap());
-                assert_eq!(hashed_entries_total, hashed_entries_walked)
             }
         );
     }
@@ -1,13 +1,19 @@
 use crate::updates::TrieKey;
 use reth_db::DatabaseError;
-use reth_primitives::trie::BranchNodeCompact;
+use reth_primitives::trie::{BranchNodeCompact, Nibbles, TrieMask};
 
 mod account_cursor;
+mod prefetch;
 mod storage_cursor;
 mod subnode;
+mod upper_trie_cache;
 
 pub use self::{
-    account_cursor::AccountTrieCursor, storage_cursor::StorageTrieCursor, subnode::CursorSubNode,
+    account_cursor::AccountTrieCursor,
+    prefetch::{PrefetchTrieCursor, TriePrefetcher},
+    storage_cursor::StorageTrieCursor,
+    subnode::CursorSubNode,
+    upper_trie_cache::{UpperTrieCache, UpperTrieCacheCursor},
 };
 
 /// A cursor for navigating a trie that works with both Tables and DupSort tables.
@@ -30,4 +36,11 @@ pub trait TrieCursor {
 
     /// Get the current entry.
     fn current(&mut self) -> Result<Option<TrieKey>, DatabaseError>;
+
+    /// Hint that the children in the mask of the node at the given path are going to be sought
+    /// soon, so that they can be read ahead.
+    ///
+    /// The hint must not change the results of any lookup. Cursors that can't read ahead ignore
+    /// it, which is the default.
+    fn prefetch(&mut self, _parent: &Nibbles, _children: TrieMask) {}
 }
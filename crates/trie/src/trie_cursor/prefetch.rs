@@ -0,0 +1,189 @@
+use super::TrieCursor;
+use crate::updates::TrieKey;
+use reth_db::{
+    cursor::{DbCursorRO, DbDupCursorRO},
+    database::Database,
+    tables,
+    transaction::DbTx,
+    DatabaseError,
+};
+use reth_primitives::{
+    trie::{
+        nodes::CHILD_INDEX_RANGE, BranchNodeCompact, Nibbles, StoredNibbles, StoredNibblesSubKey,
+        TrieMask,
+    },
+    B256,
+};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+
+/// The maximum number of queued prefetch requests. Hints are dropped while the queue is full.
+const MAX_QUEUED_REQUESTS: usize = 1024;
+
+/// The maximum number of prefetch requests served on one read-only transaction before it is
+/// renewed.
+const MAX_REQUESTS_PER_TRANSACTION: usize = 1024;
+
+/// The children of a trie node to read ahead.
+#[derive(Debug)]
+struct PrefetchRequest {
+    /// The hashed address of the account, if the node is in its storage trie.
+    hashed_address: Option<B256>,
+    /// The path of the node.
+    parent: NodePath,
+    /// The children of the node to read ahead.
+    children: TrieMask,
+}
+
+/// The path of a trie node, stored inline so that a hint doesn't allocate.
+#[derive(Debug, Clone, Copy)]
+struct NodePath {
+    /// The nibbles of the path, only the first `len` are set.
+    nibbles: [u8; 64],
+    /// The number of nibbles in the path.
+    len: usize,
+}
+
+impl NodePath {
+    /// Returns the path, unless it is longer than the path of a leaf.
+    fn new(path: &Nibbles) -> Option<Self> {
+        let mut nibbles = [0; 64];
+        nibbles.get_mut(..path.len())?.copy_from_slice(path);
+        Some(Self { nibbles, len: path.len() })
+    }
+
+    /// Returns the stored key of the child of the node at the given nibble.
+    fn child(&self, nibble: u8) -> Vec<u8> {
+        let mut child = Vec::with_capacity(self.len + 1);
+        child.extend_from_slice(&self.nibbles[..self.len]);
+        child.push(nibble);
+        child
+    }
+}
+
+/// Reads trie nodes ahead of a trie walk on a background thread.
+///
+/// The database is memory mapped, so a lookup of a node that is not in the page cache blocks the
+/// walk on disk I/O. The prefetcher looks up the hinted nodes in its own read-only transaction and
+/// discards them: the reads only fault the pages in, so the lookups of the walk hit memory while
+/// the I/O overlaps with the hashing of the previous nodes. Whether or when a prefetch completes
+/// doesn't affect the results of the walk.
+///
+/// The read-only transaction is only open while queued hints are served and is renewed after
+/// [MAX_REQUESTS_PER_TRANSACTION] of them, so an idle or long-lived prefetcher doesn't keep the
+/// database from reusing the pages freed by later writes.
+#[derive(Debug, Clone)]
+pub struct TriePrefetcher {
+    sender: SyncSender<PrefetchRequest>,
+}
+
+impl TriePrefetcher {
+    /// Spawns the prefetcher thread, which exits once all clones of the returned prefetcher are
+    /// dropped.
+    pub fn spawn<DB: Database + 'static>(db: DB) -> std::io::Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(MAX_QUEUED_REQUESTS);
+        std::thread::Builder::new().name("trie-prefetch".to_string()).spawn(move || {
+            if let Err(err) = run_prefetcher(&db, receiver) {
+                tracing::debug!(target: "trie::prefetch", %err, "trie prefetcher stopped");
+            }
+        })?;
+        Ok(Self { sender })
+    }
+
+    /// Queues the request, unless the queue is full or the thread stopped.
+    fn prefetch(&self, request: PrefetchRequest) {
+        let _ = self.sender.try_send(request);
+    }
+}
+
+/// Serves the prefetch requests until all senders are dropped.
+///
+/// The queued requests are served in batches on a read-only transaction of their own, which is
+/// closed before waiting for the next request.
+fn run_prefetcher<DB: Database>(
+    db: &DB,
+    receiver: Receiver<PrefetchRequest>,
+) -> Result<(), DatabaseError> {
+    while let Ok(request) = receiver.recv() {
+        let tx = db.tx()?;
+        let mut account_cursor = tx.cursor_read::<tables::AccountsTrie>()?;
+        let mut storage_cursor = tx.cursor_dup_read::<tables::StoragesTrie>()?;
+        let mut next = Some(request);
+        let mut served = 0;
+        while let Some(request) = next {
+            let children = CHILD_INDEX_RANGE.filter(|nibble| request.children.is_bit_set(*nibble));
+            match request.hashed_address {
+                None => {
+                    for nibble in children {
+                        account_cursor.seek(StoredNibbles::from(request.parent.child(nibble)))?;
+                    }
+                }
+                Some(hashed_address) => {
+                    for nibble in children {
+                        let subkey = StoredNibblesSubKey::from(request.parent.child(nibble));
+                        storage_cursor.seek_by_key_subkey(hashed_address, subkey)?;
+                    }
+                }
+            }
+            served += 1;
+            next =
+                if served < MAX_REQUESTS_PER_TRANSACTION { receiver.try_recv().ok() } else { None };
+        }
+    }
+    Ok(())
+}
+
+/// A [TrieCursor] that passes the prefetch hints of the walk on to a [TriePrefetcher]. All lookups
+/// go to the wrapped cursor.
+#[derive(Debug)]
+pub struct PrefetchTrieCursor<C> {
+    /// The wrapped trie cursor.
+    cursor: C,
+    /// The prefetcher, if any. Without one, the hints are ignored.
+    prefetcher: Option<TriePrefetcher>,
+    /// The hashed address of the account, if the cursor is over a storage trie.
+    hashed_address: Option<B256>,
+}
+
+impl<C> PrefetchTrieCursor<C> {
+    /// Create a new cursor wrapping the given account trie cursor.
+    pub fn account(cursor: C, prefetcher: Option<TriePrefetcher>) -> Self {
+        Self { cursor, prefetcher, hashed_address: None }
+    }
+
+    /// Create a new cursor wrapping the given cursor over the storage trie of the account.
+    pub fn storage(cursor: C, hashed_address: B256, prefetcher: Option<TriePrefetcher>) -> Self {
+        Self { cursor, prefetcher, hashed_address: Some(hashed_address) }
+    }
+}
+
+impl<C: TrieCursor> TrieCursor for PrefetchTrieCursor<C> {
+    type Key = C::Key;
+
+    fn seek_exact(
+        &mut self,
+        key: Self::Key,
+    ) -> Result<Option<(Vec<u8>, BranchNodeCompact)>, DatabaseError> {
+        self.cursor.seek_exact(key)
+    }
+
+    fn seek(
+        &mut self,
+        key: Self::Key,
+    ) -> Result<Option<(Vec<u8>, BranchNodeCompact)>, DatabaseError> {
+        self.cursor.seek(key)
+    }
+
+    fn current(&mut self) -> Result<Option<TrieKey>, DatabaseError> {
+        self.cursor.current()
+    }
+
+    fn prefetch(&mut self, parent: &Nibbles, children: TrieMask) {
+        let Some(prefetcher) = &self.prefetcher else { return };
+        let Some(parent) = NodePath::new(parent) else { return };
+        prefetcher.prefetch(PrefetchRequest {
+            hashed_address: self.hashed_address,
+            parent,
+            children,
+        });
+    }
+}
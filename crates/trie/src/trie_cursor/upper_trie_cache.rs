@@ -0,0 +1,201 @@
+use super::TrieCursor;
+use crate::updates::{TrieKey, TrieOp, TrieUpdates};
+use parking_lot::RwLock;
+use reth_db::DatabaseError;
+use reth_primitives::trie::{BranchNodeCompact, StoredNibbles};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// An in-memory copy of the upper levels of the account trie.
+///
+/// The cache holds every branch node of the account trie whose path is at most `depth` nibbles
+/// long, so an [UpperTrieCacheCursor] answers the lookups of these nodes without reading the
+/// database. The nodes are loaded once with [UpperTrieCache::load] and must be kept consistent
+/// with the `AccountsTrie` table by applying every flushed [TrieUpdates] with
+/// [UpperTrieCache::apply_updates].
+///
+/// Clones share the same nodes.
+#[derive(Debug, Clone)]
+pub struct UpperTrieCache {
+    inner: Arc<UpperTrieCacheInner>,
+}
+
+#[derive(Debug)]
+struct UpperTrieCacheInner {
+    /// The maximum path length of the cached nodes.
+    depth: usize,
+    /// The cached nodes keyed by their path.
+    nodes: RwLock<HashMap<Vec<u8>, BranchNodeCompact>>,
+    /// The number of lookups answered from the cache.
+    hits: AtomicU64,
+}
+
+impl UpperTrieCache {
+    /// Loads the branch nodes with a path of at most `depth` nibbles through the given account
+    /// trie cursor.
+    ///
+    /// Only the stored children of the loaded nodes are visited, so the number of reads is bounded
+    /// by the number of cached nodes rather than the size of the trie.
+    pub fn load<C: TrieCursor>(cursor: &mut C, depth: usize) -> Result<Self, DatabaseError> {
+        let mut nodes = HashMap::new();
+        let mut prefixes = vec![Vec::new()];
+        while let Some(prefix) = prefixes.pop() {
+            // the first node under the prefix, which is deeper if the prefix is in an extension
+            let Some((key, node)) = cursor.seek(prefix.clone().into())? else { continue };
+            if !key.starts_with(&prefix) || key.len() > depth {
+                continue
+            }
+
+            for nibble in 0..16 {
+                if node.tree_mask.is_bit_set(nibble) && key.len() < depth {
+                    let mut child = key.clone();
+                    child.push(nibble);
+                    prefixes.push(child);
+                }
+            }
+            nodes.insert(key, node);
+        }
+
+        Ok(Self {
+            inner: Arc::new(UpperTrieCacheInner {
+                depth,
+                nodes: RwLock::new(nodes),
+                hits: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    /// Returns the maximum path length of the cached nodes.
+    pub fn depth(&self) -> usize {
+        self.inner.depth
+    }
+
+    /// Returns the number of cached nodes.
+    pub fn len(&self) -> usize {
+        self.inner.nodes.read().len()
+    }
+
+    /// Returns `true` if no nodes are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of lookups answered from the cache, i.e. the number of database reads
+    /// that were saved.
+    pub fn hits(&self) -> u64 {
+        self.inner.hits.load(Ordering::Relaxed)
+    }
+
+    /// Applies the account trie updates to the cached nodes.
+    ///
+    /// Must be called with every update that is flushed to the `AccountsTrie` table, otherwise the
+    /// cache serves stale nodes.
+    pub fn apply_updates(&self, updates: &TrieUpdates) {
+        let mut nodes = self.inner.nodes.write();
+        for (key, op) in updates.iter() {
+            let TrieKey::AccountNode(key) = key else { continue };
+            if key.inner.len() > self.inner.depth {
+                continue
+            }
+            match op {
+                TrieOp::Update(node) => {
+                    nodes.insert(key.inner.to_vec(), node.clone());
+                }
+                TrieOp::Delete => {
+                    nodes.remove(key.inner.as_ref());
+                }
+            }
+        }
+    }
+
+    /// Looks up the node at the given path, returns `None` if the cache can't answer the lookup.
+    ///
+    /// The cache holds all nodes up to its depth, so an exact lookup within the depth is always
+    /// answered. A seek is only answered if the node at the path itself exists, since the next
+    /// node could be a deeper one that is not cached.
+    fn lookup(&self, key: &[u8], exact: bool) -> Option<Option<BranchNodeCompact>> {
+        if key.len() > self.inner.depth {
+            return None
+        }
+        let node = self.inner.nodes.read().get(key).cloned();
+        if node.is_none() && !exact {
+            return None
+        }
+        self.inner.hits.fetch_add(1, Ordering::Relaxed);
+        Some(node)
+    }
+}
+
+/// A [TrieCursor] over the account trie that answers the lookups of the nodes held by an
+/// [UpperTrieCache] from memory and falls back to the wrapped cursor for all others.
+#[derive(Debug)]
+pub struct UpperTrieCacheCursor<C> {
+    /// The wrapped account trie cursor.
+    cursor: C,
+    /// The cache of the upper trie nodes, if any.
+    cache: Option<UpperTrieCache>,
+    /// The path of the node the last lookup returned, if it was answered from the cache.
+    cached_key: Option<Vec<u8>>,
+}
+
+impl<C> UpperTrieCacheCursor<C> {
+    /// Create a new cursor wrapping the given account trie cursor. Without a cache, all lookups
+    /// are passed through.
+    pub fn new(cursor: C, cache: Option<UpperTrieCache>) -> Self {
+        Self { cursor, cache, cached_key: None }
+    }
+
+    fn lookup(
+        &mut self,
+        key: StoredNibbles,
+        exact: bool,
+    ) -> Result<Option<(Vec<u8>, BranchNodeCompact)>, DatabaseError>
+    where
+        C: TrieCursor<Key = StoredNibbles>,
+    {
+        if let Some(node) = self.cache.as_ref().and_then(|cache| cache.lookup(&key.inner, exact)) {
+            self.cached_key = node.as_ref().map(|_| key.inner.to_vec());
+            return Ok(node.map(|node| (key.inner.to_vec(), node)))
+        }
+
+        self.cached_key = None;
+        if exact {
+            self.cursor.seek_exact(key)
+        } else {
+            self.cursor.seek(key)
+        }
+    }
+}
+
+impl<C> TrieCursor for UpperTrieCacheCursor<C>
+where
+    C: TrieCursor<Key = StoredNibbles>,
+{
+    type Key = StoredNibbles;
+
+    fn seek_exact(
+        &mut self,
+        key: Self::Key,
+    ) -> Result<Option<(Vec<u8>, BranchNodeCompact)>, DatabaseError> {
+        self.lookup(key, true)
+    }
+
+    fn seek(
+        &mut self,
+        key: Self::Key,
+    ) -> Result<Option<(Vec<u8>, BranchNodeCompact)>, DatabaseError> {
+        self.lookup(key, false)
+    }
+
+    fn current(&mut self) -> Result<Option<TrieKey>, DatabaseError> {
+        match &self.cached_key {
+            Some(key) => Ok(Some(TrieKey::AccountNode(key.clone().into()))),
+            None => self.cursor.current(),
+        }
+    }
+}
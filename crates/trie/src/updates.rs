@@ -8,7 +8,8 @@ use reth_primitives::{
     trie::{BranchNodeCompact, Nibbles, StorageTrieEntry, StoredNibbles, StoredNibblesSubKey},
     B256,
 };
-use std::collections::{hash_map::IntoIter, HashMap};
+use serde::{Deserialize, Serialize};
+use std::collections::{hash_map::IntoIter, BTreeMap, HashMap};
 
 /// The key of a trie node.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -37,6 +38,158 @@ impl TrieOp {
     }
 }
 
+/// A trie update in a plain, serializable form that doesn't depend on the internal key and node
+/// types, for persisting trie updates outside of the database or sending them over the wire.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableTrieUpdate {
+    /// The hashed address of the account owning the updated storage trie, or `None` if the
+    /// update is to the account trie.
+    pub hashed_address: Option<B256>,
+    /// The path of the node, one nibble per byte. Empty if the whole storage trie is deleted.
+    pub path: Vec<u8>,
+    /// The new state of the node.
+    pub node: SerializableTrieNode,
+}
+
+/// The new state of a node in a [SerializableTrieUpdate].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SerializableTrieNode {
+    /// The node is a branch node with the given masks and hashes.
+    Branch {
+        /// The mask of the children that exist.
+        state_mask: u16,
+        /// The mask of the children that are stored in the database.
+        tree_mask: u16,
+        /// The mask of the children whose hashes are stored in the node.
+        hash_mask: u16,
+        /// The hashes of the children set in the hash mask.
+        hashes: Vec<B256>,
+        /// The hash of the node itself, if stored.
+        root_hash: Option<B256>,
+    },
+    /// The node is deleted.
+    Deleted,
+    /// The whole storage trie of the account is deleted.
+    StorageTrieDeleted,
+}
+
+impl SerializableTrieUpdate {
+    fn new(key: TrieKey, op: TrieOp) -> Self {
+        let (hashed_address, path) = match key {
+            TrieKey::AccountNode(nibbles) => (None, nibbles.inner.to_vec()),
+            TrieKey::StorageNode(hashed_address, nibbles) => {
+                (Some(hashed_address), nibbles.inner.to_vec())
+            }
+            TrieKey::StorageTrie(hashed_address) => {
+                return Self {
+                    hashed_address: Some(hashed_address),
+                    path: Vec::new(),
+                    node: SerializableTrieNode::StorageTrieDeleted,
+                }
+            }
+        };
+        let node = match op {
+            TrieOp::Delete => SerializableTrieNode::Deleted,
+            TrieOp::Update(node) => SerializableTrieNode::Branch {
+                state_mask: *node.state_mask,
+                tree_mask: *node.tree_mask,
+                hash_mask: *node.hash_mask,
+                hashes: node.hashes,
+                root_hash: node.root_hash,
+            },
+        };
+        Self { hashed_address, path, node }
+    }
+
+    /// Converts the update back into its trie key and operation.
+    ///
+    /// Returns `None` if the update is malformed: a storage trie deletion without an account or a
+    /// storage trie deletion with a node path.
+    pub fn into_trie_update(self) -> Option<(TrieKey, TrieOp)> {
+        let op = match self.node {
+            SerializableTrieNode::StorageTrieDeleted => {
+                let hashed_address = self.hashed_address.filter(|_| self.path.is_empty())?;
+                return Some((TrieKey::StorageTrie(hashed_address), TrieOp::Delete))
+            }
+            SerializableTrieNode::Deleted => TrieOp::Delete,
+            SerializableTrieNode::Branch {
+                state_mask,
+                tree_mask,
+                hash_mask,
+                hashes,
+                root_hash,
+            } => TrieOp::Update(BranchNodeCompact {
+                state_mask: state_mask.into(),
+                tree_mask: tree_mask.into(),
+                hash_mask: hash_mask.into(),
+                hashes,
+                root_hash,
+            }),
+        };
+        let key = match self.hashed_address {
+            Some(hashed_address) => TrieKey::StorageNode(hashed_address, self.path.into()),
+            None => TrieKey::AccountNode(self.path.into()),
+        };
+        Some((key, op))
+    }
+}
+
+/// The number of writes of each kind that flushing a [TrieUpdates] performs, see
+/// [TrieUpdates::write_stats].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TrieWriteStats {
+    /// The number of account trie nodes that are inserted or replaced.
+    pub account_node_updates: usize,
+    /// The number of account trie nodes that are deleted.
+    pub account_node_deletes: usize,
+    /// The number of storage trie nodes that are inserted or replaced.
+    pub storage_node_updates: usize,
+    /// The number of storage trie nodes that are deleted.
+    pub storage_node_deletes: usize,
+    /// The number of storage tries that are deleted as a whole.
+    pub storage_trie_deletes: usize,
+}
+
+impl TrieWriteStats {
+    /// Returns the total number of writes.
+    ///
+    /// A storage trie delete counts as a single write, although it removes all nodes of the trie.
+    pub fn total(&self) -> usize {
+        self.account_node_updates +
+            self.account_node_deletes +
+            self.storage_node_updates +
+            self.storage_node_deletes +
+            self.storage_trie_deletes
+    }
+}
+
+/// The [TrieUpdates] of the account trie and of every storage trie, see
+/// [TrieUpdates::into_grouped].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GroupedTrieUpdates {
+    /// The account trie updates, sorted by path.
+    pub account_nodes: Vec<(StoredNibbles, TrieOp)>,
+    /// The updates of every changed storage trie, sorted by hashed address.
+    pub storage_tries: Vec<StorageTrieUpdates>,
+}
+
+/// The updates of the storage trie of a single account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageTrieUpdates {
+    /// The hashed address of the account.
+    pub hashed_address: B256,
+    /// Whether the whole storage trie is deleted.
+    pub deleted: bool,
+    /// The storage trie node updates, sorted by path.
+    pub nodes: Vec<(StoredNibblesSubKey, TrieOp)>,
+}
+
+impl StorageTrieUpdates {
+    fn new(hashed_address: B256) -> Self {
+        Self { hashed_address, deleted: false, nodes: Vec::new() }
+    }
+}
+
 /// The aggregation of trie updates.
 #[derive(Debug, Default, Clone, Deref)]
 pub struct TrieUpdates {
@@ -71,8 +224,16 @@ impl TrieUpdates {
         }
     }
 
+    /// Remove all updates, keeping the allocated capacity for reuse.
+    pub fn clear(&mut self) {
+        self.trie_operations.clear();
+    }
+
     /// Extend the updates with trie updates.
     pub fn extend(&mut self, updates: impl Iterator<Item = (TrieKey, TrieOp)>) {
+        // a non-empty map only reserves capacity for half of the hinted updates, which rehashes
+        // large extensions, e.g. the storage trie deletes of many destroyed accounts, repeatedly
+        self.trie_operations.reserve(updates.size_hint().0);
         self.trie_operations.extend(updates);
     }
 
@@ -102,6 +263,144 @@ impl TrieUpdates {
         self.extend(keys.map(|key| (key, TrieOp::Delete)));
     }
 
+    /// Returns the updates in a plain, serializable form, ordered by key.
+    ///
+    /// Unlike [TrieKey] and [TrieOp], the returned [SerializableTrieUpdate]s form a stable schema
+    /// for consumers that store or send the updates elsewhere.
+    pub fn to_serializable(&self) -> Vec<SerializableTrieUpdate> {
+        let mut trie_operations = Vec::from_iter(self.trie_operations.clone());
+        trie_operations.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        trie_operations.into_iter().map(|(key, op)| SerializableTrieUpdate::new(key, op)).collect()
+    }
+
+    /// Restores the updates from their serializable form.
+    ///
+    /// Returns `None` if any of the updates is malformed, see
+    /// [SerializableTrieUpdate::into_trie_update].
+    pub fn from_serializable(
+        updates: impl IntoIterator<Item = SerializableTrieUpdate>,
+    ) -> Option<Self> {
+        let trie_operations = updates
+            .into_iter()
+            .map(SerializableTrieUpdate::into_trie_update)
+            .collect::<Option<_>>()?;
+        Some(Self { trie_operations })
+    }
+
+    /// Tallies the writes that [Self::flush] performs, e.g. to split a large set of updates across
+    /// multiple transactions.
+    ///
+    /// Updates of the root nodes are not counted, since they are never written.
+    pub fn write_stats(&self) -> TrieWriteStats {
+        let mut stats = TrieWriteStats::default();
+        for (key, op) in &self.trie_operations {
+            match (key, op) {
+                (TrieKey::AccountNode(nibbles), _) if nibbles.inner.is_empty() => {}
+                (TrieKey::StorageNode(_, nibbles), _) if nibbles.inner.is_empty() => {}
+                (TrieKey::AccountNode(_), TrieOp::Update(_)) => stats.account_node_updates += 1,
+                (TrieKey::AccountNode(_), TrieOp::Delete) => stats.account_node_deletes += 1,
+                (TrieKey::StorageNode(..), TrieOp::Update(_)) => stats.storage_node_updates += 1,
+                (TrieKey::StorageNode(..), TrieOp::Delete) => stats.storage_node_deletes += 1,
+                (TrieKey::StorageTrie(_), _) => stats.storage_trie_deletes += 1,
+            }
+        }
+        stats
+    }
+
+    /// Splits the updates into the account trie updates and the updates of each storage trie.
+    ///
+    /// Unlike the iteration order of the updates, the groups keep the writes to a storage trie
+    /// together, so a caller can flush them contiguously, in batches or in parallel per account.
+    pub fn into_grouped(self) -> GroupedTrieUpdates {
+        let mut account_nodes = Vec::new();
+        let mut storage_tries = BTreeMap::<B256, StorageTrieUpdates>::new();
+        for (key, op) in self.trie_operations {
+            match key {
+                TrieKey::AccountNode(nibbles) => account_nodes.push((nibbles, op)),
+                TrieKey::StorageNode(hashed_address, nibbles) => storage_tries
+                    .entry(hashed_address)
+                    .or_insert_with(|| StorageTrieUpdates::new(hashed_address))
+                    .nodes
+                    .push((nibbles, op)),
+                TrieKey::StorageTrie(hashed_address) => {
+                    storage_tries
+                        .entry(hashed_address)
+                        .or_insert_with(|| StorageTrieUpdates::new(hashed_address))
+                        .deleted = true;
+                }
+            }
+        }
+
+        account_nodes.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let storage_tries = storage_tries
+            .into_values()
+            .map(|mut storage_trie| {
+                storage_trie.nodes.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+                storage_trie
+            })
+            .collect();
+        GroupedTrieUpdates { account_nodes, storage_tries }
+    }
+
+    /// Partitions the updates into batches that touch disjoint keys, so that a caller can flush
+    /// a large set of updates in bounded steps.
+    ///
+    /// The database has a single writer, so the batches must be flushed one after another. Within
+    /// one write transaction, the result is the same as flushing all updates at once. If the
+    /// batches are committed on separate transactions instead, the trie is inconsistent with the
+    /// hashed state until the last batch is committed, so the caller must record a checkpoint
+    /// after every commit to resume from after a crash.
+    ///
+    /// Account trie updates and storage trie updates are never mixed within a batch. The account
+    /// trie updates are split into batches of at most `max_batch_size` updates, while the updates
+    /// of a storage trie are always kept in the same batch, since deleting a whole storage trie
+    /// interacts with the writes of its nodes. The storage tries of multiple accounts are packed
+    /// into a batch until it exceeds `max_batch_size`, a single storage trie with more updates
+    /// forms a larger batch of its own.
+    ///
+    /// # Panics
+    ///
+    /// If `max_batch_size` is zero.
+    pub fn into_write_batches(self, max_batch_size: usize) -> Vec<TrieUpdates> {
+        assert!(max_batch_size > 0, "batch size must be positive");
+
+        let GroupedTrieUpdates { account_nodes, storage_tries } = self.into_grouped();
+        let mut batches = Vec::new();
+
+        let mut batch = TrieUpdates::default();
+        for (nibbles, op) in account_nodes {
+            if batch.len() == max_batch_size {
+                batches.push(std::mem::take(&mut batch));
+            }
+            batch.trie_operations.insert(TrieKey::AccountNode(nibbles), op);
+        }
+        if !batch.is_empty() {
+            batches.push(std::mem::take(&mut batch));
+        }
+
+        for storage_trie in storage_tries {
+            let hashed_address = storage_trie.hashed_address;
+            let len = storage_trie.nodes.len() + usize::from(storage_trie.deleted);
+            if !batch.is_empty() && batch.len() + len > max_batch_size {
+                batches.push(std::mem::take(&mut batch));
+            }
+            if storage_trie.deleted {
+                batch.trie_operations.insert(TrieKey::StorageTrie(hashed_address), TrieOp::Delete);
+            }
+            batch.extend(
+                storage_trie
+                    .nodes
+                    .into_iter()
+                    .map(|(nibbles, op)| (TrieKey::StorageNode(hashed_address, nibbles), op)),
+            );
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+
+        batches
+    }
+
     /// Flush updates all aggregated updates to the database.
     pub fn flush(self, tx: &(impl DbTx + DbTxMut)) -> Result<(), reth_db::DatabaseError> {
         if self.trie_operations.is_empty() {
@@ -159,3 +458,215 @@ impl TrieUpdates {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn serializable_round_trip() {
+        let hashed_address = B256::random();
+        let node = BranchNodeCompact::new(
+            0b1011,
+            0b0001,
+            0b1010,
+            vec![B256::random(), B256::random()],
+            Some(B256::random()),
+        );
+        let updates = TrieUpdates::from([
+            (TrieKey::AccountNode(vec![0x1].into()), TrieOp::Update(node.clone())),
+            (TrieKey::AccountNode(vec![0x2, 0x3].into()), TrieOp::Delete),
+            (TrieKey::StorageNode(hashed_address, vec![0xa].into()), TrieOp::Update(node)),
+            (TrieKey::StorageNode(hashed_address, vec![0xb].into()), TrieOp::Delete),
+            (TrieKey::StorageTrie(B256::random()), TrieOp::Delete),
+        ]);
+
+        let serialized = serde_json::to_string(&updates.to_serializable()).unwrap();
+        let deserialized: Vec<SerializableTrieUpdate> = serde_json::from_str(&serialized).unwrap();
+        let restored = TrieUpdates::from_serializable(deserialized).unwrap();
+        assert_eq!(*restored, *updates);
+    }
+
+    #[test]
+    fn write_stats() {
+        let hashed_address = B256::random();
+        let node = BranchNodeCompact::new(0b1011, 0b0001, 0b1010, vec![B256::random(); 2], None);
+        let updates = TrieUpdates::from([
+            (TrieKey::AccountNode(vec![].into()), TrieOp::Update(node.clone())),
+            (TrieKey::AccountNode(vec![0x1].into()), TrieOp::Update(node.clone())),
+            (TrieKey::AccountNode(vec![0x2].into()), TrieOp::Update(node.clone())),
+            (TrieKey::AccountNode(vec![0x2, 0x3].into()), TrieOp::Delete),
+            (TrieKey::StorageNode(hashed_address, vec![].into()), TrieOp::Update(node.clone())),
+            (TrieKey::StorageNode(hashed_address, vec![0xa].into()), TrieOp::Update(node.clone())),
+            (TrieKey::StorageNode(hashed_address, vec![0xb].into()), TrieOp::Update(node)),
+            (TrieKey::StorageNode(hashed_address, vec![0xc].into()), TrieOp::Delete),
+            (TrieKey::StorageNode(hashed_address, vec![0xd].into()), TrieOp::Delete),
+            (TrieKey::StorageNode(hashed_address, vec![0xe].into()), TrieOp::Delete),
+            (TrieKey::StorageTrie(B256::random()), TrieOp::Delete),
+        ]);
+
+        let stats = updates.write_stats();
+        assert_eq!(
+            stats,
+            TrieWriteStats {
+                account_node_updates: 2,
+                account_node_deletes: 1,
+                storage_node_updates: 2,
+                storage_node_deletes: 3,
+                storage_trie_deletes: 1,
+            }
+        );
+        assert_eq!(stats.total(), 9);
+        assert_eq!(TrieUpdates::default().write_stats().total(), 0);
+    }
+
+    #[test]
+    fn into_grouped() {
+        let node = BranchNodeCompact::new(0b1011, 0b0001, 0b1010, vec![B256::random(); 2], None);
+        let (first, second) = (B256::with_last_byte(1), B256::with_last_byte(2));
+        let updates = TrieUpdates::from([
+            (TrieKey::AccountNode(vec![0x2].into()), TrieOp::Update(node.clone())),
+            (TrieKey::AccountNode(vec![0x1, 0x3].into()), TrieOp::Delete),
+            (TrieKey::StorageNode(second, vec![0xb].into()), TrieOp::Update(node.clone())),
+            (TrieKey::StorageNode(first, vec![0xc].into()), TrieOp::Delete),
+            (TrieKey::StorageNode(second, vec![0xa].into()), TrieOp::Delete),
+            (TrieKey::StorageNode(first, vec![0x1].into()), TrieOp::Update(node.clone())),
+            (TrieKey::StorageTrie(second), TrieOp::Delete),
+            (TrieKey::StorageTrie(B256::with_last_byte(3)), TrieOp::Delete),
+        ]);
+
+        let grouped = updates.clone().into_grouped();
+        assert_eq!(
+            grouped.account_nodes,
+            [
+                (StoredNibbles::from(vec![0x1, 0x3]), TrieOp::Delete),
+                (StoredNibbles::from(vec![0x2]), TrieOp::Update(node.clone())),
+            ]
+        );
+        assert_eq!(
+            grouped.storage_tries,
+            [
+                StorageTrieUpdates {
+                    hashed_address: first,
+                    deleted: false,
+                    nodes: vec![
+                        (vec![0x1].into(), TrieOp::Update(node.clone())),
+                        (vec![0xc].into(), TrieOp::Delete),
+                    ],
+                },
+                StorageTrieUpdates {
+                    hashed_address: second,
+                    deleted: true,
+                    nodes: vec![
+                        (vec![0xa].into(), TrieOp::Delete),
+                        (vec![0xb].into(), TrieOp::Update(node)),
+                    ],
+                },
+                StorageTrieUpdates {
+                    hashed_address: B256::with_last_byte(3),
+                    deleted: true,
+                    nodes: Vec::new(),
+                },
+            ]
+        );
+
+        // all entries are preserved
+        let mut restored = TrieUpdates::default();
+        restored.extend(
+            grouped
+                .account_nodes
+                .into_iter()
+                .map(|(nibbles, op)| (TrieKey::AccountNode(nibbles), op)),
+        );
+        for storage_trie in grouped.storage_tries {
+            let hashed_address = storage_trie.hashed_address;
+            if storage_trie.deleted {
+                restored.extend(std::iter::once((
+                    TrieKey::StorageTrie(hashed_address),
+                    TrieOp::Delete,
+                )));
+            }
+            restored.extend(
+                storage_trie
+                    .nodes
+                    .into_iter()
+                    .map(|(nibbles, op)| (TrieKey::StorageNode(hashed_address, nibbles), op)),
+            );
+        }
+        assert_eq!(*restored, *updates);
+    }
+
+    #[test]
+    fn into_write_batches() {
+        let node = BranchNodeCompact::new(0b1011, 0b0001, 0b1010, vec![B256::random(); 2], None);
+        let mut updates = TrieUpdates::default();
+        for nibble in 0..5u8 {
+            updates.extend(std::iter::once((
+                TrieKey::AccountNode(vec![nibble].into()),
+                TrieOp::Update(node.clone()),
+            )));
+        }
+        for (address, num_nodes) in [(1u8, 1u8), (2, 2), (3, 4)] {
+            let hashed_address = B256::with_last_byte(address);
+            updates.extend((0..num_nodes).map(|nibble| {
+                (TrieKey::StorageNode(hashed_address, vec![nibble].into()), TrieOp::Delete)
+            }));
+        }
+        updates.extend(std::iter::once((
+            TrieKey::StorageTrie(B256::with_last_byte(2)),
+            TrieOp::Delete,
+        )));
+
+        let batches = updates.clone().into_write_batches(3);
+        let hashed_addresses = |batch: &TrieUpdates| {
+            batch
+                .keys()
+                .filter_map(|key| match key {
+                    TrieKey::AccountNode(_) => None,
+                    TrieKey::StorageNode(hashed_address, _) |
+                    TrieKey::StorageTrie(hashed_address) => Some(*hashed_address),
+                })
+                .collect::<HashSet<_>>()
+        };
+        // two account trie batches, then one batch per storage trie since no two of them fit into
+        // one
+        assert_eq!(batches.iter().map(|batch| batch.len()).collect::<Vec<_>>(), [3, 2, 1, 3, 4]);
+        assert_eq!(hashed_addresses(&batches[2]), HashSet::from([B256::with_last_byte(1)]));
+        assert_eq!(hashed_addresses(&batches[3]), HashSet::from([B256::with_last_byte(2)]));
+
+        // the batches don't share keys or storage tries, and together form the original updates
+        let mut restored = TrieUpdates::default();
+        let mut seen_addresses = HashSet::new();
+        for batch in batches {
+            let addresses = hashed_addresses(&batch);
+            assert!(
+                addresses.is_empty() ||
+                    batch.keys().all(|key| !matches!(key, TrieKey::AccountNode(_)))
+            );
+            assert!(seen_addresses.is_disjoint(&addresses));
+            seen_addresses.extend(addresses);
+            for (key, op) in batch {
+                assert!(restored.trie_operations.insert(key, op).is_none());
+            }
+        }
+        assert_eq!(*restored, *updates);
+    }
+
+    #[test]
+    fn malformed_storage_trie_deletion() {
+        let update = SerializableTrieUpdate {
+            hashed_address: None,
+            path: Vec::new(),
+            node: SerializableTrieNode::StorageTrieDeleted,
+        };
+        assert_eq!(update.into_trie_update(), None);
+
+        let update = SerializableTrieUpdate {
+            hashed_address: Some(B256::random()),
+            path: vec![0x1],
+            node: SerializableTrieNode::StorageTrieDeleted,
+        };
+        assert_eq!(update.into_trie_update(), None);
+    }
+}
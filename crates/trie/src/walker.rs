@@ -26,6 +26,8 @@ pub struct TrieWalker<C> {
     pub changes: PrefixSet,
     /// The trie updates to be applied to the trie.
     trie_updates: Option<TrieUpdates>,
+    /// Whether to hint the cursor to read ahead the children of the visited nodes.
+    prefetch: bool,
 }
 
 impl<C: TrieCursor> TrieWalker<C> {
@@ -38,6 +40,7 @@ impl<C: TrieCursor> TrieWalker<C> {
             stack: vec![CursorSubNode::default()],
             can_skip_current_node: false,
             trie_updates: None,
+            prefetch: false,
         };
 
         // Set up the root node of the trie in the stack, if it exists.
@@ -52,8 +55,14 @@ impl<C: TrieCursor> TrieWalker<C> {
 
     /// Constructs a new TrieWalker from existing stack and a cursor.
     pub fn from_stack(cursor: C, stack: Vec<CursorSubNode>, changes: PrefixSet) -> Self {
-        let mut this =
-            Self { cursor, changes, stack, can_skip_current_node: false, trie_updates: None };
+        let mut this = Self {
+            cursor,
+            changes,
+            stack,
+            can_skip_current_node: false,
+            trie_updates: None,
+            prefetch: false,
+        };
         this.update_skip_node();
         this
     }
@@ -71,6 +80,21 @@ impl<C: TrieCursor> TrieWalker<C> {
         }
     }
 
+    /// Sets the flag whether the cursor is hinted to read ahead the stored children of every
+    /// branch node the walker descends into, see [TrieCursor::prefetch].
+    pub fn with_prefetch(mut self, prefetch: bool) -> Self {
+        self.set_prefetch(prefetch);
+        self
+    }
+
+    /// Sets the flag whether the cursor is hinted to read ahead the stored children of every
+    /// branch node the walker descends into, see [TrieCursor::prefetch].
+    pub fn set_prefetch(&mut self, prefetch: bool) {
+        self.prefetch = prefetch;
+        // the current node was consumed without a hint
+        self.prefetch_children();
+    }
+
     /// Split the walker into stack and trie updates.
     pub fn split(mut self) -> (Vec<CursorSubNode>, TrieUpdates) {
         let trie_updates = self.trie_updates.take();
@@ -154,6 +178,7 @@ impl<C: TrieCursor> TrieWalker<C> {
         let nibble = subnode.nibble;
         self.stack.push(subnode);
         self.update_skip_node();
+        self.prefetch_children();
 
         // Delete the current node if it's included in the prefix set or it doesn't contain the root
         // hash.
@@ -166,6 +191,20 @@ impl<C: TrieCursor> TrieWalker<C> {
         Ok(())
     }
 
+    /// Hints the cursor to read ahead the stored children of the current node, unless prefetching
+    /// is disabled or the node is skipped.
+    ///
+    /// The children are hinted speculatively, some of them may be skipped once they are reached.
+    fn prefetch_children(&mut self) {
+        if !self.prefetch || self.can_skip_current_node {
+            return
+        }
+        let Some(CursorSubNode { key, node: Some(node), .. }) = self.stack.last() else { return };
+        if !node.tree_mask.is_empty() {
+            self.cursor.prefetch(key, node.tree_mask);
+        }
+    }
+
     /// Moves to the next sibling node in the trie, updating the stack.
     fn move_to_next_sibling(
         &mut self,